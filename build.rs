@@ -1,6 +1,9 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_prost_build::configure()
-        .compile_protos(&["proto/api/eventstore.proto"], &["proto"])
+        .compile_protos(
+            &["proto/api/eventstore.proto", "proto/api/cluster.proto"],
+            &["proto"],
+        )
         .unwrap();
 
     Ok(())
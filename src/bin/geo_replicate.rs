@@ -0,0 +1,19 @@
+use graveyar_db::geo::{GeoConfig, GeoReplicator};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt::init();
+
+    let config = GeoConfig::from_env()?;
+    let streams = if config.streams.is_empty() {
+        "every stream".to_string()
+    } else {
+        format!("{} streams", config.streams.len())
+    };
+    println!(
+        "Copying {} from {} to {} (checkpoint: {}).",
+        streams, config.source_addr, config.target_addr, config.checkpoint_path
+    );
+    GeoReplicator::new(config)?.run().await;
+    Ok(())
+}
@@ -29,13 +29,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     payload: format!("{{\"worker\": {}, \"seq\": {}}}", i, j).into_bytes(),
                     timestamp: 0,
                     metadata: std::collections::HashMap::new(),
+                    content_type: "application/json".to_string(),
                 };
 
                 let req = AppendEventRequest {
                     stream_id: stream_id.clone(),
                     events: vec![event],
                     expected_version: u64::MAX, // Casts to -1 in backend (no OCC)
-                    is_forwarded: false,
+                    redirect: false,
+                    verbatim: false,
                 };
 
                 if let Err(e) = client.append_event(req).await {
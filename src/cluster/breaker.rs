@@ -0,0 +1,112 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Calls go through.
+    Closed,
+    /// Calls fail fast until the cooldown is over.
+    Open,
+    /// The cooldown is over and a single probe call is let through.
+    HalfOpen,
+}
+
+struct Inner {
+    state: BreakerState,
+    failures: u32,
+    /// When the breaker opened, or when the current probe started.
+    since: Instant,
+}
+
+/// Stops calling a dependency after repeated failures.
+///
+/// After `threshold` consecutive failures the breaker opens. Once `cooldown`
+/// has passed, one probe call is allowed: its success closes the breaker,
+/// its failure opens it again. A probe that never reports back is replaced
+/// by another after a further cooldown.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            inner: Mutex::new(Inner {
+                state: BreakerState::Closed,
+                failures: 0,
+                since: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// Whether a call may be made now. Moves an open breaker whose cooldown
+    /// is over to half-open, letting this call through as the probe.
+    pub fn allow(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            BreakerState::Closed => true,
+            BreakerState::Open | BreakerState::HalfOpen => {
+                if inner.since.elapsed() < self.cooldown {
+                    return false;
+                }
+                inner.state = BreakerState::HalfOpen;
+                inner.since = Instant::now();
+                true
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.state = BreakerState::Closed;
+        inner.failures = 0;
+    }
+
+    pub fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.failures = inner.failures.saturating_add(1);
+        if inner.state == BreakerState::HalfOpen || inner.failures >= self.threshold {
+            inner.state = BreakerState::Open;
+            inner.since = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_after_threshold_and_probes_after_cooldown() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(20));
+        breaker.record_failure();
+        assert!(breaker.allow());
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(!breaker.allow());
+
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(breaker.allow());
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        // Only one probe at a time
+        assert!(!breaker.allow());
+
+        // A failed probe reopens at once
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(breaker.allow());
+        breaker.record_success();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert!(breaker.allow());
+    }
+}
@@ -1,24 +1,201 @@
-use crate::api::event_store_client::EventStoreClient;
-use crate::api::{AppendEventRequest, Event as ProtoEvent};
+use crate::api::cluster_service_client::ClusterServiceClient;
+use crate::api::{
+    AddNodeRequest, AppendEventResponse, CompleteHandoffRequest, Event as ProtoEvent,
+    ForwardAppendRequest, GetEventsRequest, GetSnapshotRequest, GetSnapshotResponse,
+    GetStreamDigestsRequest, HandshakeRequest, HandshakeResponse, ListStreamsRequest,
+    RaftMessageRequest, ReadConsistency, RemoveNodeRequest, ReplicaHeartbeatRequest,
+    ReplicateEventsRequest, ReplicatedEvent, SaveSnapshotRequest, Snapshot as ProtoSnapshot,
+    StreamHead, UpsertSchemaRequest,
+};
+use crate::cluster::breaker::{BreakerState, CircuitBreaker};
+use crate::cluster::handshake::TopologyFingerprint;
+use crate::cluster::membership::Member;
+use crate::cluster::tls::{host_of, ClusterTls};
+use crate::cluster::RingVersion;
+use crate::domain::schema::model::Schema;
+use crate::storage::digest::StreamDigest;
 use std::collections::HashMap;
+use std::future::Future;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::RwLock;
+use tokio::time::Instant;
 use tonic::transport::Channel;
+use tonic::{Code, Status};
 
+/// Timeouts, retries and peer health tracking of calls to other nodes.
+#[derive(Clone, Debug)]
+pub struct ForwardingConfig {
+    /// Upper bound on a call, retries included. The deadline of the request
+    /// being served applies when it is shorter.
+    pub request_timeout: Duration,
+    pub connect_timeout: Duration,
+    /// HTTP/2 pings on idle connections, so dead peers are noticed.
+    pub keep_alive_interval: Duration,
+    pub keep_alive_timeout: Duration,
+    /// Extra attempts for calls that are safe to repeat.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every following one.
+    pub retry_backoff: Duration,
+    /// Consecutive failures after which calls to a peer fail fast.
+    pub failure_threshold: u32,
+    /// How long calls to a failing peer fail fast before one is tried again.
+    pub cooldown: Duration,
+}
+
+impl Default for ForwardingConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout: Duration::from_secs(3),
+            connect_timeout: Duration::from_secs(1),
+            keep_alive_interval: Duration::from_secs(10),
+            keep_alive_timeout: Duration::from_secs(5),
+            max_retries: 2,
+            retry_backoff: Duration::from_millis(50),
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(5),
+        }
+    }
+}
+
+tokio::task_local! {
+    /// Deadline of the request being served.
+    static DEADLINE: Instant;
+}
+
+/// Runs `f` with the deadline of the request being served, which bounds
+/// the calls it makes to other nodes.
+pub async fn with_deadline<F: Future>(deadline: Option<Instant>, f: F) -> F::Output {
+    match deadline {
+        Some(deadline) => DEADLINE.scope(deadline, f).await,
+        None => f.await,
+    }
+}
+
+/// Deadline of the request being served, to hand over to another task.
+pub fn current_deadline() -> Option<Instant> {
+    DEADLINE.try_with(|d| *d).ok()
+}
+
+/// Whether a call can be repeated after a failure.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Retry {
+    /// Repeating it has no further effect.
+    Always,
+    /// It may have taken effect: only retried when it could not be sent.
+    IfUnsent,
+    /// Best effort: not retried.
+    Never,
+}
+
+/// Where a forwarded append comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Forward {
+    /// Node the client sent the append to.
+    pub origin: String,
+    /// Topology epoch at which the append was routed.
+    pub epoch: u64,
+    /// Times the append was forwarded, this one included.
+    pub hops: u32,
+}
+
+impl Forward {
+    /// First forward of an append received by `origin`.
+    pub fn new(origin: &str, epoch: u64) -> Self {
+        Self {
+            origin: origin.to_string(),
+            epoch,
+            hops: 1,
+        }
+    }
+
+    /// The same append forwarded once more.
+    pub fn next(&self, epoch: u64) -> Self {
+        Self {
+            origin: self.origin.clone(),
+            epoch,
+            hops: self.hops + 1,
+        }
+    }
+}
+
+/// Ends the message of a PeerUnavailableError for a call that never reached
+/// the peer, which can therefore be sent to another node.
+pub const NOT_SENT: &str = "the request was not sent";
+
+/// Client of the other nodes' internal cluster service.
+///
+/// Keeps one channel per peer, and drops it when the peer stops answering so
+/// that the next call reconnects. Calls to a peer that keeps failing fail
+/// fast with PeerUnavailableError until its circuit breaker lets a probe
+/// through.
 #[derive(Clone)]
 pub struct ClusterClient {
-    clients: Arc<RwLock<HashMap<String, EventStoreClient<Channel>>>>,
-    auth_token: Option<String>,
+    clients: Arc<RwLock<HashMap<String, ClusterServiceClient<Channel>>>>,
+    breakers: Arc<Mutex<HashMap<String, Arc<CircuitBreaker>>>>,
+    cluster_secret: Option<String>,
+    cluster_port: Option<u16>,
+    tls: Option<ClusterTls>,
+    config: ForwardingConfig,
 }
 
 impl ClusterClient {
-    pub fn new(auth_token: Option<String>) -> Self {
+    /// `cluster_secret` is sent as a bearer token with every call.
+    pub fn new(cluster_secret: Option<String>) -> Self {
         Self {
             clients: Arc::new(RwLock::new(HashMap::new())),
-            auth_token,
+            breakers: Arc::new(Mutex::new(HashMap::new())),
+            cluster_secret,
+            cluster_port: None,
+            tls: None,
+            config: ForwardingConfig::default(),
         }
     }
+
+    /// Reaches nodes on this port instead of the one in their address, for
+    /// clusters serving the cluster service on its own listener.
+    pub fn with_port(mut self, port: Option<u16>) -> Self {
+        self.cluster_port = port;
+        self
+    }
+
+    /// Dials nodes over mutual TLS.
+    pub fn with_tls(mut self, tls: Option<ClusterTls>) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    pub fn with_config(mut self, config: ForwardingConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    fn endpoint(&self, addr: &str) -> String {
+        match (self.cluster_port, addr.rsplit_once(':')) {
+            (Some(port), Some((host, _))) => format!("{}:{}", host, port),
+            _ => addr.to_string(),
+        }
+    }
+
+    /// Health of the calls to a peer.
+    pub fn peer_state(&self, addr: &str) -> BreakerState {
+        self.breaker(addr).state()
+    }
+
+    fn breaker(&self, addr: &str) -> Arc<CircuitBreaker> {
+        self.breakers
+            .lock()
+            .unwrap()
+            .entry(addr.to_string())
+            .or_insert_with(|| {
+                Arc::new(CircuitBreaker::new(
+                    self.config.failure_threshold,
+                    self.config.cooldown,
+                ))
+            })
+            .clone()
+    }
 }
 
 impl Default for ClusterClient {
@@ -28,68 +205,558 @@ impl Default for ClusterClient {
 }
 
 impl ClusterClient {
-    pub async fn get_client(&self, addr: &str) -> Result<EventStoreClient<Channel>, String> {
-        // Fast path: read lock
-        {
-            let map = self.clients.read().await;
-            if let Some(client) = map.get(addr) {
-                return Ok(client.clone());
-            }
-        }
-
-        // Slow path: connect and write lock
-        let mut map = self.clients.write().await;
-        // Check again in case of race
-        if let Some(client) = map.get(addr) {
+    pub async fn get_client(&self, addr: &str) -> Result<ClusterServiceClient<Channel>, String> {
+        if let Some(client) = self.clients.read().await.get(addr) {
             return Ok(client.clone());
         }
 
-        let uri = format!("http://{}", addr); // Assume HTTP/2 without TLS for internal cluster for MVP
-        let channel = Channel::from_shared(uri)
+        // Connect without holding the lock: a slow or unreachable peer must
+        // not hold up calls to the others until its connect timeout.
+        // Plaintext HTTP/2 unless cluster TLS is configured
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        let uri = format!("{}://{}", scheme, self.endpoint(addr));
+        let mut endpoint = Channel::from_shared(uri)
             .map_err(|e| e.to_string())?
+            .connect_timeout(self.config.connect_timeout)
+            .http2_keep_alive_interval(self.config.keep_alive_interval)
+            .keep_alive_timeout(self.config.keep_alive_timeout)
+            .keep_alive_while_idle(true);
+        if let Some(tls) = &self.tls {
+            endpoint = endpoint
+                .tls_config(tls.client_config(host_of(addr)))
+                .map_err(|e| e.to_string())?;
+        }
+        let channel = endpoint
             .connect()
             .await
             .map_err(|e| format!("Failed to connect to peer {}: {}", addr, e))?;
 
-        let client = EventStoreClient::new(channel);
-        map.insert(addr.to_string(), client.clone());
-
+        // Another call may have connected meanwhile: keep a single channel
+        let client = self
+            .clients
+            .write()
+            .await
+            .entry(addr.to_string())
+            .or_insert_with(|| ClusterServiceClient::new(channel))
+            .clone();
         Ok(client)
     }
 
+    async fn evict(&self, addr: &str) {
+        self.clients.write().await.remove(addr);
+    }
+
+    /// Makes a call to a peer within the deadline, retrying as `retry`
+    /// allows. Errors returned by the peer itself are passed on as they are.
+    async fn call<Req, T, F, Fut>(
+        &self,
+        target: &str,
+        retry: Retry,
+        message: Req,
+        rpc: F,
+    ) -> Result<T, String>
+    where
+        Req: Clone,
+        F: Fn(ClusterServiceClient<Channel>, tonic::Request<Req>) -> Fut,
+        Fut: Future<Output = Result<T, Status>>,
+    {
+        let mut deadline = Instant::now() + self.config.request_timeout;
+        if let Some(served) = current_deadline() {
+            deadline = deadline.min(served);
+        }
+        let breaker = self.breaker(target);
+
+        let mut attempt = 0;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(format!(
+                    "DeadlineExceededError: no answer from {} in time",
+                    target
+                ));
+            }
+            if !breaker.allow() {
+                return Err(format!(
+                    "PeerUnavailableError: calls to {} are failing, retrying later; {}",
+                    target, NOT_SENT
+                ));
+            }
+
+            let (error, sent) = match self.get_client(target).await {
+                Err(e) => (e, false),
+                Ok(client) => {
+                    let mut request = self.request(message.clone());
+                    request.set_timeout(remaining);
+                    match tokio::time::timeout(remaining, rpc(client, request)).await {
+                        Ok(Ok(resp)) => {
+                            breaker.record_success();
+                            return Ok(resp);
+                        }
+                        Ok(Err(status)) if !is_transport_failure(&status) => {
+                            // The peer answered: it is healthy
+                            breaker.record_success();
+                            return Err(status.message().to_string());
+                        }
+                        Ok(Err(status)) => {
+                            self.evict(target).await;
+                            (status.to_string(), true)
+                        }
+                        Err(_) => {
+                            self.evict(target).await;
+                            ("timed out".to_string(), true)
+                        }
+                    }
+                }
+            };
+            breaker.record_failure();
+
+            let retryable = match retry {
+                Retry::Always => true,
+                Retry::IfUnsent => !sent,
+                Retry::Never => false,
+            };
+            if !retryable || attempt >= self.config.max_retries {
+                return Err(match sent {
+                    true => format!("PeerUnavailableError: {}: {}", target, error),
+                    false => format!("PeerUnavailableError: {}: {}; {}", target, error, NOT_SENT),
+                });
+            }
+            tracing::debug!(peer = %target, attempt, error = %error, "Retrying call to peer");
+            let backoff = self
+                .config
+                .retry_backoff
+                .saturating_mul(2u32.saturating_pow(attempt))
+                .min(deadline.saturating_duration_since(Instant::now()));
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+
+    /// Appends to a stream on its owner. Only retried when it could not be
+    /// sent, as it may have been applied otherwise.
     pub async fn forward_append(
         &self,
         target_node: &str,
         stream_id: &str,
         events: Vec<crate::domain::events::event::Event>,
         expected_version: i64,
-    ) -> Result<bool, String> {
-        let mut client = self.get_client(target_node).await?;
-
+        forward: Forward,
+        verbatim: bool,
+    ) -> Result<AppendEventResponse, String> {
         // Convert Domain Events to Proto Events
         let proto_events: Vec<ProtoEvent> = events.into_iter().map(|e| e.into()).collect();
 
-        let req = AppendEventRequest {
+        let req = ForwardAppendRequest {
             stream_id: stream_id.to_string(),
             events: proto_events,
             expected_version: expected_version as u64,
-            is_forwarded: true,
+            epoch: forward.epoch,
+            hops: forward.hops,
+            origin: forward.origin,
+            verbatim,
+        };
+
+        let resp = self
+            .call(
+                target_node,
+                Retry::IfUnsent,
+                req,
+                |mut client, req| async move { client.forward_append(req).await },
+            )
+            .await?
+            .into_inner();
+
+        Ok(resp)
+    }
+
+    /// Reads the copy of a stream held by another node, usually its owner.
+    /// With a consistency token, the node waits until its copy has the write.
+    pub async fn fetch_stream(
+        &self,
+        target_node: &str,
+        stream_id: &str,
+        consistency_token: Vec<u8>,
+    ) -> Result<Vec<ProtoEvent>, String> {
+        self.fetch_stream_from(target_node, stream_id, 0, consistency_token)
+            .await
+    }
+
+    /// Like `fetch_stream`, for the events after `from_version` only.
+    pub async fn fetch_stream_from(
+        &self,
+        target_node: &str,
+        stream_id: &str,
+        from_version: u64,
+        consistency_token: Vec<u8>,
+    ) -> Result<Vec<ProtoEvent>, String> {
+        let req = GetEventsRequest {
+            stream_id: stream_id.to_string(),
+            consistency: ReadConsistency::Local as i32,
+            redirect: false,
+            consistency_token,
+            max_staleness_ms: None,
+            from_version,
+        };
+
+        self.call(
+            target_node,
+            Retry::Always,
+            req,
+            |mut client, req| async move {
+                let mut stream = client.get_events(req).await?.into_inner();
+                let mut events = Vec::new();
+                while let Some(event) = stream.message().await? {
+                    events.push(event);
+                }
+                Ok(events)
+            },
+        )
+        .await
+    }
+
+    pub async fn save_snapshot(
+        &self,
+        target_node: &str,
+        snapshot: ProtoSnapshot,
+    ) -> Result<bool, String> {
+        let req = SaveSnapshotRequest {
+            snapshot: Some(snapshot),
+            redirect: false,
+        };
+
+        let resp = self
+            .call(
+                target_node,
+                Retry::Always,
+                req,
+                |mut client, req| async move { client.save_snapshot(req).await },
+            )
+            .await?
+            .into_inner();
+
+        Ok(resp.success)
+    }
+
+    pub async fn get_snapshot(
+        &self,
+        target_node: &str,
+        stream_id: &str,
+    ) -> Result<GetSnapshotResponse, String> {
+        let req = GetSnapshotRequest {
+            stream_id: stream_id.to_string(),
+            consistency: ReadConsistency::Local as i32,
+            redirect: false,
+        };
+
+        let resp = self
+            .call(
+                target_node,
+                Retry::Always,
+                req,
+                |mut client, req| async move { client.get_snapshot(req).await },
+            )
+            .await?
+            .into_inner();
+
+        Ok(resp)
+    }
+
+    /// Stores a schema on another node, without propagating it further.
+    pub async fn upsert_schema(&self, target_node: &str, schema: &Schema) -> Result<(), String> {
+        let req = UpsertSchemaRequest {
+            schema: Some(schema.clone().into()),
+        };
+        self.call(
+            target_node,
+            Retry::Always,
+            req,
+            |mut client, req| async move { client.upsert_schema(req).await },
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Confirms to a new owner that its ranges were drained here, with the
+    /// heads of the streams that moved to it.
+    pub async fn complete_handoff(
+        &self,
+        target_node: &str,
+        from_addr: &str,
+        epoch: u64,
+        heads: Vec<(String, u64)>,
+    ) -> Result<bool, String> {
+        let req = CompleteHandoffRequest {
+            from_addr: from_addr.to_string(),
+            epoch,
+            heads: heads
+                .into_iter()
+                .map(|(stream_id, version)| StreamHead { stream_id, version })
+                .collect(),
+        };
+
+        let resp = self
+            .call(
+                target_node,
+                Retry::Always,
+                req,
+                |mut client, req| async move { client.complete_handoff(req).await },
+            )
+            .await?
+            .into_inner();
+
+        Ok(resp.accepted)
+    }
+
+    /// Sends consecutive events of a stream to a follower replica. Returns the
+    /// follower's head version after applying them.
+    pub async fn replicate(
+        &self,
+        target_node: &str,
+        leader_addr: &str,
+        stream_id: &str,
+        epoch: u64,
+        events: Vec<crate::domain::events::event::Event>,
+    ) -> Result<u64, String> {
+        let req = ReplicateEventsRequest {
+            stream_id: stream_id.to_string(),
+            leader_addr: leader_addr.to_string(),
+            epoch,
+            events: events
+                .into_iter()
+                .map(|e| ReplicatedEvent {
+                    version: e.sequence_number,
+                    event: Some(e.into()),
+                })
+                .collect(),
+        };
+
+        // Followers skip the versions they already have
+        let resp = self
+            .call(
+                target_node,
+                Retry::Always,
+                req,
+                |mut client, req| async move { client.replicate_events(req).await },
+            )
+            .await?
+            .into_inner();
+
+        Ok(resp.head)
+    }
+
+    /// Delivers a Raft message. Not retried: Raft resends what gets lost.
+    pub async fn raft_message(
+        &self,
+        target_node: &str,
+        group: &str,
+        payload: Vec<u8>,
+    ) -> Result<(), String> {
+        let req = RaftMessageRequest {
+            group: group.to_string(),
+            payload,
+        };
+        self.call(
+            target_node,
+            Retry::Never,
+            req,
+            |mut client, req| async move { client.raft_message(req).await },
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn add_node(&self, target_node: &str, addr: &str, weight: u32) -> Result<(), String> {
+        let req = AddNodeRequest {
+            addr: addr.to_string(),
+            weight,
+            propagate: false,
         };
+        self.call(
+            target_node,
+            Retry::Always,
+            req,
+            |mut client, req| async move { client.add_node(req).await },
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove_node(&self, target_node: &str, addr: &str) -> Result<(), String> {
+        let req = RemoveNodeRequest {
+            addr: addr.to_string(),
+            propagate: false,
+        };
+        self.call(
+            target_node,
+            Retry::Always,
+            req,
+            |mut client, req| async move { client.remove_node(req).await },
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Introduces this node to a peer, which answers with its own topology.
+    pub async fn handshake(
+        &self,
+        target_node: &str,
+        local: TopologyFingerprint,
+    ) -> Result<HandshakeResponse, String> {
+        let req = HandshakeRequest {
+            topology: Some(local.into()),
+        };
+        let resp = self
+            .call(
+                target_node,
+                Retry::Always,
+                req,
+                |mut client, req| async move { client.handshake(req).await },
+            )
+            .await?
+            .into_inner();
+        Ok(resp)
+    }
+
+    /// Tells a read replica that it holds every write this node owns, and
+    /// which topology the members are at.
+    pub async fn replica_heartbeat(
+        &self,
+        target_node: &str,
+        member_addr: &str,
+        version: RingVersion,
+        members: Vec<Member>,
+    ) -> Result<(), String> {
+        let req = ReplicaHeartbeatRequest {
+            member_addr: member_addr.to_string(),
+            epoch: version.epoch,
+            members: members.into_iter().map(Into::into).collect(),
+            ring_digest: version.digest,
+        };
+        self.call(
+            target_node,
+            Retry::Never,
+            req,
+            |mut client, req| async move { client.replica_heartbeat(req).await },
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Digests of a node's copies of streams.
+    pub async fn stream_digests(
+        &self,
+        target_node: &str,
+        stream_ids: Vec<String>,
+    ) -> Result<Vec<StreamDigest>, String> {
+        let req = GetStreamDigestsRequest { stream_ids };
+        let resp = self
+            .call(
+                target_node,
+                Retry::Always,
+                req,
+                |mut client, req| async move { client.get_stream_digests(req).await },
+            )
+            .await?
+            .into_inner();
+        Ok(resp.digests.into_iter().map(Into::into).collect())
+    }
 
-        let mut request = tonic::Request::new(req);
-        if let Some(token) = &self.auth_token {
+    /// Streams another node holds events of.
+    pub async fn list_streams(&self, target_node: &str) -> Result<Vec<String>, String> {
+        let req = ListStreamsRequest { local_only: true };
+        let resp = self
+            .call(
+                target_node,
+                Retry::Always,
+                req,
+                |mut client, req| async move { client.list_streams(req).await },
+            )
+            .await?
+            .into_inner();
+        Ok(resp.stream_ids)
+    }
+
+    fn request<T>(&self, message: T) -> tonic::Request<T> {
+        let mut request = tonic::Request::new(message);
+        if let Some(token) = &self.cluster_secret {
             let auth_value = format!("Bearer {}", token);
             if let Ok(meta_val) = tonic::metadata::MetadataValue::from_str(&auth_value) {
                 request.metadata_mut().insert("authorization", meta_val);
             }
         }
+        request
+    }
+}
+
+/// Whether a call failed because the peer could not be reached, rather than
+/// being answered with an error.
+fn is_transport_failure(status: &Status) -> bool {
+    use std::error::Error;
+    // Statuses built from a transport error keep it as their source. Peers
+    // also answer with Unavailable, e.g. while a handoff is pending.
+    status.source().is_some() || status.code() == Code::DeadlineExceeded
+}
 
-        let resp = client
-            .append_event(request)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unreachable_peer_fails_fast_once_its_breaker_opens() {
+        let client = ClusterClient::default().with_config(ForwardingConfig {
+            max_retries: 1,
+            retry_backoff: Duration::from_millis(1),
+            failure_threshold: 2,
+            cooldown: Duration::from_secs(60),
+            ..ForwardingConfig::default()
+        });
+        // Nothing listens on port 1
+        let peer = "127.0.0.1:1";
+
+        let err = client
+            .fetch_stream(peer, "s1", Vec::new())
             .await
-            .map_err(|e| e.to_string())?
-            .into_inner();
+            .unwrap_err();
+        assert!(err.contains("PeerUnavailableError"), "{}", err);
+        // The connection was refused, so another node may take the call
+        assert!(err.contains(NOT_SENT), "{}", err);
+        assert_eq!(client.peer_state(peer), BreakerState::Open);
 
-        Ok(resp.success)
+        let err = client
+            .fetch_stream(peer, "s1", Vec::new())
+            .await
+            .unwrap_err();
+        assert!(err.contains("are failing"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_calls_give_up_at_the_deadline() {
+        let client = ClusterClient::default();
+        let err = with_deadline(
+            Some(Instant::now()),
+            client.fetch_stream("127.0.0.1:1", "s1", Vec::new()),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.contains("DeadlineExceededError"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_retry_backoff_stops_at_the_deadline() {
+        let client = ClusterClient::default().with_config(ForwardingConfig {
+            max_retries: 40,
+            retry_backoff: Duration::from_millis(1),
+            failure_threshold: u32::MAX,
+            ..ForwardingConfig::default()
+        });
+        let started = Instant::now();
+        let err = with_deadline(
+            Some(started + Duration::from_millis(200)),
+            client.fetch_stream("127.0.0.1:1", "s1", Vec::new()),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.contains("DeadlineExceededError"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(2));
     }
 }
@@ -0,0 +1,503 @@
+use crate::cluster::membership::{Member, Membership};
+use crate::cluster::RingVersion;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+const MAX_DATAGRAM: usize = 65_507;
+
+/// Length of the HMAC-SHA256 tag that starts every datagram.
+const TAG_LEN: usize = 32;
+
+/// Timing of the SWIM failure detector.
+#[derive(Clone, Debug)]
+pub struct GossipConfig {
+    /// One member is probed per interval.
+    pub probe_interval: Duration,
+    /// How long to wait for an ack, both for direct and indirect probes.
+    pub probe_timeout: Duration,
+    /// How long a suspect has to refute before it is declared dead.
+    pub suspect_timeout: Duration,
+    /// Number of members asked to probe a target that missed a direct ping.
+    pub indirect_probes: usize,
+}
+
+impl Default for GossipConfig {
+    fn default() -> Self {
+        Self {
+            probe_interval: Duration::from_secs(1),
+            probe_timeout: Duration::from_millis(500),
+            suspect_timeout: Duration::from_secs(5),
+            indirect_probes: 3,
+        }
+    }
+}
+
+/// Messages are exchanged as JSON datagrams, each preceded by its HMAC-SHA256
+/// under the cluster secret. The member table is small, so every message
+/// piggybacks it whole rather than SWIM's bounded update buffer.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum GossipMessage {
+    Ping {
+        seq: u64,
+        from: String,
+        epoch: u64,
+        /// Digest of the sender's ring at `epoch`.
+        ring_digest: u64,
+        members: Vec<Member>,
+    },
+    PingReq {
+        seq: u64,
+        from: String,
+        target: String,
+        epoch: u64,
+        /// Digest of the sender's ring at `epoch`.
+        ring_digest: u64,
+        members: Vec<Member>,
+    },
+    Ack {
+        seq: u64,
+        from: String,
+        epoch: u64,
+        /// Digest of the sender's ring at `epoch`.
+        ring_digest: u64,
+        members: Vec<Member>,
+    },
+}
+
+/// SWIM membership protocol over UDP.
+///
+/// Each member is reachable for gossip on the UDP port of its advertised
+/// address. Every `probe_interval` one member is pinged in round-robin order;
+/// if it does not ack, `indirect_probes` other members are asked to ping it,
+/// and if those fail too it becomes suspect. Suspects that do not refute
+/// within `suspect_timeout` are declared dead and leave the ring.
+///
+/// Only nodes holding the cluster secret can gossip: datagrams without a
+/// valid tag are dropped. A replayed datagram carries member states that
+/// newer incarnations and epochs already override.
+pub struct Gossiper {
+    socket: UdpSocket,
+    key: hmac::Key,
+    membership: Arc<Membership>,
+    seeds: Vec<String>,
+    config: GossipConfig,
+    seq: AtomicU64,
+    next_target: AtomicUsize,
+    pending: Mutex<HashMap<u64, oneshot::Sender<()>>>,
+}
+
+impl Gossiper {
+    /// `cluster_secret` authenticates the datagrams exchanged with the other
+    /// members.
+    pub async fn bind(
+        bind_addr: &str,
+        membership: Arc<Membership>,
+        seeds: Vec<String>,
+        config: GossipConfig,
+        cluster_secret: &str,
+    ) -> std::io::Result<Arc<Self>> {
+        let socket = UdpSocket::bind(bind_addr).await?;
+        Ok(Arc::new(Self {
+            socket,
+            key: hmac::Key::new(hmac::HMAC_SHA256, cluster_secret.as_bytes()),
+            membership,
+            seeds,
+            config,
+            seq: AtomicU64::new(0),
+            next_target: AtomicUsize::new(0),
+            pending: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn membership(&self) -> &Arc<Membership> {
+        &self.membership
+    }
+
+    /// Runs the receive and probe loops until the returned task is aborted.
+    pub fn start(self: &Arc<Self>) -> JoinHandle<()> {
+        let receiver = self.clone();
+        let prober = self.clone();
+        tokio::spawn(async move {
+            tokio::join!(receiver.receive_loop(), prober.probe_loop());
+        })
+    }
+
+    /// Announces a graceful departure to every member on the ring.
+    pub async fn leave(&self) {
+        self.membership.leave();
+        for peer in self.peers() {
+            let seq = self.next_seq();
+            self.send(&peer, &self.ping_message(seq)).await;
+        }
+    }
+
+    async fn receive_loop(self: Arc<Self>) {
+        let mut buf = vec![0u8; MAX_DATAGRAM];
+        loop {
+            let (len, src) = match self.socket.recv_from(&mut buf).await {
+                Ok(res) => res,
+                Err(e) => {
+                    tracing::warn!(error = %e, "Gossip receive failed");
+                    continue;
+                }
+            };
+            let Some(body) = self.verify(&buf[..len]) else {
+                tracing::warn!(%src, "Dropping unauthenticated gossip message");
+                continue;
+            };
+            match serde_json::from_slice::<GossipMessage>(body) {
+                Ok(msg) => self.handle(msg, src.to_string()).await,
+                Err(e) => tracing::warn!(%src, error = %e, "Dropping malformed gossip message"),
+            }
+        }
+    }
+
+    async fn handle(self: &Arc<Self>, msg: GossipMessage, src: String) {
+        match msg {
+            GossipMessage::Ping {
+                seq,
+                epoch,
+                ring_digest,
+                members,
+                ..
+            } => {
+                self.merge(members, epoch, ring_digest);
+                self.send(&src, &self.ack_message(seq)).await;
+            }
+            GossipMessage::PingReq {
+                seq,
+                target,
+                epoch,
+                ring_digest,
+                members,
+                ..
+            } => {
+                self.merge(members, epoch, ring_digest);
+                let this = self.clone();
+                tokio::spawn(async move {
+                    if this.ping(&target).await {
+                        this.send(&src, &this.ack_message(seq)).await;
+                    }
+                });
+            }
+            GossipMessage::Ack {
+                seq,
+                epoch,
+                ring_digest,
+                members,
+                ..
+            } => {
+                self.merge(members, epoch, ring_digest);
+                if let Some(tx) = self.pending.lock().unwrap().remove(&seq) {
+                    let _ = tx.send(());
+                }
+            }
+        }
+    }
+
+    async fn probe_loop(self: Arc<Self>) {
+        let mut ticker = tokio::time::interval(self.config.probe_interval);
+        loop {
+            ticker.tick().await;
+
+            for addr in self.membership.expire_suspects(self.config.suspect_timeout) {
+                tracing::warn!(member = %addr, "Cluster member declared dead");
+            }
+
+            let peers = self.peers();
+            if peers.is_empty() {
+                // Not joined yet (or alone): keep announcing ourselves to the seeds
+                for seed in self.seeds.iter().filter(|s| !self.membership.is_self(s)) {
+                    let seq = self.next_seq();
+                    self.send(seed, &self.ping_message(seq)).await;
+                }
+                continue;
+            }
+
+            let idx = self.next_target.fetch_add(1, Ordering::Relaxed);
+            let target = peers[idx % peers.len()].clone();
+            self.probe(&target, &peers).await;
+        }
+    }
+
+    async fn probe(&self, target: &str, peers: &[String]) {
+        if self.ping(target).await {
+            return;
+        }
+
+        let seq = self.next_seq();
+        let rx = self.register(seq);
+        let topology = self.membership.topology();
+        let msg = GossipMessage::PingReq {
+            seq,
+            from: self.membership.self_addr().to_string(),
+            target: target.to_string(),
+            epoch: topology.epoch(),
+            ring_digest: topology.digest(),
+            members: self.membership.members(),
+        };
+        for helper in peers
+            .iter()
+            .filter(|p| *p != target)
+            .take(self.config.indirect_probes)
+        {
+            self.send(helper, &msg).await;
+        }
+        if self.wait(seq, rx).await {
+            return;
+        }
+
+        if self.membership.suspect(target) {
+            tracing::warn!(member = %target, "Cluster member suspected");
+        }
+    }
+
+    /// Direct probe. Returns whether the target acked in time.
+    async fn ping(&self, target: &str) -> bool {
+        let seq = self.next_seq();
+        let rx = self.register(seq);
+        self.send(target, &self.ping_message(seq)).await;
+        self.wait(seq, rx).await
+    }
+
+    fn register(&self, seq: u64) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(seq, tx);
+        rx
+    }
+
+    async fn wait(&self, seq: u64, rx: oneshot::Receiver<()>) -> bool {
+        let acked = matches!(
+            tokio::time::timeout(self.config.probe_timeout, rx).await,
+            Ok(Ok(()))
+        );
+        self.pending.lock().unwrap().remove(&seq);
+        acked
+    }
+
+    async fn send(&self, to: &str, msg: &GossipMessage) {
+        let body = match serde_json::to_vec(msg) {
+            Ok(b) => b,
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to encode gossip message");
+                return;
+            }
+        };
+        if let Err(e) = self.socket.send_to(&self.sign(&body), to).await {
+            tracing::debug!(peer = %to, error = %e, "Gossip send failed");
+        }
+    }
+
+    /// The datagram carrying `body`: its tag, then the body.
+    fn sign(&self, body: &[u8]) -> Vec<u8> {
+        let tag = hmac::sign(&self.key, body);
+        [tag.as_ref(), body].concat()
+    }
+
+    /// The body of an authentic datagram.
+    fn verify<'a>(&self, datagram: &'a [u8]) -> Option<&'a [u8]> {
+        if datagram.len() < TAG_LEN {
+            return None;
+        }
+        let (tag, body) = datagram.split_at(TAG_LEN);
+        hmac::verify(&self.key, body, tag).ok().map(|_| body)
+    }
+
+    fn merge(&self, members: Vec<Member>, epoch: u64, digest: u64) {
+        if self
+            .membership
+            .merge(members, Some(RingVersion { epoch, digest }))
+        {
+            tracing::debug!(epoch = self.membership.epoch(), "Membership updated");
+        }
+    }
+
+    /// Other members worth probing.
+    fn peers(&self) -> Vec<String> {
+        self.membership
+            .members()
+            .into_iter()
+            .filter(|m| m.state.in_ring() && !self.membership.is_self(&m.addr))
+            .map(|m| m.addr)
+            .collect()
+    }
+
+    fn next_seq(&self) -> u64 {
+        self.seq.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn ping_message(&self, seq: u64) -> GossipMessage {
+        let topology = self.membership.topology();
+        GossipMessage::Ping {
+            seq,
+            from: self.membership.self_addr().to_string(),
+            epoch: topology.epoch(),
+            ring_digest: topology.digest(),
+            members: self.membership.members(),
+        }
+    }
+
+    fn ack_message(&self, seq: u64) -> GossipMessage {
+        let topology = self.membership.topology();
+        GossipMessage::Ack {
+            seq,
+            from: self.membership.self_addr().to_string(),
+            epoch: topology.epoch(),
+            ring_digest: topology.digest(),
+            members: self.membership.members(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::membership::MemberState;
+    use crate::cluster::ClusterTopology;
+    use std::time::Instant;
+
+    fn fast_config() -> GossipConfig {
+        GossipConfig {
+            probe_interval: Duration::from_millis(20),
+            probe_timeout: Duration::from_millis(20),
+            suspect_timeout: Duration::from_millis(200),
+            indirect_probes: 2,
+        }
+    }
+
+    const SECRET: &str = "test-secret";
+
+    /// Starts a node on an ephemeral localhost port, advertising that port.
+    async fn node(seeds: Vec<String>) -> (Arc<Gossiper>, JoinHandle<()>) {
+        node_with_secret(seeds, SECRET).await
+    }
+
+    async fn node_with_secret(seeds: Vec<String>, secret: &str) -> (Arc<Gossiper>, JoinHandle<()>) {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+        drop(socket);
+
+        let membership = Arc::new(Membership::new(
+            addr.clone(),
+            ClusterTopology::new(Vec::new(), 0),
+        ));
+        let gossiper = Gossiper::bind(&addr, membership, seeds, fast_config(), secret)
+            .await
+            .unwrap();
+        let task = gossiper.start();
+        (gossiper, task)
+    }
+
+    async fn eventually(what: &str, cond: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !cond() {
+            assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    fn state_of(g: &Gossiper, addr: &str) -> Option<MemberState> {
+        g.membership().get(addr).map(|m| m.state)
+    }
+
+    #[tokio::test]
+    async fn test_join_failure_detection_and_leave() {
+        let (seed, _t1) = node(Vec::new()).await;
+        let seed_addr = seed.membership().self_addr().to_string();
+        let (b, _t2) = node(vec![seed_addr.clone()]).await;
+        let (c, t3) = node(vec![seed_addr.clone()]).await;
+        let (b_addr, c_addr) = (
+            b.membership().self_addr().to_string(),
+            c.membership().self_addr().to_string(),
+        );
+
+        // Everyone learns about everyone through the seed
+        eventually("join", || {
+            [&seed, &b, &c]
+                .iter()
+                .all(|g| g.membership().topology().get_all_nodes().len() == 3)
+        })
+        .await;
+
+        // C crashes: suspected, then declared dead everywhere
+        t3.abort();
+        eventually("C dead", || {
+            state_of(&seed, &c_addr) == Some(MemberState::Dead)
+                && state_of(&b, &c_addr) == Some(MemberState::Dead)
+        })
+        .await;
+        let topology = b.membership().topology();
+        assert_eq!(topology.get_all_nodes().len(), 2);
+        assert!(topology.epoch() >= 2);
+
+        // B leaves gracefully
+        b.leave().await;
+        eventually("B left", || {
+            state_of(&seed, &b_addr) == Some(MemberState::Left)
+        })
+        .await;
+        assert_eq!(seed.membership().topology().get_all_nodes(), [seed_addr]);
+        assert!(seed
+            .membership()
+            .members()
+            .iter()
+            .filter(|m| m.addr != c_addr && m.addr != b_addr)
+            .all(|m| m.state == MemberState::Alive));
+    }
+
+    #[tokio::test]
+    async fn test_only_nodes_with_the_secret_gossip() {
+        let (seed, _t1) = node(Vec::new()).await;
+        let seed_addr = seed.membership().self_addr().to_string();
+        let (intruder, _t2) = node_with_secret(vec![seed_addr.clone()], "guess").await;
+        let intruder_addr = intruder.membership().self_addr().to_string();
+
+        // Forged or unsigned datagrams claiming a new member are dropped too
+        let forged = GossipMessage::Ping {
+            seq: 0,
+            from: "10.0.0.9:50051".to_string(),
+            epoch: 99,
+            ring_digest: 0,
+            members: vec![Member {
+                addr: "10.0.0.9:50051".to_string(),
+                weight: 1,
+                state: MemberState::Alive,
+                incarnation: 0,
+            }],
+        };
+        let body = serde_json::to_vec(&forged).unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.send_to(&body, &seed_addr).await.unwrap();
+        socket
+            .send_to(&intruder.sign(&body), &seed_addr)
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            seed.membership().topology().get_all_nodes(),
+            [seed_addr.as_str()]
+        );
+        assert!(seed.membership().get(&intruder_addr).is_none());
+        assert!(seed.membership().get("10.0.0.9:50051").is_none());
+
+        // The same message signed with the secret is accepted
+        socket.send_to(&seed.sign(&body), &seed_addr).await.unwrap();
+        eventually("forged member", || {
+            seed.membership().get("10.0.0.9:50051").is_some()
+        })
+        .await;
+    }
+}
@@ -0,0 +1,202 @@
+use crate::api::NodeTopology;
+use crate::cluster::client::ClusterClient;
+use crate::cluster::identity::{same_addr, NodeIdentity};
+use crate::cluster::ring::RingMember;
+use crate::cluster::ClusterTopology;
+
+/// How a node routes streams, exchanged with its peers when it starts.
+///
+/// Nodes that disagree on the partitioner or the virtual nodes place every
+/// stream differently. Nodes with configured rings must also agree on the
+/// members while they are at the same epoch: later epochs come from
+/// membership changes every node applies.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TopologyFingerprint {
+    pub node_id: String,
+    pub node_name: String,
+    pub addr: String,
+    pub epoch: u64,
+    pub partitioner_version: u32,
+    pub vnodes: u32,
+    /// Ring members, unless discovered through gossip.
+    pub members: Option<Vec<RingMember>>,
+}
+
+impl TopologyFingerprint {
+    pub fn new(identity: &NodeIdentity, topology: &ClusterTopology, configured_ring: bool) -> Self {
+        let members = configured_ring.then(|| {
+            let mut members = topology.members().to_vec();
+            members.sort_by(|a, b| a.addr.cmp(&b.addr));
+            members
+        });
+        Self {
+            node_id: identity.id.to_string(),
+            node_name: identity.name.clone(),
+            addr: identity.addr.clone(),
+            epoch: topology.epoch(),
+            partitioner_version: topology.partitioner().version(),
+            vnodes: topology.vnodes(),
+            members,
+        }
+    }
+
+    /// Checks that a peer routes streams like this node.
+    pub fn check(&self, peer: &TopologyFingerprint) -> Result<(), String> {
+        if same_addr(&peer.addr, &self.addr) && peer.node_id != self.node_id {
+            return Err(format!(
+                "TopologyMismatchError: {} ({}) and {} ({}) both claim address {}, check ADVERTISE_ADDR",
+                peer.node_name, peer.node_id, self.node_name, self.node_id, self.addr
+            ));
+        }
+        if peer.partitioner_version != self.partitioner_version {
+            return Err(format!(
+                "TopologyMismatchError: {} uses partitioner v{} but {} uses v{}",
+                peer.addr, peer.partitioner_version, self.addr, self.partitioner_version
+            ));
+        }
+        if peer.vnodes != self.vnodes {
+            return Err(format!(
+                "TopologyMismatchError: {} uses {} vnodes but {} uses {}, check CLUSTER_VNODES",
+                peer.addr, peer.vnodes, self.addr, self.vnodes
+            ));
+        }
+        if let (Some(ours), Some(theirs)) = (&self.members, &peer.members) {
+            if peer.epoch == self.epoch && ours != theirs {
+                return Err(format!(
+                    "TopologyMismatchError: {} and {} have different rings at epoch {}: {} vs {}, check CLUSTER_NODES",
+                    peer.addr,
+                    self.addr,
+                    self.epoch,
+                    describe(theirs),
+                    describe(ours)
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn describe(members: &[RingMember]) -> String {
+    let members: Vec<String> = members
+        .iter()
+        .map(|m| format!("{}={}", m.addr, m.weight))
+        .collect();
+    format!("[{}]", members.join(","))
+}
+
+impl From<TopologyFingerprint> for NodeTopology {
+    fn from(f: TopologyFingerprint) -> Self {
+        NodeTopology {
+            node_id: f.node_id,
+            node_name: f.node_name,
+            addr: f.addr,
+            epoch: f.epoch,
+            partitioner_version: f.partitioner_version,
+            vnodes: f.vnodes,
+            configured_ring: f.members.is_some(),
+            members: f
+                .members
+                .unwrap_or_default()
+                .into_iter()
+                .map(|m| crate::api::RingMember {
+                    addr: m.addr,
+                    weight: m.weight,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<NodeTopology> for TopologyFingerprint {
+    fn from(t: NodeTopology) -> Self {
+        let members = t.configured_ring.then(|| {
+            t.members
+                .into_iter()
+                .map(|m| RingMember {
+                    addr: m.addr,
+                    weight: m.weight,
+                })
+                .collect()
+        });
+        TopologyFingerprint {
+            node_id: t.node_id,
+            node_name: t.node_name,
+            addr: t.addr,
+            epoch: t.epoch,
+            partitioner_version: t.partitioner_version,
+            vnodes: t.vnodes,
+            members,
+        }
+    }
+}
+
+/// Introduces a starting node to its peers. Fails if a peer rejects it or
+/// routes streams differently; unreachable peers are skipped, and check the
+/// node in turn when they start.
+pub async fn handshake(
+    client: &ClusterClient,
+    local: &TopologyFingerprint,
+    peers: &[String],
+) -> Result<(), String> {
+    for peer in peers.iter().filter(|p| !same_addr(p, &local.addr)) {
+        let resp = match client.handshake(peer, local.clone()).await {
+            Ok(resp) => resp,
+            Err(e) => {
+                tracing::warn!(peer = %peer, error = %e, "Peer unreachable for the handshake");
+                continue;
+            }
+        };
+        if !resp.accepted {
+            return Err(resp.reason);
+        }
+        let remote: TopologyFingerprint = resp.topology.unwrap_or_default().into();
+        if remote.node_id == local.node_id {
+            // Ourselves, under another address
+            continue;
+        }
+        if !same_addr(&remote.addr, peer) {
+            return Err(format!(
+                "TopologyMismatchError: the node at {} believes it is {}, check ADVERTISE_ADDR",
+                peer, remote.addr
+            ));
+        }
+        local.check(&remote)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(addr: &str, nodes: &[&str], epoch: u64) -> TopologyFingerprint {
+        let nodes = nodes.iter().map(|n| n.to_string()).collect();
+        let identity = NodeIdentity::new(None, addr);
+        TopologyFingerprint::new(&identity, &ClusterTopology::new(nodes, epoch), true)
+    }
+
+    #[test]
+    fn test_rings_must_match_at_the_same_epoch() {
+        let a = fingerprint("a:1", &["a:1", "b:1"], 0);
+        assert!(a.check(&fingerprint("b:1", &["b:1", "a:1"], 0)).is_ok());
+
+        let err = a
+            .check(&fingerprint("b:1", &["a:1", "b:1", "c:1"], 0))
+            .unwrap_err();
+        assert!(err.contains("TopologyMismatchError"), "{}", err);
+        // A later epoch comes from membership changes
+        assert!(a
+            .check(&fingerprint("b:1", &["a:1", "b:1", "c:1"], 2))
+            .is_ok());
+
+        // Two nodes advertising the same address
+        assert!(a.check(&fingerprint("a:1", &["a:1", "b:1"], 0)).is_err());
+
+        let mut other = fingerprint("b:1", &["a:1", "b:1"], 0);
+        other.vnodes += 1;
+        assert!(a.check(&other).is_err());
+
+        let round_trip: TopologyFingerprint = NodeTopology::from(a.clone()).into();
+        assert_eq!(round_trip, a);
+    }
+}
@@ -0,0 +1,191 @@
+use std::net::SocketAddr;
+use std::path::Path;
+use uuid::Uuid;
+
+/// What a node does in the cluster.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NodeRole {
+    /// Owns ranges of the ring and accepts writes.
+    #[default]
+    Member,
+    /// Keeps a copy of the members' streams for reads. Owns no ranges and
+    /// refuses writes.
+    ReadReplica,
+}
+
+/// Who a node is: a UUID kept in its data directory across restarts, a name
+/// for operators, and the address other nodes reach it at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeIdentity {
+    pub id: Uuid,
+    pub name: String,
+    pub addr: String,
+    pub role: NodeRole,
+}
+
+impl NodeIdentity {
+    /// Identity with a new UUID, named after its address unless `name` is set.
+    pub fn new(name: Option<String>, addr: impl Into<String>) -> Self {
+        let addr = addr.into();
+        Self {
+            id: Uuid::now_v7(),
+            name: name.unwrap_or_else(|| addr.clone()),
+            addr,
+            role: NodeRole::Member,
+        }
+    }
+
+    pub fn with_role(mut self, role: NodeRole) -> Self {
+        self.role = role;
+        self
+    }
+
+    /// Reads the node's UUID from `path`, or creates it there on first start.
+    pub fn load_or_create(
+        path: impl AsRef<Path>,
+        name: Option<String>,
+        addr: impl Into<String>,
+    ) -> Result<Self, String> {
+        let path = path.as_ref();
+        let mut identity = Self::new(name, addr);
+        match std::fs::read_to_string(path) {
+            Ok(content) => {
+                identity.id = Uuid::parse_str(content.trim())
+                    .map_err(|e| format!("Invalid node id in {}: {}", path.display(), e))?;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                }
+                std::fs::write(path, identity.id.to_string())
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            }
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+        Ok(identity)
+    }
+
+    /// Whether `addr` designates this node.
+    pub fn is(&self, addr: &str) -> bool {
+        same_addr(&self.addr, addr)
+    }
+}
+
+/// Whether two node addresses are the same, whatever their spelling (URL
+/// scheme, IPv6 forms, case of host names).
+pub fn same_addr(a: &str, b: &str) -> bool {
+    let (a, b) = (bare_addr(a), bare_addr(b));
+    match (a.parse::<SocketAddr>(), b.parse::<SocketAddr>()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a.eq_ignore_ascii_case(b),
+    }
+}
+
+/// `host:port` of an address that may be written as a URL.
+fn bare_addr(addr: &str) -> &str {
+    let addr = addr.trim_end_matches('/');
+    ["http://", "https://"]
+        .iter()
+        .find_map(|scheme| {
+            addr.get(..scheme.len())
+                .filter(|prefix| prefix.eq_ignore_ascii_case(scheme))
+                .map(|_| &addr[scheme.len()..])
+        })
+        .unwrap_or(addr)
+}
+
+/// Finds the `CLUSTER_NODES` entry of the local node: the one matching
+/// `advertise_addr`, or the `node_id`-th of the sorted entries. A single node
+/// needs neither. Fails rather than guess, as two nodes taking the same entry
+/// would both serve its streams.
+pub fn resolve_self_addr(
+    nodes: &[String],
+    advertise_addr: Option<&str>,
+    node_id: Option<u64>,
+) -> Result<String, String> {
+    let mut sorted: Vec<&String> = nodes.iter().collect();
+    sorted.sort();
+    sorted.dedup();
+
+    let by_id = node_id
+        .map(|id| {
+            sorted
+                .get(id as usize)
+                .map(|n| n.to_string())
+                .ok_or_else(|| {
+                    format!(
+                        "NODE_ID {} is out of range: CLUSTER_NODES has {} nodes",
+                        id,
+                        sorted.len()
+                    )
+                })
+        })
+        .transpose()?;
+    let by_addr = advertise_addr
+        .map(|addr| {
+            sorted
+                .iter()
+                .find(|n| same_addr(n, addr))
+                .map(|n| n.to_string())
+                .ok_or_else(|| format!("ADVERTISE_ADDR {} is not in CLUSTER_NODES", addr))
+        })
+        .transpose()?;
+
+    match (by_addr, by_id) {
+        (Some(addr), Some(id_addr)) if addr != id_addr => Err(format!(
+            "ADVERTISE_ADDR {} and NODE_ID (node {}) designate different nodes",
+            addr, id_addr
+        )),
+        (Some(addr), _) | (None, Some(addr)) => Ok(addr),
+        (None, None) if sorted.len() == 1 => Ok(sorted[0].to_string()),
+        (None, None) => Err(
+            "Set ADVERTISE_ADDR (or NODE_ID) to tell which CLUSTER_NODES entry is this node"
+                .to_string(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_node_id_survives_restarts() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("rocksdb_node_id");
+        let first = NodeIdentity::load_or_create(&path, None, "127.0.0.1:50051").unwrap();
+        let again =
+            NodeIdentity::load_or_create(&path, Some("a".into()), "127.0.0.1:50051").unwrap();
+        assert_eq!(first.id, again.id);
+        assert_eq!(again.name, "a");
+        assert!(again.is("127.0.0.1:50051"));
+        assert!(!again.is("127.0.0.1:50052"));
+    }
+
+    #[test]
+    fn test_addresses_match_whatever_their_spelling() {
+        assert!(same_addr("127.0.0.1:50051", "http://127.0.0.1:50051"));
+        assert!(same_addr("https://Node-1:50051/", "node-1:50051"));
+        assert!(same_addr("[::1]:50051", "[0:0:0:0:0:0:0:1]:50051"));
+        assert!(!same_addr("http://127.0.0.1:50051", "127.0.0.1:50052"));
+    }
+
+    #[test]
+    fn test_resolving_the_local_node() {
+        let nodes = vec!["10.0.0.2:50051".to_string(), "10.0.0.1:50051".to_string()];
+        assert_eq!(
+            resolve_self_addr(&nodes, Some("10.0.0.2:50051"), None),
+            Ok("10.0.0.2:50051".to_string())
+        );
+        assert_eq!(
+            resolve_self_addr(&nodes, None, Some(0)),
+            Ok("10.0.0.1:50051".to_string())
+        );
+        assert!(resolve_self_addr(&nodes, None, Some(2)).is_err());
+        assert!(resolve_self_addr(&nodes, None, None).is_err());
+        assert!(resolve_self_addr(&nodes, Some("10.0.0.3:50051"), None).is_err());
+        assert!(resolve_self_addr(&nodes, Some("10.0.0.2:50051"), Some(0)).is_err());
+        assert!(resolve_self_addr(&nodes[..1], None, None).is_ok());
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tonic::async_trait;
+
+/// How long a stream lease lasts once acquired or renewed.
+pub const DEFAULT_LEASE_DURATION: Duration = Duration::from_secs(10);
+
+/// Share of the lease given up to tolerate clock skew between nodes.
+const CLOCK_SKEW_DIVISOR: u32 = 5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LeaseOutcome {
+    /// The caller holds the lease. `term` increases on every change of holder.
+    Acquired { term: u64 },
+    /// Another node holds a live lease.
+    Held { holder: String, expires_at_ms: u64 },
+}
+
+/// Shared, linearizable record of which node may write each stream.
+///
+/// A lease can be taken over only once it has expired, so two nodes never
+/// hold a live lease on the same stream, whatever their view of the topology.
+#[async_trait]
+pub trait LeaseStore: Send + Sync {
+    /// Acquires or renews the lease on `stream_id` for `holder` until `expires_at_ms`.
+    async fn acquire(
+        &self,
+        stream_id: &str,
+        holder: &str,
+        now_ms: u64,
+        expires_at_ms: u64,
+    ) -> Result<LeaseOutcome, String>;
+
+    /// Gives up a lease held by `holder`, so the next owner need not wait for it to expire.
+    async fn release(&self, stream_id: &str, holder: &str) -> Result<(), String>;
+}
+
+struct HeldLease {
+    term: u64,
+    valid_until: Instant,
+    last_used: Instant,
+}
+
+/// Stream leases held by the local node.
+///
+/// Writes to a stream are only executed while its lease is valid. Leases in
+/// use are renewed in the background (see `run_renewals`) once half of them
+/// has been used, so that writes do not wait on the lease store; `ensure`
+/// renews inline only when that falls behind. The local validity ends before
+/// the stored expiry by a fifth of the duration, to absorb clock skew.
+///
+/// The lease is checked again right before each event is written (`check`),
+/// and a write whose lease lapsed meanwhile fails. It is still not checked by
+/// the store executing it: a write sent just before the lease lapsed and
+/// delayed past that margin may land after the lease was lost. Such a write
+/// cannot replace events of the new holder, since every version is inserted
+/// at most once, but it can take the next version.
+pub struct LeaseManager {
+    store: Arc<dyn LeaseStore>,
+    holder: String,
+    duration: Duration,
+    held: Mutex<HashMap<String, HeldLease>>,
+}
+
+impl LeaseManager {
+    pub fn new(store: Arc<dyn LeaseStore>, holder: impl Into<String>, duration: Duration) -> Self {
+        Self {
+            store,
+            holder: holder.into(),
+            duration,
+            held: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Makes sure the local node holds the lease on `stream_id`. Returns its term.
+    pub async fn ensure(&self, stream_id: &str) -> Result<u64, String> {
+        let now = Instant::now();
+        if let Some(lease) = self.held.lock().unwrap().get_mut(stream_id) {
+            lease.last_used = now;
+            if lease.valid_until > now + self.duration / 2 {
+                return Ok(lease.term);
+            }
+        }
+        self.renew(stream_id).await
+    }
+
+    /// Fails unless the lease on `stream_id` taken at `term` is still valid.
+    /// Called right before writing, since `ensure` may have been called long
+    /// before: behind a slow store or earlier events of the same write.
+    pub fn check(&self, stream_id: &str, term: u64) -> Result<(), String> {
+        match self.held.lock().unwrap().get(stream_id) {
+            Some(lease) if lease.term == term && lease.valid_until > Instant::now() => Ok(()),
+            _ => Err(format!(
+                "LeaseExpiredError: lease on stream {} (term {}) lapsed before the write",
+                stream_id, term
+            )),
+        }
+    }
+
+    /// Renews, every eighth of the lease duration, the leases used within the
+    /// last duration that are about half used. Unused leases are left to
+    /// expire, and renewed by `ensure` when used again.
+    pub async fn run_renewals(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(self.duration / 8);
+        loop {
+            interval.tick().await;
+            let now = Instant::now();
+            let due: Vec<String> = {
+                let held = self.held.lock().unwrap();
+                held.iter()
+                    .filter(|(_, lease)| {
+                        now.duration_since(lease.last_used) < self.duration
+                            && lease.valid_until <= now + self.duration / 2 + self.duration / 8
+                    })
+                    .map(|(stream_id, _)| stream_id.clone())
+                    .collect()
+            };
+            for stream_id in due {
+                if let Err(e) = self.renew(&stream_id).await {
+                    tracing::debug!(stream_id = %stream_id, error = %e, "Stream lease not renewed");
+                }
+            }
+        }
+    }
+
+    async fn renew(&self, stream_id: &str) -> Result<u64, String> {
+        let started = Instant::now();
+        let now_ms = unix_millis();
+        let expires_at_ms = now_ms + self.duration.as_millis() as u64;
+        match self
+            .store
+            .acquire(stream_id, &self.holder, now_ms, expires_at_ms)
+            .await
+            .map_err(|e| format!("LeaseStoreError: {}", e))?
+        {
+            LeaseOutcome::Acquired { term } => {
+                let valid_until = started + self.duration - self.duration / CLOCK_SKEW_DIVISOR;
+                let mut held = self.held.lock().unwrap();
+                let last_used = held.get(stream_id).map_or(started, |l| l.last_used);
+                held.insert(
+                    stream_id.to_string(),
+                    HeldLease {
+                        term,
+                        valid_until,
+                        last_used,
+                    },
+                );
+                Ok(term)
+            }
+            LeaseOutcome::Held {
+                holder,
+                expires_at_ms,
+            } => {
+                self.held.lock().unwrap().remove(stream_id);
+                Err(format!(
+                    "LeaseHeldError: stream {} is leased by {} for another {}ms",
+                    stream_id,
+                    holder,
+                    expires_at_ms.saturating_sub(now_ms)
+                ))
+            }
+        }
+    }
+
+    pub async fn release(&self, stream_id: &str) {
+        if self.held.lock().unwrap().remove(stream_id).is_none() {
+            return;
+        }
+        if let Err(e) = self.store.release(stream_id, &self.holder).await {
+            tracing::warn!(stream_id = %stream_id, error = %e, "Failed to release stream lease");
+        }
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+struct StoredLease {
+    holder: String,
+    expires_at_ms: u64,
+    term: u64,
+}
+
+/// Lease store for a single process, used in tests.
+#[derive(Default)]
+pub struct InMemoryLeaseStore {
+    leases: Mutex<HashMap<String, StoredLease>>,
+}
+
+impl InMemoryLeaseStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl LeaseStore for InMemoryLeaseStore {
+    async fn acquire(
+        &self,
+        stream_id: &str,
+        holder: &str,
+        now_ms: u64,
+        expires_at_ms: u64,
+    ) -> Result<LeaseOutcome, String> {
+        let mut leases = self.leases.lock().unwrap();
+        let lease = leases
+            .entry(stream_id.to_string())
+            .or_insert_with(|| StoredLease {
+                holder: holder.to_string(),
+                expires_at_ms: 0,
+                term: 1,
+            });
+
+        if lease.holder != holder {
+            if lease.expires_at_ms >= now_ms {
+                return Ok(LeaseOutcome::Held {
+                    holder: lease.holder.clone(),
+                    expires_at_ms: lease.expires_at_ms,
+                });
+            }
+            lease.holder = holder.to_string();
+            lease.term += 1;
+        }
+        lease.expires_at_ms = expires_at_ms;
+        Ok(LeaseOutcome::Acquired { term: lease.term })
+    }
+
+    async fn release(&self, stream_id: &str, holder: &str) -> Result<(), String> {
+        if let Some(lease) = self.leases.lock().unwrap().get_mut(stream_id) {
+            if lease.holder == holder {
+                lease.expires_at_ms = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_only_one_live_holder() {
+        let store: Arc<dyn LeaseStore> = Arc::new(InMemoryLeaseStore::new());
+        let a = LeaseManager::new(store.clone(), "A", DEFAULT_LEASE_DURATION);
+        let b = LeaseManager::new(store.clone(), "B", DEFAULT_LEASE_DURATION);
+
+        assert_eq!(a.ensure("user-1").await, Ok(1));
+        let err = b.ensure("user-1").await.unwrap_err();
+        assert!(err.contains("LeaseHeldError"), "{}", err);
+
+        // A hands the stream over explicitly
+        a.release("user-1").await;
+        assert_eq!(b.ensure("user-1").await, Ok(2));
+        assert!(a.ensure("user-1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_expired_lease_is_taken_over() {
+        let store: Arc<dyn LeaseStore> = Arc::new(InMemoryLeaseStore::new());
+        // A dies right after acquiring a very short lease
+        let a = LeaseManager::new(store.clone(), "A", Duration::from_millis(1));
+        let b = LeaseManager::new(store.clone(), "B", DEFAULT_LEASE_DURATION);
+
+        a.ensure("user-1").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert_eq!(b.ensure("user-1").await, Ok(2));
+    }
+
+    #[tokio::test]
+    async fn test_check_fails_once_the_lease_lapsed() {
+        let store: Arc<dyn LeaseStore> = Arc::new(InMemoryLeaseStore::new());
+        let a = LeaseManager::new(store, "A", Duration::from_millis(50));
+
+        let term = a.ensure("user-1").await.unwrap();
+        assert_eq!(a.check("user-1", term), Ok(()));
+        assert!(a.check("user-1", term + 1).is_err());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let err = a.check("user-1", term).unwrap_err();
+        assert!(err.contains("LeaseExpiredError"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_leases_in_use_are_renewed_in_the_background() {
+        let store: Arc<dyn LeaseStore> = Arc::new(InMemoryLeaseStore::new());
+        let a = Arc::new(LeaseManager::new(store, "A", Duration::from_millis(80)));
+        tokio::spawn(a.clone().run_renewals());
+
+        let term = a.ensure("user-1").await.unwrap();
+        // Past the local validity of the lease as first acquired
+        tokio::time::sleep(Duration::from_millis(70)).await;
+        assert_eq!(a.check("user-1", term), Ok(()));
+    }
+}
@@ -0,0 +1,548 @@
+use crate::cluster::identity::same_addr;
+use crate::cluster::partitioner::Partitioner;
+use crate::cluster::ring::{ring_digest, RingMember};
+use crate::cluster::{ClusterTopology, RingVersion};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// SWIM member state. At equal incarnation, a later state overrides an earlier one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum MemberState {
+    Alive,
+    Suspect,
+    Dead,
+    Left,
+}
+
+impl MemberState {
+    /// Whether the member keeps its ranges on the ring. Suspects keep them until
+    /// they are confirmed dead, so a slow node does not cause ownership churn.
+    pub fn in_ring(&self) -> bool {
+        matches!(self, MemberState::Alive | MemberState::Suspect)
+    }
+}
+
+/// A member record, as disseminated by gossip.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Member {
+    pub addr: String,
+    pub weight: u32,
+    pub state: MemberState,
+    /// Only incremented by the member itself, to refute suspicion of it.
+    pub incarnation: u64,
+}
+
+impl From<Member> for crate::api::ClusterMember {
+    fn from(member: Member) -> Self {
+        use crate::api::cluster_member::State;
+
+        let state = match member.state {
+            MemberState::Alive => State::Alive,
+            MemberState::Suspect => State::Suspect,
+            MemberState::Dead => State::Dead,
+            MemberState::Left => State::Left,
+        };
+        crate::api::ClusterMember {
+            addr: member.addr,
+            state: state as i32,
+            incarnation: member.incarnation,
+            weight: member.weight,
+        }
+    }
+}
+
+impl From<crate::api::ClusterMember> for Member {
+    fn from(member: crate::api::ClusterMember) -> Self {
+        use crate::api::cluster_member::State;
+
+        let state = match member.state() {
+            State::Alive => MemberState::Alive,
+            State::Suspect => MemberState::Suspect,
+            State::Dead => MemberState::Dead,
+            State::Left => MemberState::Left,
+        };
+        Member {
+            addr: member.addr,
+            weight: member.weight,
+            state,
+            incarnation: member.incarnation,
+        }
+    }
+}
+
+struct Inner {
+    members: BTreeMap<String, Member>,
+    /// Local time at which each current suspect was first suspected.
+    suspected_at: HashMap<String, Instant>,
+    topology: Arc<ClusterTopology>,
+}
+
+/// The local view of cluster membership, and the topology derived from it.
+///
+/// Every change to the set of members on the ring rebuilds the topology under
+/// the next epoch. Epochs learnt from peers are adopted when higher and the
+/// peer's ring is the same, so nodes that saw the same changes converge on the
+/// same epoch. A peer holding another ring under an epoch at least as high
+/// moves the local epoch past it, so that one epoch never names two rings for
+/// long.
+pub struct Membership {
+    self_addr: String,
+    vnodes: u32,
+    partitioner: Partitioner,
+    inner: RwLock<Inner>,
+    changes: watch::Sender<Arc<ClusterTopology>>,
+}
+
+impl Membership {
+    /// Starts from a static topology, with every node alive. The local node is
+    /// added to the ring if the topology does not contain it.
+    pub fn new(self_addr: impl Into<String>, topology: ClusterTopology) -> Self {
+        Self::build(self_addr.into(), topology, true)
+    }
+
+    /// Like `new`, for a node that follows the ring without joining it, such
+    /// as a read replica.
+    pub fn observer(self_addr: impl Into<String>, topology: ClusterTopology) -> Self {
+        Self::build(self_addr.into(), topology, false)
+    }
+
+    fn build(self_addr: String, topology: ClusterTopology, join: bool) -> Self {
+        let mut members: BTreeMap<String, Member> = topology
+            .members()
+            .iter()
+            .map(|m| {
+                let member = Member {
+                    addr: m.addr.clone(),
+                    weight: m.weight,
+                    state: MemberState::Alive,
+                    incarnation: 0,
+                };
+                (m.addr.clone(), member)
+            })
+            .collect();
+        if join {
+            members.entry(self_addr.clone()).or_insert_with(|| Member {
+                addr: self_addr.clone(),
+                weight: 1,
+                state: MemberState::Alive,
+                incarnation: 0,
+            });
+        }
+
+        let vnodes = topology.vnodes();
+        let partitioner = topology.partitioner();
+        let topology = Arc::new(build_topology(
+            &members,
+            vnodes,
+            partitioner,
+            topology.epoch(),
+        ));
+        let (changes, _) = watch::channel(topology.clone());
+
+        Self {
+            self_addr,
+            vnodes,
+            partitioner,
+            inner: RwLock::new(Inner {
+                members,
+                suspected_at: HashMap::new(),
+                topology,
+            }),
+            changes,
+        }
+    }
+
+    pub fn self_addr(&self) -> &str {
+        &self.self_addr
+    }
+
+    /// Whether `addr` designates the local node.
+    pub fn is_self(&self, addr: &str) -> bool {
+        same_addr(&self.self_addr, addr)
+    }
+
+    pub fn topology(&self) -> Arc<ClusterTopology> {
+        self.inner.read().unwrap().topology.clone()
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.topology().epoch()
+    }
+
+    pub fn members(&self) -> Vec<Member> {
+        self.inner
+            .read()
+            .unwrap()
+            .members
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    pub fn get(&self, addr: &str) -> Option<Member> {
+        self.inner.read().unwrap().members.get(addr).cloned()
+    }
+
+    /// Merges records and, if known, the ring version received from a peer.
+    /// Returns whether the local view changed.
+    pub fn merge(&self, updates: Vec<Member>, remote: Option<RingVersion>) -> bool {
+        let mut inner = self.inner.write().unwrap();
+        let before = ring_set(&inner.members);
+
+        let mut changed = false;
+        for update in updates {
+            changed |= self.apply(&mut inner, update);
+        }
+
+        let ring_changed = ring_set(&inner.members) != before;
+        self.advance_epoch(&mut inner, ring_changed, remote);
+        changed
+    }
+
+    /// Marks a member that failed a direct and an indirect probe as suspect.
+    pub fn suspect(&self, addr: &str) -> bool {
+        let update = match self.get(addr) {
+            Some(m) if m.state == MemberState::Alive => Member {
+                state: MemberState::Suspect,
+                ..m
+            },
+            _ => return false,
+        };
+        self.merge(vec![update], None)
+    }
+
+    /// Confirms as dead the suspects that did not refute within `timeout`.
+    pub fn expire_suspects(&self, timeout: Duration) -> Vec<String> {
+        let expired: Vec<Member> = {
+            let inner = self.inner.read().unwrap();
+            inner
+                .suspected_at
+                .iter()
+                .filter(|(_, since)| since.elapsed() >= timeout)
+                .filter_map(|(addr, _)| inner.members.get(addr))
+                .map(|m| Member {
+                    state: MemberState::Dead,
+                    ..m.clone()
+                })
+                .collect()
+        };
+        let addrs = expired.iter().map(|m| m.addr.clone()).collect();
+        if !expired.is_empty() {
+            self.merge(expired, None);
+        }
+        addrs
+    }
+
+    /// Marks the local node as having left the cluster. Returns its final
+    /// record, or `None` for an observer, which never joined.
+    pub fn leave(&self) -> Option<Member> {
+        self.override_member(&self.self_addr.clone(), MemberState::Left, None)
+    }
+
+    /// Adds a member (or changes its weight) by administrative decision.
+    /// Its new record overrides any gossiped one.
+    pub fn add_member(&self, addr: &str, weight: u32) -> Member {
+        self.override_member(addr, MemberState::Alive, Some(weight.max(1)))
+            .expect("added members always exist")
+    }
+
+    /// Removes a member by administrative decision. Returns `None` if unknown.
+    pub fn remove_member(&self, addr: &str) -> Option<Member> {
+        self.override_member(addr, MemberState::Left, None)
+    }
+
+    /// Receives topology changes. The current topology is marked as seen.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<ClusterTopology>> {
+        self.changes.subscribe()
+    }
+
+    fn override_member(
+        &self,
+        addr: &str,
+        state: MemberState,
+        weight: Option<u32>,
+    ) -> Option<Member> {
+        let mut inner = self.inner.write().unwrap();
+        let before = ring_set(&inner.members);
+
+        let member = match (inner.members.get(addr), weight) {
+            (Some(current), _) => Member {
+                addr: addr.to_string(),
+                weight: weight.unwrap_or(current.weight),
+                state,
+                incarnation: current.incarnation + 1,
+            },
+            (None, Some(weight)) => Member {
+                addr: addr.to_string(),
+                weight,
+                state,
+                incarnation: 0,
+            },
+            (None, None) => return None,
+        };
+        inner.suspected_at.remove(addr);
+        inner.members.insert(addr.to_string(), member.clone());
+
+        let ring_changed = ring_set(&inner.members) != before;
+        self.advance_epoch(&mut inner, ring_changed, None);
+        Some(member)
+    }
+
+    fn apply(&self, inner: &mut Inner, update: Member) -> bool {
+        if self.is_self(&update.addr) {
+            // We are the authority on ourselves: refute suspicion with a new
+            // incarnation. An observer is not a member, and takes no record
+            // of itself.
+            let Some(me) = inner
+                .members
+                .values_mut()
+                .find(|m| same_addr(&m.addr, &self.self_addr))
+            else {
+                return false;
+            };
+            if me.state == MemberState::Alive
+                && update.state != MemberState::Alive
+                && update.incarnation >= me.incarnation
+            {
+                me.incarnation = update.incarnation + 1;
+                return true;
+            }
+            return false;
+        }
+
+        let newer = match inner.members.get(&update.addr) {
+            None => true,
+            Some(current) => {
+                update.incarnation > current.incarnation
+                    || (update.incarnation == current.incarnation && update.state > current.state)
+            }
+        };
+        if !newer {
+            return false;
+        }
+
+        if update.state == MemberState::Suspect {
+            inner
+                .suspected_at
+                .entry(update.addr.clone())
+                .or_insert_with(Instant::now);
+        } else {
+            inner.suspected_at.remove(&update.addr);
+        }
+        inner.members.insert(update.addr.clone(), update);
+        true
+    }
+
+    fn advance_epoch(&self, inner: &mut Inner, ring_changed: bool, remote: Option<RingVersion>) {
+        let current = inner.topology.epoch();
+        let mut epoch = current + ring_changed as u64;
+        if let Some(remote) = remote {
+            if remote.digest == ring_digest(&ring_members(&inner.members)) {
+                epoch = epoch.max(remote.epoch);
+            } else if remote.epoch >= epoch {
+                // The peer built another ring under this epoch or a later one.
+                // It gossiped its records along, so move past its epoch: the
+                // peer adopts ours once it has merged the same records.
+                tracing::warn!(
+                    epoch,
+                    remote_epoch = remote.epoch,
+                    "Conflicting rings under the same epoch, advancing past it"
+                );
+                epoch = remote.epoch + 1;
+            }
+        }
+        if epoch != current {
+            inner.topology = Arc::new(build_topology(
+                &inner.members,
+                self.vnodes,
+                self.partitioner,
+                epoch,
+            ));
+            self.changes.send_replace(inner.topology.clone());
+        }
+    }
+}
+
+fn ring_set(members: &BTreeMap<String, Member>) -> Vec<(String, u32)> {
+    members
+        .values()
+        .filter(|m| m.state.in_ring())
+        .map(|m| (m.addr.clone(), m.weight))
+        .collect()
+}
+
+fn ring_members(members: &BTreeMap<String, Member>) -> Vec<RingMember> {
+    members
+        .values()
+        .filter(|m| m.state.in_ring())
+        .map(|m| RingMember {
+            addr: m.addr.clone(),
+            weight: m.weight,
+        })
+        .collect()
+}
+
+fn build_topology(
+    members: &BTreeMap<String, Member>,
+    vnodes: u32,
+    partitioner: Partitioner,
+    epoch: u64,
+) -> ClusterTopology {
+    ClusterTopology::with_partitioner(ring_members(members), vnodes, partitioner, epoch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn membership() -> Membership {
+        let topology = ClusterTopology::new(vec!["A".to_string(), "B".to_string()], None);
+        Membership::new("A", topology)
+    }
+
+    fn record(addr: &str, state: MemberState, incarnation: u64) -> Member {
+        Member {
+            addr: addr.to_string(),
+            weight: 1,
+            state,
+            incarnation,
+        }
+    }
+
+    #[test]
+    fn test_incarnation_precedence() {
+        let m = membership();
+
+        assert!(m.merge(vec![record("B", MemberState::Suspect, 0)], None));
+        // Stale alive record does not clear the suspicion
+        assert!(!m.merge(vec![record("B", MemberState::Alive, 0)], None));
+        assert_eq!(m.get("B").unwrap().state, MemberState::Suspect);
+
+        // The member refuted with a higher incarnation
+        assert!(m.merge(vec![record("B", MemberState::Alive, 1)], None));
+        assert_eq!(m.get("B").unwrap().state, MemberState::Alive);
+        // Suspects stay on the ring
+        assert_eq!(m.epoch(), 0);
+    }
+
+    #[test]
+    fn test_self_refutes_suspicion() {
+        let m = membership();
+        assert!(m.merge(vec![record("A", MemberState::Dead, 3)], None));
+
+        let me = m.get("A").unwrap();
+        assert_eq!(me.state, MemberState::Alive);
+        assert_eq!(me.incarnation, 4);
+    }
+
+    #[test]
+    fn test_observer_takes_no_record_of_itself() {
+        let topology = ClusterTopology::new(vec!["A".to_string()], 0);
+        let m = Membership::observer("R", topology);
+
+        assert!(!m.merge(vec![record("R", MemberState::Alive, 0)], None));
+        assert!(m.get("R").is_none());
+        assert!(m.leave().is_none());
+        assert_eq!(m.topology().get_all_nodes(), ["A"]);
+    }
+
+    #[test]
+    fn test_ring_changes_bump_epoch() {
+        let m = membership();
+
+        m.merge(vec![record("C", MemberState::Alive, 0)], None);
+        assert_eq!(m.epoch(), 1);
+        assert_eq!(m.topology().get_all_nodes(), ["A", "B", "C"]);
+
+        m.merge(vec![record("C", MemberState::Dead, 0)], None);
+        assert_eq!(m.epoch(), 2);
+        assert_eq!(m.topology().get_all_nodes(), ["A", "B"]);
+
+        // A peer that saw the same change under the same epoch does not bump it again
+        let version = m.topology().version();
+        m.merge(vec![record("C", MemberState::Dead, 0)], Some(version));
+        assert_eq!(m.epoch(), 2);
+
+        // Higher epochs of the same ring are adopted
+        m.merge(
+            Vec::new(),
+            Some(RingVersion {
+                epoch: 7,
+                ..version
+            }),
+        );
+        assert_eq!(m.epoch(), 7);
+    }
+
+    #[test]
+    fn test_concurrent_changes_under_one_epoch() {
+        let topology = ClusterTopology::new(vec!["A".to_string(), "B".to_string()], 0);
+        let a = Membership::new("A", topology.clone());
+        let b = Membership::new("B", topology);
+
+        // Each node applies a different join: same epoch, different rings
+        a.add_member("C", 1);
+        b.add_member("D", 1);
+        assert_eq!(a.epoch(), b.epoch());
+        assert_ne!(a.topology().digest(), b.topology().digest());
+
+        // Either order of exchange ends on one ring under one epoch
+        a.merge(b.members(), Some(b.topology().version()));
+        b.merge(a.members(), Some(a.topology().version()));
+        assert_eq!(a.topology().version(), b.topology().version());
+        assert_eq!(a.topology().get_all_nodes(), ["A", "B", "C", "D"]);
+
+        // A peer claiming the current epoch for another ring is moved past
+        let epoch = a.epoch();
+        a.merge(
+            Vec::new(),
+            Some(RingVersion {
+                epoch,
+                digest: a.topology().digest() ^ 1,
+            }),
+        );
+        assert_eq!(a.epoch(), epoch + 1);
+
+        // ...but an older epoch of another ring is ignored
+        a.merge(
+            Vec::new(),
+            Some(RingVersion {
+                epoch: 0,
+                digest: 1,
+            }),
+        );
+        assert_eq!(a.epoch(), epoch + 1);
+    }
+
+    #[test]
+    fn test_admin_changes_override_gossip() {
+        let m = membership();
+        let mut changes = m.subscribe();
+
+        m.add_member("C", 2);
+        assert_eq!(m.epoch(), 1);
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(changes.borrow_and_update().epoch(), 1);
+
+        // Removal wins over the record the member itself last gossiped
+        m.merge(vec![record("B", MemberState::Alive, 5)], None);
+        assert_eq!(m.remove_member("B").unwrap().incarnation, 6);
+        assert!(!m.merge(vec![record("B", MemberState::Alive, 5)], None));
+        assert_eq!(m.topology().get_all_nodes(), ["A", "C"]);
+        assert!(m.remove_member("Z").is_none());
+    }
+
+    #[test]
+    fn test_expire_suspects() {
+        let m = membership();
+        assert!(m.suspect("B"));
+        assert!(m.expire_suspects(Duration::from_secs(60)).is_empty());
+
+        assert_eq!(m.expire_suspects(Duration::ZERO), vec!["B".to_string()]);
+        assert_eq!(m.get("B").unwrap().state, MemberState::Dead);
+        assert_eq!(m.topology().get_all_nodes(), ["A"]);
+    }
+}
@@ -1,7 +1,17 @@
+pub mod breaker;
 pub mod client;
-
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+pub mod gossip;
+pub mod handshake;
+pub mod identity;
+pub mod lease;
+pub mod membership;
+pub mod partitioner;
+pub mod raft;
+pub mod ring;
+pub mod tls;
+
+use crate::cluster::partitioner::Partitioner;
+use crate::cluster::ring::{ring_digest, HashRing, RingMember, TokenRange, DEFAULT_VNODES};
 
 #[derive(Clone, Debug, PartialEq)] // Added Debug/PartialEq
 pub struct ChainOwner {
@@ -9,41 +19,129 @@ pub struct ChainOwner {
     pub epoch: u64,
 }
 
+/// Identifies a ring as gossiped between nodes: the epoch it was built under
+/// and a digest of its members. Nodes that applied different concurrent
+/// changes can reach the same epoch, which the digest tells apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RingVersion {
+    pub epoch: u64,
+    pub digest: u64,
+}
+
 #[derive(Clone)]
 pub struct ClusterTopology {
     nodes: Vec<String>,
+    members: Vec<RingMember>,
+    ring: HashRing,
     epoch: u64,
 }
 
 impl ClusterTopology {
+    /// Equal-weight topology with the default number of virtual nodes.
     pub fn new(nodes: Vec<String>, epoch: u64) -> Self {
-        let mut sorted_nodes = nodes;
+        let members: Vec<RingMember> = nodes.into_iter().map(RingMember::new).collect();
+        Self::with_members(members, DEFAULT_VNODES, epoch)
+    }
+
+    pub fn with_members(members: Vec<RingMember>, vnodes: u32, epoch: u64) -> Self {
+        Self::with_partitioner(members, vnodes, Partitioner::default(), epoch)
+    }
+
+    pub fn with_partitioner(
+        members: Vec<RingMember>,
+        vnodes: u32,
+        partitioner: Partitioner,
+        epoch: u64,
+    ) -> Self {
+        let mut sorted_nodes: Vec<String> = members.iter().map(|m| m.addr.clone()).collect();
         sorted_nodes.sort();
+        sorted_nodes.dedup();
         Self {
             nodes: sorted_nodes,
+            ring: HashRing::with_partitioner(&members, vnodes, partitioner),
+            members,
             epoch,
         }
     }
 
     pub fn get_owner(&self, stream_id: &str) -> ChainOwner {
-        let mut hasher = DefaultHasher::new();
-        stream_id.hash(&mut hasher);
-        let hash = hasher.finish();
-
-        let idx = (hash as usize) % self.nodes.len();
+        let node_addr = self
+            .ring
+            .owner(stream_id)
+            .expect("Cluster topology has no nodes")
+            .to_string();
         ChainOwner {
-            node_addr: self.nodes[idx].clone(),
+            node_addr,
             epoch: self.epoch,
         }
     }
 
+    /// The `n` nodes holding copies of a stream, its owner (the leader) first.
+    /// Fewer are returned when the cluster has fewer than `n` nodes.
+    pub fn replicas_for(&self, stream_id: &str, n: usize) -> Vec<String> {
+        self.ring
+            .replicas(stream_id, n)
+            .into_iter()
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// The `n` nodes holding copies of a token range, its owner first.
+    pub fn replicas_for_range(&self, range: &TokenRange, n: usize) -> Vec<String> {
+        self.ring
+            .replicas_of_token(range.end, n)
+            .into_iter()
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Token ranges of the ring in token order, each with its owning node.
+    pub fn token_ranges(&self) -> Vec<TokenRange> {
+        self.ring.token_ranges()
+    }
+
+    /// Token ranges owned by a single node.
+    pub fn ranges_for(&self, node_addr: &str) -> Vec<TokenRange> {
+        self.ring
+            .token_ranges()
+            .into_iter()
+            .filter(|r| r.node_addr == node_addr)
+            .collect()
+    }
+
+    pub fn vnodes(&self) -> u32 {
+        self.ring.vnodes()
+    }
+
+    /// The hash function version all nodes must share for ownership to agree.
+    pub fn partitioner(&self) -> Partitioner {
+        self.ring.partitioner()
+    }
+
     pub fn get_all_nodes(&self) -> &[String] {
         &self.nodes
     }
 
+    /// Ring placement of the nodes, including weights.
+    pub fn members(&self) -> &[RingMember] {
+        &self.members
+    }
+
     pub fn epoch(&self) -> u64 {
         self.epoch
     }
+
+    /// Digest of the members and weights placed on the ring.
+    pub fn digest(&self) -> u64 {
+        ring_digest(&self.members)
+    }
+
+    pub fn version(&self) -> RingVersion {
+        RingVersion {
+            epoch: self.epoch,
+            digest: self.digest(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -90,10 +188,36 @@ mod tests {
         let o1 = t1.get_owner("stream-1");
         let o2 = t2.get_owner("stream-1");
 
-        // Usually, hashes differ with node count.
-        // We just assert they return valid owners from their respective sets.
         assert!(t1.get_all_nodes().contains(&o1.node_addr));
         assert!(t2.get_all_nodes().contains(&o2.node_addr));
         assert_ne!(o1.epoch, o2.epoch);
+
+        // With the ring, a stream only changes owner if it moves to the new node
+        for i in 0..1_000 {
+            let stream = format!("stream-{}", i);
+            let (o1, o2) = (t1.get_owner(&stream), t2.get_owner(&stream));
+            assert!(o1.node_addr == o2.node_addr || o2.node_addr == "C");
+        }
+    }
+
+    #[test]
+    fn test_ranges_for_node() {
+        let nodes = vec!["A".to_string(), "B".to_string()];
+        let topology = ClusterTopology::new(nodes, 1);
+
+        let a_ranges = topology.ranges_for("A");
+        let b_ranges = topology.ranges_for("B");
+        assert!(!a_ranges.is_empty() && !b_ranges.is_empty());
+        assert_eq!(
+            a_ranges.len() + b_ranges.len(),
+            topology.token_ranges().len()
+        );
+
+        let owner = topology.get_owner("stream-1");
+        let token = topology.partitioner().token("stream-1");
+        assert!(topology
+            .ranges_for(&owner.node_addr)
+            .iter()
+            .any(|r| r.contains(token)));
     }
 }
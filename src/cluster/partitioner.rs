@@ -0,0 +1,84 @@
+use xxhash_rust::xxh64::xxh64;
+
+/// Seed for ring tokens (node-level routing).
+const TOKEN_SEED: u64 = 0;
+/// Seed for worker selection inside a node. Must differ from `TOKEN_SEED` so the
+/// two levels are independent: otherwise a node owning half of the ring would
+/// only ever use half of its workers.
+const WORKER_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Hash functions used to route streams, versioned so that every node of a
+/// cluster provably agrees on ownership across upgrades.
+///
+/// `V1`: XXH64 over the UTF-8 bytes of the stream id. Ring tokens use seed 0,
+/// worker slots use seed `0x9E3779B97F4A7C15`. Any change to these functions
+/// requires a new version; existing versions must never change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Partitioner {
+    #[default]
+    V1,
+}
+
+impl Partitioner {
+    pub fn version(&self) -> u32 {
+        match self {
+            Partitioner::V1 => 1,
+        }
+    }
+
+    pub fn from_version(version: u32) -> Option<Self> {
+        match version {
+            1 => Some(Partitioner::V1),
+            _ => None,
+        }
+    }
+
+    /// Position of a key (or virtual node label) on the consistent-hash ring.
+    pub fn token(&self, key: &str) -> u64 {
+        match self {
+            Partitioner::V1 => xxh64(key.as_bytes(), TOKEN_SEED),
+        }
+    }
+
+    /// Local worker for a stream, out of `num_workers`.
+    pub fn worker_slot(&self, stream_id: &str, num_workers: usize) -> usize {
+        match self {
+            Partitioner::V1 => {
+                (xxh64(stream_id.as_bytes(), WORKER_SEED) % num_workers as u64) as usize
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::ClusterTopology;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_v1_is_stable() {
+        // Pinned values: if these change, stream ownership changes across upgrades.
+        let p = Partitioner::V1;
+        assert_eq!(p.token(""), 0xEF46_DB37_51D8_E999);
+        assert_eq!(p.token("stream-1"), 0xF5DF_26F5_951D_89F5);
+        assert_eq!(p.worker_slot("stream-1", 32), 19);
+        assert_eq!(Partitioner::from_version(p.version()), Some(p));
+        assert_eq!(Partitioner::from_version(0), None);
+    }
+
+    #[test]
+    fn test_worker_routing_is_independent_of_node_routing() {
+        let nodes = vec!["A".to_string(), "B".to_string()];
+        let topology = ClusterTopology::new(nodes, 0);
+        let p = Partitioner::V1;
+
+        // Streams owned by a single node still spread over all of its workers
+        let slots: HashSet<usize> = (0..5_000)
+            .map(|i| format!("stream-{}", i))
+            .filter(|s| topology.get_owner(s).node_addr == "A")
+            .map(|s| p.worker_slot(&s, 32))
+            .collect();
+        assert_eq!(slots.len(), 32);
+    }
+}
@@ -0,0 +1,390 @@
+use crate::cluster::client::ClusterClient;
+use crate::cluster::raft::node::{Envelope, RaftNode, Role};
+use crate::cluster::raft::{RaftConfig, StateMachine};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+
+/// Carries Raft messages between the members of a group. Delivery is best
+/// effort: Raft copes with lost, duplicated and reordered messages.
+pub trait RaftTransport: Send + Sync {
+    fn send(&self, group: &str, envelope: Envelope);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RaftStatus {
+    pub role: Role,
+    pub term: u64,
+    pub leader: Option<String>,
+    pub commit: u64,
+    pub applied: u64,
+}
+
+type Responder = oneshot::Sender<Result<Vec<u8>, String>>;
+
+/// A snapshot built off the driver task, with the applied index it covers.
+type BuiltSnapshot = (u64, Result<Vec<u8>, String>);
+
+enum Input {
+    Message(Envelope),
+    Propose {
+        command: Vec<u8>,
+        resp_tx: Responder,
+    },
+    Status {
+        resp_tx: oneshot::Sender<RaftStatus>,
+    },
+    ReadIndex {
+        resp_tx: oneshot::Sender<Result<u64, String>>,
+    },
+}
+
+/// A read waiting for its round to be confirmed and its index applied.
+struct PendingRead {
+    round: u64,
+    index: u64,
+    resp_tx: oneshot::Sender<Result<u64, String>>,
+}
+
+/// Handle on a running Raft group member.
+///
+/// The member runs in its own task, which owns the node and the state
+/// machine: it ticks the node, feeds it messages and proposals, sends what
+/// it produces and applies committed entries. Snapshots are serialized on a
+/// blocking thread, so that the member keeps answering meanwhile.
+#[derive(Clone)]
+pub struct RaftGroup {
+    id: String,
+    tx: mpsc::Sender<Input>,
+}
+
+impl RaftGroup {
+    pub fn spawn(
+        id: impl Into<String>,
+        node: RaftNode,
+        state_machine: Box<dyn StateMachine>,
+        transport: Arc<dyn RaftTransport>,
+        config: &RaftConfig,
+    ) -> Self {
+        let id = id.into();
+        let (tx, rx) = mpsc::channel(4096);
+        let (built_tx, built_rx) = mpsc::channel(1);
+        let driver = Driver {
+            group: id.clone(),
+            node,
+            state_machine,
+            transport,
+            snapshot_threshold: config.snapshot_threshold,
+            pending: BTreeMap::new(),
+            unstarted_reads: Vec::new(),
+            reads: Vec::new(),
+            building: false,
+            built_tx,
+        };
+        tokio::spawn(driver.run(rx, built_rx, config.tick_interval));
+        Self { id, tx }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Replicates a command and returns the state machine's response once
+    /// it is applied locally. Fails with NotLeaderError on followers.
+    pub async fn propose(&self, command: Vec<u8>) -> Result<Vec<u8>, String> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.tx
+            .send(Input::Propose { command, resp_tx })
+            .await
+            .map_err(|_| format!("RaftError: group {} is stopped", self.id))?;
+        resp_rx
+            .await
+            .map_err(|_| format!("RaftError: group {} stopped before applying", self.id))?
+    }
+
+    /// Queues a message from another member; dropped if the queue is full.
+    pub fn deliver(&self, envelope: Envelope) {
+        if self.tx.try_send(Input::Message(envelope)).is_err() {
+            tracing::debug!(group = %self.id, "Dropping Raft message");
+        }
+    }
+
+    /// Waits until this member has confirmed with a majority that it still
+    /// leads the group, and applied every entry committed before the call.
+    /// Reads of the local state are then linearizable. Returns the applied
+    /// index waited for; fails with NotLeaderError on other members.
+    pub async fn read_index(&self) -> Result<u64, String> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.tx
+            .send(Input::ReadIndex { resp_tx })
+            .await
+            .map_err(|_| format!("RaftError: group {} is stopped", self.id))?;
+        resp_rx
+            .await
+            .map_err(|_| format!("RaftError: group {} stopped before the read", self.id))?
+    }
+
+    pub async fn status(&self) -> Result<RaftStatus, String> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.tx
+            .send(Input::Status { resp_tx })
+            .await
+            .map_err(|_| format!("RaftError: group {} is stopped", self.id))?;
+        resp_rx
+            .await
+            .map_err(|_| format!("RaftError: group {} is stopped", self.id))
+    }
+}
+
+struct Driver {
+    group: String,
+    node: RaftNode,
+    state_machine: Box<dyn StateMachine>,
+    transport: Arc<dyn RaftTransport>,
+    snapshot_threshold: u64,
+    /// Local proposals by index, with the term they were proposed in.
+    pending: BTreeMap<u64, (u64, Responder)>,
+    /// Reads waiting for a read round, which the reads received meanwhile share.
+    unstarted_reads: Vec<oneshot::Sender<Result<u64, String>>>,
+    reads: Vec<PendingRead>,
+    /// Whether a snapshot is being built.
+    building: bool,
+    built_tx: mpsc::Sender<BuiltSnapshot>,
+}
+
+impl Driver {
+    async fn run(
+        mut self,
+        mut rx: mpsc::Receiver<Input>,
+        mut built_rx: mpsc::Receiver<BuiltSnapshot>,
+        tick_interval: std::time::Duration,
+    ) {
+        let mut ticker = tokio::time::interval(tick_interval);
+        loop {
+            let res = tokio::select! {
+                _ = ticker.tick() => self.node.tick(),
+                input = rx.recv() => match input {
+                    Some(input) => self.handle(input),
+                    None => break,
+                },
+                Some((index, built)) = built_rx.recv() => {
+                    self.building = false;
+                    built.and_then(|data| self.node.compact(index, data))
+                }
+            };
+            // Storage failures leave the member in an unknown state: stop it
+            if let Err(e) = res.and_then(|_| self.process()) {
+                tracing::error!(group = %self.group, error = %e, "Raft group member stopped");
+                break;
+            }
+        }
+    }
+
+    fn handle(&mut self, input: Input) -> Result<(), String> {
+        match input {
+            Input::Message(envelope) => self.node.step(envelope),
+            Input::Propose { command, resp_tx } => {
+                match self.node.propose(command) {
+                    Ok((index, term)) => {
+                        self.pending.insert(index, (term, resp_tx));
+                    }
+                    Err(e) => {
+                        let _ = resp_tx.send(Err(e));
+                    }
+                }
+                Ok(())
+            }
+            Input::ReadIndex { resp_tx } => {
+                self.unstarted_reads.push(resp_tx);
+                Ok(())
+            }
+            Input::Status { resp_tx } => {
+                let _ = resp_tx.send(RaftStatus {
+                    role: self.node.role(),
+                    term: self.node.term(),
+                    leader: self.node.leader().map(str::to_string),
+                    commit: self.node.commit_index(),
+                    applied: self.node.applied_index(),
+                });
+                Ok(())
+            }
+        }
+    }
+
+    fn process(&mut self) -> Result<(), String> {
+        // Started before taking the messages, which carry the round
+        if !self.unstarted_reads.is_empty() {
+            match self.node.read_index() {
+                Ok(Some((round, index))) => {
+                    for resp_tx in self.unstarted_reads.drain(..) {
+                        self.reads.push(PendingRead {
+                            round,
+                            index,
+                            resp_tx,
+                        });
+                    }
+                }
+                // Retried once the leader commits an entry of its term
+                Ok(None) => {}
+                Err(e) => {
+                    for resp_tx in self.unstarted_reads.drain(..) {
+                        let _ = resp_tx.send(Err(e.clone()));
+                    }
+                }
+            }
+        }
+
+        for envelope in self.node.take_messages() {
+            self.transport.send(&self.group, envelope);
+        }
+
+        if let Some(snapshot) = self.node.take_snapshot_to_restore() {
+            self.state_machine.restore(&snapshot)?;
+            // Proposals covered by the snapshot may or may not have survived
+            let later = self.pending.split_off(&(snapshot.index + 1));
+            for (_, (_, resp_tx)) in std::mem::replace(&mut self.pending, later) {
+                let _ = resp_tx.send(Err(
+                    "RaftError: proposal outcome unknown after installing a snapshot".to_string(),
+                ));
+            }
+        }
+
+        for entry in self.node.take_committed() {
+            let response = self.state_machine.apply(&entry)?;
+            if let Some((term, resp_tx)) = self.pending.remove(&entry.index) {
+                let res = if term == entry.term {
+                    Ok(response)
+                } else {
+                    Err(format!(
+                        "NotLeaderError: proposal at index {} was replaced by a new leader",
+                        entry.index
+                    ))
+                };
+                let _ = resp_tx.send(res);
+            }
+        }
+
+        self.complete_reads();
+
+        if !self.building
+            && (self.node.wants_snapshot()
+                || self.node.applied_since_compaction() >= self.snapshot_threshold)
+        {
+            let index = self.node.applied_index();
+            let build = self.state_machine.snapshot()?;
+            self.building = true;
+            let built_tx = self.built_tx.clone();
+            tokio::task::spawn_blocking(move || {
+                let _ = built_tx.blocking_send((index, build()));
+            });
+        }
+        Ok(())
+    }
+
+    /// Answers the reads whose round a majority confirmed, once their index
+    /// is applied. All fail if this member stepped down.
+    fn complete_reads(&mut self) {
+        if self.reads.is_empty() {
+            return;
+        }
+        if self.node.role() != Role::Leader {
+            let e = format!(
+                "NotLeaderError: {} lost the leadership before the read (leader: {})",
+                self.node.id(),
+                self.node.leader().unwrap_or("unknown")
+            );
+            for read in self.reads.drain(..) {
+                let _ = read.resp_tx.send(Err(e.clone()));
+            }
+            return;
+        }
+        let confirmed = self.node.confirmed_read_round();
+        let applied = self.node.applied_index();
+        let (done, waiting) = std::mem::take(&mut self.reads)
+            .into_iter()
+            .partition(|r: &PendingRead| r.round <= confirmed && r.index <= applied);
+        self.reads = waiting;
+        for read in done {
+            let _ = read.resp_tx.send(Ok(read.index));
+        }
+    }
+}
+
+/// Transport between groups in one process, for tests. Nodes can be cut off
+/// to simulate partitions.
+#[derive(Default)]
+pub struct LocalTransport {
+    routes: Mutex<HashMap<(String, String), RaftGroup>>,
+    isolated: Mutex<HashSet<String>>,
+}
+
+impl LocalTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&self, node: &str, group: RaftGroup) {
+        self.routes
+            .lock()
+            .unwrap()
+            .insert((node.to_string(), group.id().to_string()), group);
+    }
+
+    pub fn isolate(&self, node: &str) {
+        self.isolated.lock().unwrap().insert(node.to_string());
+    }
+
+    pub fn heal(&self) {
+        self.isolated.lock().unwrap().clear();
+    }
+}
+
+impl RaftTransport for LocalTransport {
+    fn send(&self, group: &str, envelope: Envelope) {
+        {
+            let isolated = self.isolated.lock().unwrap();
+            if isolated.contains(&envelope.from) || isolated.contains(&envelope.to) {
+                return;
+            }
+        }
+        let target = self
+            .routes
+            .lock()
+            .unwrap()
+            .get(&(envelope.to.clone(), group.to_string()))
+            .cloned();
+        if let Some(target) = target {
+            target.deliver(envelope);
+        }
+    }
+}
+
+/// Sends Raft messages to the other nodes over the internal gRPC API.
+pub struct GrpcRaftTransport {
+    client: ClusterClient,
+}
+
+impl GrpcRaftTransport {
+    pub fn new(client: ClusterClient) -> Self {
+        Self { client }
+    }
+}
+
+impl RaftTransport for GrpcRaftTransport {
+    fn send(&self, group: &str, envelope: Envelope) {
+        let payload = match serde_cbor::to_vec(&envelope) {
+            Ok(p) => p,
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to encode Raft message");
+                return;
+            }
+        };
+        let client = self.client.clone();
+        let group = group.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = client.raft_message(&envelope.to, &group, payload).await {
+                tracing::debug!(peer = %envelope.to, group = %group, error = %e, "Raft message not delivered");
+            }
+        });
+    }
+}
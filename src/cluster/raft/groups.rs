@@ -0,0 +1,113 @@
+use crate::cluster::identity::same_addr;
+use crate::cluster::raft::group::{RaftGroup, RaftTransport};
+use crate::cluster::raft::log::RaftStorage;
+use crate::cluster::raft::node::{Envelope, RaftNode};
+use crate::cluster::raft::{RaftConfig, StateMachine};
+use crate::cluster::ClusterTopology;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Identifies the Raft group of a replica set: its members, sorted. Ring
+/// ranges with the same replicas share one group.
+pub fn group_id(members: &[String]) -> String {
+    let mut members = members.to_vec();
+    members.sort();
+    members.join(",")
+}
+
+/// The Raft group a stream belongs to.
+pub fn group_of(topology: &ClusterTopology, stream_id: &str, factor: usize) -> String {
+    group_id(&topology.replicas_for(stream_id, factor))
+}
+
+/// The Raft groups a node is a member of.
+///
+/// Every ring range is replicated by a group made of its owner and the next
+/// `factor - 1` nodes on the ring. Group membership follows the topology the
+/// groups were started with; it does not change with later ring changes.
+pub struct RaftGroups {
+    self_addr: String,
+    factor: usize,
+    topology: Arc<ClusterTopology>,
+    groups: HashMap<String, RaftGroup>,
+}
+
+impl RaftGroups {
+    /// Starts a member for every group that includes `self_addr`. `open`
+    /// returns the storage and state machine of a group, given its id.
+    pub fn start<F>(
+        self_addr: &str,
+        topology: Arc<ClusterTopology>,
+        factor: usize,
+        config: RaftConfig,
+        transport: Arc<dyn RaftTransport>,
+        mut open: F,
+    ) -> Result<Self, String>
+    where
+        F: FnMut(&str) -> Result<(Box<dyn RaftStorage>, Box<dyn StateMachine>), String>,
+    {
+        let mut groups = HashMap::new();
+        for range in topology.token_ranges() {
+            let members = topology.replicas_for_range(&range, factor);
+            let id = group_id(&members);
+            // The member as the ring spells it, which the other members use
+            let Some(me) = members.iter().find(|m| same_addr(m, self_addr)).cloned() else {
+                continue;
+            };
+            if groups.contains_key(&id) {
+                continue;
+            }
+
+            let (storage, state_machine) = open(&id)?;
+            let node = RaftNode::new(
+                &me,
+                members,
+                config.clone(),
+                storage,
+                state_machine.applied_index(),
+            )?;
+            let group =
+                RaftGroup::spawn(id.clone(), node, state_machine, transport.clone(), &config);
+            groups.insert(id, group);
+        }
+        tracing::info!(node = %self_addr, groups = groups.len(), "Raft groups started");
+
+        Ok(Self {
+            self_addr: self_addr.to_string(),
+            factor,
+            topology,
+            groups,
+        })
+    }
+
+    pub fn self_addr(&self) -> &str {
+        &self.self_addr
+    }
+
+    /// The topology the groups were started with.
+    pub fn topology(&self) -> &Arc<ClusterTopology> {
+        &self.topology
+    }
+
+    /// Members of the group replicating a stream, its ring owner first.
+    pub fn members_of(&self, stream_id: &str) -> Vec<String> {
+        self.topology.replicas_for(stream_id, self.factor)
+    }
+
+    /// The local member of the stream's group, if this node is in it.
+    pub fn group_for(&self, stream_id: &str) -> Option<&RaftGroup> {
+        self.groups
+            .get(&group_of(&self.topology, stream_id, self.factor))
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = &RaftGroup> {
+        self.groups.values()
+    }
+
+    pub fn deliver(&self, group: &str, envelope: Envelope) {
+        match self.groups.get(group) {
+            Some(member) => member.deliver(envelope),
+            None => tracing::debug!(group = %group, "Raft message for a group this node is not in"),
+        }
+    }
+}
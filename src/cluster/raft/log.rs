@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A replicated command. Leaders append an entry with an empty command when
+/// elected, to commit the entries of previous terms.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub term: u64,
+    pub index: u64,
+    pub command: Vec<u8>,
+}
+
+/// State that must survive a restart before a node answers any message.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HardState {
+    pub term: u64,
+    pub voted_for: Option<String>,
+    pub commit: u64,
+}
+
+/// State machine contents up to and including entry `index`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub index: u64,
+    pub term: u64,
+    pub data: Vec<u8>,
+}
+
+/// What a node finds in its storage on start.
+#[derive(Clone, Debug, Default)]
+pub struct RaftState {
+    pub hard_state: HardState,
+    /// Index and term of the last entry dropped by compaction.
+    pub compacted: (u64, u64),
+    pub entries: Vec<Entry>,
+}
+
+/// Durable storage of a Raft node's term, vote and log.
+///
+/// Every call must be durable when it returns: a node only sends messages
+/// after persisting the state they depend on.
+pub trait RaftStorage: Send {
+    fn load(&self) -> Result<RaftState, String>;
+
+    fn save_hard_state(&mut self, state: &HardState) -> Result<(), String>;
+
+    /// Appends entries following the last stored one.
+    fn append(&mut self, entries: &[Entry]) -> Result<(), String>;
+
+    /// Drops the entries from `index` on, which a new leader overwrote.
+    fn truncate(&mut self, index: u64) -> Result<(), String>;
+
+    /// Drops the entries up to and including `index`, now covered by a snapshot.
+    fn compact(&mut self, index: u64, term: u64) -> Result<(), String>;
+}
+
+/// Raft storage kept in memory, for tests.
+#[derive(Default)]
+pub struct MemoryRaftStorage {
+    hard_state: HardState,
+    compacted: (u64, u64),
+    entries: BTreeMap<u64, Entry>,
+}
+
+impl MemoryRaftStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RaftStorage for MemoryRaftStorage {
+    fn load(&self) -> Result<RaftState, String> {
+        Ok(RaftState {
+            hard_state: self.hard_state.clone(),
+            compacted: self.compacted,
+            entries: self.entries.values().cloned().collect(),
+        })
+    }
+
+    fn save_hard_state(&mut self, state: &HardState) -> Result<(), String> {
+        self.hard_state = state.clone();
+        Ok(())
+    }
+
+    fn append(&mut self, entries: &[Entry]) -> Result<(), String> {
+        for entry in entries {
+            self.entries.insert(entry.index, entry.clone());
+        }
+        Ok(())
+    }
+
+    fn truncate(&mut self, index: u64) -> Result<(), String> {
+        self.entries.split_off(&index);
+        Ok(())
+    }
+
+    fn compact(&mut self, index: u64, term: u64) -> Result<(), String> {
+        self.entries = self.entries.split_off(&(index + 1));
+        self.compacted = (index, term);
+        Ok(())
+    }
+}
+
+/// In-memory view of the log: the entries after the last compaction.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RaftLog {
+    /// Index and term of the last compacted entry.
+    offset: u64,
+    offset_term: u64,
+    entries: Vec<Entry>,
+}
+
+impl RaftLog {
+    pub fn new(compacted: (u64, u64), entries: Vec<Entry>) -> Self {
+        Self {
+            offset: compacted.0,
+            offset_term: compacted.1,
+            entries,
+        }
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn last_index(&self) -> u64 {
+        self.offset + self.entries.len() as u64
+    }
+
+    pub fn last_term(&self) -> u64 {
+        self.entries
+            .last()
+            .map(|e| e.term)
+            .unwrap_or(self.offset_term)
+    }
+
+    /// Term of the entry at `index`, if it is known.
+    pub fn term(&self, index: u64) -> Option<u64> {
+        if index == self.offset {
+            return Some(self.offset_term);
+        }
+        self.get(index).map(|e| e.term)
+    }
+
+    pub fn get(&self, index: u64) -> Option<&Entry> {
+        if index <= self.offset {
+            return None;
+        }
+        self.entries.get((index - self.offset - 1) as usize)
+    }
+
+    /// Up to `max` entries starting at `index`.
+    pub fn slice(&self, index: u64, max: usize) -> Vec<Entry> {
+        if index <= self.offset {
+            return Vec::new();
+        }
+        self.entries
+            .iter()
+            .skip((index - self.offset - 1) as usize)
+            .take(max)
+            .cloned()
+            .collect()
+    }
+
+    pub fn push(&mut self, entry: Entry) {
+        self.entries.push(entry);
+    }
+
+    pub fn truncate(&mut self, index: u64) {
+        self.entries
+            .truncate(index.saturating_sub(self.offset + 1) as usize);
+    }
+
+    /// Drops the entries up to `index`; everything is dropped if the log does
+    /// not contain `index` with `term`.
+    pub fn compact(&mut self, index: u64, term: u64) {
+        if self.term(index) == Some(term) {
+            self.entries.drain(..(index - self.offset) as usize);
+        } else {
+            self.entries.clear();
+        }
+        self.offset = index;
+        self.offset_term = term;
+    }
+}
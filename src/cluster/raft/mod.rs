@@ -0,0 +1,60 @@
+pub mod group;
+pub mod groups;
+pub mod log;
+pub mod node;
+
+use crate::cluster::raft::log::{Entry, Snapshot};
+use std::time::Duration;
+
+/// Timing and batching of the Raft groups. Timeouts are counted in ticks.
+#[derive(Clone, Debug)]
+pub struct RaftConfig {
+    pub tick_interval: Duration,
+    /// Ticks without hearing from a leader before a follower starts an
+    /// election. The actual timeout is randomized up to twice as long.
+    pub election_ticks: u32,
+    pub heartbeat_ticks: u32,
+    pub max_append_entries: usize,
+    /// Applied entries kept in the log before it is compacted into a snapshot.
+    pub snapshot_threshold: u64,
+    /// Largest piece of a snapshot sent in one message. Snapshots are sent
+    /// piece by piece to stay under the transport's message size limit.
+    pub snapshot_chunk_size: usize,
+}
+
+impl Default for RaftConfig {
+    fn default() -> Self {
+        Self {
+            tick_interval: Duration::from_millis(100),
+            election_ticks: 10,
+            heartbeat_ticks: 2,
+            max_append_entries: 256,
+            snapshot_threshold: 10_000,
+            snapshot_chunk_size: 1024 * 1024,
+        }
+    }
+}
+
+/// Serializes a snapshot captured by `StateMachine::snapshot`.
+pub type SnapshotBuilder = Box<dyn FnOnce() -> Result<Vec<u8>, String> + Send>;
+
+/// The replicated state of a Raft group.
+///
+/// Committed entries are applied in log order on every member, so `apply`
+/// must be deterministic. Entries with an empty command are leader no-ops;
+/// they are applied too, so the state machine can record its applied index.
+pub trait StateMachine: Send {
+    /// Index of the last applied entry, as persisted by the state machine.
+    fn applied_index(&self) -> u64;
+
+    /// Applies an entry and returns the response for its proposer. An error
+    /// means the state could not be updated, and stops the member.
+    fn apply(&mut self, entry: &Entry) -> Result<Vec<u8>, String>;
+
+    /// Captures the state up to the applied index. The returned builder
+    /// serializes it on a blocking thread, while entries keep being applied.
+    fn snapshot(&mut self) -> Result<SnapshotBuilder, String>;
+
+    /// Replaces the state with a snapshot taken by another member.
+    fn restore(&mut self, snapshot: &Snapshot) -> Result<(), String>;
+}
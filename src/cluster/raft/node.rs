@@ -0,0 +1,1069 @@
+use crate::cluster::raft::log::{Entry, HardState, RaftLog, RaftStorage, Snapshot};
+use crate::cluster::raft::RaftConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Role {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Message {
+    RequestVote {
+        term: u64,
+        last_log_index: u64,
+        last_log_term: u64,
+    },
+    Vote {
+        term: u64,
+        granted: bool,
+    },
+    AppendEntries {
+        term: u64,
+        prev_log_index: u64,
+        prev_log_term: u64,
+        entries: Vec<Entry>,
+        leader_commit: u64,
+        /// Latest read round of the leader (see `RaftNode::read_index`).
+        #[serde(default)]
+        read_round: u64,
+    },
+    /// Answers both AppendEntries and InstallSnapshot. On success
+    /// `match_index` is the last index known to match the leader's log; on
+    /// failure, an index from which the leader should retry. `read_round` is
+    /// the latest read round received from the leader.
+    AppendResponse {
+        term: u64,
+        success: bool,
+        match_index: u64,
+        #[serde(default)]
+        read_round: u64,
+    },
+    /// A piece of the leader's snapshot at `index`, starting at `offset` of
+    /// its data. The follower installs it once it has the piece that is `done`.
+    InstallSnapshot {
+        term: u64,
+        index: u64,
+        snapshot_term: u64,
+        offset: u64,
+        data: Vec<u8>,
+        done: bool,
+    },
+    /// Answers a piece of a snapshot other than the last: `received` is how
+    /// much of snapshot `index` the follower holds, where the leader resumes.
+    SnapshotProgress {
+        term: u64,
+        index: u64,
+        received: u64,
+    },
+}
+
+impl Message {
+    fn term(&self) -> u64 {
+        match self {
+            Message::RequestVote { term, .. }
+            | Message::Vote { term, .. }
+            | Message::AppendEntries { term, .. }
+            | Message::AppendResponse { term, .. }
+            | Message::InstallSnapshot { term, .. }
+            | Message::SnapshotProgress { term, .. } => *term,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub from: String,
+    pub to: String,
+    pub message: Message,
+}
+
+/// A single Raft participant.
+///
+/// The node does no I/O besides its storage and has no clock: the caller
+/// feeds it ticks, incoming messages and proposals, then collects the
+/// messages to send and the committed entries to apply. This keeps it
+/// deterministic, so a cluster can be simulated step by step in tests.
+pub struct RaftNode {
+    id: String,
+    peers: Vec<String>,
+    config: RaftConfig,
+    storage: Box<dyn RaftStorage>,
+
+    role: Role,
+    term: u64,
+    voted_for: Option<String>,
+    leader: Option<String>,
+    log: RaftLog,
+    commit: u64,
+    applied: u64,
+
+    election_elapsed: u32,
+    election_timeout: u32,
+    heartbeat_elapsed: u32,
+    rng: u64,
+    votes: HashSet<String>,
+    next_index: HashMap<String, u64>,
+    match_index: HashMap<String, u64>,
+    /// Peers heard from in the current election timeout, while leader.
+    active: HashSet<String>,
+    /// Read rounds started, and the latest one each peer answered, while
+    /// leader. A round answered by a majority confirms the leadership.
+    read_round: u64,
+    read_acks: HashMap<String, u64>,
+    /// Latest read round received from the current leader, while follower.
+    leader_read_round: u64,
+
+    /// Latest snapshot of the state machine, sent to followers that are
+    /// behind the start of the log.
+    snapshot: Option<Snapshot>,
+    snapshot_wanted: bool,
+    /// Per peer, the index of the snapshot being sent to it and how much of
+    /// it the peer holds, while leader.
+    snapshot_sent: HashMap<String, (u64, u64)>,
+    /// Pieces of a snapshot received from the leader so far.
+    incoming: Option<Snapshot>,
+    /// Snapshot received from the leader, waiting to be restored.
+    to_restore: Option<Snapshot>,
+    outbox: Vec<Envelope>,
+}
+
+impl RaftNode {
+    /// Restores a node from `storage`. `applied` is the last entry the state
+    /// machine has applied; entries up to it are not handed out again.
+    pub fn new(
+        id: impl Into<String>,
+        peers: Vec<String>,
+        config: RaftConfig,
+        storage: Box<dyn RaftStorage>,
+        applied: u64,
+    ) -> Result<Self, String> {
+        let id = id.into();
+        let state = storage.load()?;
+        let log = RaftLog::new(state.compacted, state.entries);
+        let applied = applied.max(log.offset());
+        let mut node = Self {
+            peers: peers.into_iter().filter(|p| *p != id).collect(),
+            rng: xxhash_rust::xxh64::xxh64(id.as_bytes(), 0) | 1,
+            id,
+            config,
+            storage,
+            role: Role::Follower,
+            term: state.hard_state.term,
+            voted_for: state.hard_state.voted_for,
+            leader: None,
+            commit: state.hard_state.commit.max(applied),
+            applied,
+            log,
+            election_elapsed: 0,
+            election_timeout: 0,
+            heartbeat_elapsed: 0,
+            votes: HashSet::new(),
+            next_index: HashMap::new(),
+            match_index: HashMap::new(),
+            active: HashSet::new(),
+            read_round: 0,
+            read_acks: HashMap::new(),
+            leader_read_round: 0,
+            snapshot: None,
+            snapshot_wanted: false,
+            snapshot_sent: HashMap::new(),
+            incoming: None,
+            to_restore: None,
+            outbox: Vec::new(),
+        };
+        node.reset_election_timeout();
+        Ok(node)
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn term(&self) -> u64 {
+        self.term
+    }
+
+    pub fn leader(&self) -> Option<&str> {
+        self.leader.as_deref()
+    }
+
+    pub fn commit_index(&self) -> u64 {
+        self.commit
+    }
+
+    pub fn applied_index(&self) -> u64 {
+        self.applied
+    }
+
+    pub fn last_index(&self) -> u64 {
+        self.log.last_index()
+    }
+
+    /// Advances the logical clock by one tick.
+    pub fn tick(&mut self) -> Result<(), String> {
+        self.election_elapsed += 1;
+        if self.role == Role::Leader {
+            if self.election_elapsed >= self.config.election_ticks {
+                self.election_elapsed = 0;
+                // Step down when cut off from a majority, so that clients
+                // go to the leader the majority elects
+                if self.active.len() + 1 < self.quorum() {
+                    tracing::warn!(node = %self.id, term = self.term, "Raft leader lost its quorum");
+                    self.become_follower(self.term, None);
+                    return Ok(());
+                }
+                self.active.clear();
+            }
+            self.heartbeat_elapsed += 1;
+            if self.heartbeat_elapsed >= self.config.heartbeat_ticks {
+                self.heartbeat_elapsed = 0;
+                self.broadcast_append();
+            }
+        } else if self.election_elapsed >= self.election_timeout {
+            self.campaign()?;
+        }
+        Ok(())
+    }
+
+    /// Appends a command to the log. Only the leader accepts proposals; the
+    /// command is committed once a majority stores it. Returns its index and term.
+    pub fn propose(&mut self, command: Vec<u8>) -> Result<(u64, u64), String> {
+        if self.role != Role::Leader {
+            return Err(format!(
+                "NotLeaderError: {} is not the leader (leader: {})",
+                self.id,
+                self.leader.as_deref().unwrap_or("unknown")
+            ));
+        }
+        let entry = Entry {
+            term: self.term,
+            index: self.log.last_index() + 1,
+            command,
+        };
+        let (index, term) = (entry.index, entry.term);
+        self.storage.append(std::slice::from_ref(&entry))?;
+        self.log.push(entry);
+        self.broadcast_append();
+        self.maybe_commit()?;
+        Ok((index, term))
+    }
+
+    pub fn step(&mut self, envelope: Envelope) -> Result<(), String> {
+        let Envelope { from, message, .. } = envelope;
+        let term = message.term();
+
+        if term > self.term {
+            let leader = match message {
+                Message::AppendEntries { .. } | Message::InstallSnapshot { .. } => {
+                    Some(from.clone())
+                }
+                _ => None,
+            };
+            self.become_follower(term, leader);
+            self.persist()?;
+        } else if term < self.term {
+            // Tell a stale node about the current term
+            match message {
+                Message::RequestVote { .. } => self.send(
+                    &from,
+                    Message::Vote {
+                        term: self.term,
+                        granted: false,
+                    },
+                ),
+                Message::AppendEntries { .. } | Message::InstallSnapshot { .. } => self.send(
+                    &from,
+                    Message::AppendResponse {
+                        term: self.term,
+                        success: false,
+                        match_index: 0,
+                        read_round: 0,
+                    },
+                ),
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        match message {
+            Message::RequestVote {
+                last_log_index,
+                last_log_term,
+                ..
+            } => self.handle_request_vote(&from, last_log_index, last_log_term),
+            Message::Vote { granted, .. } => self.handle_vote(&from, granted),
+            Message::AppendEntries {
+                prev_log_index,
+                prev_log_term,
+                entries,
+                leader_commit,
+                read_round,
+                ..
+            } => {
+                self.follow(&from);
+                self.leader_read_round = self.leader_read_round.max(read_round);
+                self.handle_append(&from, prev_log_index, prev_log_term, entries, leader_commit)
+            }
+            Message::AppendResponse {
+                success,
+                match_index,
+                read_round,
+                ..
+            } => self.handle_append_response(&from, success, match_index, read_round),
+            Message::InstallSnapshot {
+                index,
+                snapshot_term,
+                offset,
+                data,
+                done,
+                ..
+            } => {
+                self.follow(&from);
+                self.handle_snapshot(&from, index, snapshot_term, offset, data, done)
+            }
+            Message::SnapshotProgress {
+                index, received, ..
+            } => self.handle_snapshot_progress(&from, index, received),
+        }
+    }
+
+    /// Messages to send, in the order they were produced.
+    pub fn take_messages(&mut self) -> Vec<Envelope> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    /// A snapshot received from the leader, to restore into the state
+    /// machine before applying any further entry.
+    pub fn take_snapshot_to_restore(&mut self) -> Option<Snapshot> {
+        self.to_restore.take()
+    }
+
+    /// Committed entries not handed out yet. They must be applied in order.
+    pub fn take_committed(&mut self) -> Vec<Entry> {
+        let entries = self
+            .log
+            .slice(self.applied + 1, (self.commit - self.applied) as usize);
+        self.applied += entries.len() as u64;
+        entries
+    }
+
+    /// Whether a follower needs entries that compaction dropped, so the
+    /// caller should take a snapshot.
+    pub fn wants_snapshot(&self) -> bool {
+        self.snapshot_wanted
+    }
+
+    /// Number of applied entries still in the log.
+    pub fn applied_since_compaction(&self) -> u64 {
+        self.applied - self.log.offset()
+    }
+
+    /// Starts a read round: returns it with the commit index. Once a
+    /// majority answered the round (`confirmed_read_round`), this node was
+    /// still the leader when it started, and a read served from the state
+    /// machine after applying that index is linearizable (Raft's ReadIndex).
+    /// None until an entry of the leader's term is committed, before which
+    /// the commit index may lag behind the previous leader's.
+    pub fn read_index(&mut self) -> Result<Option<(u64, u64)>, String> {
+        if self.role != Role::Leader {
+            return Err(format!(
+                "NotLeaderError: {} is not the leader (leader: {})",
+                self.id,
+                self.leader.as_deref().unwrap_or("unknown")
+            ));
+        }
+        if self.log.term(self.commit) != Some(self.term) {
+            return Ok(None);
+        }
+        self.read_round += 1;
+        self.broadcast_append();
+        Ok(Some((self.read_round, self.commit)))
+    }
+
+    /// Latest read round answered by a majority, counting the leader.
+    pub fn confirmed_read_round(&self) -> u64 {
+        if self.role != Role::Leader {
+            return 0;
+        }
+        let mut rounds: Vec<u64> = self
+            .peers
+            .iter()
+            .map(|p| self.read_acks.get(p).copied().unwrap_or(0))
+            .collect();
+        rounds.push(self.read_round);
+        rounds.sort_unstable_by(|a, b| b.cmp(a));
+        rounds[self.quorum() - 1]
+    }
+
+    /// Records a snapshot of the state machine at `index`, an applied index,
+    /// and drops the log entries it covers. A snapshot older than the start
+    /// of the log, e.g. one built while the leader's was installed, is ignored.
+    pub fn compact(&mut self, index: u64, data: Vec<u8>) -> Result<(), String> {
+        if index <= self.log.offset() || index > self.applied {
+            return Ok(());
+        }
+        let term = self
+            .log
+            .term(index)
+            .ok_or_else(|| format!("No term for applied index {}", index))?;
+        self.storage.compact(index, term)?;
+        self.log.compact(index, term);
+        self.snapshot = Some(Snapshot { index, term, data });
+        self.snapshot_wanted = false;
+        Ok(())
+    }
+
+    fn handle_request_vote(
+        &mut self,
+        candidate: &str,
+        last_log_index: u64,
+        last_log_term: u64,
+    ) -> Result<(), String> {
+        let up_to_date =
+            (last_log_term, last_log_index) >= (self.log.last_term(), self.log.last_index());
+        let can_vote = match &self.voted_for {
+            None => true,
+            Some(v) => v == candidate,
+        };
+        let granted = up_to_date && can_vote;
+        if granted {
+            self.voted_for = Some(candidate.to_string());
+            self.election_elapsed = 0;
+            self.persist()?;
+        }
+        self.send(
+            candidate,
+            Message::Vote {
+                term: self.term,
+                granted,
+            },
+        );
+        Ok(())
+    }
+
+    fn handle_vote(&mut self, from: &str, granted: bool) -> Result<(), String> {
+        if self.role != Role::Candidate || !granted {
+            return Ok(());
+        }
+        self.votes.insert(from.to_string());
+        if self.votes.len() >= self.quorum() {
+            self.become_leader()?;
+        }
+        Ok(())
+    }
+
+    fn handle_append(
+        &mut self,
+        leader: &str,
+        prev_log_index: u64,
+        prev_log_term: u64,
+        entries: Vec<Entry>,
+        leader_commit: u64,
+    ) -> Result<(), String> {
+        if prev_log_index > self.log.last_index() {
+            return self.reply_append(leader, false, self.log.last_index());
+        }
+        // Entries up to the compaction point are committed, so they match
+        if prev_log_index >= self.log.offset()
+            && self.log.term(prev_log_index) != Some(prev_log_term)
+        {
+            let retry_from = self.commit.min(prev_log_index - 1);
+            return self.reply_append(leader, false, retry_from);
+        }
+
+        let last_new = prev_log_index + entries.len() as u64;
+        let mut new_entries = Vec::new();
+        for entry in entries {
+            if entry.index <= self.log.offset() {
+                continue;
+            }
+            match self.log.term(entry.index) {
+                Some(term) if term == entry.term => continue,
+                Some(_) => {
+                    // Conflicting suffix from a deposed leader
+                    self.storage.truncate(entry.index)?;
+                    self.log.truncate(entry.index);
+                    new_entries.push(entry);
+                }
+                None => new_entries.push(entry),
+            }
+        }
+        if !new_entries.is_empty() {
+            self.storage.append(&new_entries)?;
+            for entry in new_entries {
+                self.log.push(entry);
+            }
+        }
+
+        let commit = leader_commit.min(last_new);
+        if commit > self.commit {
+            self.commit = commit;
+            self.persist()?;
+        }
+        self.reply_append(leader, true, last_new)
+    }
+
+    fn handle_append_response(
+        &mut self,
+        from: &str,
+        success: bool,
+        match_index: u64,
+        read_round: u64,
+    ) -> Result<(), String> {
+        if self.role != Role::Leader {
+            return Ok(());
+        }
+        self.active.insert(from.to_string());
+        let acked = self.read_acks.entry(from.to_string()).or_insert(0);
+        *acked = (*acked).max(read_round);
+
+        if success {
+            let matched = self.match_index.entry(from.to_string()).or_insert(0);
+            *matched = (*matched).max(match_index);
+            let next = *matched + 1;
+            self.next_index.insert(from.to_string(), next);
+            self.maybe_commit()?;
+            if next <= self.log.last_index() {
+                self.send_append(from);
+            }
+        } else {
+            let next = self.next_index.entry(from.to_string()).or_insert(1);
+            *next = (match_index + 1).min(next.saturating_sub(1)).max(1);
+            self.send_append(from);
+        }
+        Ok(())
+    }
+
+    fn handle_snapshot(
+        &mut self,
+        leader: &str,
+        index: u64,
+        term: u64,
+        offset: u64,
+        data: Vec<u8>,
+        done: bool,
+    ) -> Result<(), String> {
+        if index <= self.commit {
+            self.incoming = None;
+            return self.reply_append(leader, true, self.commit);
+        }
+        let mut snapshot = match self.incoming.take() {
+            Some(s) if s.index == index && s.term == term => s,
+            _ => Snapshot {
+                index,
+                term,
+                data: Vec::new(),
+            },
+        };
+        // A lost or repeated piece: tell the leader where to resume
+        if offset != snapshot.data.len() as u64 {
+            let received = snapshot.data.len() as u64;
+            self.incoming = Some(snapshot);
+            return self.reply_snapshot_progress(leader, index, received);
+        }
+        snapshot.data.extend(data);
+        if !done {
+            let received = snapshot.data.len() as u64;
+            self.incoming = Some(snapshot);
+            return self.reply_snapshot_progress(leader, index, received);
+        }
+
+        self.storage.compact(index, term)?;
+        if self.log.term(index) != Some(term) {
+            self.storage.truncate(index + 1)?;
+        }
+        self.log.compact(index, term);
+        self.commit = index;
+        self.applied = index;
+        self.persist()?;
+        self.to_restore = Some(snapshot);
+        self.reply_append(leader, true, index)
+    }
+
+    fn reply_snapshot_progress(
+        &mut self,
+        leader: &str,
+        index: u64,
+        received: u64,
+    ) -> Result<(), String> {
+        self.send(
+            leader,
+            Message::SnapshotProgress {
+                term: self.term,
+                index,
+                received,
+            },
+        );
+        Ok(())
+    }
+
+    fn handle_snapshot_progress(
+        &mut self,
+        from: &str,
+        index: u64,
+        received: u64,
+    ) -> Result<(), String> {
+        if self.role != Role::Leader {
+            return Ok(());
+        }
+        self.active.insert(from.to_string());
+        if self.snapshot.as_ref().map(|s| s.index) != Some(index) {
+            // The next heartbeat sends the current snapshot from the start
+            return Ok(());
+        }
+        // Only progress sends the next piece: repeated answers, to pieces
+        // sent again by heartbeats, would otherwise each start another stream
+        if self.snapshot_sent.get(from) == Some(&(index, received)) {
+            return Ok(());
+        }
+        self.snapshot_sent
+            .insert(from.to_string(), (index, received));
+        self.send_append(from);
+        Ok(())
+    }
+
+    fn reply_append(
+        &mut self,
+        leader: &str,
+        success: bool,
+        match_index: u64,
+    ) -> Result<(), String> {
+        self.send(
+            leader,
+            Message::AppendResponse {
+                term: self.term,
+                success,
+                match_index,
+                read_round: self.leader_read_round,
+            },
+        );
+        Ok(())
+    }
+
+    fn campaign(&mut self) -> Result<(), String> {
+        self.role = Role::Candidate;
+        self.term += 1;
+        self.voted_for = Some(self.id.clone());
+        self.leader = None;
+        self.votes = HashSet::from([self.id.clone()]);
+        self.election_elapsed = 0;
+        self.reset_election_timeout();
+        self.persist()?;
+        tracing::debug!(node = %self.id, term = self.term, "Raft election started");
+
+        if self.votes.len() >= self.quorum() {
+            return self.become_leader();
+        }
+        let (last_log_index, last_log_term) = (self.log.last_index(), self.log.last_term());
+        for peer in self.peers.clone() {
+            self.send(
+                &peer,
+                Message::RequestVote {
+                    term: self.term,
+                    last_log_index,
+                    last_log_term,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn become_follower(&mut self, term: u64, leader: Option<String>) {
+        if term > self.term {
+            self.voted_for = None;
+        }
+        self.term = term;
+        self.role = Role::Follower;
+        self.leader = leader;
+        // Read rounds are numbered by each leader
+        self.leader_read_round = 0;
+        self.election_elapsed = 0;
+        self.reset_election_timeout();
+    }
+
+    /// Accepts `leader` as the leader of the current term.
+    fn follow(&mut self, leader: &str) {
+        if self.role != Role::Follower || self.leader.as_deref() != Some(leader) {
+            self.become_follower(self.term, Some(leader.to_string()));
+        }
+        self.election_elapsed = 0;
+    }
+
+    fn become_leader(&mut self) -> Result<(), String> {
+        tracing::info!(node = %self.id, term = self.term, "Raft leader elected");
+        self.role = Role::Leader;
+        self.leader = Some(self.id.clone());
+        self.heartbeat_elapsed = 0;
+        self.election_elapsed = 0;
+        self.active.clear();
+        self.read_acks.clear();
+        self.snapshot_sent.clear();
+        let next = self.log.last_index() + 1;
+        self.next_index = self.peers.iter().map(|p| (p.clone(), next)).collect();
+        self.match_index = self.peers.iter().map(|p| (p.clone(), 0)).collect();
+
+        // Committing an entry of its own term also commits those of earlier terms
+        self.propose(Vec::new())?;
+        Ok(())
+    }
+
+    fn maybe_commit(&mut self) -> Result<(), String> {
+        let mut matched: Vec<u64> = self.match_index.values().copied().collect();
+        matched.push(self.log.last_index());
+        matched.sort_unstable_by(|a, b| b.cmp(a));
+        let majority = matched[self.quorum() - 1];
+
+        // Only entries of the current term are committed by counting replicas
+        if majority > self.commit && self.log.term(majority) == Some(self.term) {
+            self.commit = majority;
+            self.persist()?;
+        }
+        Ok(())
+    }
+
+    fn broadcast_append(&mut self) {
+        for peer in self.peers.clone() {
+            self.send_append(&peer);
+        }
+    }
+
+    fn send_append(&mut self, peer: &str) {
+        let next = self
+            .next_index
+            .get(peer)
+            .copied()
+            .unwrap_or(self.log.last_index() + 1);
+
+        if next <= self.log.offset() {
+            match &self.snapshot {
+                Some(snapshot) if snapshot.index >= next - 1 => {
+                    let offset = match self.snapshot_sent.get(peer) {
+                        Some((index, received)) if *index == snapshot.index => *received,
+                        _ => 0,
+                    };
+                    let len = snapshot.data.len();
+                    let start = (offset as usize).min(len);
+                    let end = start
+                        .saturating_add(self.config.snapshot_chunk_size.max(1))
+                        .min(len);
+                    let message = Message::InstallSnapshot {
+                        term: self.term,
+                        index: snapshot.index,
+                        snapshot_term: snapshot.term,
+                        offset: start as u64,
+                        data: snapshot.data[start..end].to_vec(),
+                        done: end == len,
+                    };
+                    self.send(peer, message);
+                }
+                _ => self.snapshot_wanted = true,
+            }
+            return;
+        }
+
+        let prev_log_index = next - 1;
+        let message = Message::AppendEntries {
+            term: self.term,
+            prev_log_index,
+            prev_log_term: self.log.term(prev_log_index).unwrap_or(0),
+            entries: self.log.slice(next, self.config.max_append_entries),
+            leader_commit: self.commit,
+            read_round: self.read_round,
+        };
+        self.send(peer, message);
+    }
+
+    fn send(&mut self, to: &str, message: Message) {
+        self.outbox.push(Envelope {
+            from: self.id.clone(),
+            to: to.to_string(),
+            message,
+        });
+    }
+
+    fn persist(&mut self) -> Result<(), String> {
+        self.storage.save_hard_state(&HardState {
+            term: self.term,
+            voted_for: self.voted_for.clone(),
+            commit: self.commit,
+        })
+    }
+
+    fn quorum(&self) -> usize {
+        let members = self.peers.len() + 1;
+        members / 2 + 1
+    }
+
+    /// Randomized in `[election_ticks, 2 * election_ticks)` so that
+    /// candidates rarely split the vote.
+    fn reset_election_timeout(&mut self) {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let ticks = self.config.election_ticks.max(1);
+        self.election_timeout = ticks + (self.rng % ticks as u64) as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::raft::log::MemoryRaftStorage;
+    use std::collections::{BTreeMap, VecDeque};
+
+    /// Simulated network of nodes that applies committed commands to a
+    /// per-node list, with partitions.
+    struct Sim {
+        nodes: BTreeMap<String, RaftNode>,
+        applied: BTreeMap<String, Vec<Vec<u8>>>,
+        in_flight: VecDeque<Envelope>,
+        isolated: HashSet<String>,
+        /// Pieces of snapshots delivered.
+        snapshot_pieces: usize,
+    }
+
+    impl Sim {
+        fn new(ids: &[&str], config: RaftConfig) -> Self {
+            let all: Vec<String> = ids.iter().map(|s| s.to_string()).collect();
+            let nodes = all
+                .iter()
+                .map(|id| {
+                    let node = RaftNode::new(
+                        id.clone(),
+                        all.clone(),
+                        config.clone(),
+                        Box::new(MemoryRaftStorage::new()),
+                        0,
+                    )
+                    .unwrap();
+                    (id.clone(), node)
+                })
+                .collect();
+            Self {
+                nodes,
+                applied: all.iter().map(|id| (id.clone(), Vec::new())).collect(),
+                in_flight: VecDeque::new(),
+                isolated: HashSet::new(),
+                snapshot_pieces: 0,
+            }
+        }
+
+        fn node(&mut self, id: &str) -> &mut RaftNode {
+            self.nodes.get_mut(id).unwrap()
+        }
+
+        /// Ticks every node once, then delivers messages until the network is quiet.
+        fn round(&mut self) {
+            for node in self.nodes.values_mut() {
+                node.tick().unwrap();
+            }
+            self.settle();
+        }
+
+        fn settle(&mut self) {
+            loop {
+                for (id, node) in self.nodes.iter_mut() {
+                    self.in_flight.extend(node.take_messages());
+                    if let Some(snapshot) = node.take_snapshot_to_restore() {
+                        let commands: Vec<Vec<u8>> =
+                            serde_cbor::from_slice(&snapshot.data).unwrap();
+                        *self.applied.get_mut(id).unwrap() = commands;
+                    }
+                    for entry in node.take_committed() {
+                        if !entry.command.is_empty() {
+                            self.applied.get_mut(id).unwrap().push(entry.command);
+                        }
+                    }
+                }
+                let Some(envelope) = self.in_flight.pop_front() else {
+                    break;
+                };
+                if self.isolated.contains(&envelope.from) || self.isolated.contains(&envelope.to) {
+                    continue;
+                }
+                if let Message::InstallSnapshot { .. } = envelope.message {
+                    self.snapshot_pieces += 1;
+                }
+                let to = envelope.to.clone();
+                self.node(&to).step(envelope).unwrap();
+            }
+        }
+
+        fn run_until(&mut self, what: &str, cond: impl Fn(&Sim) -> bool) {
+            for _ in 0..1_000 {
+                if cond(self) {
+                    return;
+                }
+                self.round();
+            }
+            panic!("timed out waiting for {}", what);
+        }
+
+        fn leaders(&self) -> Vec<String> {
+            self.nodes
+                .values()
+                .filter(|n| n.role() == Role::Leader && !self.isolated.contains(n.id()))
+                .map(|n| n.id().to_string())
+                .collect()
+        }
+
+        fn snapshot(&mut self, id: &str) {
+            let data = serde_cbor::to_vec(&self.applied[id]).unwrap();
+            let index = self.nodes[id].applied_index();
+            self.node(id).compact(index, data).unwrap();
+        }
+    }
+
+    fn config() -> RaftConfig {
+        RaftConfig {
+            election_ticks: 10,
+            heartbeat_ticks: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_elects_one_leader_and_replicates() {
+        let mut sim = Sim::new(&["A", "B", "C"], config());
+        sim.run_until("leader", |s| s.leaders().len() == 1);
+        let leader = sim.leaders()[0].clone();
+
+        for i in 0..5u8 {
+            sim.node(&leader).propose(vec![i]).unwrap();
+        }
+        // Followers learn the commit index from the next heartbeat
+        sim.run_until("followers to apply", |s| {
+            s.applied.values().all(|a| a.len() == 5)
+        });
+        for applied in sim.applied.values() {
+            assert_eq!(applied, &(0..5u8).map(|i| vec![i]).collect::<Vec<_>>());
+        }
+
+        let follower = sim.nodes.keys().find(|id| **id != leader).cloned().unwrap();
+        let err = sim.node(&follower).propose(vec![9]).unwrap_err();
+        assert!(err.contains("NotLeaderError"), "{}", err);
+    }
+
+    #[test]
+    fn test_partitioned_leader_cannot_commit() {
+        let mut sim = Sim::new(&["A", "B", "C"], config());
+        sim.run_until("leader", |s| s.leaders().len() == 1);
+        let old = sim.leaders()[0].clone();
+        sim.node(&old).propose(b"committed".to_vec()).unwrap();
+        sim.settle();
+
+        // Cut the leader off: it keeps accepting proposals that never commit
+        sim.isolated.insert(old.clone());
+        let (lost_index, _) = sim.node(&old).propose(b"lost".to_vec()).unwrap();
+        sim.run_until("new leader", |s| s.leaders().len() == 1);
+        let new = sim.leaders()[0].clone();
+        assert_ne!(new, old);
+        assert!(sim.nodes[&old].commit_index() < lost_index);
+        sim.node(&new).propose(b"after".to_vec()).unwrap();
+        sim.settle();
+
+        // The old leader stepped down for lack of a quorum
+        sim.run_until("old leader steps down", |s| {
+            s.nodes[&old].role() != Role::Leader
+        });
+
+        // Healed: the old leader's uncommitted entry is replaced
+        sim.isolated.clear();
+        sim.run_until("convergence", |s| s.applied.values().all(|a| a.len() == 2));
+        for applied in sim.applied.values() {
+            assert_eq!(applied, &vec![b"committed".to_vec(), b"after".to_vec()]);
+        }
+    }
+
+    #[test]
+    fn test_lagging_follower_gets_snapshot() {
+        let mut sim = Sim::new(&["A", "B", "C"], config());
+        sim.run_until("leader", |s| s.leaders().len() == 1);
+        let leader = sim.leaders()[0].clone();
+        let lagging = sim.nodes.keys().find(|id| **id != leader).cloned().unwrap();
+
+        sim.isolated.insert(lagging.clone());
+        for i in 0..20u8 {
+            sim.node(&leader).propose(vec![i]).unwrap();
+        }
+        sim.settle();
+        sim.round();
+        // Both up-to-date nodes compact, so whichever leads must send a snapshot
+        for id in sim.nodes.keys().cloned().collect::<Vec<_>>() {
+            if id != lagging {
+                sim.snapshot(&id);
+                assert_eq!(sim.nodes[&id].applied_since_compaction(), 0);
+            }
+        }
+
+        sim.isolated.clear();
+        sim.run_until("catch-up", |s| s.applied[&lagging].len() == 20);
+        assert_eq!(sim.applied[&lagging], sim.applied[&leader]);
+    }
+
+    #[test]
+    fn test_snapshot_larger_than_a_message_is_sent_in_pieces() {
+        let mut sim = Sim::new(
+            &["A", "B", "C"],
+            RaftConfig {
+                snapshot_chunk_size: 16,
+                ..config()
+            },
+        );
+        sim.run_until("leader", |s| s.leaders().len() == 1);
+        let leader = sim.leaders()[0].clone();
+        let lagging = sim.nodes.keys().find(|id| **id != leader).cloned().unwrap();
+
+        sim.isolated.insert(lagging.clone());
+        for i in 0..20u8 {
+            sim.node(&leader).propose(vec![i; 8]).unwrap();
+        }
+        sim.settle();
+        sim.round();
+        for id in sim.nodes.keys().cloned().collect::<Vec<_>>() {
+            if id != lagging {
+                sim.snapshot(&id);
+            }
+        }
+        let size = sim.nodes[&leader].snapshot.as_ref().unwrap().data.len();
+        assert!(size > 10 * 16, "snapshot of {} bytes", size);
+
+        sim.isolated.clear();
+        sim.run_until("catch-up", |s| s.applied[&lagging].len() == 20);
+        assert_eq!(sim.applied[&lagging], sim.applied[&leader]);
+        assert!(sim.snapshot_pieces >= size.div_ceil(16));
+    }
+
+    #[test]
+    fn test_read_index_needs_a_majority() {
+        let mut sim = Sim::new(&["A", "B", "C"], config());
+        sim.run_until("leader", |s| s.leaders().len() == 1);
+        let leader = sim.leaders()[0].clone();
+        sim.node(&leader).propose(b"x".to_vec()).unwrap();
+        sim.settle();
+
+        // The leader's no-op and "x" are committed
+        let (round, index) = sim.node(&leader).read_index().unwrap().unwrap();
+        assert_eq!(index, 2);
+        sim.settle();
+        assert!(sim.nodes[&leader].confirmed_read_round() >= round);
+
+        // Cut off, the leader cannot confirm that it still leads
+        sim.isolated.insert(leader.clone());
+        let (round, _) = sim.node(&leader).read_index().unwrap().unwrap();
+        sim.settle();
+        assert!(sim.nodes[&leader].confirmed_read_round() < round);
+
+        let follower = sim.nodes.keys().find(|id| **id != leader).cloned().unwrap();
+        let err = sim.node(&follower).read_index().unwrap_err();
+        assert!(err.contains("NotLeaderError"), "{}", err);
+    }
+}
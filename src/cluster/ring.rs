@@ -0,0 +1,294 @@
+use crate::cluster::partitioner::Partitioner;
+use std::collections::BTreeMap;
+use xxhash_rust::xxh64::Xxh64;
+
+/// Virtual nodes per unit of weight when none are configured.
+pub const DEFAULT_VNODES: u32 = 256;
+
+/// A cluster member as placed on the ring.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RingMember {
+    pub addr: String,
+    /// Relative share of the ring; a member with weight 2 gets twice the virtual nodes.
+    pub weight: u32,
+}
+
+impl RingMember {
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            weight: 1,
+        }
+    }
+}
+
+/// Fingerprint of a ring's placement: the same for the same members and
+/// weights, whatever order they are listed in.
+pub fn ring_digest(members: &[RingMember]) -> u64 {
+    let mut sorted: Vec<&RingMember> = members.iter().collect();
+    sorted.sort_by(|a, b| a.addr.cmp(&b.addr));
+    let mut hasher = Xxh64::new(0);
+    for member in sorted {
+        hasher.update(member.addr.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(&member.weight.to_le_bytes());
+    }
+    hasher.digest()
+}
+
+/// A contiguous slice of the token space owned by one member.
+///
+/// Covers tokens in `(start, end]`. The range that wraps around the end of the
+/// token space has `start > end` and covers `(start, u64::MAX]` plus `[0, end]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenRange {
+    pub start: u64,
+    pub end: u64,
+    pub node_addr: String,
+}
+
+impl TokenRange {
+    pub fn contains(&self, token: u64) -> bool {
+        if self.start < self.end {
+            token > self.start && token <= self.end
+        } else {
+            token > self.start || token <= self.end
+        }
+    }
+
+    /// Whether two ranges share at least one token. The intersection of two arcs
+    /// ends where one of them ends, so it is enough to test both ends.
+    pub fn overlaps(&self, other: &TokenRange) -> bool {
+        self.contains(other.end) || other.contains(self.end)
+    }
+}
+
+/// Consistent-hash ring with virtual nodes.
+///
+/// Each member is hashed onto the ring `vnodes * weight` times; a key belongs to
+/// the first virtual node at or after its token, wrapping around. Adding or
+/// removing one of N members therefore only moves about 1/N of the keys.
+#[derive(Clone, Debug)]
+pub struct HashRing {
+    tokens: BTreeMap<u64, String>,
+    vnodes: u32,
+    partitioner: Partitioner,
+}
+
+impl HashRing {
+    pub fn new(members: &[RingMember], vnodes: u32) -> Self {
+        Self::with_partitioner(members, vnodes, Partitioner::default())
+    }
+
+    pub fn with_partitioner(members: &[RingMember], vnodes: u32, partitioner: Partitioner) -> Self {
+        let vnodes = vnodes.max(1);
+        let mut tokens = BTreeMap::new();
+        for member in members {
+            for i in 0..vnodes * member.weight.max(1) {
+                let token = partitioner.token(&format!("{}#{}", member.addr, i));
+                // On the (unlikely) collision the smaller address wins, for determinism
+                tokens
+                    .entry(token)
+                    .and_modify(|owner: &mut String| {
+                        if member.addr < *owner {
+                            *owner = member.addr.clone();
+                        }
+                    })
+                    .or_insert_with(|| member.addr.clone());
+            }
+        }
+        Self {
+            tokens,
+            vnodes,
+            partitioner,
+        }
+    }
+
+    pub fn vnodes(&self) -> u32 {
+        self.vnodes
+    }
+
+    pub fn partitioner(&self) -> Partitioner {
+        self.partitioner
+    }
+
+    /// Returns the member owning `key`, or `None` if the ring is empty.
+    pub fn owner(&self, key: &str) -> Option<&str> {
+        self.owner_of_token(self.partitioner.token(key))
+    }
+
+    pub fn owner_of_token(&self, token: u64) -> Option<&str> {
+        self.tokens
+            .range(token..)
+            .next()
+            .or_else(|| self.tokens.iter().next())
+            .map(|(_, addr)| addr.as_str())
+    }
+
+    /// Returns up to `n` distinct members for `key`: its owner first, then the
+    /// next members met walking the ring clockwise. If the owner leaves the
+    /// ring, the second member becomes the owner.
+    pub fn replicas(&self, key: &str, n: usize) -> Vec<&str> {
+        self.replicas_of_token(self.partitioner.token(key), n)
+    }
+
+    pub fn replicas_of_token(&self, token: u64, n: usize) -> Vec<&str> {
+        let mut replicas: Vec<&str> = Vec::with_capacity(n);
+        for addr in self
+            .tokens
+            .range(token..)
+            .chain(self.tokens.range(..token))
+            .map(|(_, addr)| addr.as_str())
+        {
+            if replicas.len() == n {
+                break;
+            }
+            if !replicas.contains(&addr) {
+                replicas.push(addr);
+            }
+        }
+        replicas
+    }
+
+    /// Lists the token ranges of the ring in token order, merging adjacent
+    /// virtual nodes that belong to the same member.
+    pub fn token_ranges(&self) -> Vec<TokenRange> {
+        let last = match self.tokens.keys().next_back() {
+            Some(t) => *t,
+            None => return Vec::new(),
+        };
+
+        let mut ranges: Vec<TokenRange> = Vec::new();
+        let mut start = last;
+        for (token, addr) in &self.tokens {
+            match ranges.last_mut() {
+                Some(prev) if prev.node_addr == *addr => prev.end = *token,
+                _ => ranges.push(TokenRange {
+                    start,
+                    end: *token,
+                    node_addr: addr.clone(),
+                }),
+            }
+            start = *token;
+        }
+
+        // The wrapping range continues into the first one when both have the same owner
+        if ranges.len() > 1 && ranges[0].node_addr == ranges[ranges.len() - 1].node_addr {
+            let tail = ranges.pop().expect("len > 1");
+            ranges[0].start = tail.start;
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn ring(addrs: &[&str]) -> HashRing {
+        let members: Vec<RingMember> = addrs.iter().map(|a| RingMember::new(*a)).collect();
+        HashRing::new(&members, DEFAULT_VNODES)
+    }
+
+    fn shares(ring: &HashRing, keys: usize) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for i in 0..keys {
+            let owner = ring.owner(&format!("stream-{}", i)).unwrap();
+            *counts.entry(owner.to_string()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_adding_node_moves_about_one_nth() {
+        let before = ring(&["A", "B"]);
+        let after = ring(&["A", "B", "C"]);
+
+        let keys = 10_000;
+        let mut moved = 0;
+        for i in 0..keys {
+            let key = format!("stream-{}", i);
+            let (o1, o2) = (before.owner(&key).unwrap(), after.owner(&key).unwrap());
+            if o1 != o2 {
+                // Keys only ever move to the new member
+                assert_eq!(o2, "C");
+                moved += 1;
+            }
+        }
+        let fraction = moved as f64 / keys as f64;
+        assert!(fraction > 0.2 && fraction < 0.45, "moved {}", fraction);
+    }
+
+    #[test]
+    fn test_weights() {
+        let members = vec![
+            RingMember::new("A"),
+            RingMember {
+                addr: "B".to_string(),
+                weight: 3,
+            },
+        ];
+        let ring = HashRing::new(&members, DEFAULT_VNODES);
+        let counts = shares(&ring, 10_000);
+        let b_share = counts["B"] as f64 / 10_000.0;
+        assert!(b_share > 0.65 && b_share < 0.85, "B share {}", b_share);
+    }
+
+    #[test]
+    fn test_token_ranges_cover_ring() {
+        let ring = ring(&["A", "B", "C"]);
+        let ranges = ring.token_ranges();
+        assert!(ranges.len() >= 3);
+
+        for i in 0..1_000 {
+            let token = Partitioner::default().token(&format!("stream-{}", i));
+            let covering: Vec<&TokenRange> = ranges.iter().filter(|r| r.contains(token)).collect();
+            assert_eq!(covering.len(), 1);
+            assert_eq!(covering[0].node_addr, ring.owner_of_token(token).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let range = |start, end| TokenRange {
+            start,
+            end,
+            node_addr: String::new(),
+        };
+        assert!(range(10, 20).overlaps(&range(15, 30)));
+        assert!(range(10, 20).overlaps(&range(12, 14)));
+        assert!(!range(10, 20).overlaps(&range(20, 30)));
+        // Wrapping range
+        assert!(range(u64::MAX - 5, 5).overlaps(&range(0, 1)));
+        assert!(!range(u64::MAX - 5, 5).overlaps(&range(5, 100)));
+    }
+
+    #[test]
+    fn test_replicas_follow_the_ring() {
+        let full = ring(&["A", "B", "C", "D"]);
+        for i in 0..1_000 {
+            let key = format!("stream-{}", i);
+            let replicas = full.replicas(&key, 3);
+            assert_eq!(replicas.len(), 3);
+            assert_eq!(replicas[0], full.owner(&key).unwrap());
+            assert!(replicas[1] != replicas[0] && !replicas[..2].contains(&replicas[2]));
+
+            // Without its owner, the key belongs to its first follower
+            let others: Vec<&str> = ["A", "B", "C", "D"]
+                .into_iter()
+                .filter(|a| *a != replicas[0])
+                .collect();
+            assert_eq!(ring(&others).owner(&key), Some(replicas[1]));
+        }
+        assert_eq!(ring(&["A", "B"]).replicas("stream-1", 3).len(), 2);
+    }
+
+    #[test]
+    fn test_single_node_owns_everything() {
+        let ring = ring(&["A"]);
+        let ranges = ring.token_ranges();
+        assert_eq!(ranges.len(), 1);
+        assert!(ranges[0].contains(0) && ranges[0].contains(u64::MAX));
+    }
+}
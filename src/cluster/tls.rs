@@ -0,0 +1,119 @@
+use rustls_pki_types::{CertificateDer, ServerName};
+use tonic::transport::{Certificate, ClientTlsConfig, Identity, ServerTlsConfig};
+
+/// Locations of the TLS material the nodes of a cluster authenticate each
+/// other with.
+#[derive(Clone, Debug)]
+pub struct ClusterTlsConfig {
+    /// CA bundle that issues node certificates. It should be dedicated to
+    /// the cluster: any certificate it issued is accepted as a node's.
+    pub ca_path: String,
+    pub cert_path: String,
+    pub key_path: String,
+    /// Name every node certificate is issued for. When unset, a node's
+    /// certificate must name the host of its cluster address.
+    pub server_name: Option<String>,
+}
+
+/// Mutual TLS between nodes: each side presents its node certificate and
+/// checks the other's against the cluster CA.
+#[derive(Clone)]
+pub struct ClusterTls {
+    ca: Certificate,
+    identity: Identity,
+    server_name: Option<String>,
+}
+
+impl ClusterTls {
+    pub async fn load(config: &ClusterTlsConfig) -> Result<Self, String> {
+        let read = |path: String| async move {
+            tokio::fs::read(&path)
+                .await
+                .map_err(|e| format!("Failed to read {}: {}", path, e))
+        };
+        let ca = read(config.ca_path.clone()).await?;
+        let cert = read(config.cert_path.clone()).await?;
+        let key = read(config.key_path.clone()).await?;
+
+        Ok(Self {
+            ca: Certificate::from_pem(ca),
+            identity: Identity::from_pem(cert, key),
+            server_name: config.server_name.clone(),
+        })
+    }
+
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// Dials a node, verifying that its certificate names `host` (or the
+    /// shared server name).
+    pub fn client_config(&self, host: &str) -> ClientTlsConfig {
+        ClientTlsConfig::new()
+            .ca_certificate(self.ca.clone())
+            .identity(self.identity.clone())
+            .domain_name(self.server_name.as_deref().unwrap_or(host))
+    }
+
+    /// Accepts only clients presenting a certificate issued by the cluster CA.
+    pub fn server_config(&self) -> ServerTlsConfig {
+        ServerTlsConfig::new()
+            .identity(self.identity.clone())
+            .client_ca_root(self.ca.clone())
+            .client_auth_optional(false)
+    }
+}
+
+/// Host part of a node address: "10.0.0.1:50051" -> "10.0.0.1", "[::1]:50051" -> "::1".
+pub fn host_of(addr: &str) -> &str {
+    let host = addr.rsplit_once(':').map(|(h, _)| h).unwrap_or(addr);
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+/// Checks that a peer's certificate was issued for one of `names`, given as
+/// DNS names or IP addresses. The chain itself is verified by the TLS layer.
+pub fn verify_peer_identity(cert: &CertificateDer<'_>, names: &[&str]) -> Result<(), String> {
+    let cert = webpki::EndEntityCert::try_from(cert)
+        .map_err(|e| format!("Invalid peer certificate: {}", e))?;
+    let matches = names.iter().any(|name| {
+        ServerName::try_from(*name)
+            .map(|name| cert.verify_is_valid_for_subject_name(&name).is_ok())
+            .unwrap_or(false)
+    });
+    if matches {
+        Ok(())
+    } else {
+        Err("Peer certificate does not name a cluster member".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls_pki_types::pem::PemObject;
+
+    // Self-signed, for node-2.cluster and 10.0.0.2
+    const NODE_CERT: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIBmzCCAUCgAwIBAgIUBx8i5jeycRIPS+GllWoWyqClFVkwCgYIKoZIzj0EAwIw
+ETEPMA0GA1UEAwwGbm9kZS0yMCAXDTI2MTAxODIwMDM0M1oYDzIxMjYwOTI0MjAw
+MzQzWjARMQ8wDQYDVQQDDAZub2RlLTIwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNC
+AAR1vECL1fRMjaQfDHH0a+Ew9hsfRFAK2atMuZM/wS5ZO8QniaG5r7edxHBkbXSh
+pueTwzDPZ2629SFy85VKinFao3QwcjAdBgNVHQ4EFgQUG6EJI29cvGYQOXHhMX46
+5/z3yfIwHwYDVR0jBBgwFoAUG6EJI29cvGYQOXHhMX465/z3yfIwDwYDVR0TAQH/
+BAUwAwEB/zAfBgNVHREEGDAWgg5ub2RlLTIuY2x1c3RlcocECgAAAjAKBggqhkjO
+PQQDAgNJADBGAiEAxxvorv/CwjlU5BOIiuKB5m6sXJshq/sGvnXmx57RNk4CIQCO
+7IrEVrk0HdCVSFb66ElZ9A0F5QneWtpIeBk6WhBAPw==
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn test_peer_identity_must_name_a_member() {
+        let cert = CertificateDer::from_pem_slice(NODE_CERT.as_bytes()).unwrap();
+
+        assert!(verify_peer_identity(&cert, &["10.0.0.1", host_of("10.0.0.2:50061")]).is_ok());
+        assert!(verify_peer_identity(&cert, &["node-2.cluster"]).is_ok());
+        assert!(verify_peer_identity(&cert, &["10.0.0.1", "node-1.cluster"]).is_err());
+        assert_eq!(host_of("[::1]:50061"), "::1");
+    }
+}
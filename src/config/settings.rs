@@ -1,13 +1,58 @@
-use std::{env, time::Duration};
+use crate::cluster::client::ForwardingConfig;
+use crate::cluster::gossip::GossipConfig;
+use crate::cluster::identity::{resolve_self_addr, same_addr, NodeRole};
+use crate::cluster::ring::{RingMember, DEFAULT_VNODES};
+use crate::cluster::tls::ClusterTlsConfig;
+use crate::pipeline::anti_entropy::AntiEntropyConfig;
+use crate::pipeline::replication::{ReplicationConfig, ReplicationMode};
+use std::{collections::HashMap, env, time::Duration};
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Config {
     pub scylla_uri: Option<String>,
     pub scylla_keyspace: String,
     pub request_timeout: Duration,
-    pub node_id: u64,
+    /// Index of this node in the sorted `CLUSTER_NODES`, superseded by
+    /// `ADVERTISE_ADDR`.
+    pub node_id: Option<u64>,
+    /// Name of the node in logs and tooling (default: its address).
+    pub node_name: Option<String>,
+    /// `NODE_ROLE`: "member" (default) or "read-replica".
+    pub node_role: NodeRole,
     pub cluster_nodes: Vec<String>,
+    /// Ring weights from `CLUSTER_NODES` entries of the form `addr=weight` (default 1).
+    pub node_weights: HashMap<String, u32>,
+    pub cluster_vnodes: u32,
+    /// Gossip seed addresses from `CLUSTER_SEEDS`. When set, membership is
+    /// discovered through SWIM gossip instead of the static `CLUSTER_NODES`.
+    pub cluster_seeds: Vec<String>,
+    /// Address other nodes reach this one at, for gRPC (TCP) and gossip (UDP).
+    /// For static clusters, the `CLUSTER_NODES` entry of this node.
+    pub advertise_addr: String,
+    pub gossip: GossipConfig,
+    /// Deadlines, retries and peer health of calls to other nodes.
+    pub forwarding: ForwardingConfig,
+    pub replication: ReplicationConfig,
+    /// Background comparison of the copies of streams, from
+    /// `ANTI_ENTROPY_INTERVAL_MS` and `ANTI_ENTROPY_REPAIR`.
+    pub anti_entropy: AntiEntropyConfig,
+    /// Time between two replays of the writes the RocksDB fallback took
+    /// while ScyllaDB was failing, from `FALLBACK_RECONCILE_INTERVAL_MS`.
+    pub fallback_reconcile_interval: Duration,
+    /// Consecutive availability errors of ScyllaDB after which it is no
+    /// longer called for `STORAGE_COOLDOWN_MS` before a probe, from
+    /// `STORAGE_FAILURE_THRESHOLD`.
+    pub storage_failure_threshold: u32,
+    pub storage_cooldown: Duration,
     pub port: u16,
+    /// Port of the internal cluster service, the same on every node. When
+    /// unset, it is served on `port` next to the public API.
+    pub cluster_port: Option<u16>,
+    /// Bearer token nodes present to each other's cluster service, and key
+    /// of the gossip datagrams. Required as soon as there are other nodes.
+    pub cluster_secret: Option<String>,
+    /// Mutual TLS between nodes, from the `CLUSTER_TLS_*` variables.
+    pub cluster_tls: Option<ClusterTlsConfig>,
     pub db_path: String,
     pub auth_token: Option<String>,
     pub tls_cert_path: Option<String>,
@@ -29,33 +74,274 @@ impl Config {
 
         let node_id = env::var("NODE_ID")
             .ok()
-            .and_then(|v| v.parse::<u64>().ok())
-            .unwrap_or(0);
+            .map(|v| {
+                v.parse::<u64>()
+                    .map_err(|_| format!("Invalid NODE_ID {}", v))
+            })
+            .transpose()?;
+        let node_name = env::var("NODE_NAME").ok();
+        let node_role = match env::var("NODE_ROLE").as_deref() {
+            Err(_) | Ok("member") => NodeRole::Member,
+            Ok("read-replica") => NodeRole::ReadReplica,
+            Ok(other) => {
+                return Err(format!(
+                    "NODE_ROLE must be \"member\" or \"read-replica\", got \"{}\"",
+                    other
+                ))
+            }
+        };
 
-        let cluster_nodes = env::var("CLUSTER_NODES")
+        let cluster_entries: Vec<String> = env::var("CLUSTER_NODES")
             .ok()
             .map(|s| s.split(',').map(|s| s.trim().to_string()).collect())
             .unwrap_or_else(|| vec!["127.0.0.1:50051".to_string()]); // Default single node
 
+        let mut cluster_nodes = Vec::with_capacity(cluster_entries.len());
+        let mut node_weights = HashMap::new();
+        for entry in cluster_entries {
+            match entry.split_once('=') {
+                Some((addr, weight)) => {
+                    let weight = weight
+                        .trim()
+                        .parse::<u32>()
+                        .map_err(|_| format!("Invalid weight in CLUSTER_NODES entry {}", entry))?;
+                    node_weights.insert(addr.trim().to_string(), weight);
+                    cluster_nodes.push(addr.trim().to_string());
+                }
+                None => cluster_nodes.push(entry),
+            }
+        }
+
+        let cluster_vnodes = env::var("CLUSTER_VNODES")
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or(DEFAULT_VNODES);
+
         let port = env::var("PORT")
             .ok()
             .and_then(|v| v.parse::<u16>().ok())
             .unwrap_or(50051);
 
+        let cluster_seeds: Vec<String> = env::var("CLUSTER_SEEDS")
+            .ok()
+            .map(|s| {
+                s.split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // Discovered clusters learn members from what nodes advertise; static
+        // ones must name this node among CLUSTER_NODES, unless it is a read
+        // replica, which follows the members from outside the ring
+        let advertise_addr = if node_role == NodeRole::ReadReplica {
+            if !cluster_seeds.is_empty() {
+                return Err(
+                    "A read replica follows a static CLUSTER_NODES: unset CLUSTER_SEEDS"
+                        .to_string(),
+                );
+            }
+            let addr = env::var("ADVERTISE_ADDR")
+                .map_err(|_| "A read replica needs its ADVERTISE_ADDR".to_string())?;
+            if cluster_nodes.iter().any(|n| same_addr(n, &addr)) {
+                return Err(format!(
+                    "Read replica {} must not be listed in CLUSTER_NODES",
+                    addr
+                ));
+            }
+            addr
+        } else if cluster_seeds.is_empty() {
+            resolve_self_addr(
+                &cluster_nodes,
+                env::var("ADVERTISE_ADDR").ok().as_deref(),
+                node_id,
+            )?
+        } else {
+            env::var("ADVERTISE_ADDR").unwrap_or_else(|_| format!("127.0.0.1:{}", port))
+        };
+
+        let defaults = GossipConfig::default();
+        let millis = |name: &str, default: Duration| {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(default)
+        };
+        let gossip = GossipConfig {
+            probe_interval: millis("GOSSIP_PROBE_INTERVAL_MS", defaults.probe_interval),
+            probe_timeout: millis("GOSSIP_PROBE_TIMEOUT_MS", defaults.probe_timeout),
+            suspect_timeout: millis("GOSSIP_SUSPECT_TIMEOUT_MS", defaults.suspect_timeout),
+            indirect_probes: defaults.indirect_probes,
+        };
+
+        let defaults = ForwardingConfig::default();
+        let count = |name: &str, default: u32| {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<u32>().ok())
+                .unwrap_or(default)
+        };
+        let keep_alive_interval = millis("CLUSTER_KEEPALIVE_MS", defaults.keep_alive_interval);
+        let forwarding = ForwardingConfig {
+            request_timeout,
+            connect_timeout: millis("CLUSTER_CONNECT_TIMEOUT_MS", defaults.connect_timeout),
+            keep_alive_interval,
+            keep_alive_timeout: keep_alive_interval.min(defaults.keep_alive_timeout),
+            max_retries: count("FORWARD_MAX_RETRIES", defaults.max_retries),
+            retry_backoff: millis("FORWARD_RETRY_BACKOFF_MS", defaults.retry_backoff),
+            failure_threshold: count("PEER_FAILURE_THRESHOLD", defaults.failure_threshold),
+            cooldown: millis("PEER_COOLDOWN_MS", defaults.cooldown),
+        };
+
+        let factor = env::var("REPLICATION_FACTOR")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(1)
+            .max(1);
+        let write_acks = env::var("REPLICATION_WRITE_ACKS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(factor);
+        if write_acks == 0 || write_acks > factor {
+            return Err(format!(
+                "REPLICATION_WRITE_ACKS must be between 1 and REPLICATION_FACTOR ({})",
+                factor
+            ));
+        }
+        let mode = match env::var("REPLICATION_MODE").as_deref() {
+            Err(_) | Ok("leader") => ReplicationMode::Leader,
+            Ok("raft") => ReplicationMode::Raft,
+            Ok(other) => {
+                return Err(format!(
+                    "REPLICATION_MODE must be \"leader\" or \"raft\", got \"{}\"",
+                    other
+                ))
+            }
+        };
+        let read_replicas: Vec<String> = env::var("READ_REPLICAS")
+            .ok()
+            .map(|s| {
+                s.split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        if mode == ReplicationMode::Raft
+            && (node_role == NodeRole::ReadReplica || !read_replicas.is_empty())
+        {
+            return Err("Read replicas need REPLICATION_MODE=leader".to_string());
+        }
+        let replication = ReplicationConfig {
+            mode,
+            factor,
+            write_acks,
+            ack_timeout: request_timeout,
+            read_replicas,
+        };
+
+        let anti_entropy = AntiEntropyConfig {
+            interval: env::var("ANTI_ENTROPY_INTERVAL_MS")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|ms| *ms > 0)
+                .map(Duration::from_millis),
+            repair: env::var("ANTI_ENTROPY_REPAIR").is_ok_and(|v| v == "true"),
+        };
+
+        let fallback_reconcile_interval =
+            millis("FALLBACK_RECONCILE_INTERVAL_MS", Duration::from_secs(1));
+        let storage_failure_threshold = env::var("STORAGE_FAILURE_THRESHOLD")
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or(5);
+        let storage_cooldown = millis("STORAGE_COOLDOWN_MS", Duration::from_secs(5));
+
         // Allow configurable DB path for multi-node local run
         let db_path = env::var("DB_PATH").unwrap_or_else(|_| "data/rocksdb".to_string());
 
         let auth_token = env::var("AUTH_TOKEN").ok();
+        let cluster_port = env::var("CLUSTER_PORT")
+            .ok()
+            .map(|v| {
+                v.parse::<u16>()
+                    .map_err(|_| format!("Invalid CLUSTER_PORT {}", v))
+            })
+            .transpose()?
+            .filter(|p| *p != port);
+        let cluster_secret = env::var("CLUSTER_SECRET").ok();
+
+        let cluster_tls = match (
+            env::var("CLUSTER_TLS_CA_PATH").ok(),
+            env::var("CLUSTER_TLS_CERT_PATH").ok(),
+            env::var("CLUSTER_TLS_KEY_PATH").ok(),
+        ) {
+            (Some(ca_path), Some(cert_path), Some(key_path)) => Some(ClusterTlsConfig {
+                ca_path,
+                cert_path,
+                key_path,
+                server_name: env::var("CLUSTER_TLS_SERVER_NAME").ok(),
+            }),
+            (None, None, None) => None,
+            _ => {
+                return Err(
+                    "CLUSTER_TLS_CA_PATH, CLUSTER_TLS_CERT_PATH and CLUSTER_TLS_KEY_PATH must be set together"
+                        .to_string(),
+                )
+            }
+        };
+        // Client certificates are required on the cluster listener, which
+        // clients of the public API could not present
+        if cluster_tls.is_some() && cluster_port.is_none() {
+            return Err("Cluster TLS requires its own listener: set CLUSTER_PORT".to_string());
+        }
+        // Other nodes reach the cluster service and gossip, both of which
+        // can change the ring: only holders of the secret may use them
+        let multi_node = cluster_nodes.len() > 1
+            || !cluster_seeds.is_empty()
+            || node_role == NodeRole::ReadReplica
+            || !replication.read_replicas.is_empty();
+        if multi_node && cluster_secret.is_none() {
+            return Err(
+                "A multi-node cluster needs CLUSTER_SECRET to authenticate its nodes".to_string(),
+            );
+        }
         let tls_cert_path = env::var("TLS_CERT_PATH").ok();
         let tls_key_path = env::var("TLS_KEY_PATH").ok();
+        // Nodes dial each other in plaintext or over cluster TLS, so they
+        // cannot reach a cluster service sharing the public TLS listener
+        if multi_node && tls_cert_path.is_some() && cluster_port.is_none() {
+            return Err(
+                "With TLS_CERT_PATH, the cluster service needs its own listener: set CLUSTER_PORT"
+                    .to_string(),
+            );
+        }
 
         Ok(Self {
             scylla_uri,
             scylla_keyspace,
             request_timeout,
             node_id,
+            node_name,
+            node_role,
             cluster_nodes,
+            node_weights,
+            cluster_vnodes,
+            cluster_seeds,
+            advertise_addr,
+            gossip,
+            forwarding,
+            replication,
+            anti_entropy,
+            fallback_reconcile_interval,
+            storage_failure_threshold,
+            storage_cooldown,
             port,
+            cluster_port,
+            cluster_secret,
+            cluster_tls,
             db_path,
             auth_token,
             tls_cert_path,
@@ -63,3 +349,55 @@ impl Config {
         })
     }
 }
+
+/// Leaves out the values of `cluster_secret` and `auth_token`, so that the
+/// configuration can be logged.
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let redacted = |secret: &Option<String>| secret.as_ref().map(|_| "<redacted>");
+        f.debug_struct("Config")
+            .field("scylla_uri", &self.scylla_uri)
+            .field("scylla_keyspace", &self.scylla_keyspace)
+            .field("request_timeout", &self.request_timeout)
+            .field("node_id", &self.node_id)
+            .field("node_name", &self.node_name)
+            .field("node_role", &self.node_role)
+            .field("cluster_nodes", &self.cluster_nodes)
+            .field("node_weights", &self.node_weights)
+            .field("cluster_vnodes", &self.cluster_vnodes)
+            .field("cluster_seeds", &self.cluster_seeds)
+            .field("advertise_addr", &self.advertise_addr)
+            .field("gossip", &self.gossip)
+            .field("forwarding", &self.forwarding)
+            .field("replication", &self.replication)
+            .field("anti_entropy", &self.anti_entropy)
+            .field(
+                "fallback_reconcile_interval",
+                &self.fallback_reconcile_interval,
+            )
+            .field("storage_failure_threshold", &self.storage_failure_threshold)
+            .field("storage_cooldown", &self.storage_cooldown)
+            .field("port", &self.port)
+            .field("cluster_port", &self.cluster_port)
+            .field("cluster_secret", &redacted(&self.cluster_secret))
+            .field("cluster_tls", &self.cluster_tls)
+            .field("db_path", &self.db_path)
+            .field("auth_token", &redacted(&self.auth_token))
+            .field("tls_cert_path", &self.tls_cert_path)
+            .field("tls_key_path", &self.tls_key_path)
+            .finish()
+    }
+}
+
+impl Config {
+    /// Ring placement of the configured cluster nodes.
+    pub fn ring_members(&self) -> Vec<RingMember> {
+        self.cluster_nodes
+            .iter()
+            .map(|addr| RingMember {
+                addr: addr.clone(),
+                weight: self.node_weights.get(addr).copied().unwrap_or(1),
+            })
+            .collect()
+    }
+}
@@ -1,6 +1,6 @@
 use crate::api as proto;
 use crate::domain::events::event::Event;
-use crate::domain::events::event_kind::{EventId, EventKind, EventPayload, Timestamp};
+use crate::domain::events::event_kind::{ContentType, EventId, EventKind, EventPayload, Timestamp};
 
 impl TryFrom<proto::Event> for Event {
     type Error = String;
@@ -22,6 +22,7 @@ impl TryFrom<proto::Event> for Event {
             sequence_number: 0,
             event_type,
             payload: EventPayload(proto_event.payload),
+            content_type: ContentType::parse(&proto_event.content_type),
             timestamp: Timestamp(proto_event.timestamp),
             metadata: proto_event.metadata,
         })
@@ -37,6 +38,7 @@ impl From<Event> for proto::Event {
             payload: domain_event.payload.0,
             timestamp: domain_event.timestamp.0,
             metadata: domain_event.metadata,
+            content_type: domain_event.content_type.to_string(),
         }
     }
 }
@@ -1,4 +1,4 @@
-use crate::domain::events::event_kind::{EventId, EventKind, EventPayload, Timestamp};
+use crate::domain::events::event_kind::{ContentType, EventId, EventKind, EventPayload, Timestamp};
 
 use serde::{Deserialize, Serialize};
 
@@ -25,6 +25,11 @@ pub struct Event {
     /// The binary payload of the event.
     pub payload: EventPayload,
 
+    /// The declared encoding of `payload`.
+    /// Events persisted before this field existed are read back as JSON.
+    #[serde(default)]
+    pub content_type: ContentType,
+
     /// The wall-clock time when the event was created/ingested.
     pub timestamp: Timestamp,
 
@@ -43,8 +48,15 @@ impl Event {
             sequence_number: 0, // Assigned by storage
             event_type,
             payload,
+            content_type: ContentType::default(),
             timestamp: Timestamp::now(),
             metadata: std::collections::HashMap::new(),
         }
     }
+
+    /// Sets the declared payload encoding.
+    pub fn with_content_type(mut self, content_type: ContentType) -> Self {
+        self.content_type = content_type;
+        self
+    }
 }
@@ -50,5 +50,74 @@ impl std::fmt::Display for EventKind {
     }
 }
 
+/// Declared encoding of an event payload.
+///
+/// Serialized as its MIME type (e.g. `application/json`). Unknown MIME types are
+/// kept verbatim as `Custom` so they round-trip through storage unchanged.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ContentType {
+    #[default]
+    Json,
+    Cbor,
+    Protobuf,
+    OctetStream,
+    Custom(String),
+}
+
+impl ContentType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ContentType::Json => "application/json",
+            ContentType::Cbor => "application/cbor",
+            ContentType::Protobuf => "application/protobuf",
+            ContentType::OctetStream => "application/octet-stream",
+            ContentType::Custom(s) => s,
+        }
+    }
+
+    /// Parses a MIME type. An empty string maps to JSON, which is what payloads
+    /// were assumed to be before the content type was declared.
+    pub fn parse(s: &str) -> Self {
+        // Ignore parameters such as "; charset=utf-8"
+        let mime = s
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        match mime.as_str() {
+            "" | "application/json" | "text/json" => ContentType::Json,
+            "application/cbor" => ContentType::Cbor,
+            "application/protobuf" | "application/x-protobuf" => ContentType::Protobuf,
+            "application/octet-stream" => ContentType::OctetStream,
+            _ => ContentType::Custom(s.trim().to_string()),
+        }
+    }
+
+    /// True if the payload can be decoded into a JSON document (JSON or CBOR).
+    pub fn is_structured(&self) -> bool {
+        matches!(self, ContentType::Json | ContentType::Cbor)
+    }
+}
+
+impl std::fmt::Display for ContentType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ContentType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ContentType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(ContentType::parse(&s))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SchemaType(pub String);
@@ -2,7 +2,7 @@
 mod tests {
     use super::*;
     use crate::domain::events::event::Event;
-    use crate::domain::events::event_kind::{EventKind, EventPayload};
+    use crate::domain::events::event_kind::{ContentType, EventKind, EventPayload};
 
     #[test]
     fn test_event_creation_v7() {
@@ -25,4 +25,38 @@ mod tests {
         assert_eq!(event.id.0, deserialized.id.0);
         assert_eq!(event.stream_id, deserialized.stream_id);
     }
+
+    #[test]
+    fn test_content_type_parse_round_trip() {
+        assert_eq!(ContentType::parse(""), ContentType::Json);
+        assert_eq!(
+            ContentType::parse("application/json; charset=utf-8"),
+            ContentType::Json
+        );
+        assert_eq!(ContentType::parse("application/cbor"), ContentType::Cbor);
+        assert_eq!(
+            ContentType::parse("application/x-protobuf"),
+            ContentType::Protobuf
+        );
+
+        let custom = ContentType::parse("application/vnd.acme+avro");
+        assert_eq!(
+            custom,
+            ContentType::Custom("application/vnd.acme+avro".to_string())
+        );
+        assert_eq!(ContentType::parse(custom.as_str()), custom);
+    }
+
+    #[test]
+    fn test_content_type_persisted() {
+        let event = Event::new("stream-1", EventKind::Internal, EventPayload(vec![1, 2, 3]))
+            .with_content_type(ContentType::OctetStream);
+
+        let bytes = serde_cbor::to_vec(&event).expect("Failed to serialize");
+        let decoded: Event = serde_cbor::from_slice(&bytes).expect("Failed to deserialize");
+        assert_eq!(decoded.content_type, ContentType::OctetStream);
+
+        let proto_event: crate::api::Event = decoded.into();
+        assert_eq!(proto_event.content_type, "application/octet-stream");
+    }
 }
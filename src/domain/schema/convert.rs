@@ -1,6 +1,7 @@
 use crate::api as proto;
 use crate::domain::schema::model::{
-    EnumType, Field, FieldConstraints, FieldType, PrimitiveType, Schema,
+    EnumType, Field, FieldConstraints, FieldType, NormalizationSettings, PrimitiveType, Schema,
+    ValidationRule,
 };
 use std::collections::HashMap;
 
@@ -15,6 +16,33 @@ impl From<proto::Schema> for Schema {
         Schema {
             name: proto_schema.name,
             fields,
+            rules: proto_schema.rules.into_iter().map(Into::into).collect(),
+            normalization: proto_schema
+                .normalization
+                .map(Into::into)
+                .unwrap_or_default(),
+            project_state: proto_schema.project_state,
+        }
+    }
+}
+
+impl From<proto::NormalizationSettings> for NormalizationSettings {
+    fn from(proto_n: proto::NormalizationSettings) -> Self {
+        NormalizationSettings {
+            apply_defaults: proto_n.apply_defaults,
+            coerce_numeric_strings: proto_n.coerce_numeric_strings,
+            strip_unknown_fields: proto_n.strip_unknown_fields,
+            preserve_original: proto_n.preserve_original,
+        }
+    }
+}
+
+impl From<proto::ValidationRule> for ValidationRule {
+    fn from(proto_rule: proto::ValidationRule) -> Self {
+        ValidationRule {
+            name: proto_rule.name,
+            expression: proto_rule.expression,
+            message: proto_rule.message,
         }
     }
 }
@@ -28,6 +56,10 @@ impl From<proto::Field> for Field {
             nullable: proto_field.nullable,
             overrides_on_null: proto_field.overrides_on_null,
             constraints: proto_field.constraints.map(|c| c.into()),
+            // Defaults are sent as JSON; anything unparsable is taken as a literal string
+            default_value: proto_field
+                .default_json
+                .map(|raw| serde_json::from_str(&raw).unwrap_or(serde_json::Value::String(raw))),
         }
     }
 }
@@ -89,6 +121,30 @@ impl From<Schema> for proto::Schema {
         proto::Schema {
             name: domain_schema.name,
             fields,
+            rules: domain_schema.rules.into_iter().map(Into::into).collect(),
+            normalization: Some(domain_schema.normalization.into()),
+            project_state: domain_schema.project_state,
+        }
+    }
+}
+
+impl From<NormalizationSettings> for proto::NormalizationSettings {
+    fn from(domain_n: NormalizationSettings) -> Self {
+        proto::NormalizationSettings {
+            apply_defaults: domain_n.apply_defaults,
+            coerce_numeric_strings: domain_n.coerce_numeric_strings,
+            strip_unknown_fields: domain_n.strip_unknown_fields,
+            preserve_original: domain_n.preserve_original,
+        }
+    }
+}
+
+impl From<ValidationRule> for proto::ValidationRule {
+    fn from(domain_rule: ValidationRule) -> Self {
+        proto::ValidationRule {
+            name: domain_rule.name,
+            expression: domain_rule.expression,
+            message: domain_rule.message,
         }
     }
 }
@@ -100,6 +156,7 @@ impl From<Field> for proto::Field {
             nullable: domain_field.nullable,
             overrides_on_null: domain_field.overrides_on_null,
             constraints: domain_field.constraints.map(|c| c.into()),
+            default_json: domain_field.default_value.map(|v| v.to_string()),
         }
     }
 }
@@ -0,0 +1,133 @@
+use crate::domain::schema::model::{Field, FieldType};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Applies `patch` to `target` with JSON merge-patch (RFC 7386) semantics,
+/// refined by the schema's partial-update flags.
+///
+/// A `null` in the patch is resolved per field:
+/// - undeclared field: removed, as in plain merge-patch;
+/// - `overrides_on_null` and `nullable`: stored as `null`;
+/// - `overrides_on_null` but not `nullable`: removed;
+/// - otherwise: ignored, the previous value is kept.
+///
+/// Objects declared as sub-schemas are merged recursively with their own fields.
+pub fn merge_patch(target: &mut Value, patch: &Value, fields: &HashMap<String, Field>) {
+    let patch_obj = match patch.as_object() {
+        Some(obj) => obj,
+        None => {
+            *target = patch.clone();
+            return;
+        }
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let target_obj = target.as_object_mut().expect("target is an object");
+
+    for (key, value) in patch_obj {
+        let field = fields.get(key);
+
+        if value.is_null() {
+            match field {
+                None => {
+                    target_obj.remove(key);
+                }
+                Some(f) if f.overrides_on_null && f.nullable => {
+                    target_obj.insert(key.clone(), Value::Null);
+                }
+                Some(f) if f.overrides_on_null => {
+                    target_obj.remove(key);
+                }
+                Some(_) => {}
+            }
+            continue;
+        }
+
+        if value.is_object() {
+            let empty = HashMap::new();
+            let sub_fields = match field.map(|f| &f.field_type) {
+                Some(FieldType::SubSchema(sub)) => &sub.fields,
+                _ => &empty,
+            };
+            let entry = target_obj.entry(key.clone()).or_insert(Value::Null);
+            merge_patch(entry, value, sub_fields);
+        } else {
+            target_obj.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::schema::model::{PrimitiveType, Schema};
+    use serde_json::json;
+
+    fn field(nullable: bool, overrides_on_null: bool) -> Field {
+        Field {
+            field_type: FieldType::Primitive(PrimitiveType::String),
+            nullable,
+            overrides_on_null,
+            constraints: None,
+            default_value: None,
+        }
+    }
+
+    #[test]
+    fn test_merge_patch_basic() {
+        let mut doc = json!({ "name": "Alice", "tags": ["a"], "extra": 1 });
+        merge_patch(
+            &mut doc,
+            &json!({ "tags": ["b"], "extra": null, "age": 30 }),
+            &HashMap::new(),
+        );
+        assert_eq!(doc, json!({ "name": "Alice", "tags": ["b"], "age": 30 }));
+    }
+
+    #[test]
+    fn test_merge_patch_null_rules() {
+        let mut fields = HashMap::new();
+        fields.insert("keep".to_string(), field(true, false));
+        fields.insert("clear".to_string(), field(true, true));
+        fields.insert("drop".to_string(), field(false, true));
+
+        let mut doc = json!({ "keep": "k", "clear": "c", "drop": "d" });
+        merge_patch(
+            &mut doc,
+            &json!({ "keep": null, "clear": null, "drop": null }),
+            &fields,
+        );
+        assert_eq!(doc, json!({ "keep": "k", "clear": null }));
+    }
+
+    #[test]
+    fn test_merge_patch_nested_sub_schema() {
+        let mut address_fields = HashMap::new();
+        address_fields.insert("city".to_string(), field(true, false));
+        let mut fields = HashMap::new();
+        fields.insert(
+            "address".to_string(),
+            Field {
+                field_type: FieldType::SubSchema(Box::new(Schema {
+                    name: "Address".to_string(),
+                    fields: address_fields,
+                    ..Default::default()
+                })),
+                ..field(true, false)
+            },
+        );
+
+        let mut doc = json!({ "address": { "city": "Lisbon", "zip": "1000" } });
+        merge_patch(
+            &mut doc,
+            &json!({ "address": { "city": null, "zip": null, "street": "Main" } }),
+            &fields,
+        );
+        assert_eq!(
+            doc,
+            json!({ "address": { "city": "Lisbon", "street": "Main" } })
+        );
+    }
+}
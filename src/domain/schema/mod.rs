@@ -1,3 +1,6 @@
 pub mod convert;
+pub mod merge;
 pub mod model;
+pub mod normalize;
+pub mod rules;
 pub mod validation;
@@ -1,10 +1,51 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 pub struct Schema {
     pub name: String,
     pub fields: HashMap<String, Field>,
+    /// Cross-field rules evaluated against the whole payload.
+    #[serde(default)]
+    pub rules: Vec<ValidationRule>,
+    /// How payloads are normalized before validation and persistence.
+    #[serde(default)]
+    pub normalization: NormalizationSettings,
+    /// Fold events of this type into a per-stream current-state document.
+    #[serde(default)]
+    pub project_state: bool,
+}
+
+/// Per-schema switches for the append-time normalization step.
+/// All disabled by default, in which case payloads are stored exactly as received.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct NormalizationSettings {
+    /// Fill missing (or null, unless `overrides_on_null`) fields with their declared default.
+    pub apply_defaults: bool,
+    /// Convert strings such as `"42"` into numbers for fields typed as Number.
+    pub coerce_numeric_strings: bool,
+    /// Drop payload fields that are not declared in the schema.
+    pub strip_unknown_fields: bool,
+    /// Keep the payload as received in the event metadata when normalization changed it.
+    pub preserve_original: bool,
+}
+
+impl NormalizationSettings {
+    pub fn is_enabled(&self) -> bool {
+        self.apply_defaults || self.coerce_numeric_strings || self.strip_unknown_fields
+    }
+}
+
+/// A named CEL expression that must evaluate to `true` for a payload to be valid.
+///
+/// Top-level payload fields are bound as variables (`end_date > start_date`),
+/// and the full document and event metadata are available as `payload` and `metadata`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ValidationRule {
+    pub name: String,
+    pub expression: String,
+    /// Reported when the rule evaluates to `false`.
+    pub message: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -13,6 +54,9 @@ pub struct Field {
     pub nullable: bool,
     pub overrides_on_null: bool,
     pub constraints: Option<FieldConstraints>,
+    /// Value used by normalization when the field is absent.
+    #[serde(default)]
+    pub default_value: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
@@ -0,0 +1,266 @@
+use crate::domain::events::event::Event;
+use crate::domain::events::event_kind::ContentType;
+use crate::domain::schema::model::{
+    Field, FieldType, NormalizationSettings, PrimitiveType, Schema,
+};
+use crate::domain::schema::validation::{decode_payload, ValidationError};
+use serde_json::{Map, Number, Value};
+use std::collections::HashMap;
+
+/// Metadata key holding the payload as received, when `preserve_original` is set.
+pub const ORIGINAL_PAYLOAD_KEY: &str = "$original_payload";
+
+/// Normalizes an event payload in place according to the schema's settings.
+///
+/// Only JSON and CBOR payloads are touched; the payload is re-encoded in its
+/// original content type. Returns whether the payload was changed.
+pub fn normalize_event(event: &mut Event, schema: &Schema) -> Result<bool, ValidationError> {
+    let settings = &schema.normalization;
+    if !settings.is_enabled() {
+        return Ok(false);
+    }
+
+    let mut value = match decode_payload(&event.payload.0, &event.content_type) {
+        Some(res) => res?,
+        None => return Ok(false),
+    };
+    let original = settings.preserve_original.then(|| value.clone());
+
+    if !normalize_value(&mut value, schema) {
+        return Ok(false);
+    }
+
+    let encoded = match event.content_type {
+        ContentType::Cbor => serde_cbor::to_vec(&value)?,
+        _ => serde_json::to_vec(&value)?,
+    };
+
+    if let Some(original) = original {
+        let original_text = match event.content_type {
+            // Keep the exact bytes for JSON; CBOR is rendered as JSON text
+            ContentType::Json => String::from_utf8_lossy(&event.payload.0).into_owned(),
+            _ => original.to_string(),
+        };
+        event
+            .metadata
+            .insert(ORIGINAL_PAYLOAD_KEY.to_string(), original_text);
+    }
+    event.payload.0 = encoded;
+
+    Ok(true)
+}
+
+/// Applies defaults, coercion and unknown-field stripping to a decoded document.
+/// Returns whether anything was changed.
+pub fn normalize_value(value: &mut Value, schema: &Schema) -> bool {
+    match value.as_object_mut() {
+        Some(obj) => normalize_object(obj, &schema.fields, &schema.normalization),
+        None => false,
+    }
+}
+
+fn normalize_object(
+    obj: &mut Map<String, Value>,
+    fields: &HashMap<String, Field>,
+    settings: &NormalizationSettings,
+) -> bool {
+    let mut changed = false;
+
+    if settings.strip_unknown_fields {
+        let before = obj.len();
+        obj.retain(|key, _| fields.contains_key(key));
+        changed |= obj.len() != before;
+    }
+
+    for (name, field) in fields {
+        match obj.get_mut(name) {
+            None => {
+                if let (true, Some(default)) = (settings.apply_defaults, &field.default_value) {
+                    obj.insert(name.clone(), default.clone());
+                    changed = true;
+                }
+            }
+            Some(val) if val.is_null() => {
+                // An explicit null is meaningful when the field overrides on null
+                if let (true, false, Some(default)) = (
+                    settings.apply_defaults,
+                    field.overrides_on_null,
+                    &field.default_value,
+                ) {
+                    *val = default.clone();
+                    changed = true;
+                }
+            }
+            Some(val) => changed |= normalize_typed(val, &field.field_type, settings),
+        }
+    }
+
+    changed
+}
+
+fn normalize_typed(
+    val: &mut Value,
+    field_type: &FieldType,
+    settings: &NormalizationSettings,
+) -> bool {
+    match (field_type, val) {
+        (FieldType::Primitive(PrimitiveType::Number), val) if settings.coerce_numeric_strings => {
+            match val.as_str().and_then(parse_number) {
+                Some(n) => {
+                    *val = Value::Number(n);
+                    true
+                }
+                None => false,
+            }
+        }
+        (FieldType::SubSchema(sub), Value::Object(obj)) => {
+            normalize_object(obj, &sub.fields, settings)
+        }
+        (FieldType::Array(element_type), Value::Array(items)) => {
+            items.iter_mut().fold(false, |changed, item| {
+                normalize_typed(item, element_type, settings) | changed
+            })
+        }
+        _ => false,
+    }
+}
+
+fn parse_number(s: &str) -> Option<Number> {
+    let s = s.trim();
+    if let Ok(i) = s.parse::<i64>() {
+        return Some(Number::from(i));
+    }
+    s.parse::<f64>().ok().and_then(Number::from_f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::events::event_kind::{EventKind, EventPayload};
+    use serde_json::json;
+
+    fn field(field_type: FieldType, default_value: Option<Value>) -> Field {
+        Field {
+            field_type,
+            nullable: true,
+            overrides_on_null: false,
+            constraints: None,
+            default_value,
+        }
+    }
+
+    fn order_schema(normalization: NormalizationSettings) -> Schema {
+        let mut fields = HashMap::new();
+        fields.insert(
+            "status".to_string(),
+            field(
+                FieldType::Primitive(PrimitiveType::String),
+                Some(json!("pending")),
+            ),
+        );
+        fields.insert(
+            "total".to_string(),
+            field(FieldType::Primitive(PrimitiveType::Number), None),
+        );
+        fields.insert(
+            "quantities".to_string(),
+            field(
+                FieldType::Array(Box::new(FieldType::Primitive(PrimitiveType::Number))),
+                None,
+            ),
+        );
+        Schema {
+            name: "Order".to_string(),
+            fields,
+            rules: Vec::new(),
+            normalization,
+            project_state: false,
+        }
+    }
+
+    fn all_enabled() -> NormalizationSettings {
+        NormalizationSettings {
+            apply_defaults: true,
+            coerce_numeric_strings: true,
+            strip_unknown_fields: true,
+            preserve_original: false,
+        }
+    }
+
+    #[test]
+    fn test_normalize_value() {
+        let schema = order_schema(all_enabled());
+        let mut doc = json!({
+            "total": "12.5",
+            "quantities": ["1", 2, "x"],
+            "debug": true
+        });
+
+        assert!(normalize_value(&mut doc, &schema));
+        assert_eq!(
+            doc,
+            json!({
+                "status": "pending",
+                "total": 12.5,
+                "quantities": [1, 2, "x"]
+            })
+        );
+
+        // Already normalized
+        assert!(!normalize_value(&mut doc, &schema));
+    }
+
+    #[test]
+    fn test_null_respects_overrides_on_null() {
+        let mut schema = order_schema(all_enabled());
+        let mut doc = json!({ "status": null });
+        assert!(normalize_value(&mut doc, &schema));
+        assert_eq!(doc["status"], json!("pending"));
+
+        schema.fields.get_mut("status").unwrap().overrides_on_null = true;
+        let mut doc = json!({ "status": null });
+        normalize_value(&mut doc, &schema);
+        assert_eq!(doc["status"], Value::Null);
+    }
+
+    #[test]
+    fn test_disabled_leaves_payload_untouched() {
+        let schema = order_schema(NormalizationSettings::default());
+        let raw = br#"{"total":"1","extra":1}"#.to_vec();
+        let mut event = Event::new("order-1", EventKind::Internal, EventPayload(raw.clone()));
+
+        assert!(!normalize_event(&mut event, &schema).unwrap());
+        assert_eq!(event.payload.0, raw);
+    }
+
+    #[test]
+    fn test_normalize_event_preserves_original() {
+        let schema = order_schema(NormalizationSettings {
+            preserve_original: true,
+            ..all_enabled()
+        });
+        let raw = br#"{"total":"3","extra":1}"#.to_vec();
+        let mut event = Event::new("order-1", EventKind::Internal, EventPayload(raw.clone()));
+
+        assert!(normalize_event(&mut event, &schema).unwrap());
+        let stored: Value = serde_json::from_slice(&event.payload.0).unwrap();
+        assert_eq!(stored, json!({ "status": "pending", "total": 3 }));
+        assert_eq!(
+            event.metadata.get(ORIGINAL_PAYLOAD_KEY).unwrap().as_bytes(),
+            raw.as_slice()
+        );
+    }
+
+    #[test]
+    fn test_normalize_cbor_event() {
+        let schema = order_schema(all_enabled());
+        let raw = serde_cbor::to_vec(&json!({ "total": "7" })).unwrap();
+        let mut event = Event::new("order-1", EventKind::Internal, EventPayload(raw))
+            .with_content_type(ContentType::Cbor);
+
+        assert!(normalize_event(&mut event, &schema).unwrap());
+        let stored: Value = serde_cbor::from_slice(&event.payload.0).unwrap();
+        assert_eq!(stored, json!({ "status": "pending", "total": 7 }));
+        assert!(!event.metadata.contains_key(ORIGINAL_PAYLOAD_KEY));
+    }
+}
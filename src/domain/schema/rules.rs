@@ -0,0 +1,142 @@
+use crate::domain::schema::model::{Schema, ValidationRule};
+use crate::domain::schema::validation::ValidationError;
+use cel_interpreter::{Context, Program, Value as CelValue};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, RwLock};
+
+/// Rules of a schema compiled into programs, keyed by schema name.
+///
+/// Each entry keeps the expressions it was compiled from, so a schema whose
+/// rules changed since (e.g. upserted on another node) is recompiled once
+/// instead of running stale programs.
+static COMPILED: LazyLock<RwLock<HashMap<String, Arc<CompiledRules>>>> =
+    LazyLock::new(Default::default);
+
+struct CompiledRules {
+    expressions: Vec<String>,
+    /// Compile errors are kept so broken stored rules keep being reported.
+    programs: Vec<Result<Program, String>>,
+}
+
+impl CompiledRules {
+    fn compile(schema: &Schema) -> Self {
+        Self {
+            expressions: schema.rules.iter().map(|r| r.expression.clone()).collect(),
+            programs: schema
+                .rules
+                .iter()
+                .map(|r| Program::compile(&r.expression).map_err(|e| e.to_string()))
+                .collect(),
+        }
+    }
+
+    fn matches(&self, schema: &Schema) -> bool {
+        self.expressions.len() == schema.rules.len()
+            && self
+                .expressions
+                .iter()
+                .zip(&schema.rules)
+                .all(|(expr, rule)| *expr == rule.expression)
+    }
+}
+
+/// Compiles every rule of a schema, reporting the ones that are not valid CEL.
+/// Called on upsert so broken expressions are rejected before they reach the append path.
+pub fn check_rules(schema: &Schema) -> Result<(), Vec<ValidationError>> {
+    let errors: Vec<ValidationError> = schema
+        .rules
+        .iter()
+        .filter_map(|rule| compile(rule).err())
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Compiles the rules of a stored schema once, so appends only execute them.
+pub fn register_rules(schema: &Schema) {
+    let compiled = Arc::new(CompiledRules::compile(schema));
+    COMPILED
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(schema.name.clone(), compiled);
+}
+
+/// Returns the compiled rules of a schema, compiling them on first use or
+/// when the schema's expressions no longer match the cached ones.
+fn compiled_rules(schema: &Schema) -> Arc<CompiledRules> {
+    if let Some(compiled) = COMPILED
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&schema.name)
+        .filter(|c| c.matches(schema))
+    {
+        return compiled.clone();
+    }
+    let compiled = Arc::new(CompiledRules::compile(schema));
+    COMPILED
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(schema.name.clone(), compiled.clone());
+    compiled
+}
+
+/// Evaluates the schema's cross-field rules against a decoded payload.
+///
+/// Returns one error per rule that evaluated to `false`, failed to evaluate,
+/// or produced a non-boolean result.
+pub fn evaluate_rules(
+    payload: &Value,
+    metadata: &HashMap<String, String>,
+    schema: &Schema,
+) -> Vec<ValidationError> {
+    if schema.rules.is_empty() {
+        return Vec::new();
+    }
+
+    let mut context = Context::default();
+    if let Some(obj) = payload.as_object() {
+        for (key, val) in obj {
+            // Keys that are not valid identifiers are still reachable via `payload[...]`
+            let _ = context.add_variable(key.as_str(), val);
+        }
+    }
+    // Bound last so they win over payload fields with the same name
+    let _ = context.add_variable("payload", payload);
+    let _ = context.add_variable("metadata", metadata);
+
+    let compiled = compiled_rules(schema);
+    let mut errors = Vec::new();
+    for (rule, program) in schema.rules.iter().zip(&compiled.programs) {
+        let program = match program {
+            Ok(p) => p,
+            Err(e) => {
+                errors.push(ValidationError::RuleError(rule.name.clone(), e.clone()));
+                continue;
+            }
+        };
+
+        match program.execute(&context) {
+            Ok(CelValue::Bool(true)) => {}
+            Ok(CelValue::Bool(false)) => errors.push(ValidationError::RuleViolation {
+                rule: rule.name.clone(),
+                message: rule.message.clone(),
+            }),
+            Ok(other) => errors.push(ValidationError::RuleError(
+                rule.name.clone(),
+                format!("expected a boolean result, got {:?}", other),
+            )),
+            Err(e) => errors.push(ValidationError::RuleError(rule.name.clone(), e.to_string())),
+        }
+    }
+    errors
+}
+
+fn compile(rule: &ValidationRule) -> Result<Program, ValidationError> {
+    Program::compile(&rule.expression)
+        .map_err(|e| ValidationError::RuleError(rule.name.clone(), e.to_string()))
+}
@@ -1,10 +1,15 @@
+use crate::domain::events::event::Event;
+use crate::domain::events::event_kind::ContentType;
 use crate::domain::schema::model::{FieldType, PrimitiveType, Schema};
 use serde_json::Value;
+use std::collections::HashMap;
 
 #[derive(Debug, thiserror::Error)]
 pub enum ValidationError {
     #[error("Payload is not valid JSON")]
     InvalidJson(#[from] serde_json::Error),
+    #[error("Payload is not valid CBOR")]
+    InvalidCbor(#[from] serde_cbor::Error),
     #[error("Field {0} is required but missing")]
     MissingField(String),
     #[error("Field {0} has invalid type")]
@@ -19,14 +24,95 @@ pub enum ValidationError {
     MaxLength(String, usize, i32),
     #[error("Field {0} does not match regex {1}")]
     Regex(String, String),
+    #[error("Rule {rule} violated: {message}")]
+    RuleViolation { rule: String, message: String },
+    #[error("Rule {0} could not be evaluated: {1}")]
+    RuleError(String, String),
+}
+
+impl ValidationError {
+    /// Stable, machine-readable code reported in validation results.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ValidationError::InvalidJson(_) => "INVALID_JSON",
+            ValidationError::InvalidCbor(_) => "INVALID_CBOR",
+            ValidationError::MissingField(_) => "MISSING_FIELD",
+            ValidationError::InvalidType(_) => "INVALID_TYPE",
+            ValidationError::MinValue(..) => "MIN_VALUE",
+            ValidationError::MaxValue(..) => "MAX_VALUE",
+            ValidationError::MinLength(..) => "MIN_LENGTH",
+            ValidationError::MaxLength(..) => "MAX_LENGTH",
+            ValidationError::Regex(..) => "REGEX",
+            ValidationError::RuleViolation { .. } => "RULE_VIOLATION",
+            ValidationError::RuleError(..) => "RULE_ERROR",
+        }
+    }
+
+    /// The field or rule name the error refers to, if any.
+    pub fn subject(&self) -> Option<&str> {
+        match self {
+            ValidationError::InvalidJson(_) | ValidationError::InvalidCbor(_) => None,
+            ValidationError::MissingField(f)
+            | ValidationError::InvalidType(f)
+            | ValidationError::MinValue(f, ..)
+            | ValidationError::MaxValue(f, ..)
+            | ValidationError::MinLength(f, ..)
+            | ValidationError::MaxLength(f, ..)
+            | ValidationError::Regex(f, _)
+            | ValidationError::RuleError(f, _) => Some(f),
+            ValidationError::RuleViolation { rule, .. } => Some(rule),
+        }
+    }
+}
+
+/// Decodes a payload into a JSON document according to its content type.
+///
+/// Returns `None` for content types the server cannot interpret (protobuf,
+/// octet-stream, custom), which are treated as opaque bytes.
+pub fn decode_payload(
+    payload: &[u8],
+    content_type: &ContentType,
+) -> Option<Result<Value, ValidationError>> {
+    match content_type {
+        ContentType::Json => Some(serde_json::from_slice(payload).map_err(Into::into)),
+        ContentType::Cbor => Some(serde_cbor::from_slice(payload).map_err(Into::into)),
+        _ => None,
+    }
+}
+
+/// Validates an event against a schema: field constraints on the payload,
+/// then the schema's cross-field rules with the event metadata in scope.
+pub fn validate_event(event: &Event, schema: &Schema) -> Result<(), Vec<ValidationError>> {
+    match decode_payload(&event.payload.0, &event.content_type) {
+        Some(Ok(json_val)) => validate_value(&json_val, &event.metadata, schema),
+        Some(Err(e)) => Err(vec![e]),
+        None => Ok(()),
+    }
+}
+
+/// Validates a payload against a schema, branching on its declared content type.
+/// Opaque (binary) payloads are accepted without inspection.
+pub fn validate_payload(
+    payload: &[u8],
+    content_type: &ContentType,
+    schema: &Schema,
+) -> Result<(), Vec<ValidationError>> {
+    match decode_payload(payload, content_type) {
+        Some(Ok(json_val)) => validate_value(&json_val, &HashMap::new(), schema),
+        Some(Err(e)) => Err(vec![e]),
+        None => Ok(()),
+    }
 }
 
 pub fn validate_event_payload(payload: &[u8], schema: &Schema) -> Result<(), Vec<ValidationError>> {
-    let json_val: Value = match serde_json::from_slice(payload) {
-        Ok(v) => v,
-        Err(e) => return Err(vec![ValidationError::InvalidJson(e)]),
-    };
+    validate_payload(payload, &ContentType::Json, schema)
+}
 
+fn validate_value(
+    json_val: &Value,
+    metadata: &HashMap<String, String>,
+    schema: &Schema,
+) -> Result<(), Vec<ValidationError>> {
     let mut errors = Vec::new();
 
     // 1. Iterate Schema Fields
@@ -99,6 +185,11 @@ pub fn validate_event_payload(payload: &[u8], schema: &Schema) -> Result<(), Vec
         }
     }
 
+    // 2. Cross-field rules
+    errors.extend(crate::domain::schema::rules::evaluate_rules(
+        json_val, metadata, schema,
+    ));
+
     if errors.is_empty() {
         Ok(())
     } else {
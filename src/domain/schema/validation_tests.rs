@@ -1,7 +1,14 @@
 #[cfg(test)]
 mod tests {
-    use crate::domain::schema::model::{Field, FieldConstraints, FieldType, PrimitiveType, Schema};
-    use crate::domain::schema::validation::{validate_event_payload, ValidationError};
+    use crate::domain::events::event::Event;
+    use crate::domain::events::event_kind::{ContentType, EventKind, EventPayload};
+    use crate::domain::schema::model::{
+        Field, FieldConstraints, FieldType, PrimitiveType, Schema, ValidationRule,
+    };
+    use crate::domain::schema::rules::{check_rules, register_rules};
+    use crate::domain::schema::validation::{
+        validate_event, validate_event_payload, validate_payload, ValidationError,
+    };
     use std::collections::HashMap;
 
     #[test]
@@ -13,6 +20,7 @@ mod tests {
                 field_type: FieldType::Primitive(PrimitiveType::String),
                 nullable: false,
                 overrides_on_null: false,
+                default_value: None,
                 constraints: Some(FieldConstraints {
                     required: true,
                     min_length: Some(1),
@@ -24,6 +32,9 @@ mod tests {
         let schema = Schema {
             name: "User".to_string(),
             fields,
+            rules: Vec::new(),
+            normalization: Default::default(),
+            project_state: false,
         };
 
         let json = serde_json::json!({
@@ -43,6 +54,7 @@ mod tests {
                 field_type: FieldType::Primitive(PrimitiveType::Number),
                 nullable: false,
                 overrides_on_null: false,
+                default_value: None,
                 constraints: Some(FieldConstraints {
                     required: true,
                     min_value: Some(18.0),
@@ -54,6 +66,9 @@ mod tests {
         let schema = Schema {
             name: "User".to_string(),
             fields,
+            rules: Vec::new(),
+            normalization: Default::default(),
+            project_state: false,
         };
 
         let json = serde_json::json!({
@@ -73,4 +88,183 @@ mod tests {
             _ => panic!("Wrong error"),
         }
     }
+
+    fn rule(name: &str, expression: &str, message: &str) -> ValidationRule {
+        ValidationRule {
+            name: name.to_string(),
+            expression: expression.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    fn order_schema() -> Schema {
+        Schema {
+            name: "Order".to_string(),
+            fields: HashMap::new(),
+            rules: vec![
+                rule(
+                    "date_order",
+                    "end_date > start_date",
+                    "end_date must be after start_date",
+                ),
+                rule(
+                    "promo_discount",
+                    "type != 'promo' || discount <= total",
+                    "promo discount cannot exceed total",
+                ),
+            ],
+            normalization: Default::default(),
+            project_state: false,
+        }
+    }
+
+    #[test]
+    fn test_validation_cbor_payload() {
+        let schema = order_schema();
+
+        let ok = serde_cbor::to_vec(&serde_json::json!({
+            "start_date": "2024-01-01",
+            "end_date": "2024-02-01",
+            "type": "order"
+        }))
+        .unwrap();
+        assert!(validate_payload(&ok, &ContentType::Cbor, &schema).is_ok());
+
+        let reversed = serde_cbor::to_vec(&serde_json::json!({
+            "start_date": "2024-02-01",
+            "end_date": "2024-01-01",
+            "type": "order"
+        }))
+        .unwrap();
+        let errs = validate_payload(&reversed, &ContentType::Cbor, &schema).unwrap_err();
+        assert!(matches!(errs[0], ValidationError::RuleViolation { .. }));
+
+        // CBOR bytes are not JSON
+        let errs = validate_payload(&ok, &ContentType::Json, &schema).unwrap_err();
+        assert!(matches!(errs[0], ValidationError::InvalidJson(_)));
+    }
+
+    #[test]
+    fn test_validation_skips_binary_payload() {
+        let schema = order_schema();
+        let payload = vec![0xde, 0xad, 0xbe, 0xef];
+
+        assert!(validate_payload(&payload, &ContentType::OctetStream, &schema).is_ok());
+        assert!(validate_payload(&payload, &ContentType::Protobuf, &schema).is_ok());
+        assert!(validate_payload(
+            &payload,
+            &ContentType::Custom("application/vnd.acme+avro".to_string()),
+            &schema
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_rules_pass() {
+        let json = serde_json::json!({
+            "start_date": "2024-01-01",
+            "end_date": "2024-02-01",
+            "type": "promo",
+            "discount": 5,
+            "total": 10.5
+        });
+        let payload = serde_json::to_vec(&json).unwrap();
+
+        assert!(validate_event_payload(&payload, &order_schema()).is_ok());
+    }
+
+    #[test]
+    fn test_rules_report_each_violation() {
+        let json = serde_json::json!({
+            "start_date": "2024-02-01",
+            "end_date": "2024-01-01",
+            "type": "promo",
+            "discount": 50,
+            "total": 10
+        });
+        let payload = serde_json::to_vec(&json).unwrap();
+
+        let errs = validate_event_payload(&payload, &order_schema()).unwrap_err();
+        let messages: Vec<String> = errs
+            .iter()
+            .map(|e| match e {
+                ValidationError::RuleViolation { message, .. } => message.clone(),
+                other => panic!("Unexpected error {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "end_date must be after start_date",
+                "promo discount cannot exceed total"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rules_see_metadata() {
+        let schema = Schema {
+            name: "Audit".to_string(),
+            fields: HashMap::new(),
+            rules: vec![rule(
+                "has_actor",
+                "'actor' in metadata && metadata.actor == payload.created_by",
+                "created_by must match the actor metadata",
+            )],
+            normalization: Default::default(),
+            project_state: false,
+        };
+        let payload = serde_json::to_vec(&serde_json::json!({ "created_by": "alice" })).unwrap();
+
+        let mut event = Event::new("audit-1", EventKind::Internal, EventPayload(payload));
+        assert!(validate_event(&event, &schema).is_err());
+
+        event
+            .metadata
+            .insert("actor".to_string(), "alice".to_string());
+        assert!(validate_event(&event, &schema).is_ok());
+    }
+
+    #[test]
+    fn test_rules_non_boolean_and_invalid() {
+        let mut schema = order_schema();
+        schema.rules = vec![rule("not_bool", "total + 1", "unused")];
+        let payload = serde_json::to_vec(&serde_json::json!({ "total": 1 })).unwrap();
+
+        let errs = validate_event_payload(&payload, &schema).unwrap_err();
+        assert!(matches!(&errs[0], ValidationError::RuleError(name, _) if name == "not_bool"));
+
+        schema.rules = vec![rule("broken", "total >", "unused")];
+        let errs = check_rules(&schema).unwrap_err();
+        assert!(matches!(&errs[0], ValidationError::RuleError(name, _) if name == "broken"));
+        assert!(check_rules(&order_schema()).is_ok());
+    }
+
+    #[test]
+    fn test_registered_rules_follow_schema_changes() {
+        let mut schema = order_schema();
+        schema.name = "Booking".to_string();
+        register_rules(&schema);
+        let payload = serde_json::to_vec(&serde_json::json!({
+            "start_date": "2024-01-01",
+            "end_date": "2024-02-01",
+            "type": "order",
+            "total": 1
+        }))
+        .unwrap();
+        assert!(validate_event_payload(&payload, &schema).is_ok());
+
+        // Rules changed without a local upsert: the stale programs are not reused
+        schema.rules = vec![rule("min_total", "total >= 10", "total too small")];
+        let errs = validate_event_payload(&payload, &schema).unwrap_err();
+        assert!(
+            matches!(&errs[0], ValidationError::RuleViolation { rule, .. } if rule == "min_total")
+        );
+
+        // A stored rule that does not compile is still reported on every append
+        schema.rules = vec![rule("broken", "total >", "unused")];
+        register_rules(&schema);
+        let errs = validate_event_payload(&payload, &schema).unwrap_err();
+        assert!(matches!(&errs[0], ValidationError::RuleError(name, _) if name == "broken"));
+    }
 }
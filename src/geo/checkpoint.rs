@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Progress of geo-replication, kept in a JSON file so that a restarted
+/// replicator resumes where it stopped.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Version up to which each stream was copied to the target.
+    #[serde(default)]
+    pub streams: BTreeMap<String, u64>,
+    /// Streams that diverged on the target, with the reason. They are no
+    /// longer copied until their entry is removed from the file.
+    #[serde(default)]
+    pub conflicts: BTreeMap<String, String>,
+}
+
+/// A checkpoint and the file it is saved to.
+pub struct CheckpointFile {
+    path: PathBuf,
+    pub checkpoint: Checkpoint,
+}
+
+impl CheckpointFile {
+    /// Reads the checkpoint at `path`, or starts an empty one.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref().to_path_buf();
+        let checkpoint = match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content)
+                .map_err(|e| format!("Invalid checkpoint {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Checkpoint::default(),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        Ok(Self { path, checkpoint })
+    }
+
+    /// Writes the checkpoint through a temporary file, so a crash leaves
+    /// either the old or the new one.
+    pub fn save(&self) -> Result<(), String> {
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let content = serde_json::to_vec_pretty(&self.checkpoint).map_err(|e| e.to_string())?;
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, content)
+            .and_then(|_| std::fs::rename(&tmp, &self.path))
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_checkpoint_survives_restarts() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("geo").join("checkpoint.json");
+        let mut file = CheckpointFile::load(&path).unwrap();
+        assert_eq!(file.checkpoint, Checkpoint::default());
+
+        file.checkpoint.streams.insert("user-1".into(), 3);
+        file.checkpoint
+            .conflicts
+            .insert("user-2".into(), "diverged".into());
+        file.save().unwrap();
+        assert_eq!(
+            CheckpointFile::load(&path).unwrap().checkpoint,
+            file.checkpoint
+        );
+    }
+}
@@ -0,0 +1,378 @@
+pub mod checkpoint;
+
+use crate::api::event_store_client::EventStoreClient;
+use crate::api::{
+    AppendEventRequest, Event as ProtoEvent, GetEventsRequest, ListStreamsRequest, ReadConsistency,
+};
+use crate::geo::checkpoint::CheckpointFile;
+use std::collections::VecDeque;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+use tonic::transport::Channel;
+
+/// Settings of the geo-replicator, from the `GEO_*` variables.
+#[derive(Clone, Debug)]
+pub struct GeoConfig {
+    /// Public API of a node of each cluster, e.g. "http://10.0.0.1:50051".
+    pub source_addr: String,
+    pub target_addr: String,
+    /// `AUTH_TOKEN` of each cluster.
+    pub source_token: Option<String>,
+    pub target_token: Option<String>,
+    /// Streams to copy. Empty copies every stream of the source, listed
+    /// again once the previous listing has been gone through.
+    pub streams: Vec<String>,
+    pub checkpoint_path: String,
+    pub poll_interval: Duration,
+    /// Events per append on the target, and most events copied of a stream
+    /// per poll.
+    pub batch_size: usize,
+    /// Most streams read per poll. The next poll goes on with the others.
+    pub streams_per_poll: usize,
+}
+
+impl GeoConfig {
+    pub fn from_env() -> Result<Self, String> {
+        let required = |name: &str| env::var(name).map_err(|_| format!("{} is undefined", name));
+        let streams: Vec<String> = env::var("GEO_STREAMS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        Ok(Self {
+            source_addr: required("GEO_SOURCE_ADDR")?,
+            target_addr: required("GEO_TARGET_ADDR")?,
+            source_token: env::var("GEO_SOURCE_TOKEN").ok(),
+            target_token: env::var("GEO_TARGET_TOKEN").ok(),
+            streams,
+            checkpoint_path: env::var("GEO_CHECKPOINT_PATH")
+                .unwrap_or_else(|_| "data/geo_checkpoint.json".to_string()),
+            poll_interval: env::var("GEO_POLL_INTERVAL_MS")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(Duration::from_secs(1)),
+            batch_size: env::var("GEO_BATCH_SIZE")
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(500)
+                .max(1),
+            streams_per_poll: env::var("GEO_STREAMS_PER_POLL")
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(100)
+                .max(1),
+        })
+    }
+}
+
+/// Position in `source` from which the target copy of a stream misses
+/// events, or a GeoConflictError when the target diverged: it holds events
+/// the source does not have at the same version, or fewer than `copied`.
+///
+/// Both copies are read from after version `base`, and `copied` counts the
+/// events copied after it.
+pub fn events_to_copy(
+    stream_id: &str,
+    source: &[ProtoEvent],
+    target: &[ProtoEvent],
+    copied: u64,
+    base: u64,
+) -> Result<usize, String> {
+    if (target.len() as u64) < copied {
+        return Err(format!(
+            "GeoConflictError: stream {} has {} events on the target but {} were copied there",
+            stream_id,
+            base + target.len() as u64,
+            base + copied
+        ));
+    }
+    if let Some(i) = source
+        .iter()
+        .zip(target)
+        .position(|(ours, theirs)| ours.id != theirs.id)
+    {
+        return Err(format!(
+            "GeoConflictError: stream {} diverged at version {}: event {} on the source, {} on the target",
+            stream_id,
+            base + i as u64 + 1,
+            source[i].id,
+            target[i].id
+        ));
+    }
+    if target.len() > source.len() {
+        return Err(format!(
+            "GeoConflictError: stream {} has {} events on the target but {} on the source",
+            stream_id,
+            base + target.len() as u64,
+            base + source.len() as u64
+        ));
+    }
+    Ok(target.len())
+}
+
+/// Copies streams from one cluster to another, asynchronously.
+///
+/// Each poll reads up to `streams_per_poll` of the configured streams (or of
+/// those the source lists) from the last copied event on, and appends at most
+/// a batch of what the target misses, verbatim and with the version it
+/// expects. Events keep their order, IDs, payloads and metadata, and a target
+/// stream written to by anyone else is detected rather than interleaved.
+/// Diverged streams are recorded in the checkpoint and left alone.
+pub struct GeoReplicator {
+    config: GeoConfig,
+    source: EventStoreClient<Channel>,
+    target: EventStoreClient<Channel>,
+    checkpoint: CheckpointFile,
+    /// Streams of the current pass not read yet.
+    pending: VecDeque<String>,
+    /// Whether a stream of the current pass had more to copy than a batch.
+    behind: bool,
+}
+
+impl GeoReplicator {
+    pub fn new(config: GeoConfig) -> Result<Self, String> {
+        let channel = |addr: &str| {
+            Channel::from_shared(addr.to_string())
+                .map(|endpoint| endpoint.connect_lazy())
+                .map_err(|e| format!("Invalid address {}: {}", addr, e))
+        };
+        Ok(Self {
+            source: EventStoreClient::new(channel(&config.source_addr)?),
+            target: EventStoreClient::new(channel(&config.target_addr)?),
+            checkpoint: CheckpointFile::load(&config.checkpoint_path)?,
+            pending: VecDeque::new(),
+            behind: false,
+            config,
+        })
+    }
+
+    /// Polls until a pass over the streams finds no stream left behind, then
+    /// waits for the poll interval.
+    pub async fn run(mut self) {
+        loop {
+            self.poll().await;
+            if self.pending.is_empty() && !std::mem::take(&mut self.behind) {
+                tokio::time::sleep(self.config.poll_interval).await;
+            }
+        }
+    }
+
+    /// Copies the new events of the next `streams_per_poll` streams, starting
+    /// a new pass over the streams when the last one is through. Returns how
+    /// many events were copied.
+    pub async fn poll(&mut self) -> usize {
+        if self.pending.is_empty() {
+            let streams = if self.config.streams.is_empty() {
+                match self.list_source_streams().await {
+                    Ok(streams) => streams,
+                    Err(e) => {
+                        tracing::warn!(error = %e, "Failed to list the streams of the source");
+                        return 0;
+                    }
+                }
+            } else {
+                self.config.streams.clone()
+            };
+            self.pending = streams.into();
+        }
+        let count = self.config.streams_per_poll.min(self.pending.len());
+        let streams: Vec<String> = self.pending.drain(..count).collect();
+        let mut copied = 0;
+        for stream_id in streams {
+            if self
+                .checkpoint
+                .checkpoint
+                .conflicts
+                .contains_key(&stream_id)
+            {
+                continue;
+            }
+            match self.copy_stream(&stream_id).await {
+                Ok(n) => {
+                    self.behind |= n == self.config.batch_size;
+                    copied += n
+                }
+                Err(e) if e.contains("GeoConflictError") => {
+                    tracing::error!(stream_id = %stream_id, error = %e, "Stream diverged on the target, no longer copied");
+                    self.checkpoint.checkpoint.conflicts.insert(stream_id, e);
+                    if let Err(e) = self.checkpoint.save() {
+                        tracing::error!(error = %e, "Failed to save the geo-replication checkpoint");
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(stream_id = %stream_id, error = %e, "Failed to copy stream")
+                }
+            }
+        }
+        copied
+    }
+
+    async fn list_source_streams(&mut self) -> Result<Vec<String>, String> {
+        let req = ListStreamsRequest { local_only: false };
+        let resp = self
+            .source
+            .list_streams(request(req, &self.config.source_token)?)
+            .await
+            .map_err(|s| s.message().to_string())?
+            .into_inner();
+        Ok(resp.stream_ids)
+    }
+
+    async fn copy_stream(&mut self, stream_id: &str) -> Result<usize, String> {
+        let copied = self
+            .checkpoint
+            .checkpoint
+            .streams
+            .get(stream_id)
+            .copied()
+            .unwrap_or(0);
+        // From the last copied event, to check that it is still on the target.
+        // A target that took a batch the checkpoint missed holds a batch more.
+        let base = copied.saturating_sub(1);
+        let limit = (copied - base) as usize + self.config.batch_size;
+        let source = read(
+            &mut self.source,
+            &self.config.source_token,
+            stream_id,
+            base,
+            limit,
+        )
+        .await?;
+        if base + source.len() as u64 == copied {
+            return Ok(0);
+        }
+        let target = read(
+            &mut self.target,
+            &self.config.target_token,
+            stream_id,
+            base,
+            limit + 1,
+        )
+        .await?;
+        let from = events_to_copy(stream_id, &source, &target, copied - base, base)?;
+
+        let mut version = base as usize + from;
+        if version as u64 > copied {
+            // Copied before the checkpoint could record it
+            self.checkpoint
+                .checkpoint
+                .streams
+                .insert(stream_id.to_string(), version as u64);
+            self.checkpoint.save()?;
+        }
+        let start = version;
+        for batch in source[from..].chunks(self.config.batch_size) {
+            // Stored as the source stored them: normalizing them again could
+            // change them, or reject them under the target's schemas
+            let req = AppendEventRequest {
+                stream_id: stream_id.to_string(),
+                events: batch.to_vec(),
+                expected_version: version as u64,
+                redirect: false,
+                verbatim: true,
+            };
+            let resp = self
+                .target
+                .append_event(request(req, &self.config.target_token)?)
+                .await
+                .map_err(|s| s.message().to_string())?
+                .into_inner();
+            if !resp.success {
+                return Err(format!(
+                    "GeoConflictError: stream {} was written on the target while being copied at version {}",
+                    stream_id, version
+                ));
+            }
+            version += batch.len();
+            self.checkpoint
+                .checkpoint
+                .streams
+                .insert(stream_id.to_string(), version as u64);
+            self.checkpoint.save()?;
+        }
+        Ok(version - start)
+    }
+}
+
+/// At most `limit` events of a stream, after version `from_version`.
+async fn read(
+    client: &mut EventStoreClient<Channel>,
+    token: &Option<String>,
+    stream_id: &str,
+    from_version: u64,
+    limit: usize,
+) -> Result<Vec<ProtoEvent>, String> {
+    let req = GetEventsRequest {
+        stream_id: stream_id.to_string(),
+        consistency: ReadConsistency::Owner as i32,
+        redirect: false,
+        consistency_token: Vec::new(),
+        max_staleness_ms: None,
+        from_version,
+    };
+    let mut stream = client
+        .get_events(request(req, token)?)
+        .await
+        .map_err(|s| s.message().to_string())?
+        .into_inner();
+    let mut events = Vec::new();
+    while events.len() < limit {
+        match stream
+            .message()
+            .await
+            .map_err(|s| s.message().to_string())?
+        {
+            Some(event) => events.push(event),
+            None => break,
+        }
+    }
+    Ok(events)
+}
+
+fn request<T>(message: T, token: &Option<String>) -> Result<tonic::Request<T>, String> {
+    let mut request = tonic::Request::new(message);
+    if let Some(token) = token {
+        let value = tonic::metadata::MetadataValue::from_str(&format!("Bearer {}", token))
+            .map_err(|e| e.to_string())?;
+        request.metadata_mut().insert("authorization", value);
+    }
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(ids: &[&str]) -> Vec<ProtoEvent> {
+        ids.iter()
+            .map(|id| ProtoEvent {
+                id: id.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_diverged_targets_are_detected() {
+        let source = events(&["a", "b", "c"]);
+        assert_eq!(events_to_copy("s", &source, &[], 0, 0), Ok(0));
+        assert_eq!(events_to_copy("s", &source, &events(&["a"]), 1, 0), Ok(1));
+        assert_eq!(events_to_copy("s", &source, &source, 3, 0), Ok(3));
+        // Read from after version 4, with the fifth event copied
+        assert_eq!(events_to_copy("s", &source, &events(&["a"]), 1, 4), Ok(1));
+
+        // Written on the target, lost from it, or longer than the source
+        for (target, copied) in [
+            (events(&["a", "x"]), 1),
+            (events(&["a"]), 2),
+            (events(&["a", "b", "c", "d"]), 3),
+        ] {
+            let err = events_to_copy("s", &source, &target, copied, 0).unwrap_err();
+            assert!(err.contains("GeoConflictError"), "{}", err);
+        }
+    }
+}
@@ -1,21 +1,131 @@
+use crate::cluster::membership::Membership;
+use crate::cluster::tls::{host_of, verify_peer_identity};
+use std::sync::Arc;
 use tonic::{service::Interceptor, Request, Status};
 
 #[derive(Clone)]
 pub struct AuthInterceptor {
-    token: String,
+    token: Option<String>,
 }
 
 impl AuthInterceptor {
     pub fn new(token: String) -> Self {
+        Self { token: Some(token) }
+    }
+
+    /// Lets every request through when `token` is None.
+    pub fn optional(token: Option<String>) -> Self {
         Self { token }
     }
 }
 
 impl Interceptor for AuthInterceptor {
     fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let Some(token) = &self.token else {
+            return Ok(request);
+        };
         match request.metadata().get("authorization") {
-            Some(t) if t == &format!("Bearer {}", self.token) => Ok(request),
+            Some(t) if t == &format!("Bearer {}", token) => Ok(request),
             _ => Err(Status::unauthenticated("Invalid or missing token")),
         }
     }
 }
+
+/// Guards the internal cluster service: checks the cluster secret and, over
+/// mutual TLS, that the caller's certificate names a cluster member.
+#[derive(Clone)]
+pub struct ClusterInterceptor {
+    secret: AuthInterceptor,
+    membership: Option<Arc<Membership>>,
+    server_name: Option<String>,
+    /// Read replicas, which are not members yet call members.
+    read_replicas: Vec<String>,
+}
+
+impl ClusterInterceptor {
+    pub fn new(cluster_secret: Option<String>) -> Self {
+        Self {
+            secret: AuthInterceptor::optional(cluster_secret),
+            membership: None,
+            server_name: None,
+            read_replicas: Vec::new(),
+        }
+    }
+
+    /// Requires a client certificate issued for the host of a current member,
+    /// or for `server_name` when all nodes share one.
+    pub fn with_peer_identity(
+        mut self,
+        membership: Arc<Membership>,
+        server_name: Option<String>,
+    ) -> Self {
+        self.membership = Some(membership);
+        self.server_name = server_name;
+        self
+    }
+
+    /// Also accepts the certificates of these read replicas.
+    pub fn with_read_replicas(mut self, read_replicas: Vec<String>) -> Self {
+        self.read_replicas = read_replicas;
+        self
+    }
+}
+
+impl Interceptor for ClusterInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let request = self.secret.call(request)?;
+        let Some(membership) = &self.membership else {
+            return Ok(request);
+        };
+
+        let certs = request
+            .peer_certs()
+            .ok_or_else(|| Status::unauthenticated("Client certificate required"))?;
+        let cert = certs
+            .first()
+            .ok_or_else(|| Status::unauthenticated("Client certificate required"))?;
+
+        let addrs: Vec<String> = match &self.server_name {
+            Some(name) => vec![name.clone()],
+            None => membership
+                .members()
+                .into_iter()
+                .map(|m| m.addr)
+                .chain(self.read_replicas.iter().cloned())
+                .collect(),
+        };
+        let names: Vec<&str> = addrs.iter().map(|a| host_of(a)).collect();
+        verify_peer_identity(cert, &names).map_err(Status::permission_denied)?;
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    fn bearer(token: &str) -> Request<()> {
+        let mut request = Request::new(());
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        request
+    }
+
+    #[test]
+    fn test_cluster_service_accepts_only_the_cluster_secret() {
+        let mut public = AuthInterceptor::optional(Some("client-token".to_string()));
+        let mut cluster = ClusterInterceptor::new(Some("cluster-secret".to_string()));
+
+        assert!(public.call(bearer("client-token")).is_ok());
+        let err = cluster.call(bearer("client-token")).unwrap_err();
+        assert_eq!(err.code(), Code::Unauthenticated);
+        let err = cluster.call(Request::new(())).unwrap_err();
+        assert_eq!(err.code(), Code::Unauthenticated);
+
+        assert!(cluster.call(bearer("cluster-secret")).is_ok());
+        assert!(public.call(bearer("cluster-secret")).is_err());
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::time::Instant;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+use crate::api::{
+    cluster_service_server::ClusterService, AppendEventResponse, Event as ProtoEvent,
+    ForwardAppendRequest, GetEventsRequest, GetStreamDigestsRequest, GetStreamDigestsResponse,
+    HandshakeRequest, HandshakeResponse, ListStreamsRequest, ListStreamsResponse,
+    ReplicaHeartbeatRequest, ReplicaHeartbeatResponse, UpsertSchemaRequest, UpsertSchemaResponse,
+};
+use crate::cluster::client::{with_deadline, Forward};
+use crate::cluster::handshake::TopologyFingerprint;
+use crate::cluster::RingVersion;
+use crate::domain::events::event::Event as DomainEvent;
+use crate::grpc::{
+    append_status, await_token, get_local_snapshot, lagging, local_events, request_deadline,
+    save_local_snapshot, schema_status, send_events, verify_streams,
+};
+use crate::pipeline::anti_entropy::AntiEntropy;
+use crate::pipeline::consistency::DEFAULT_TOKEN_WAIT;
+use crate::pipeline::EventPipeline;
+use crate::storage::snapshot::SnapshotStore;
+
+/// Internal service the nodes of a cluster call each other on. Requests are
+/// served locally: routing already happened on the calling node, except for
+/// appends a Raft follower passes on to its leader.
+pub struct ClusterGrpcService {
+    pipeline: Arc<EventPipeline>,
+    snapshot_store: Arc<dyn SnapshotStore>,
+    configured_ring: bool,
+    anti_entropy: Option<Arc<AntiEntropy>>,
+    /// Nodes whose handshake was rejected, until one succeeds.
+    rejected: Mutex<HashSet<String>>,
+}
+
+impl ClusterGrpcService {
+    pub fn new(pipeline: Arc<EventPipeline>, snapshot_store: Arc<dyn SnapshotStore>) -> Self {
+        Self {
+            pipeline,
+            snapshot_store,
+            configured_ring: false,
+            anti_entropy: None,
+            rejected: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Compares ring members in handshakes, for clusters configured with
+    /// `CLUSTER_NODES` rather than discovered through gossip.
+    pub fn with_configured_ring(mut self, configured_ring: bool) -> Self {
+        self.configured_ring = configured_ring;
+        self
+    }
+
+    /// Serves VerifyStreams, repairs included, with `anti_entropy`.
+    pub fn with_anti_entropy(mut self, anti_entropy: Arc<AntiEntropy>) -> Self {
+        self.anti_entropy = Some(anti_entropy);
+        self
+    }
+
+    fn fingerprint(&self) -> TopologyFingerprint {
+        TopologyFingerprint::new(
+            self.pipeline.identity(),
+            &self.pipeline.topology(),
+            self.configured_ring,
+        )
+    }
+
+    fn check_peer(&self, addr: &str) -> Result<(), Status> {
+        if self.rejected.lock().unwrap().contains(addr) {
+            return Err(Status::failed_precondition(format!(
+                "TopologyMismatchError: {} failed its handshake with {}",
+                addr,
+                self.pipeline.membership().self_addr()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl ClusterService for ClusterGrpcService {
+    type GetEventsStream = ReceiverStream<Result<ProtoEvent, Status>>;
+
+    async fn forward_append(
+        &self,
+        request: Request<ForwardAppendRequest>,
+    ) -> Result<Response<AppendEventResponse>, Status> {
+        let deadline = request_deadline(request.metadata());
+        let req = request.into_inner();
+        let stream_id = req.stream_id;
+        self.check_peer(&req.origin)?;
+        let forward = Forward {
+            origin: req.origin,
+            epoch: req.epoch,
+            hops: req.hops.max(1),
+        };
+
+        let mut domain_events = Vec::with_capacity(req.events.len());
+        for proto_event in req.events {
+            let mut event: DomainEvent = proto_event
+                .try_into()
+                .map_err(|e: String| Status::invalid_argument(e))?;
+            event.stream_id = stream_id.clone();
+            domain_events.push(event);
+        }
+
+        // The deadline travels with the append to the worker, so that waiting
+        // for followers does not outlive the forwarding node's call
+        let token = with_deadline(
+            deadline,
+            self.pipeline.append_event_as_owner(
+                &stream_id,
+                domain_events,
+                req.expected_version as i64,
+                Some(forward),
+                req.verbatim,
+            ),
+        )
+        .await
+        .map_err(append_status)?;
+
+        Ok(Response::new(AppendEventResponse {
+            success: token.is_some(),
+            consistency_token: token.map(|t| t.encode()).unwrap_or_default(),
+        }))
+    }
+
+    async fn get_events(
+        &self,
+        request: Request<GetEventsRequest>,
+    ) -> Result<Response<Self::GetEventsStream>, Status> {
+        let deadline = request_deadline(request.metadata());
+        let req = request.into_inner();
+        // Routed here as the Raft leader, which may have been deposed since
+        self.pipeline
+            .confirm_leadership(&req.stream_id)
+            .await
+            .map_err(append_status)?;
+        // The caller already gave up on its own copy: wait out the deadline
+        let until = deadline.unwrap_or_else(|| Instant::now() + DEFAULT_TOKEN_WAIT);
+        if !await_token(
+            &self.pipeline,
+            &req.stream_id,
+            &req.consistency_token,
+            until,
+        )
+        .await?
+        {
+            return Err(lagging(&req.stream_id));
+        }
+        let events = local_events(&self.pipeline, &req.stream_id, req.from_version).await?;
+        Ok(Response::new(send_events(events)))
+    }
+
+    async fn list_streams(
+        &self,
+        _request: Request<ListStreamsRequest>,
+    ) -> Result<Response<ListStreamsResponse>, Status> {
+        let stream_ids = self
+            .pipeline
+            .list_streams()
+            .await
+            .map_err(Status::internal)?;
+        Ok(Response::new(ListStreamsResponse { stream_ids }))
+    }
+
+    async fn save_snapshot(
+        &self,
+        request: Request<crate::api::SaveSnapshotRequest>,
+    ) -> Result<Response<crate::api::SaveSnapshotResponse>, Status> {
+        let req = request.into_inner();
+        let proto_snap = req
+            .snapshot
+            .ok_or_else(|| Status::invalid_argument("Missing snapshot"))?;
+
+        save_local_snapshot(self.snapshot_store.as_ref(), proto_snap).await?;
+        Ok(Response::new(crate::api::SaveSnapshotResponse {
+            success: true,
+        }))
+    }
+
+    async fn get_snapshot(
+        &self,
+        request: Request<crate::api::GetSnapshotRequest>,
+    ) -> Result<Response<crate::api::GetSnapshotResponse>, Status> {
+        let req = request.into_inner();
+        let resp = get_local_snapshot(self.snapshot_store.as_ref(), &req.stream_id).await?;
+        Ok(Response::new(resp))
+    }
+
+    async fn upsert_schema(
+        &self,
+        request: Request<UpsertSchemaRequest>,
+    ) -> Result<Response<UpsertSchemaResponse>, Status> {
+        let req = request.into_inner();
+        let proto_schema = req
+            .schema
+            .ok_or_else(|| Status::invalid_argument("Schema is required"))?;
+
+        self.pipeline
+            .upsert_schema(proto_schema.into(), true)
+            .await
+            .map_err(schema_status)?;
+
+        Ok(Response::new(UpsertSchemaResponse {
+            success: true,
+            message: "Schema stored".to_string(),
+        }))
+    }
+
+    async fn add_node(
+        &self,
+        request: Request<crate::api::AddNodeRequest>,
+    ) -> Result<Response<crate::api::MembershipChangeResponse>, Status> {
+        let req = request.into_inner();
+        if req.addr.is_empty() {
+            return Err(Status::invalid_argument("addr is required"));
+        }
+
+        let epoch = self
+            .pipeline
+            .add_node(&req.addr, req.weight.max(1), !req.propagate)
+            .await;
+        Ok(Response::new(crate::api::MembershipChangeResponse {
+            epoch,
+        }))
+    }
+
+    async fn remove_node(
+        &self,
+        request: Request<crate::api::RemoveNodeRequest>,
+    ) -> Result<Response<crate::api::MembershipChangeResponse>, Status> {
+        let req = request.into_inner();
+        let epoch = self
+            .pipeline
+            .remove_node(&req.addr, !req.propagate)
+            .await
+            .map_err(Status::not_found)?;
+        Ok(Response::new(crate::api::MembershipChangeResponse {
+            epoch,
+        }))
+    }
+
+    async fn complete_handoff(
+        &self,
+        request: Request<crate::api::CompleteHandoffRequest>,
+    ) -> Result<Response<crate::api::CompleteHandoffResponse>, Status> {
+        let req = request.into_inner();
+        self.check_peer(&req.from_addr)?;
+        let heads = req
+            .heads
+            .into_iter()
+            .map(|h| (h.stream_id, h.version))
+            .collect();
+        let accepted = self
+            .pipeline
+            .complete_handoff(&req.from_addr, req.epoch, heads);
+        Ok(Response::new(crate::api::CompleteHandoffResponse {
+            accepted,
+        }))
+    }
+
+    async fn replicate_events(
+        &self,
+        request: Request<crate::api::ReplicateEventsRequest>,
+    ) -> Result<Response<crate::api::ReplicateEventsResponse>, Status> {
+        let req = request.into_inner();
+        self.check_peer(&req.leader_addr)?;
+        let mut events = Vec::with_capacity(req.events.len());
+        for replicated in req.events {
+            let proto_event = replicated
+                .event
+                .ok_or_else(|| Status::invalid_argument("replicated event is missing"))?;
+            let mut event: DomainEvent = proto_event
+                .try_into()
+                .map_err(|e: String| Status::invalid_argument(e))?;
+            event.sequence_number = replicated.version;
+            events.push(event);
+        }
+
+        let head = self
+            .pipeline
+            .apply_replicated(&req.stream_id, &req.leader_addr, req.epoch, events)
+            .await
+            .map_err(|e| {
+                if e.contains("NotOwnerError") || e.contains("StaleEpochError") {
+                    Status::failed_precondition(e)
+                } else {
+                    Status::internal(e)
+                }
+            })?;
+        Ok(Response::new(crate::api::ReplicateEventsResponse { head }))
+    }
+
+    async fn raft_message(
+        &self,
+        request: Request<crate::api::RaftMessageRequest>,
+    ) -> Result<Response<crate::api::RaftMessageResponse>, Status> {
+        let req = request.into_inner();
+        self.pipeline
+            .deliver_raft_message(&req.group, &req.payload)
+            .map_err(|e| {
+                if e.contains("RaftError") {
+                    Status::failed_precondition(e)
+                } else {
+                    Status::invalid_argument(e)
+                }
+            })?;
+        Ok(Response::new(crate::api::RaftMessageResponse {}))
+    }
+
+    async fn handshake(
+        &self,
+        request: Request<HandshakeRequest>,
+    ) -> Result<Response<HandshakeResponse>, Status> {
+        let peer: TopologyFingerprint = request
+            .into_inner()
+            .topology
+            .ok_or_else(|| Status::invalid_argument("topology is required"))?
+            .into();
+        let local = self.fingerprint();
+
+        let reason = match local.check(&peer) {
+            Ok(()) => {
+                self.rejected.lock().unwrap().remove(&peer.addr);
+                String::new()
+            }
+            Err(e) => {
+                tracing::warn!(peer = %peer.addr, error = %e, "Rejected handshake");
+                self.rejected.lock().unwrap().insert(peer.addr.clone());
+                e
+            }
+        };
+        Ok(Response::new(HandshakeResponse {
+            accepted: reason.is_empty(),
+            reason,
+            topology: Some(local.into()),
+        }))
+    }
+
+    async fn replica_heartbeat(
+        &self,
+        request: Request<ReplicaHeartbeatRequest>,
+    ) -> Result<Response<ReplicaHeartbeatResponse>, Status> {
+        let req = request.into_inner();
+        self.check_peer(&req.member_addr)?;
+        let members = req.members.into_iter().map(Into::into).collect();
+        self.pipeline
+            .record_heartbeat(
+                &req.member_addr,
+                RingVersion {
+                    epoch: req.epoch,
+                    digest: req.ring_digest,
+                },
+                members,
+            )
+            .map_err(Status::failed_precondition)?;
+        Ok(Response::new(ReplicaHeartbeatResponse {}))
+    }
+
+    async fn get_stream_digests(
+        &self,
+        request: Request<GetStreamDigestsRequest>,
+    ) -> Result<Response<GetStreamDigestsResponse>, Status> {
+        let req = request.into_inner();
+        let digests = self
+            .pipeline
+            .stream_digests(&req.stream_ids)
+            .await
+            .map_err(Status::internal)?;
+        Ok(Response::new(GetStreamDigestsResponse {
+            digests: digests.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn verify_streams(
+        &self,
+        request: Request<crate::api::VerifyStreamsRequest>,
+    ) -> Result<Response<crate::api::VerifyStreamsResponse>, Status> {
+        verify_streams(self.anti_entropy.as_deref(), request.into_inner()).await
+    }
+}
@@ -1,24 +1,283 @@
+use prost::Message;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio::time::Instant;
 use tokio_stream::wrappers::ReceiverStream;
-use tonic::{Request, Response, Status};
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Request, Response, Status};
 
 use crate::api::{
     event_store_server::EventStore, AppendEventRequest, AppendEventResponse, Event as ProtoEvent,
-    GetEventsRequest, GetSchemaRequest, GetSchemaResponse, UpsertSchemaRequest,
-    UpsertSchemaResponse,
+    GetEventsRequest, GetSchemaRequest, GetSchemaResponse, ListStreamsRequest, ListStreamsResponse,
+    UpsertSchemaRequest, UpsertSchemaResponse,
 };
+use crate::cluster::client::with_deadline;
+use crate::cluster::membership::Membership;
 use crate::domain::events::event::Event as DomainEvent;
-use crate::pipeline::EventPipeline;
+use crate::pipeline::anti_entropy::{AntiEntropy, DifferenceKind, StreamDifference};
+use crate::pipeline::consistency::{ConsistencyToken, DEFAULT_TOKEN_WAIT};
+use crate::pipeline::{EventPipeline, ReadConsistency};
+use crate::storage::hybrid::HybridEventStore;
+use crate::storage::snapshot::SnapshotStore;
 
 pub mod auth;
+pub mod cluster;
 
 pub struct GrpcService {
     pipeline: Arc<EventPipeline>,
     snapshot_store: Arc<dyn crate::storage::snapshot::SnapshotStore>,
+    anti_entropy: Option<Arc<AntiEntropy>>,
+    hybrid: Option<Arc<HybridEventStore>>,
+}
+
+fn read_consistency(consistency: crate::api::ReadConsistency) -> ReadConsistency {
+    match consistency {
+        crate::api::ReadConsistency::Owner => ReadConsistency::Owner,
+        crate::api::ReadConsistency::Local => ReadConsistency::Local,
+    }
+}
+
+fn append_status(e: String) -> Status {
+    if e.contains("NotOwnerError")
+        || e.contains("StaleEpochError")
+        || e.contains("TopologyMismatchError")
+        || e.contains("ReadOnlyError")
+    {
+        Status::failed_precondition(e)
+    } else if e.contains("UnderReplicatedError") {
+        // Persisted on the owner: retrying would write the events again
+        Status::aborted(e)
+    } else if e.contains("DeadlineExceededError") {
+        Status::deadline_exceeded(e)
+    } else if e.contains("PeerUnavailableError")
+        || e.contains("HandoffPendingError")
+        || e.contains("LeaseHeldError")
+        || e.contains("LeaseExpiredError")
+        || e.contains("LeaseStoreError")
+        || e.contains("NotLeaderError")
+        || e.contains("RaftError")
+    {
+        Status::unavailable(e)
+    } else {
+        Status::internal(e)
+    }
+}
+
+/// FAILED_PRECONDITION carrying an OwnerRedirect in its details and the
+/// owner's address in the "owner-addr" metadata.
+fn owner_redirect(stream_id: &str, owner_addr: String, epoch: u64, message: String) -> Status {
+    let mut metadata = MetadataMap::new();
+    if let Ok(value) = owner_addr.parse() {
+        metadata.insert("owner-addr", value);
+    }
+    let redirect = crate::api::OwnerRedirect {
+        stream_id: stream_id.to_string(),
+        owner_addr,
+        epoch,
+    };
+    Status::with_details_and_metadata(
+        Code::FailedPrecondition,
+        message,
+        redirect.encode_to_vec().into(),
+        metadata,
+    )
+}
+
+fn topology_message(membership: &Membership) -> crate::api::ClusterTopology {
+    let topology = membership.topology();
+    crate::api::ClusterTopology {
+        epoch: topology.epoch(),
+        partitioner_version: topology.partitioner().version(),
+        vnodes: topology.vnodes(),
+        members: membership.members().into_iter().map(Into::into).collect(),
+        ranges: topology
+            .token_ranges()
+            .into_iter()
+            .map(|r| crate::api::TokenRange {
+                start: r.start,
+                end: r.end,
+                node_addr: r.node_addr,
+            })
+            .collect(),
+        self_addr: membership.self_addr().to_string(),
+    }
+}
+
+/// Status of a call forwarded to another node that failed.
+fn peer_status(e: String) -> Status {
+    if e.contains("DeadlineExceededError") {
+        Status::deadline_exceeded(e)
+    } else {
+        Status::unavailable(e)
+    }
+}
+
+/// Deadline set by the client through the "grpc-timeout" header, which
+/// bounds the calls made to other nodes to serve the request.
+fn request_deadline(metadata: &MetadataMap) -> Option<Instant> {
+    let value = metadata.get("grpc-timeout")?.to_str().ok()?;
+    if value.len() < 2 {
+        return None;
+    }
+    let (amount, unit) = value.split_at(value.len() - 1);
+    let amount: u64 = amount.parse().ok()?;
+    let timeout = match unit {
+        "H" => Duration::from_secs(amount.saturating_mul(3600)),
+        "M" => Duration::from_secs(amount.saturating_mul(60)),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return None,
+    };
+    Instant::now().checked_add(timeout)
+}
+
+fn schema_status(e: String) -> Status {
+    if e.contains("InvalidSchema") {
+        Status::invalid_argument(e)
+    } else if e.contains("ReadOnlyError") {
+        Status::failed_precondition(e)
+    } else {
+        Status::internal(e)
+    }
+}
+
+/// Until when a read carrying a consistency token waits for the local copy
+/// of its stream, leaving time to hand it to the owner afterwards.
+fn token_wait(deadline: Option<Instant>) -> Instant {
+    let now = Instant::now();
+    let wait = match deadline {
+        Some(deadline) => (deadline.saturating_duration_since(now) / 2).min(DEFAULT_TOKEN_WAIT),
+        None => DEFAULT_TOKEN_WAIT,
+    };
+    now + wait
+}
+
+/// Waits until the local copy of a stream holds the write of a consistency
+/// token, if the request carries one. Returns whether it does.
+async fn await_token(
+    pipeline: &EventPipeline,
+    stream_id: &str,
+    token: &[u8],
+    until: Instant,
+) -> Result<bool, Status> {
+    match ConsistencyToken::decode(token).map_err(Status::invalid_argument)? {
+        Some(token) => pipeline
+            .wait_for_token(stream_id, &token, until)
+            .await
+            .map_err(append_status),
+        None => Ok(true),
+    }
+}
+
+/// Reports the lag of a read replica in the "replica-lag-ms" metadata of a
+/// read it served from its own copy.
+fn report_lag<T>(pipeline: &EventPipeline, mut response: Response<T>) -> Response<T> {
+    if let Some(lag) = pipeline.replica_lag() {
+        response
+            .metadata_mut()
+            .insert("replica-lag-ms", (lag.as_millis() as u64).into());
+    }
+    response
+}
+
+fn lagging(stream_id: &str) -> Status {
+    Status::unavailable(format!(
+        "ConsistencyTimeoutError: stream {} has not caught up with the requested write",
+        stream_id
+    ))
+}
+
+async fn local_events(
+    pipeline: &EventPipeline,
+    stream_id: &str,
+    from_version: u64,
+) -> Result<Vec<ProtoEvent>, Status> {
+    let events = pipeline
+        .fetch_stream_from(stream_id, from_version)
+        .await
+        .map_err(Status::internal)?;
+    Ok(events.into_iter().map(ProtoEvent::from).collect())
+}
+
+fn send_events(events: Vec<ProtoEvent>) -> ReceiverStream<Result<ProtoEvent, Status>> {
+    let (tx, rx) = mpsc::channel(128);
+
+    // Spawn sender
+    tokio::spawn(async move {
+        for proto_event in events {
+            if (tx.send(Ok(proto_event)).await).is_err() {
+                break; // Receiver closed
+            }
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+async fn save_local_snapshot(
+    store: &dyn SnapshotStore,
+    proto_snap: crate::api::Snapshot,
+) -> Result<(), Status> {
+    let snapshot = crate::storage::snapshot::Snapshot {
+        stream_id: proto_snap.stream_id,
+        version: proto_snap.version,
+        payload: proto_snap.payload,
+        timestamp: proto_snap.timestamp,
+    };
+
+    store
+        .save_snapshot(snapshot)
+        .await
+        .map_err(|e| Status::internal(e.to_string()))
+}
+
+async fn get_local_snapshot(
+    store: &dyn SnapshotStore,
+    stream_id: &str,
+) -> Result<crate::api::GetSnapshotResponse, Status> {
+    let snap_opt = store
+        .get_snapshot(stream_id)
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?;
+
+    Ok(match snap_opt {
+        Some(s) => crate::api::GetSnapshotResponse {
+            snapshot: Some(crate::api::Snapshot {
+                stream_id: s.stream_id,
+                version: s.version,
+                payload: s.payload,
+                timestamp: s.timestamp,
+            }),
+            found: true,
+        },
+        None => crate::api::GetSnapshotResponse {
+            snapshot: None,
+            found: false,
+        },
+    })
 }
 
 impl GrpcService {
+    /// Refuses a request for a stream owned by `owner` with an OwnerRedirect.
+    fn redirect(&self, stream_id: &str, owner: String) -> Status {
+        let message = format!("NotOwnerError: stream {} is owned by {}", stream_id, owner);
+        owner_redirect(stream_id, owner, self.pipeline.topology().epoch(), message)
+    }
+
+    /// Maps an append error, redirecting NotOwnerError (or ReadOnlyError on a
+    /// read replica) to the current owner.
+    async fn append_error(&self, stream_id: &str, e: String) -> Status {
+        if e.contains("NotOwnerError") || e.contains("ReadOnlyError") {
+            if let Ok(Some(owner)) = self.pipeline.route(stream_id, ReadConsistency::Owner).await {
+                return owner_redirect(stream_id, owner, self.pipeline.topology().epoch(), e);
+            }
+        }
+        append_status(e)
+    }
+
     pub fn new(
         pipeline: Arc<EventPipeline>,
         snapshot_store: Arc<dyn crate::storage::snapshot::SnapshotStore>,
@@ -26,19 +285,36 @@ impl GrpcService {
         Self {
             pipeline,
             snapshot_store,
+            anti_entropy: None,
+            hybrid: None,
         }
     }
+
+    /// Serves VerifyStreams with `anti_entropy`.
+    pub fn with_anti_entropy(mut self, anti_entropy: Arc<AntiEntropy>) -> Self {
+        self.anti_entropy = Some(anti_entropy);
+        self
+    }
+
+    /// Reports the mode of `hybrid` in GetStorageStatus. Without it, the
+    /// node has a single store, always in primary mode.
+    pub fn with_hybrid_store(mut self, hybrid: Arc<HybridEventStore>) -> Self {
+        self.hybrid = Some(hybrid);
+        self
+    }
 }
 
 #[tonic::async_trait]
 impl EventStore for GrpcService {
     // Defines the stream generic for GetEvents for clarity
     type GetEventsStream = ReceiverStream<Result<ProtoEvent, Status>>;
+    type WatchClusterTopologyStream = ReceiverStream<Result<crate::api::ClusterTopology, Status>>;
 
     async fn append_event(
         &self,
         request: Request<AppendEventRequest>,
     ) -> Result<Response<AppendEventResponse>, Status> {
+        let deadline = request_deadline(request.metadata());
         let req = request.into_inner();
         let stream_id = req.stream_id;
         let expected_version = req.expected_version as i64; // Be careful with conversion logic, see SDK notes
@@ -55,61 +331,129 @@ impl EventStore for GrpcService {
             domain_events.push(event);
         }
 
-        let is_forwarded = req.is_forwarded;
-
-        let result = if is_forwarded {
-            self.pipeline
-                .append_event_as_owner(&stream_id, domain_events, expected_version)
+        if req.redirect {
+            if let Some(owner) = self
+                .pipeline
+                .route(&stream_id, ReadConsistency::Owner)
                 .await
-        } else {
-            self.pipeline
-                .append_event(&stream_id, domain_events, expected_version)
-                .await
-        };
+                .map_err(append_status)?
+            {
+                return Err(self.redirect(&stream_id, owner));
+            }
+        }
 
-        let success = result.map_err(|e| {
-            if e.contains("NotOwnerError") {
-                Status::failed_precondition(e)
+        let append = async {
+            if req.verbatim {
+                self.pipeline
+                    .copy_events(&stream_id, domain_events, expected_version)
+                    .await
             } else {
-                Status::internal(e)
+                self.pipeline
+                    .append_event(&stream_id, domain_events, expected_version)
+                    .await
             }
-        })?;
+        };
+        let token = match with_deadline(deadline, append).await {
+            Ok(token) => token,
+            Err(e) => return Err(self.append_error(&stream_id, e).await),
+        };
 
-        Ok(Response::new(AppendEventResponse { success }))
+        Ok(Response::new(AppendEventResponse {
+            success: token.is_some(),
+            consistency_token: token.map(|t| t.encode()).unwrap_or_default(),
+        }))
     }
 
     async fn get_events(
         &self,
         request: Request<GetEventsRequest>,
     ) -> Result<Response<Self::GetEventsStream>, Status> {
+        let deadline = request_deadline(request.metadata());
         let req = request.into_inner();
-        let stream_id = req.stream_id;
+        let stream_id = req.stream_id.clone();
 
-        let events = self
+        let owner = self
             .pipeline
-            .fetch_stream(&stream_id)
+            .route_read(
+                &stream_id,
+                read_consistency(req.consistency()),
+                req.max_staleness_ms.map(Duration::from_millis),
+            )
             .await
-            .map_err(Status::internal)?;
-
-        let (tx, rx) = mpsc::channel(128);
-
-        // Spawn sender
-        tokio::spawn(async move {
-            for event in events {
-                let proto_event: ProtoEvent = event.into();
-                if (tx.send(Ok(proto_event)).await).is_err() {
-                    break; // Receiver closed
+            .map_err(append_status)?;
+        if let (true, Some(owner)) = (req.redirect, &owner) {
+            return Err(self.redirect(&stream_id, owner.clone()));
+        }
+        let token = req.consistency_token;
+        let owner = match owner {
+            Some(owner) => Some(owner),
+            None => {
+                let until = token_wait(deadline);
+                if await_token(&self.pipeline, &stream_id, &token, until).await? {
+                    None
+                } else {
+                    // This copy lags behind the write, which its owner has
+                    let owner = self
+                        .pipeline
+                        .route(&stream_id, ReadConsistency::Owner)
+                        .await
+                        .map_err(append_status)?
+                        .ok_or_else(|| lagging(&stream_id))?;
+                    if req.redirect {
+                        return Err(self.redirect(&stream_id, owner));
+                    }
+                    Some(owner)
                 }
             }
-        });
+        };
+        match owner {
+            Some(owner) => {
+                let events = with_deadline(
+                    deadline,
+                    self.pipeline.cluster_client().fetch_stream_from(
+                        &owner,
+                        &stream_id,
+                        req.from_version,
+                        token,
+                    ),
+                )
+                .await
+                .map_err(peer_status)?;
+                Ok(Response::new(send_events(events)))
+            }
+            None => {
+                let events = local_events(&self.pipeline, &stream_id, req.from_version).await?;
+                Ok(report_lag(
+                    &self.pipeline,
+                    Response::new(send_events(events)),
+                ))
+            }
+        }
+    }
 
-        Ok(Response::new(ReceiverStream::new(rx)))
+    async fn list_streams(
+        &self,
+        request: Request<ListStreamsRequest>,
+    ) -> Result<Response<ListStreamsResponse>, Status> {
+        let deadline = request_deadline(request.metadata());
+        let stream_ids = if request.into_inner().local_only {
+            self.pipeline
+                .list_streams()
+                .await
+                .map_err(Status::internal)?
+        } else {
+            with_deadline(deadline, self.pipeline.list_cluster_streams())
+                .await
+                .map_err(peer_status)?
+        };
+        Ok(Response::new(ListStreamsResponse { stream_ids }))
     }
 
     async fn upsert_schema(
         &self,
         request: Request<UpsertSchemaRequest>,
     ) -> Result<Response<UpsertSchemaResponse>, Status> {
+        let deadline = request_deadline(request.metadata());
         let req = request.into_inner();
         let proto_schema = req
             .schema
@@ -117,14 +461,21 @@ impl EventStore for GrpcService {
 
         let schema: crate::domain::schema::model::Schema = proto_schema.into();
 
-        self.pipeline
-            .upsert_schema(schema)
+        let unreached = with_deadline(deadline, self.pipeline.upsert_schema(schema, false))
             .await
-            .map_err(Status::internal)?;
+            .map_err(schema_status)?;
 
+        let message = if unreached.is_empty() {
+            "Schema upserted".to_string()
+        } else {
+            format!(
+                "Schema upserted, but not propagated to {}",
+                unreached.join(", ")
+            )
+        };
         Ok(Response::new(UpsertSchemaResponse {
             success: true,
-            message: "Schema upserted".to_string(),
+            message,
         }))
     }
 
@@ -156,61 +507,434 @@ impl EventStore for GrpcService {
         }
     }
 
+    async fn validate_events(
+        &self,
+        request: Request<crate::api::ValidateEventsRequest>,
+    ) -> Result<Response<crate::api::ValidateEventsResponse>, Status> {
+        let req = request.into_inner();
+
+        let mut domain_events = Vec::new();
+        for proto_event in req.events {
+            let event: DomainEvent = proto_event
+                .try_into()
+                .map_err(|e: String| Status::invalid_argument(e))?;
+            domain_events.push(event);
+        }
+        let inline_schema = req.schema.map(crate::domain::schema::model::Schema::from);
+
+        let checked = self
+            .pipeline
+            .validate_events(&req.stream_id, domain_events, inline_schema)
+            .await
+            .map_err(|e| {
+                if e.contains("InvalidSchema") {
+                    Status::invalid_argument(e)
+                } else {
+                    Status::internal(e)
+                }
+            })?;
+
+        let results: Vec<crate::api::EventValidationResult> = checked
+            .into_iter()
+            .enumerate()
+            .map(
+                |(index, (event, check))| crate::api::EventValidationResult {
+                    index: index as u32,
+                    event_id: event.id.0.to_string(),
+                    event_type: check.event_type,
+                    valid: check.errors.is_empty(),
+                    schema_found: check.schema_found,
+                    normalized: check.normalized,
+                    normalized_payload: if check.normalized {
+                        event.payload.0
+                    } else {
+                        Vec::new()
+                    },
+                    issues: check
+                        .errors
+                        .iter()
+                        .map(|e| crate::api::ValidationIssue {
+                            code: e.code().to_string(),
+                            subject: e.subject().unwrap_or_default().to_string(),
+                            message: e.to_string(),
+                        })
+                        .collect(),
+                },
+            )
+            .collect();
+
+        Ok(Response::new(crate::api::ValidateEventsResponse {
+            valid: results.iter().all(|r| r.valid),
+            results,
+        }))
+    }
+
     async fn save_snapshot(
         &self,
         request: Request<crate::api::SaveSnapshotRequest>,
     ) -> Result<Response<crate::api::SaveSnapshotResponse>, Status> {
+        let deadline = request_deadline(request.metadata());
         let req = request.into_inner();
         let proto_snap = req
             .snapshot
             .ok_or_else(|| Status::invalid_argument("Missing snapshot"))?;
+        self.pipeline
+            .check_writable()
+            .map_err(Status::failed_precondition)?;
 
-        let snapshot = crate::storage::snapshot::Snapshot {
-            stream_id: proto_snap.stream_id,
-            version: proto_snap.version,
-            payload: proto_snap.payload,
-            timestamp: proto_snap.timestamp,
-        };
-
-        self.snapshot_store
-            .save_snapshot(snapshot)
+        let owner = self
+            .pipeline
+            .route(&proto_snap.stream_id, ReadConsistency::Owner)
             .await
-            .map_err(|e| Status::internal(e.to_string()))?;
+            .map_err(append_status)?;
+        if let (true, Some(owner)) = (req.redirect, &owner) {
+            return Err(self.redirect(&proto_snap.stream_id, owner.clone()));
+        }
+        let success = match owner {
+            Some(owner) => with_deadline(
+                deadline,
+                self.pipeline
+                    .cluster_client()
+                    .save_snapshot(&owner, proto_snap),
+            )
+            .await
+            .map_err(peer_status)?,
+            None => {
+                save_local_snapshot(self.snapshot_store.as_ref(), proto_snap).await?;
+                true
+            }
+        };
 
-        Ok(Response::new(crate::api::SaveSnapshotResponse {
-            success: true,
-        }))
+        Ok(Response::new(crate::api::SaveSnapshotResponse { success }))
     }
 
     async fn get_snapshot(
         &self,
         request: Request<crate::api::GetSnapshotRequest>,
     ) -> Result<Response<crate::api::GetSnapshotResponse>, Status> {
+        let deadline = request_deadline(request.metadata());
         let req = request.into_inner();
 
-        let snap_opt = self
-            .snapshot_store
-            .get_snapshot(&req.stream_id)
+        let owner = self
+            .pipeline
+            .route(&req.stream_id, read_consistency(req.consistency()))
             .await
-            .map_err(|e| Status::internal(e.to_string()))?;
+            .map_err(append_status)?;
+        if let (true, Some(owner)) = (req.redirect, &owner) {
+            return Err(self.redirect(&req.stream_id, owner.clone()));
+        }
+        let resp = match owner {
+            Some(owner) => with_deadline(
+                deadline,
+                self.pipeline
+                    .cluster_client()
+                    .get_snapshot(&owner, &req.stream_id),
+            )
+            .await
+            .map_err(peer_status)?,
+            None => get_local_snapshot(self.snapshot_store.as_ref(), &req.stream_id).await?,
+        };
 
-        match snap_opt {
-            Some(s) => {
-                let proto_snap = crate::api::Snapshot {
-                    stream_id: s.stream_id,
-                    version: s.version,
-                    payload: s.payload,
-                    timestamp: s.timestamp,
-                };
-                Ok(Response::new(crate::api::GetSnapshotResponse {
-                    snapshot: Some(proto_snap),
-                    found: true,
-                }))
+        Ok(Response::new(resp))
+    }
+
+    async fn get_state(
+        &self,
+        request: Request<crate::api::GetStateRequest>,
+    ) -> Result<Response<crate::api::GetStateResponse>, Status> {
+        let deadline = request_deadline(request.metadata());
+        let req = request.into_inner();
+
+        // State is projected locally: a lagging node can only redirect
+        let until = deadline.unwrap_or_else(|| Instant::now() + DEFAULT_TOKEN_WAIT);
+        let token = &req.consistency_token;
+        if !await_token(&self.pipeline, &req.stream_id, token, until).await? {
+            return Err(
+                match self
+                    .pipeline
+                    .route(&req.stream_id, ReadConsistency::Owner)
+                    .await
+                    .map_err(append_status)?
+                {
+                    Some(owner) => self.redirect(&req.stream_id, owner),
+                    None => lagging(&req.stream_id),
+                },
+            );
+        }
+        let max_staleness = req.max_staleness_ms.map(Duration::from_millis);
+        if let (Some(lag), Some(max)) = (self.pipeline.replica_lag(), max_staleness) {
+            if lag > max {
+                if let Some(owner) = self
+                    .pipeline
+                    .route(&req.stream_id, ReadConsistency::Owner)
+                    .await
+                    .map_err(append_status)?
+                {
+                    return Err(self.redirect(&req.stream_id, owner));
+                }
             }
-            None => Ok(Response::new(crate::api::GetSnapshotResponse {
-                snapshot: None,
+        }
+
+        let state_opt = self
+            .pipeline
+            .get_state(&req.stream_id, req.at_version)
+            .await
+            .map_err(Status::internal)?;
+
+        let resp = match state_opt {
+            Some(s) => crate::api::GetStateResponse {
+                found: true,
+                version: s.version,
+                state: s.document,
+                timestamp: s.timestamp,
+            },
+            None => crate::api::GetStateResponse {
                 found: false,
-            })),
+                version: 0,
+                state: Vec::new(),
+                timestamp: 0,
+            },
+        };
+        Ok(report_lag(&self.pipeline, Response::new(resp)))
+    }
+
+    async fn get_token_ranges(
+        &self,
+        request: Request<crate::api::GetTokenRangesRequest>,
+    ) -> Result<Response<crate::api::GetTokenRangesResponse>, Status> {
+        let req = request.into_inner();
+        let topology = self.pipeline.topology();
+
+        let ranges = if req.node_addr.is_empty() {
+            topology.token_ranges()
+        } else {
+            topology.ranges_for(&req.node_addr)
+        };
+
+        Ok(Response::new(crate::api::GetTokenRangesResponse {
+            epoch: topology.epoch(),
+            vnodes: topology.vnodes(),
+            partitioner_version: topology.partitioner().version(),
+            ranges: ranges
+                .into_iter()
+                .map(|r| crate::api::TokenRange {
+                    start: r.start,
+                    end: r.end,
+                    node_addr: r.node_addr,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn get_cluster_members(
+        &self,
+        _request: Request<crate::api::GetClusterMembersRequest>,
+    ) -> Result<Response<crate::api::GetClusterMembersResponse>, Status> {
+        let membership = self.pipeline.membership();
+        let members = membership.members().into_iter().map(Into::into).collect();
+
+        Ok(Response::new(crate::api::GetClusterMembersResponse {
+            members,
+            epoch: membership.epoch(),
+            self_addr: membership.self_addr().to_string(),
+        }))
+    }
+
+    async fn get_cluster_topology(
+        &self,
+        _request: Request<crate::api::GetClusterTopologyRequest>,
+    ) -> Result<Response<crate::api::ClusterTopology>, Status> {
+        Ok(Response::new(topology_message(self.pipeline.membership())))
+    }
+
+    async fn watch_cluster_topology(
+        &self,
+        _request: Request<crate::api::GetClusterTopologyRequest>,
+    ) -> Result<Response<Self::WatchClusterTopologyStream>, Status> {
+        let membership = self.pipeline.membership().clone();
+        let mut changes = membership.subscribe();
+        let (tx, rx) = mpsc::channel(16);
+
+        tokio::spawn(async move {
+            loop {
+                if tx.send(Ok(topology_message(&membership))).await.is_err() {
+                    break; // Receiver closed
+                }
+                if changes.changed().await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn verify_streams(
+        &self,
+        request: Request<crate::api::VerifyStreamsRequest>,
+    ) -> Result<Response<crate::api::VerifyStreamsResponse>, Status> {
+        let req = request.into_inner();
+        if req.repair {
+            return Err(Status::permission_denied(
+                "Repairs are only served on the cluster service",
+            ));
+        }
+        verify_streams(self.anti_entropy.as_deref(), req).await
+    }
+
+    async fn get_storage_status(
+        &self,
+        _request: Request<crate::api::GetStorageStatusRequest>,
+    ) -> Result<Response<crate::api::GetStorageStatusResponse>, Status> {
+        let Some(hybrid) = &self.hybrid else {
+            return Ok(Response::new(crate::api::GetStorageStatusResponse {
+                mode: crate::api::StorageMode::Primary as i32,
+                pending_writes: 0,
+                conflicted_streams: Vec::new(),
+            }));
+        };
+        let status = hybrid
+            .status()
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(crate::api::GetStorageStatusResponse {
+            mode: crate::api::StorageMode::from(status.mode) as i32,
+            pending_writes: status.pending_writes,
+            conflicted_streams: status.conflicted_streams,
+        }))
+    }
+}
+
+/// Runs VerifyStreams with `anti_entropy`, when the node has one.
+async fn verify_streams(
+    anti_entropy: Option<&AntiEntropy>,
+    req: crate::api::VerifyStreamsRequest,
+) -> Result<Response<crate::api::VerifyStreamsResponse>, Status> {
+    let anti_entropy = anti_entropy
+        .ok_or_else(|| Status::unimplemented("Anti-entropy is not available on this node"))?;
+    let report = anti_entropy
+        .verify(req.stream_ids, req.repair)
+        .await
+        .map_err(Status::internal)?;
+    Ok(Response::new(crate::api::VerifyStreamsResponse {
+        streams_checked: report.streams_checked,
+        differences: report
+            .differences
+            .into_iter()
+            .map(difference_message)
+            .collect(),
+    }))
+}
+
+fn difference_message(d: StreamDifference) -> crate::api::StreamDifference {
+    use crate::api::stream_difference::Kind;
+
+    let kind = match d.kind {
+        DifferenceKind::Lagging => Kind::Lagging,
+        DifferenceKind::Diverged => Kind::Diverged,
+    };
+    crate::api::StreamDifference {
+        stream_id: d.stream_id,
+        kind: kind as i32,
+        ahead: d.ahead,
+        behind: d.behind,
+        ahead_digest: Some(d.ahead_digest.into()),
+        behind_digest: Some(d.behind_digest.into()),
+        repaired: d.repaired,
+        error: d.error.unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_owner_redirect_carries_the_owner() {
+        let status = owner_redirect(
+            "user-1",
+            "10.0.0.2:50051".to_string(),
+            7,
+            "NotOwnerError: moved".to_string(),
+        );
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(
+            status.metadata().get("owner-addr").unwrap(),
+            "10.0.0.2:50051"
+        );
+
+        let redirect = crate::api::OwnerRedirect::decode(status.details()).unwrap();
+        assert_eq!(redirect.stream_id, "user-1");
+        assert_eq!(redirect.owner_addr, "10.0.0.2:50051");
+        assert_eq!(redirect.epoch, 7);
+    }
+
+    #[test]
+    fn test_request_deadline_from_grpc_timeout() {
+        let mut metadata = MetadataMap::new();
+        assert!(request_deadline(&metadata).is_none());
+
+        metadata.insert("grpc-timeout", "250m".parse().unwrap());
+        let remaining = request_deadline(&metadata).unwrap() - Instant::now();
+        assert!(remaining <= Duration::from_millis(250));
+        assert!(remaining > Duration::from_millis(200));
+
+        metadata.insert("grpc-timeout", "2X".parse().unwrap());
+        assert!(request_deadline(&metadata).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_public_append_ignores_the_former_forwarding_fields() {
+        use crate::cluster::client::ClusterClient;
+        use crate::cluster::identity::NodeIdentity;
+        use crate::cluster::ClusterTopology;
+        use crate::domain::events::event_kind::{EventKind, EventPayload};
+        use crate::pipeline::replication::ReplicationConfig;
+        use crate::storage::event_store::EventStore as _;
+        use crate::storage::memory::InMemoryEventStore;
+        use crate::storage::rocksdb::snapshot_store::RocksSnapshotStore;
+        use crate::storage::rocksdb::state_store::RocksStateStore;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Arc::new(rocksdb::DB::open_default(dir.path()).unwrap());
+        let storage = Arc::new(InMemoryEventStore::new());
+        // The other node is unreachable: only routing could write there
+        let pipeline = Arc::new(EventPipeline::new(
+            storage.clone(),
+            Arc::new(RocksStateStore::new(db.clone())),
+            ClusterTopology::new(
+                vec!["127.0.0.1:50051".to_string(), "127.0.0.1:1".to_string()],
+                0,
+            ),
+            NodeIdentity::new(None, "127.0.0.1:50051"),
+            ClusterClient::default(),
+            None,
+            ReplicationConfig::default(),
+        ));
+        let stream_id = (0..)
+            .map(|i| format!("user-{}", i))
+            .find(|s| pipeline.topology().get_owner(s).node_addr == "127.0.0.1:1")
+            .unwrap();
+        let service = GrpcService::new(pipeline, Arc::new(RocksSnapshotStore::new(db)));
+
+        // is_forwarded (field 4) and epoch (field 5), by which the public API
+        // used to take writes without routing them
+        let event = DomainEvent::new(
+            &stream_id,
+            EventKind::Internal,
+            EventPayload(b"{}".to_vec()),
+        );
+        let mut bytes = AppendEventRequest {
+            stream_id: stream_id.clone(),
+            events: vec![event.into()],
+            expected_version: 0,
+            redirect: false,
+            verbatim: false,
         }
+        .encode_to_vec();
+        bytes.extend_from_slice(&[0x20, 0x01, 0x28, 0x00]);
+        let request = AppendEventRequest::decode(bytes.as_slice()).unwrap();
+
+        assert!(service.append_event(Request::new(request)).await.is_err());
+        assert!(storage.fetch_stream(&stream_id).await.unwrap().is_empty());
     }
 }
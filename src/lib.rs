@@ -6,6 +6,7 @@
 //!
 //! - `config`: Configuration management.
 //! - `domain`: Core domain types and event definitions.
+//! - `geo`: Asynchronous replication between clusters.
 //! - `grpc`: gRPC API implementation.
 //! - `pipeline`: Event processing pipeline.
 //! - `storage`: Pluggable storage engine traits and implementations.
@@ -13,6 +14,7 @@
 pub mod cluster;
 pub mod config;
 pub mod domain;
+pub mod geo;
 pub mod grpc;
 pub mod pipeline;
 pub mod storage;
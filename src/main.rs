@@ -1,10 +1,35 @@
 use graveyar_db::{
-    api::event_store_server::EventStoreServer,
+    api::{cluster_service_server::ClusterServiceServer, event_store_server::EventStoreServer},
+    cluster::{
+        client::ClusterClient,
+        gossip::Gossiper,
+        handshake::{handshake, TopologyFingerprint},
+        identity::{NodeIdentity, NodeRole},
+        lease::LeaseStore,
+        raft::{
+            group::GrpcRaftTransport,
+            groups::{group_of, RaftGroups},
+            RaftConfig,
+        },
+        ring::RingMember,
+        tls::ClusterTls,
+        ClusterTopology,
+    },
     config,
-    grpc::GrpcService,
-    pipeline::EventPipeline,
+    grpc::{
+        auth::{AuthInterceptor, ClusterInterceptor},
+        cluster::ClusterGrpcService,
+        GrpcService,
+    },
+    pipeline::{anti_entropy::AntiEntropy, replication::ReplicationMode, EventPipeline},
     storage::{
-        event_store::EventStore, hybrid::HybridEventStore, rocksdb::event_store::RocksEventStore,
+        event_store::EventStore,
+        hybrid::HybridEventStore,
+        rocksdb::{
+            event_store::RocksEventStore,
+            outbox::RocksOutbox,
+            raft::{RocksRaftStorage, RocksStateMachine},
+        },
         scylla::session::ScyllaStore,
     },
 };
@@ -29,6 +54,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Set global provider
     opentelemetry::global::set_tracer_provider(tracer_provider);
 
+    // Metrics go to the same OTLP endpoint
+    let metric_exporter = opentelemetry_otlp::MetricExporter::builder()
+        .with_http()
+        .build()?;
+    let meter_provider = opentelemetry_sdk::metrics::SdkMeterProvider::builder()
+        .with_reader(
+            opentelemetry_sdk::metrics::PeriodicReader::builder(
+                metric_exporter,
+                opentelemetry_sdk::runtime::Tokio,
+            )
+            .build(),
+        )
+        .build();
+    opentelemetry::global::set_meter_provider(meter_provider);
+
     // Create a tracing layer with the configured tracer
     let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
 
@@ -46,46 +86,229 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // 1. Storage Initialization
     let rocks_store = Arc::new(RocksEventStore::new(&config.db_path)?);
 
+    // Raft groups are fixed to the starting ring and keep their state in RocksDB
+    let raft_enabled = config.replication.mode == ReplicationMode::Raft;
+    if raft_enabled && (config.scylla_uri.is_some() || !config.cluster_seeds.is_empty()) {
+        return Err("REPLICATION_MODE=raft needs a static CLUSTER_NODES cluster on RocksDB: unset SCYLLA_URI and CLUSTER_SEEDS".into());
+    }
+
+    // Stream ownership leases need a store shared by all nodes
+    let mut lease_store: Option<Arc<dyn LeaseStore>> = None;
+    // Primary and fallback stores, compared by anti-entropy
+    let mut tiers: Option<(Arc<dyn EventStore>, Arc<dyn EventStore>)> = None;
+    let mut hybrid_store: Option<Arc<HybridEventStore>> = None;
+
     let storage: Arc<dyn EventStore> = if let Some(scylla_uri) = &config.scylla_uri {
         println!("Initializing ScyllaDB at {}...", scylla_uri);
         match ScyllaStore::new(scylla_uri, &config.scylla_keyspace).await {
             Ok(scylla) => {
                 println!("ScyllaDB connected. Using Hybrid Storage (Primary: Scylla, Fallback: RocksDB).");
-                Arc::new(HybridEventStore::new(Arc::new(scylla), rocks_store))
+                let scylla = Arc::new(scylla);
+                lease_store = Some(scylla.clone());
+                tiers = Some((scylla.clone(), rocks_store.clone()));
+                let hybrid = Arc::new(
+                    HybridEventStore::new(scylla, rocks_store.clone())
+                        .with_outbox(RocksOutbox::new(rocks_store.clone()))
+                        .with_breaker(config.storage_failure_threshold, config.storage_cooldown),
+                );
+                tokio::spawn(
+                    hybrid
+                        .clone()
+                        .run_reconciliation(config.fallback_reconcile_interval),
+                );
+                hybrid_store = Some(hybrid.clone());
+                hybrid
             }
             Err(e) => {
                 eprintln!(
                     "Failed to connect to ScyllaDB: {}. Falling back to RocksDB only.",
                     e
                 );
-                rocks_store
+                rocks_store.clone()
             }
         }
     } else {
         println!("No SCYLLA_URI configured. Using RocksDB only.");
-        rocks_store
+        rocks_store.clone()
     };
 
-    // 2. Pipeline
-    let pipeline = Arc::new(EventPipeline::new(
-        storage,
-        config.cluster_nodes.clone(),
-        config.node_id,
-        config.auth_token.clone(),
-    ));
-
-    // 3. Snapshot Store (Local RocksDB)
+    // 2. Snapshot & State Stores (Local RocksDB)
     let snapshot_db_path = format!("{}_snapshots", config.db_path);
     let mut opts = rocksdb::Options::default();
     opts.create_if_missing(true);
     let snapshot_db =
         Arc::new(rocksdb::DB::open(&opts, &snapshot_db_path).expect("Failed to open Snapshot DB"));
     let snapshot_store = Arc::new(
-        graveyar_db::storage::rocksdb::snapshot_store::RocksSnapshotStore::new(snapshot_db),
+        graveyar_db::storage::rocksdb::snapshot_store::RocksSnapshotStore::new(snapshot_db.clone()),
     );
+    let state_store =
+        Arc::new(graveyar_db::storage::rocksdb::state_store::RocksStateStore::new(snapshot_db));
+
+    // 3. Pipeline
+    // Initialize Topology with Epoch 0: either the static CLUSTER_NODES, or only
+    // ourselves when membership is discovered through gossip
+    let gossip_enabled = !config.cluster_seeds.is_empty();
+    let members = if gossip_enabled {
+        vec![RingMember::new(config.advertise_addr.clone())]
+    } else {
+        config.ring_members()
+    };
+    let topology = ClusterTopology::with_members(members, config.cluster_vnodes, 0);
+    let identity = NodeIdentity::load_or_create(
+        format!("{}_node_id", config.db_path),
+        config.node_name.clone(),
+        config.advertise_addr.clone(),
+    )?
+    .with_role(config.node_role);
+    println!(
+        "Node {} ({}) at {}, {:?}.",
+        identity.name, identity.id, identity.addr, identity.role
+    );
+    let cluster_tls = match &config.cluster_tls {
+        Some(tls) => Some(ClusterTls::load(tls).await?),
+        None => None,
+    };
+    let cluster_client = ClusterClient::new(config.cluster_secret.clone())
+        .with_port(config.cluster_port)
+        .with_tls(cluster_tls.clone())
+        .with_config(config.forwarding.clone());
+    let mut pipeline = EventPipeline::new(
+        storage,
+        state_store,
+        topology,
+        identity,
+        cluster_client.clone(),
+        lease_store,
+        config.replication.clone(),
+    );
+
+    if raft_enabled {
+        let self_addr = pipeline.membership().self_addr().to_string();
+        let topology = pipeline.topology();
+        let factor = config.replication.factor;
+        let checkpoint_dir = format!("{}_raft_checkpoints", config.db_path);
+        let transport = Arc::new(GrpcRaftTransport::new(cluster_client.clone()));
+        let groups = RaftGroups::start(
+            &self_addr,
+            topology.clone(),
+            factor,
+            RaftConfig::default(),
+            transport,
+            |group| {
+                let storage = RocksRaftStorage::new(rocks_store.clone(), group);
+                let ring = topology.clone();
+                let id = group.to_string();
+                let owns = Arc::new(move |stream: &str| group_of(&ring, stream, factor) == id);
+                let state_machine =
+                    RocksStateMachine::new(rocks_store.clone(), group, &checkpoint_dir, owns)?;
+                Ok((Box::new(storage) as _, Box::new(state_machine) as _))
+            },
+        )?;
+        println!(
+            "Raft replication enabled: {} groups, factor {}.",
+            groups.groups().count(),
+            factor
+        );
+        pipeline = pipeline.with_consensus(Arc::new(groups));
+    }
+    let pipeline = Arc::new(pipeline);
+
+    // Failure detection: gossip also runs for static clusters, seeded with
+    // CLUSTER_NODES, so that dead owners leave the ring and their ranges fail over
+    let seeds = if gossip_enabled {
+        config.cluster_seeds.clone()
+    } else {
+        config.cluster_nodes.clone()
+    };
+
+    // Refuse to start next to peers that would route streams differently
+    let fingerprint =
+        TopologyFingerprint::new(pipeline.identity(), &pipeline.topology(), !gossip_enabled);
+    handshake(&cluster_client, &fingerprint, &seeds).await?;
+    // Read replicas stay out of gossip, which would put them on the ring
+    let replica = config.node_role == NodeRole::ReadReplica;
+    if !raft_enabled && !replica && (seeds.len() > 1 || gossip_enabled) {
+        let cluster_secret = config
+            .cluster_secret
+            .as_deref()
+            .ok_or("Gossip needs a CLUSTER_SECRET to authenticate its datagrams")?;
+        let gossiper = Gossiper::bind(
+            &format!("0.0.0.0:{}", config.port),
+            pipeline.membership().clone(),
+            seeds.clone(),
+            config.gossip.clone(),
+            cluster_secret,
+        )
+        .await?;
+        println!(
+            "Gossip enabled on UDP port {} (seeds: {:?}).",
+            config.port, seeds
+        );
+        gossiper.start();
+    }
 
     // 4. gRPC Service
-    let service = GrpcService::new(pipeline, snapshot_store);
+    let mut anti_entropy = AntiEntropy::new(pipeline.clone());
+    if let Some((primary, fallback)) = tiers {
+        anti_entropy = anti_entropy.with_tiers(primary, fallback);
+    }
+    let anti_entropy = Arc::new(anti_entropy);
+    if config.anti_entropy.interval.is_some() {
+        println!("Anti-entropy enabled: {:?}.", config.anti_entropy);
+        tokio::spawn(anti_entropy.clone().run(config.anti_entropy.clone()));
+    }
+    let mut service = GrpcService::new(pipeline.clone(), snapshot_store.clone())
+        .with_anti_entropy(anti_entropy.clone());
+    if let Some(hybrid) = hybrid_store {
+        service = service.with_hybrid_store(hybrid);
+    }
+    let mut cluster_interceptor = ClusterInterceptor::new(config.cluster_secret.clone());
+    if let Some(tls) = &cluster_tls {
+        cluster_interceptor = cluster_interceptor
+            .with_peer_identity(
+                pipeline.membership().clone(),
+                tls.server_name().map(str::to_string),
+            )
+            .with_read_replicas(config.replication.read_replicas.clone());
+    }
+    let cluster_service = ClusterServiceServer::with_interceptor(
+        ClusterGrpcService::new(pipeline, snapshot_store)
+            .with_configured_ring(!gossip_enabled)
+            .with_anti_entropy(anti_entropy),
+        cluster_interceptor,
+    );
+    if config.cluster_secret.is_none() {
+        println!(
+            "Cluster service authentication DISABLED (single node, no CLUSTER_SECRET configured)."
+        );
+    }
+
+    // The cluster service gets its own listener when CLUSTER_PORT is set, so
+    // that it can be kept off the network clients reach
+    let mut cluster_service = Some(cluster_service);
+    if let Some(cluster_port) = config.cluster_port {
+        let cluster_addr = format!("0.0.0.0:{}", cluster_port).parse()?;
+        println!("Cluster service listening on {}", cluster_addr);
+        let cluster_service = cluster_service.take().unwrap();
+        let mut cluster_builder = Server::builder();
+        match &cluster_tls {
+            Some(tls) => {
+                println!("Cluster mutual TLS enabled.");
+                cluster_builder = cluster_builder.tls_config(tls.server_config())?;
+            }
+            None => println!("Cluster TLS DISABLED (no CLUSTER_TLS_* configured)."),
+        }
+        tokio::spawn(async move {
+            if let Err(e) = cluster_builder
+                .add_service(cluster_service)
+                .serve(cluster_addr)
+                .await
+            {
+                eprintln!("Cluster service failed: {}", e);
+            }
+        });
+    }
+
     let addr = format!("0.0.0.0:{}", config.port).parse()?;
 
     println!("Server listening on {}", addr);
@@ -106,20 +329,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("TLS DISABLED (missing TLS_CERT_PATH or TLS_KEY_PATH).");
     }
 
-    if let Some(token) = config.auth_token.clone() {
+    if config.auth_token.is_some() {
         println!("Authentication enabled with Bearer Token.");
-        let interceptor = graveyar_db::grpc::auth::AuthInterceptor::new(token);
-        builder
-            .add_service(EventStoreServer::with_interceptor(service, interceptor))
-            .serve(addr)
-            .await?;
     } else {
         println!("Authentication DISABLED (no AUTH_TOKEN configured).");
-        builder
-            .add_service(EventStoreServer::new(service))
-            .serve(addr)
-            .await?;
     }
+    let interceptor = AuthInterceptor::optional(config.auth_token.clone());
+    builder
+        .add_service(EventStoreServer::with_interceptor(service, interceptor))
+        .add_optional_service(cluster_service)
+        .serve(addr)
+        .await?;
 
     Ok(())
 }
@@ -0,0 +1,425 @@
+use crate::domain::events::event::Event;
+use crate::pipeline::EventPipeline;
+use crate::storage::digest::StreamDigest;
+use crate::storage::event_store::EventStore;
+use opentelemetry::metrics::Counter;
+use opentelemetry::KeyValue;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Settings of the background anti-entropy job.
+#[derive(Clone, Debug, Default)]
+pub struct AntiEntropyConfig {
+    /// Time between two rounds over every stream. None disables the job.
+    pub interval: Option<Duration>,
+    /// Whether rounds repair the copies that lag behind.
+    pub repair: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DifferenceKind {
+    /// `behind` holds a prefix of `ahead`.
+    Lagging,
+    /// The copies hold different events at the same versions.
+    Diverged,
+}
+
+/// Two copies of a stream that do not match.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamDifference {
+    pub stream_id: String,
+    pub kind: DifferenceKind,
+    /// "primary", "fallback" or a node address. For diverged copies,
+    /// `ahead` is the reference copy.
+    pub ahead: String,
+    pub behind: String,
+    pub ahead_digest: StreamDigest,
+    pub behind_digest: StreamDigest,
+    pub repaired: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub streams_checked: u64,
+    pub differences: Vec<StreamDifference>,
+}
+
+/// How `behind` relates to `ahead`, given their events. None if they match.
+fn compare(ahead: &[Event], behind: &[Event]) -> Option<DifferenceKind> {
+    if StreamDigest::of(ahead) == StreamDigest::of(behind) {
+        return None;
+    }
+    let common = ahead.len().min(behind.len());
+    if behind.len() <= ahead.len()
+        && StreamDigest::of(&ahead[..common]) == StreamDigest::of(&behind[..common])
+    {
+        Some(DifferenceKind::Lagging)
+    } else {
+        Some(DifferenceKind::Diverged)
+    }
+}
+
+struct Metrics {
+    checked: Counter<u64>,
+    differences: Counter<u64>,
+    repairs: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = opentelemetry::global::meter("graveyar_db");
+        Self {
+            checked: meter
+                .u64_counter("anti_entropy.streams_checked")
+                .with_description("Streams compared by anti-entropy")
+                .build(),
+            differences: meter
+                .u64_counter("anti_entropy.differences")
+                .with_description("Copies of streams found lagging or diverged")
+                .build(),
+            repairs: meter
+                .u64_counter("anti_entropy.repairs")
+                .with_description("Repairs of lagging copies, by result")
+                .build(),
+        }
+    }
+
+    fn record(&self, between: &'static str, difference: &StreamDifference) {
+        let kind = match difference.kind {
+            DifferenceKind::Lagging => "lagging",
+            DifferenceKind::Diverged => "diverged",
+        };
+        self.differences.add(
+            1,
+            &[
+                KeyValue::new("between", between),
+                KeyValue::new("kind", kind),
+            ],
+        );
+        if difference.repaired || difference.error.is_some() {
+            let result = if difference.repaired { "ok" } else { "failed" };
+            self.repairs.add(1, &[KeyValue::new("result", result)]);
+        }
+    }
+}
+
+/// Compares the copies of streams: between the primary and fallback stores
+/// of this node, and between this node and the other nodes holding copies of
+/// the streams it owns. Digests are compared first, then events only where
+/// they differ; copies are only repaired by appending what they lack, so
+/// diverged copies are reported and left for an operator.
+pub struct AntiEntropy {
+    pipeline: Arc<EventPipeline>,
+    tiers: Option<(Arc<dyn EventStore>, Arc<dyn EventStore>)>,
+    metrics: Metrics,
+}
+
+impl AntiEntropy {
+    pub fn new(pipeline: Arc<EventPipeline>) -> Self {
+        Self {
+            pipeline,
+            tiers: None,
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// Also compares the two stores of a `HybridEventStore`.
+    pub fn with_tiers(
+        mut self,
+        primary: Arc<dyn EventStore>,
+        fallback: Arc<dyn EventStore>,
+    ) -> Self {
+        self.tiers = Some((primary, fallback));
+        self
+    }
+
+    pub async fn run(self: Arc<Self>, config: AntiEntropyConfig) {
+        let Some(interval) = config.interval else {
+            return;
+        };
+        let mut ticks = tokio::time::interval(interval);
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            match self.verify(Vec::new(), config.repair).await {
+                Ok(report) => {
+                    for d in &report.differences {
+                        tracing::warn!(stream_id = %d.stream_id, kind = ?d.kind, ahead = %d.ahead, behind = %d.behind, repaired = d.repaired, "Copies of a stream differ");
+                    }
+                }
+                Err(e) => tracing::warn!(error = %e, "Anti-entropy round failed"),
+            }
+        }
+    }
+
+    /// Compares the copies of `stream_ids`, or of every stream when empty,
+    /// and repairs lagging ones if `repair` is set.
+    pub async fn verify(
+        &self,
+        stream_ids: Vec<String>,
+        repair: bool,
+    ) -> Result<VerifyReport, String> {
+        let mut report = VerifyReport::default();
+        let mut streams: BTreeSet<String> = stream_ids.into_iter().collect();
+        let all = streams.is_empty();
+
+        if let Some((primary, fallback)) = &self.tiers {
+            // The fallback only holds the writes it took while the primary
+            // was failing: a stream missing from it is not a difference
+            let held: BTreeSet<String> = fallback
+                .list_streams()
+                .await
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .collect();
+            if all {
+                streams.extend(primary.list_streams().await.map_err(|e| e.to_string())?);
+                streams.extend(held.iter().cloned());
+            }
+            for stream_id in streams.iter().filter(|s| held.contains(*s)) {
+                if let Some(d) = self
+                    .verify_tiers(primary, fallback, stream_id, repair)
+                    .await?
+                {
+                    self.metrics.record("tiers", &d);
+                    report.differences.push(d);
+                }
+            }
+        } else if all {
+            streams.extend(self.pipeline.list_streams().await?);
+        }
+
+        for d in self.verify_copies(&streams, repair).await? {
+            self.metrics.record("nodes", &d);
+            report.differences.push(d);
+        }
+
+        report.streams_checked = streams.len() as u64;
+        self.metrics.checked.add(report.streams_checked, &[]);
+        Ok(report)
+    }
+
+    async fn verify_tiers(
+        &self,
+        primary: &Arc<dyn EventStore>,
+        fallback: &Arc<dyn EventStore>,
+        stream_id: &str,
+        repair: bool,
+    ) -> Result<Option<StreamDifference>, String> {
+        // Writes normally go to the primary: read it last, so that one
+        // landing in between makes the fallback lag rather than diverge
+        let fallback_events = fallback
+            .fetch_stream(stream_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let primary_events = primary
+            .fetch_stream(stream_id)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let copies = [
+            ("primary", primary, &primary_events),
+            ("fallback", fallback, &fallback_events),
+        ];
+        let (ahead, behind) = if fallback_events.len() > primary_events.len() {
+            (&copies[1], &copies[0])
+        } else {
+            (&copies[0], &copies[1])
+        };
+        let Some(kind) = compare(ahead.2, behind.2) else {
+            return Ok(None);
+        };
+
+        let mut difference = StreamDifference {
+            stream_id: stream_id.to_string(),
+            kind,
+            ahead: ahead.0.to_string(),
+            behind: behind.0.to_string(),
+            ahead_digest: StreamDigest::of(ahead.2),
+            behind_digest: StreamDigest::of(behind.2),
+            repaired: false,
+            error: None,
+        };
+        if repair && kind == DifferenceKind::Lagging {
+            let mut result = Ok(());
+            for event in &ahead.2[behind.2.len()..] {
+                result = behind
+                    .1
+                    .append_event(stream_id, event.clone(), event.sequence_number - 1)
+                    .await
+                    .map_err(|e| e.to_string());
+                if result.is_err() {
+                    break;
+                }
+            }
+            difference.repaired = result.is_ok();
+            difference.error = result.err();
+        }
+        Ok(Some(difference))
+    }
+
+    /// Compares this node's copies of the streams it owns with the copies of
+    /// their followers and read replicas.
+    async fn verify_copies(
+        &self,
+        streams: &BTreeSet<String>,
+        repair: bool,
+    ) -> Result<Vec<StreamDifference>, String> {
+        let mut by_node: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for stream_id in streams {
+            for node in self.pipeline.copies_of(stream_id) {
+                by_node.entry(node).or_default().push(stream_id.clone());
+            }
+        }
+
+        let client = self.pipeline.cluster_client();
+        let self_addr = self.pipeline.identity().addr.clone();
+        let mut differences = Vec::new();
+        for (node, stream_ids) in by_node {
+            // Remote copies first, so that a write landing in between makes
+            // them lag rather than diverge
+            let digests = match client.stream_digests(&node, stream_ids.clone()).await {
+                Ok(digests) => digests,
+                Err(e) => {
+                    tracing::warn!(node = %node, error = %e, "Anti-entropy skipped an unreachable node");
+                    continue;
+                }
+            };
+
+            for (stream_id, remote) in stream_ids.into_iter().zip(digests) {
+                let local = self.pipeline.fetch_stream(&stream_id).await?;
+                let local_digest = StreamDigest::of(&local);
+                if local_digest == remote {
+                    continue;
+                }
+                let common = (remote.count as usize).min(local.len());
+                let kind = if remote.count <= local_digest.count
+                    && StreamDigest::of(&local[..common]) == remote
+                {
+                    DifferenceKind::Lagging
+                } else {
+                    DifferenceKind::Diverged
+                };
+
+                let mut difference = StreamDifference {
+                    stream_id: stream_id.clone(),
+                    kind,
+                    ahead: self_addr.clone(),
+                    behind: node.clone(),
+                    ahead_digest: local_digest,
+                    behind_digest: remote,
+                    repaired: false,
+                    error: None,
+                };
+                if repair && kind == DifferenceKind::Lagging {
+                    let epoch = self.pipeline.topology().get_owner(&stream_id).epoch;
+                    match client
+                        .replicate(
+                            &node,
+                            &self_addr,
+                            &stream_id,
+                            epoch,
+                            local[common..].to_vec(),
+                        )
+                        .await
+                    {
+                        Ok(head) if head >= local_digest.head => difference.repaired = true,
+                        Ok(head) => {
+                            difference.error =
+                                Some(format!("copy at version {} after repair", head))
+                        }
+                        Err(e) => difference.error = Some(e),
+                    }
+                }
+                differences.push(difference);
+            }
+        }
+        Ok(differences)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::client::ClusterClient;
+    use crate::cluster::identity::NodeIdentity;
+    use crate::cluster::ClusterTopology;
+    use crate::domain::events::event_kind::{EventKind, EventPayload};
+    use crate::pipeline::replication::ReplicationConfig;
+    use crate::storage::memory::InMemoryEventStore;
+    use crate::storage::rocksdb::state_store::RocksStateStore;
+
+    fn event(payload: u8) -> Event {
+        Event::new("s", EventKind::Internal, EventPayload(vec![payload]))
+    }
+
+    #[tokio::test]
+    async fn test_lagging_tiers_are_repaired_and_diverged_ones_reported() {
+        let primary = Arc::new(InMemoryEventStore::new());
+        let fallback = Arc::new(InMemoryEventStore::new());
+        let shared = event(1);
+        for (version, e) in [shared.clone(), event(2)].into_iter().enumerate() {
+            primary
+                .append_event("lagging", e, version as u64)
+                .await
+                .unwrap();
+        }
+        fallback.append_event("lagging", shared, 0).await.unwrap();
+        primary.append_event("diverged", event(3), 0).await.unwrap();
+        fallback
+            .append_event("diverged", event(4), 0)
+            .await
+            .unwrap();
+        // Never written while the primary was failing
+        primary
+            .append_event("primary-only", event(5), 0)
+            .await
+            .unwrap();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Arc::new(rocksdb::DB::open_default(dir.path()).unwrap());
+        let pipeline = EventPipeline::new(
+            Arc::new(InMemoryEventStore::new()),
+            Arc::new(RocksStateStore::new(db)),
+            ClusterTopology::new(vec!["127.0.0.1:50051".to_string()], 0),
+            NodeIdentity::new(None, "127.0.0.1:50051"),
+            ClusterClient::default(),
+            None,
+            ReplicationConfig::default(),
+        );
+        let anti_entropy =
+            AntiEntropy::new(Arc::new(pipeline)).with_tiers(primary.clone(), fallback.clone());
+
+        let report = anti_entropy.verify(Vec::new(), true).await.unwrap();
+        assert_eq!(report.streams_checked, 3);
+        let kinds: Vec<_> = report
+            .differences
+            .iter()
+            .map(|d| (d.stream_id.as_str(), d.kind, d.repaired))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("diverged", DifferenceKind::Diverged, false),
+                ("lagging", DifferenceKind::Lagging, true),
+            ]
+        );
+        assert_eq!(
+            StreamDigest::of(&fallback.fetch_stream("lagging").await.unwrap()),
+            StreamDigest::of(&primary.fetch_stream("lagging").await.unwrap())
+        );
+
+        let report = anti_entropy
+            .verify(vec!["lagging".into(), "primary-only".into()], false)
+            .await
+            .unwrap();
+        assert!(report.differences.is_empty());
+        assert!(fallback
+            .fetch_stream("primary-only")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,11 +1,36 @@
+use crate::cluster::raft::group::RaftGroup;
 use crate::domain::events::event::Event;
 use tokio::sync::oneshot;
+use tokio::time::Instant;
 
 pub enum PipelineCommand {
     Append {
         stream_id: String,
         events: Vec<Event>,
         expected_version: i64,
+        /// Topology epoch at which ownership was checked.
+        epoch: u64,
+        /// Deadline of the request, which bounds the wait for followers.
+        deadline: Option<Instant>,
+        /// Answered with the version of the stream after the append, or None
+        /// when it was not at `expected_version`.
+        resp_tx: oneshot::Sender<Result<Option<u64>, String>>,
+    },
+    /// Appends through the stream's Raft group, on the local member.
+    Propose {
+        stream_id: String,
+        events: Vec<Event>,
+        expected_version: i64,
+        group: RaftGroup,
         resp_tx: oneshot::Sender<Result<bool, String>>,
     },
+    /// Applies events replicated by the stream's leader. Answered with the
+    /// local head version of the stream.
+    Replicate {
+        stream_id: String,
+        events: Vec<Event>,
+        resp_tx: oneshot::Sender<Result<u64, String>>,
+    },
+    /// Answered once every command queued before it has been executed.
+    Barrier { resp_tx: oneshot::Sender<()> },
 }
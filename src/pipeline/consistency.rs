@@ -0,0 +1,56 @@
+use prost::Message;
+use std::time::Duration;
+
+/// How long a read carrying a consistency token waits for the serving node
+/// to catch up, when the client set no deadline.
+pub const DEFAULT_TOKEN_WAIT: Duration = Duration::from_secs(1);
+
+/// Interval at which a waiting read checks the local copy of the stream.
+pub const TOKEN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Position of a write: the version of its stream after the write, and the
+/// topology epoch it was written at. Handed to clients as opaque bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConsistencyToken {
+    pub version: u64,
+    pub epoch: u64,
+}
+
+impl ConsistencyToken {
+    pub fn encode(&self) -> Vec<u8> {
+        crate::api::ConsistencyToken {
+            version: self.version,
+            epoch: self.epoch,
+        }
+        .encode_to_vec()
+    }
+
+    /// Reads a token sent by a client. Empty bytes mean no token.
+    pub fn decode(bytes: &[u8]) -> Result<Option<Self>, String> {
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        let token = crate::api::ConsistencyToken::decode(bytes)
+            .map_err(|e| format!("InvalidConsistencyToken: {}", e))?;
+        Ok(Some(Self {
+            version: token.version,
+            epoch: token.epoch,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_round_trip() {
+        let token = ConsistencyToken {
+            version: 42,
+            epoch: 3,
+        };
+        assert_eq!(ConsistencyToken::decode(&token.encode()), Ok(Some(token)));
+        assert_eq!(ConsistencyToken::decode(&[]), Ok(None));
+        assert!(ConsistencyToken::decode(&[0xff, 0xff]).is_err());
+    }
+}
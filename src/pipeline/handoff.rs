@@ -0,0 +1,342 @@
+use crate::cluster::client::ClusterClient;
+use crate::cluster::identity::same_addr;
+use crate::cluster::lease::LeaseManager;
+use crate::cluster::ring::TokenRange;
+use crate::cluster::ClusterTopology;
+use crate::pipeline::command::PipelineCommand;
+use crate::storage::event_store::EventStore;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, watch};
+
+/// How long a new owner waits for the previous owner of a range before it
+/// starts accepting writes without its confirmation.
+pub const HANDOFF_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Incoming {
+    epoch: u64,
+    previous: Arc<ClusterTopology>,
+    /// Previous owners that have not confirmed the handoff yet.
+    waiting_on: HashSet<String>,
+    deadline: Instant,
+}
+
+/// Bookkeeping for epoch-fenced ownership changes.
+///
+/// On a topology change the previous owner of a moved range drains its
+/// workers and sends the heads of the moved streams its storage holds; until
+/// then, or until `HANDOFF_TIMEOUT`, the new owner refuses writes to the
+/// range. A stream whose head was handed over only accepts writes once the
+/// new owner's storage shows that head, copying the missing events from the
+/// previous owner when storage is not shared, so writes never fork a stream.
+pub struct HandoffState {
+    self_addr: String,
+    timeout: Duration,
+    /// Epoch of the last topology change processed by `begin_incoming`.
+    epoch: AtomicU64,
+    incoming: Mutex<Option<Incoming>>,
+    /// Head version each handed-over stream must reach before accepting
+    /// writes, and the previous owner that holds it.
+    floors: Mutex<HashMap<String, (u64, String)>>,
+}
+
+impl HandoffState {
+    pub fn new(self_addr: impl Into<String>, epoch: u64, timeout: Duration) -> Self {
+        Self {
+            self_addr: self_addr.into(),
+            timeout,
+            epoch: AtomicU64::new(epoch),
+            incoming: Mutex::new(None),
+            floors: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Starts waiting on the nodes that owned, in `previous`, ranges that
+    /// `current` assigns to this node. Nodes no longer on the ring cannot
+    /// answer and are not waited on.
+    pub fn begin_incoming(&self, previous: Arc<ClusterTopology>, current: &ClusterTopology) {
+        let gained = self.own_ranges(current);
+        let waiting_on: HashSet<String> = previous
+            .token_ranges()
+            .into_iter()
+            .filter(|r| !self.is_self(&r.node_addr))
+            .filter(|r| current.get_all_nodes().contains(&r.node_addr))
+            .filter(|r| gained.iter().any(|g| g.overlaps(r)))
+            .map(|r| r.node_addr)
+            .collect();
+
+        let mut incoming = self.incoming.lock().unwrap();
+        self.epoch.store(current.epoch(), Ordering::SeqCst);
+        *incoming = if waiting_on.is_empty() {
+            None
+        } else {
+            tracing::info!(epoch = current.epoch(), waiting_on = ?waiting_on, "Waiting for ownership handoff");
+            Some(Incoming {
+                epoch: current.epoch(),
+                previous,
+                waiting_on,
+                deadline: Instant::now() + self.timeout,
+            })
+        };
+    }
+
+    /// Fails while the stream's previous owner has not handed it over, or while
+    /// the change to `epoch` has not been processed yet.
+    pub fn check_incoming(&self, stream_id: &str, epoch: u64) -> Result<(), String> {
+        if self.epoch.load(Ordering::SeqCst) < epoch {
+            return Err(format!(
+                "HandoffPendingError: topology change to Epoch {} is being processed",
+                epoch
+            ));
+        }
+
+        let mut guard = self.incoming.lock().unwrap();
+        let incoming = match guard.as_ref() {
+            Some(i) => i,
+            None => return Ok(()),
+        };
+        if Instant::now() >= incoming.deadline {
+            tracing::warn!(epoch = incoming.epoch, waiting_on = ?incoming.waiting_on, "Ownership handoff timed out");
+            *guard = None;
+            return Ok(());
+        }
+
+        let previous_owner = incoming.previous.get_owner(stream_id).node_addr;
+        if incoming.waiting_on.contains(&previous_owner) {
+            return Err(format!(
+                "HandoffPendingError: stream {} is being handed over from {} (Epoch {})",
+                stream_id, previous_owner, incoming.epoch
+            ));
+        }
+        Ok(())
+    }
+
+    /// Records a previous owner's confirmation and the heads it handed over.
+    /// Confirmations for another epoch are ignored.
+    pub fn complete_incoming(&self, from: &str, epoch: u64, heads: Vec<(String, u64)>) -> bool {
+        let mut guard = self.incoming.lock().unwrap();
+        match guard.as_mut() {
+            Some(incoming) if incoming.epoch == epoch => {
+                incoming.waiting_on.remove(from);
+                if incoming.waiting_on.is_empty() {
+                    *guard = None;
+                }
+            }
+            _ => return false,
+        }
+        drop(guard);
+
+        let mut floors = self.floors.lock().unwrap();
+        for (stream_id, version) in heads {
+            let floor = floors.entry(stream_id).or_insert((0, from.to_string()));
+            if version >= floor.0 {
+                *floor = (version, from.to_string());
+            }
+        }
+        true
+    }
+
+    pub fn floor(&self, stream_id: &str) -> Option<(u64, String)> {
+        self.floors.lock().unwrap().get(stream_id).cloned()
+    }
+
+    pub fn clear_floor(&self, stream_id: &str) {
+        self.floors.lock().unwrap().remove(stream_id);
+    }
+
+    /// Nodes that `current` gives ranges this node owned in `previous`, each
+    /// with the streams among `streams` that moved to it.
+    pub fn outgoing(
+        &self,
+        previous: &ClusterTopology,
+        current: &ClusterTopology,
+        streams: Vec<String>,
+    ) -> BTreeMap<String, Vec<String>> {
+        let lost = self.own_ranges(previous);
+        let mut targets: BTreeMap<String, Vec<String>> = current
+            .token_ranges()
+            .into_iter()
+            .filter(|r| !self.is_self(&r.node_addr))
+            .filter(|r| lost.iter().any(|l| l.overlaps(r)))
+            .map(|r| (r.node_addr, Vec::new()))
+            .collect();
+
+        for stream_id in streams {
+            if !self.is_self(&previous.get_owner(&stream_id).node_addr) {
+                continue;
+            }
+            if let Some(moved) = targets.get_mut(&current.get_owner(&stream_id).node_addr) {
+                moved.push(stream_id);
+            }
+        }
+        targets
+    }
+
+    /// Whether `addr` designates this node, however the ring spells it.
+    fn is_self(&self, addr: &str) -> bool {
+        same_addr(&self.self_addr, addr)
+    }
+
+    fn own_ranges(&self, topology: &ClusterTopology) -> Vec<TokenRange> {
+        topology
+            .token_ranges()
+            .into_iter()
+            .filter(|r| self.is_self(&r.node_addr))
+            .collect()
+    }
+}
+
+/// Runs the old-owner side of every topology change: once each worker has
+/// executed the writes queued before the change (later ones are fenced by
+/// epoch), the leases of moved streams are released and their heads are sent
+/// to the new owners.
+pub(crate) struct HandoffCoordinator {
+    pub handoff: Arc<HandoffState>,
+    pub leases: Option<Arc<LeaseManager>>,
+    pub workers: Vec<mpsc::Sender<PipelineCommand>>,
+    pub storage: Arc<dyn EventStore + Send + Sync>,
+    pub cluster_client: ClusterClient,
+    pub self_addr: String,
+}
+
+impl HandoffCoordinator {
+    pub async fn run(
+        self,
+        mut changes: watch::Receiver<Arc<ClusterTopology>>,
+        mut previous: Arc<ClusterTopology>,
+    ) {
+        while changes.changed().await.is_ok() {
+            let current = changes.borrow_and_update().clone();
+            self.handoff.begin_incoming(previous.clone(), &current);
+            self.drain().await;
+
+            let streams = match self.storage.list_streams().await {
+                Ok(streams) => streams,
+                Err(e) => {
+                    tracing::warn!(error = %e, "Failed to list streams for handoff");
+                    Vec::new()
+                }
+            };
+            for (node, streams) in self.handoff.outgoing(&previous, &current, streams) {
+                let mut heads = Vec::with_capacity(streams.len());
+                for stream_id in streams {
+                    if let Some(leases) = &self.leases {
+                        leases.release(&stream_id).await;
+                    }
+                    match self.storage.stream_head(&stream_id).await {
+                        Ok(head) => heads.push((stream_id, head)),
+                        Err(e) => {
+                            tracing::warn!(stream_id = %stream_id, error = %e, "Failed to read stream head for handoff")
+                        }
+                    }
+                }
+                if let Err(e) = self
+                    .cluster_client
+                    .complete_handoff(&node, &self.self_addr, current.epoch(), heads)
+                    .await
+                {
+                    tracing::warn!(peer = %node, epoch = current.epoch(), error = %e, "Ownership handoff failed");
+                }
+            }
+
+            previous = current;
+        }
+    }
+
+    async fn drain(&self) {
+        for worker in &self.workers {
+            let (resp_tx, resp_rx) = oneshot::channel();
+            if worker
+                .send(PipelineCommand::Barrier { resp_tx })
+                .await
+                .is_ok()
+            {
+                let _ = resp_rx.await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topology(nodes: &[&str], epoch: u64) -> Arc<ClusterTopology> {
+        Arc::new(ClusterTopology::new(
+            nodes.iter().map(|n| n.to_string()).collect(),
+            epoch,
+        ))
+    }
+
+    /// A stream owned by `from` before and by `to` after the change.
+    fn moved_stream(
+        previous: &ClusterTopology,
+        current: &ClusterTopology,
+        from: &str,
+        to: &str,
+    ) -> String {
+        (0..)
+            .map(|i| format!("stream-{}", i))
+            .find(|s| {
+                previous.get_owner(s).node_addr == from && current.get_owner(s).node_addr == to
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_handoff_between_old_and_new_owner() {
+        let before = topology(&["A", "B"], 0);
+        let after = topology(&["A", "B", "C"], 1);
+        let stream = moved_stream(&before, &after, "A", "C");
+
+        let kept = moved_stream(&before, &after, "A", "A");
+        let elsewhere = moved_stream(&before, &after, "B", "C");
+
+        let old_owner = HandoffState::new("A", 0, HANDOFF_TIMEOUT);
+        let outgoing = old_owner.outgoing(&before, &after, vec![stream.clone(), kept, elsewhere]);
+        assert_eq!(outgoing["C"], vec![stream.clone()]);
+        assert!(!outgoing.contains_key("B"));
+
+        let new_owner = HandoffState::new("C", 0, HANDOFF_TIMEOUT);
+        assert!(new_owner.check_incoming(&stream, 1).is_err());
+        new_owner.begin_incoming(before.clone(), &after);
+        let err = new_owner.check_incoming(&stream, 1).unwrap_err();
+        assert!(err.contains("HandoffPendingError"));
+
+        // Stale confirmation is ignored
+        assert!(!new_owner.complete_incoming("A", 0, Vec::new()));
+        assert!(new_owner.complete_incoming("A", 1, vec![(stream.clone(), 4)]));
+        // Still waiting on B, but not for streams that came from A
+        assert!(new_owner.check_incoming(&stream, 1).is_ok());
+        assert_eq!(new_owner.floor(&stream), Some((4, "A".to_string())));
+    }
+
+    #[test]
+    fn test_handoff_recognises_a_differently_spelled_self() {
+        let before = topology(&["10.0.0.1:1", "10.0.0.2:1"], 0);
+        let after = topology(&["10.0.0.1:1", "10.0.0.2:1", "10.0.0.3:1"], 1);
+        let stream = moved_stream(&before, &after, "10.0.0.1:1", "10.0.0.3:1");
+
+        let old_owner = HandoffState::new("http://10.0.0.1:1", 0, HANDOFF_TIMEOUT);
+        let outgoing = old_owner.outgoing(&before, &after, vec![stream.clone()]);
+        assert_eq!(outgoing["10.0.0.3:1"], vec![stream.clone()]);
+        assert!(!outgoing.contains_key("10.0.0.1:1"));
+
+        let new_owner = HandoffState::new("http://10.0.0.3:1", 0, HANDOFF_TIMEOUT);
+        new_owner.begin_incoming(before, &after);
+        assert!(new_owner.check_incoming(&stream, 1).is_err());
+    }
+
+    #[test]
+    fn test_handoff_times_out() {
+        let before = topology(&["A", "B"], 0);
+        let after = topology(&["A", "B", "C"], 1);
+        let stream = moved_stream(&before, &after, "B", "C");
+
+        let new_owner = HandoffState::new("C", 0, Duration::ZERO);
+        new_owner.begin_incoming(before, &after);
+        assert!(new_owner.check_incoming(&stream, 1).is_ok());
+    }
+}
@@ -1,19 +1,66 @@
+pub mod anti_entropy;
 pub mod command;
+pub mod consistency;
+pub mod handoff;
+pub mod projection;
+pub mod replica;
+pub mod replication;
 pub mod worker;
 
-use crate::cluster::client::ClusterClient;
-use crate::cluster::ClusterTopology;
+use crate::cluster::client::{current_deadline, ClusterClient, Forward, NOT_SENT};
+use crate::cluster::identity::{NodeIdentity, NodeRole};
+use crate::cluster::lease::{LeaseManager, LeaseStore, DEFAULT_LEASE_DURATION};
+use crate::cluster::membership::{Member, Membership};
+use crate::cluster::raft::groups::RaftGroups;
+use crate::cluster::raft::node::Envelope;
+use crate::cluster::{ClusterTopology, RingVersion};
 use crate::domain::events::event::Event;
+use crate::domain::schema::model::Schema;
+use crate::domain::schema::validation::ValidationError;
 use crate::pipeline::command::PipelineCommand;
+use crate::pipeline::consistency::{ConsistencyToken, TOKEN_POLL_INTERVAL};
+use crate::pipeline::handoff::{HandoffCoordinator, HandoffState, HANDOFF_TIMEOUT};
+use crate::pipeline::projection::StateProjector;
+use crate::pipeline::replica::ReplicaLag;
+use crate::pipeline::replication::{ReplicationConfig, Replicator};
 use crate::pipeline::worker::Worker;
+use crate::storage::digest::StreamDigest;
 use crate::storage::event_store::EventStore;
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use crate::storage::state::{StateStore, StreamState};
+use std::collections::BTreeSet;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::{mpsc, oneshot};
 
 const NUM_WORKERS: usize = 32;
 
+/// Forwards an append may take before reaching the node that applies it:
+/// a node outside the stream's Raft group, a group member, then its leader.
+/// More means the nodes disagree on where the stream belongs.
+pub const MAX_FORWARD_HOPS: u32 = 3;
+
+/// Outcome of the append-time schema steps for a single event.
+#[derive(Debug)]
+pub struct EventCheck {
+    /// Event type used for the schema lookup.
+    pub event_type: String,
+    /// Whether a schema (registered or inline) applied to the event.
+    pub schema_found: bool,
+    /// Whether normalization rewrote the payload.
+    pub normalized: bool,
+    pub errors: Vec<ValidationError>,
+}
+
+/// Which copy of a stream a read is served from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReadConsistency {
+    /// The stream's owner, or the leader of its Raft group.
+    #[default]
+    Owner,
+    /// This node's copy, which may miss recent writes.
+    Local,
+}
+
 /// The primary event processing pipeline.
 ///
 /// `EventPipeline` is responsibility for:
@@ -24,25 +71,76 @@ const NUM_WORKERS: usize = 32;
 pub struct EventPipeline {
     storage: Arc<dyn EventStore + Send + Sync>,
     workers: Vec<mpsc::Sender<PipelineCommand>>,
-    topology: ClusterTopology,
+    projector: Arc<StateProjector>,
+    membership: Arc<Membership>,
+    handoff: Arc<HandoffState>,
     cluster_client: ClusterClient,
-    self_addr: String,
+    identity: NodeIdentity,
+    consensus: Option<Arc<RaftGroups>>,
+    replicator: Option<Arc<Replicator>>,
+    /// Set on read replicas.
+    replica: Option<ReplicaLag>,
 }
 
 impl EventPipeline {
     /// Creates a new pipeline instance, initializing worker pools and cluster topology.
     pub fn new(
         storage: Arc<dyn EventStore + Send + Sync>,
-        cluster_nodes: Vec<String>,
-        self_node_id: u64,
-        auth_token: Option<String>,
+        state_store: Arc<dyn StateStore>,
+        topology: ClusterTopology,
+        identity: NodeIdentity,
+        cluster_client: ClusterClient,
+        lease_store: Option<Arc<dyn LeaseStore>>,
+        replication: ReplicationConfig,
     ) -> Self {
-        let mut workers = Vec::with_capacity(NUM_WORKERS);
+        let projector = Arc::new(StateProjector::new(storage.clone(), state_store));
 
+        let self_addr = identity.addr.clone();
+        let replica = (identity.role == NodeRole::ReadReplica).then(ReplicaLag::new);
+        let membership = Arc::new(match replica {
+            Some(_) => Membership::observer(self_addr.clone(), topology),
+            None => Membership::new(self_addr.clone(), topology),
+        });
+        let handoff = Arc::new(HandoffState::new(
+            self_addr.clone(),
+            membership.epoch(),
+            HANDOFF_TIMEOUT,
+        ));
+        let leases = lease_store.map(|store| {
+            Arc::new(LeaseManager::new(
+                store,
+                self_addr.clone(),
+                DEFAULT_LEASE_DURATION,
+            ))
+        });
+        if let Some(leases) = &leases {
+            tokio::spawn(leases.clone().run_renewals());
+        }
+
+        let replicator = replication.enabled().then(|| {
+            Arc::new(Replicator::new(
+                replication,
+                membership.clone(),
+                storage.clone(),
+                cluster_client.clone(),
+            ))
+        });
+        if let Some(replicator) = replicator.as_ref().filter(|r| r.has_read_replicas()) {
+            tokio::spawn(replicator.clone().run_heartbeats());
+        }
+
+        let mut workers = Vec::with_capacity(NUM_WORKERS);
         for id in 0..NUM_WORKERS {
             let (tx, rx) = mpsc::channel::<PipelineCommand>(1024);
             let store = storage.clone();
-            let worker = Worker::new(id, store);
+            let worker = Worker::new(
+                id,
+                store,
+                projector.clone(),
+                membership.clone(),
+                leases.clone(),
+                replicator.clone(),
+            );
 
             tokio::spawn(async move {
                 worker.run(rx).await;
@@ -50,93 +148,282 @@ impl EventPipeline {
             workers.push(tx);
         }
 
-        // Initialize Topology with Epoch 0 (MVP Static)
-        let topology = ClusterTopology::new(cluster_nodes.clone(), 0);
-
-        // Determine self address based on ID, safe fallback if config is weird
-        let sorted_nodes = topology.get_all_nodes();
-        let self_addr = if (self_node_id as usize) < sorted_nodes.len() {
-            sorted_nodes[self_node_id as usize].clone()
-        } else {
-            // Fallback for single node dev mode if config mismatch, assuming first one
-            sorted_nodes
-                .first()
-                .cloned()
-                .unwrap_or_else(|| "127.0.0.1:50051".to_string())
+        let coordinator = HandoffCoordinator {
+            handoff: handoff.clone(),
+            leases: leases.clone(),
+            workers: workers.clone(),
+            storage: storage.clone(),
+            cluster_client: cluster_client.clone(),
+            self_addr,
         };
-
-        let cluster_client = ClusterClient::new(auth_token);
+        tokio::spawn(coordinator.run(membership.subscribe(), membership.topology()));
 
         Self {
             storage,
             workers,
-            topology,
+            projector,
+            membership,
+            handoff,
             cluster_client,
-            self_addr,
+            identity,
+            consensus: None,
+            replicator,
+            replica,
         }
     }
 
+    /// Sends appends through the Raft groups of the local node instead of
+    /// the ring owner. Ownership epochs, handoffs and leases then no longer
+    /// apply: each group orders its writes itself.
+    pub fn with_consensus(mut self, groups: Arc<RaftGroups>) -> Self {
+        self.consensus = Some(groups);
+        self
+    }
+
     /// Appends events to a stream.
     ///
     /// This method acts as the Gateway/Router. It determines if the current node
     /// owns the stream. If so, it processes locally. If not, it forwards the request
     /// to the correct owner via gRPC.
+    ///
+    /// Returns the token of the write, or None when the stream was not at
+    /// `expected_version`.
     #[tracing::instrument(skip(self, events), fields(stream_id = %stream_id, event_count = events.len()))]
     pub async fn append_event(
         &self,
         stream_id: &str,
         events: Vec<Event>,
         expected_version: i64,
-    ) -> Result<bool, String> {
-        let owner = self.topology.get_owner(stream_id);
+    ) -> Result<Option<ConsistencyToken>, String> {
+        self.append(stream_id, events, expected_version, false)
+            .await
+    }
+
+    /// Appends copies of events accepted elsewhere, such as by another
+    /// cluster, as `append_event` does but storing them verbatim: they are
+    /// neither normalized nor validated again.
+    #[tracing::instrument(skip(self, events), fields(stream_id = %stream_id, event_count = events.len()))]
+    pub async fn copy_events(
+        &self,
+        stream_id: &str,
+        events: Vec<Event>,
+        expected_version: i64,
+    ) -> Result<Option<ConsistencyToken>, String> {
+        self.append(stream_id, events, expected_version, true).await
+    }
 
-        if owner.node_addr == self.self_addr {
-            self.append_event_as_owner(stream_id, events, expected_version)
+    async fn append(
+        &self,
+        stream_id: &str,
+        events: Vec<Event>,
+        expected_version: i64,
+        verbatim: bool,
+    ) -> Result<Option<ConsistencyToken>, String> {
+        self.check_writable()?;
+        if let Some(consensus) = &self.consensus {
+            let members = consensus.members_of(stream_id);
+            if !members.iter().any(|m| self.identity.is(m)) {
+                return self
+                    .forward_to_group(&members, stream_id, events, expected_version, verbatim)
+                    .await;
+            }
+            return self
+                .append_via_consensus(
+                    consensus,
+                    stream_id,
+                    events,
+                    expected_version,
+                    None,
+                    verbatim,
+                )
+                .await;
+        }
+
+        let owner = self.membership.topology().get_owner(stream_id);
+
+        if self.identity.is(&owner.node_addr) {
+            self.append_event_as_owner(stream_id, events, expected_version, None, verbatim)
                 .await
         } else {
-            self.cluster_client
-                .forward_append(&owner.node_addr, stream_id, events, expected_version)
+            self.forward_append(
+                &owner.node_addr,
+                stream_id,
+                events,
+                expected_version,
+                Forward::new(&self.identity.addr, owner.epoch),
+                verbatim,
+            )
+            .await
+        }
+    }
+
+    async fn forward_append(
+        &self,
+        target: &str,
+        stream_id: &str,
+        events: Vec<Event>,
+        expected_version: i64,
+        forward: Forward,
+        verbatim: bool,
+    ) -> Result<Option<ConsistencyToken>, String> {
+        let resp = self
+            .cluster_client
+            .forward_append(
+                target,
+                stream_id,
+                events,
+                expected_version,
+                forward,
+                verbatim,
+            )
+            .await?;
+        if !resp.success {
+            return Ok(None);
+        }
+        Ok(Some(
+            ConsistencyToken::decode(&resp.consistency_token)?.unwrap_or_default(),
+        ))
+    }
+
+    /// Forwards an append from outside a Raft group to its members in turn,
+    /// until one can be reached. Only members know the leader: a follower
+    /// passes the append on, which the hop limit allows for. A member that
+    /// may have received the append is not replaced, to avoid applying it twice.
+    async fn forward_to_group(
+        &self,
+        members: &[String],
+        stream_id: &str,
+        events: Vec<Event>,
+        expected_version: i64,
+        verbatim: bool,
+    ) -> Result<Option<ConsistencyToken>, String> {
+        let mut last_err = format!(
+            "NotOwnerError: Raft group of stream {} has no members",
+            stream_id
+        );
+        for member in members {
+            match self
+                .forward_append(
+                    member,
+                    stream_id,
+                    events.clone(),
+                    expected_version,
+                    Forward::new(&self.identity.addr, 0),
+                    verbatim,
+                )
                 .await
+            {
+                Err(e) if e.contains(NOT_SENT) => last_err = e,
+                res => return res,
+            }
         }
+        Err(last_err)
+    }
+
+    /// Token of a successful append of `count` events through Raft.
+    async fn token_after(
+        &self,
+        stream_id: &str,
+        expected_version: i64,
+        count: usize,
+        epoch: u64,
+    ) -> Result<ConsistencyToken, String> {
+        let version = if expected_version >= 0 {
+            expected_version as u64 + count as u64
+        } else {
+            // Appended at the head: later writes may be included, which
+            // only makes reads wait for more
+            self.stream_head(stream_id).await?
+        };
+        Ok(ConsistencyToken { version, epoch })
     }
 
     /// Strict Entry point: Only processes if WE are the owner.
     /// Used for forwarded requests or strict validation.
+    ///
+    /// The epoch in `forwarded` is the one the forwarding node routed at. A
+    /// write routed at an older epoch is rejected with the current owner, so
+    /// that the sender refreshes its ring and routes again; one routed at a
+    /// newer epoch waits for the handoff of that epoch.
+    ///
+    /// `verbatim` events are stored as given, as by `copy_events`.
     pub async fn append_event_as_owner(
         &self,
         stream_id: &str,
-        events: Vec<Event>,
+        mut events: Vec<Event>,
         expected_version: i64,
-    ) -> Result<bool, String> {
+        forwarded: Option<Forward>,
+        verbatim: bool,
+    ) -> Result<Option<ConsistencyToken>, String> {
+        if let Some(forward) = forwarded.as_ref().filter(|f| f.hops > MAX_FORWARD_HOPS) {
+            return Err(self.forward_loop(stream_id, forward));
+        }
+        if let Some(consensus) = &self.consensus {
+            return self
+                .append_via_consensus(
+                    consensus,
+                    stream_id,
+                    events,
+                    expected_version,
+                    forwarded,
+                    verbatim,
+                )
+                .await;
+        }
+
         // 1. Validate Ownership Again (Safety)
-        let owner = self.topology.get_owner(stream_id);
-        if owner.node_addr != self.self_addr {
+        let owner = self.membership.topology().get_owner(stream_id);
+        if let Some(forward) = &forwarded {
+            // Same epoch, yet each node takes the other for the owner
+            if forward.epoch == owner.epoch && forward.origin == owner.node_addr {
+                return Err(self.forward_loop(stream_id, forward));
+            }
+        }
+        if !self.identity.is(&owner.node_addr) {
             return Err(format!(
                 "NotOwnerError: Node {} received write for stream {} but owner is {} (Epoch {})",
-                self.self_addr, stream_id, owner.node_addr, owner.epoch
+                self.identity.addr, stream_id, owner.node_addr, owner.epoch
             ));
         }
-
-        // Schema Validation (Soft Fail)
-        for event in &events {
-            let type_str = format!("{:?}", event.event_type);
-            // Optimization: Only check if looks like custom event or check existence
-            if let Ok(Some(schema)) = self.storage.get_schema(&type_str).await {
-                if let Err(errs) = crate::domain::schema::validation::validate_event_payload(
-                    &event.payload.0,
-                    &schema,
-                ) {
-                    tracing::warn!(stream_id = %stream_id, event_type = %type_str, errors = ?errs, "Schema validation failed (Soft Fail)");
-                    // To enable Hard Fail: return Err(format!("Schema Validation Error: {:?}", errs));
-                }
+        let routed_at = forwarded.as_ref().map_or(owner.epoch, |f| f.epoch);
+        if routed_at < owner.epoch {
+            return Err(format!(
+                "StaleEpochError: write for stream {} was routed at Epoch {} but owner is {} (Epoch {})",
+                stream_id, routed_at, owner.node_addr, owner.epoch
+            ));
+        }
+        self.handoff.check_incoming(stream_id, routed_at)?;
+        if let Some((floor, previous_owner)) = self.handoff.floor(stream_id) {
+            let mut head = self.stream_head(stream_id).await?;
+            if head < floor {
+                // Storage is not shared with the previous owner
+                head = self
+                    .copy_handed_over(stream_id, &previous_owner, owner.epoch)
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::warn!(stream_id = %stream_id, from = %previous_owner, error = %e, "Failed to copy handed-over stream");
+                        head
+                    });
+            }
+            if head < floor {
+                return Err(format!(
+                    "HandoffPendingError: stream {} was handed over at version {} but storage shows version {}",
+                    stream_id, floor, head
+                ));
             }
+            self.handoff.clear_floor(stream_id);
+        }
+
+        if !verbatim {
+            self.check_for_append(stream_id, &mut events).await;
         }
 
         // 2. Local Processing via Sharded Workers
-        let mut hasher = DefaultHasher::new();
-        stream_id.hash(&mut hasher);
-        let hash = hasher.finish();
-        let worker_idx = (hash as usize) % self.workers.len();
+        let worker_idx = self
+            .membership
+            .topology()
+            .partitioner()
+            .worker_slot(stream_id, self.workers.len());
 
         let (resp_tx, resp_rx) = oneshot::channel();
 
@@ -144,6 +431,8 @@ impl EventPipeline {
             stream_id: stream_id.to_string(),
             events,
             expected_version,
+            epoch: owner.epoch,
+            deadline: current_deadline(),
             resp_tx,
         };
 
@@ -152,9 +441,394 @@ impl EventPipeline {
             .await
             .map_err(|e| e.to_string())?;
 
+        let version = resp_rx.await.map_err(|e| e.to_string())??;
+        Ok(version.map(|version| ConsistencyToken {
+            version,
+            epoch: owner.epoch,
+        }))
+    }
+
+    /// Copies the events of a handed-over stream that local storage lacks
+    /// from `previous_owner`. Returns the new local head.
+    async fn copy_handed_over(
+        &self,
+        stream_id: &str,
+        previous_owner: &str,
+        epoch: u64,
+    ) -> Result<u64, String> {
+        let events = self
+            .cluster_client
+            .fetch_stream(previous_owner, stream_id, Vec::new())
+            .await?
+            .into_iter()
+            .map(Event::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        tracing::info!(stream_id = %stream_id, from = %previous_owner, epoch, events = events.len(), "Copying handed-over stream");
+
+        let worker_idx = self
+            .membership
+            .topology()
+            .partitioner()
+            .worker_slot(stream_id, self.workers.len());
+        let (resp_tx, resp_rx) = oneshot::channel();
+        let cmd = PipelineCommand::Replicate {
+            stream_id: stream_id.to_string(),
+            events,
+            resp_tx,
+        };
+        self.workers[worker_idx]
+            .send(cmd)
+            .await
+            .map_err(|e| e.to_string())?;
         resp_rx.await.map_err(|e| e.to_string())?
     }
 
+    fn forward_loop(&self, stream_id: &str, forward: &Forward) -> String {
+        format!(
+            "TopologyMismatchError: write for stream {} from {} (Epoch {}) reached node {} after {} hops without finding its owner; the nodes' topologies disagree",
+            stream_id, forward.origin, forward.epoch, self.identity.addr, forward.hops
+        )
+    }
+
+    /// Appends through the stream's Raft group. A follower forwards to the
+    /// group's leader, within the hop limit.
+    async fn append_via_consensus(
+        &self,
+        consensus: &RaftGroups,
+        stream_id: &str,
+        mut events: Vec<Event>,
+        expected_version: i64,
+        forwarded: Option<Forward>,
+        verbatim: bool,
+    ) -> Result<Option<ConsistencyToken>, String> {
+        let group = consensus.group_for(stream_id).ok_or_else(|| {
+            format!(
+                "NotOwnerError: Node {} is not in the Raft group of stream {}",
+                self.identity.addr, stream_id
+            )
+        })?;
+
+        let leader = group.status().await?.leader;
+        match leader {
+            Some(leader) if !self.identity.is(&leader) => {
+                // Members can disagree on the leader during an election
+                let forward = match forwarded {
+                    Some(f) if f.hops >= MAX_FORWARD_HOPS || f.origin == leader => {
+                        return Err(format!(
+                            "NotLeaderError: write for stream {} was forwarded {} times without reaching the leader of Raft group {}",
+                            stream_id, f.hops, group.id()
+                        ));
+                    }
+                    Some(f) => f.next(0),
+                    None => Forward::new(&self.identity.addr, 0),
+                };
+                return self
+                    .forward_append(
+                        &leader,
+                        stream_id,
+                        events,
+                        expected_version,
+                        forward,
+                        verbatim,
+                    )
+                    .await;
+            }
+            None => {
+                return Err(format!(
+                    "NotLeaderError: Raft group {} is electing a leader",
+                    group.id()
+                ));
+            }
+            _ => {}
+        }
+
+        if !verbatim {
+            self.check_for_append(stream_id, &mut events).await;
+        }
+        let count = events.len();
+
+        let worker_idx = consensus
+            .topology()
+            .partitioner()
+            .worker_slot(stream_id, self.workers.len());
+        let (resp_tx, resp_rx) = oneshot::channel();
+        let cmd = PipelineCommand::Propose {
+            stream_id: stream_id.to_string(),
+            events,
+            expected_version,
+            group: group.clone(),
+            resp_tx,
+        };
+        self.workers[worker_idx]
+            .send(cmd)
+            .await
+            .map_err(|e| e.to_string())?;
+        if !resp_rx.await.map_err(|e| e.to_string())?? {
+            return Ok(None);
+        }
+        let epoch = consensus.topology().epoch();
+        self.token_after(stream_id, expected_version, count, epoch)
+            .await
+            .map(Some)
+    }
+
+    /// Hands a message from another member to the local Raft group.
+    pub fn deliver_raft_message(&self, group: &str, payload: &[u8]) -> Result<(), String> {
+        let consensus = self
+            .consensus
+            .as_ref()
+            .ok_or("RaftError: this node does not run Raft groups")?;
+        let envelope: Envelope = serde_cbor::from_slice(payload).map_err(|e| e.to_string())?;
+        consensus.deliver(group, envelope);
+        Ok(())
+    }
+
+    /// Schema normalization and validation (soft fail).
+    async fn check_for_append(&self, stream_id: &str, events: &mut [Event]) {
+        for event in events.iter_mut() {
+            let check = self.check_event(event, None).await;
+            if !check.errors.is_empty() {
+                tracing::warn!(stream_id = %stream_id, event_type = %check.event_type, content_type = %event.content_type, errors = ?check.errors, "Schema validation failed (Soft Fail)");
+                // To enable Hard Fail: return Err(format!("Schema Validation Error: {:?}", check.errors));
+            }
+        }
+    }
+
+    /// Accepts a previous owner's confirmation that it drained its writes for
+    /// the ranges this node gained at `epoch`, with the heads of moved streams.
+    pub fn complete_handoff(&self, from: &str, epoch: u64, heads: Vec<(String, u64)>) -> bool {
+        self.handoff.complete_incoming(from, epoch, heads)
+    }
+
+    /// Applies events that `leader` replicated to this node as a follower.
+    /// Returns the local head version of the stream.
+    pub async fn apply_replicated(
+        &self,
+        stream_id: &str,
+        leader: &str,
+        epoch: u64,
+        events: Vec<Event>,
+    ) -> Result<u64, String> {
+        let topology = self.membership.topology();
+        if epoch < topology.epoch() {
+            return Err(format!(
+                "StaleEpochError: events for stream {} were replicated at Epoch {} but node {} is at Epoch {}",
+                stream_id, epoch, self.identity.addr, topology.epoch()
+            ));
+        }
+        let owner = topology.get_owner(stream_id);
+        if epoch == owner.epoch && owner.node_addr != leader {
+            return Err(format!(
+                "NotOwnerError: {} replicated stream {} but owner is {} (Epoch {})",
+                leader, stream_id, owner.node_addr, owner.epoch
+            ));
+        }
+
+        let worker_idx = topology
+            .partitioner()
+            .worker_slot(stream_id, self.workers.len());
+        let (resp_tx, resp_rx) = oneshot::channel();
+        let cmd = PipelineCommand::Replicate {
+            stream_id: stream_id.to_string(),
+            events,
+            resp_tx,
+        };
+        self.workers[worker_idx]
+            .send(cmd)
+            .await
+            .map_err(|e| e.to_string())?;
+        resp_rx.await.map_err(|e| e.to_string())?
+    }
+
+    /// Adds a node to the ring (or changes its weight) under a new epoch.
+    /// Unless `is_forwarded`, the change is also sent to every other node.
+    pub async fn add_node(&self, addr: &str, weight: u32, is_forwarded: bool) -> u64 {
+        let peers = self.peers();
+        self.membership.add_member(addr, weight);
+        if !is_forwarded {
+            for peer in peers.iter().filter(|p| p.as_str() != addr) {
+                if let Err(e) = self.cluster_client.add_node(peer, addr, weight).await {
+                    tracing::warn!(peer = %peer, error = %e, "Failed to propagate node addition");
+                }
+            }
+        }
+        self.membership.epoch()
+    }
+
+    /// Removes a node from the ring under a new epoch.
+    /// Unless `is_forwarded`, the change is also sent to every other node,
+    /// including the removed one.
+    pub async fn remove_node(&self, addr: &str, is_forwarded: bool) -> Result<u64, String> {
+        let peers = self.peers();
+        if self.membership.remove_member(addr).is_none() {
+            return Err(format!("NodeNotFound: {} is not a cluster member", addr));
+        }
+        if !is_forwarded {
+            for peer in &peers {
+                if let Err(e) = self.cluster_client.remove_node(peer, addr).await {
+                    tracing::warn!(peer = %peer, error = %e, "Failed to propagate node removal");
+                }
+            }
+        }
+        Ok(self.membership.epoch())
+    }
+
+    fn peers(&self) -> Vec<String> {
+        self.membership
+            .topology()
+            .get_all_nodes()
+            .iter()
+            .filter(|n| !self.identity.is(n))
+            .cloned()
+            .collect()
+    }
+
+    /// Waits until the local copy of a stream holds the write of `token`, at
+    /// most until `deadline`. Returns whether it does.
+    ///
+    /// Versions only compare within one history of the stream. A token from
+    /// an epoch this node has not reached yet fails with a StaleEpochError,
+    /// and one from before the last ring change is only waited out by the
+    /// stream's owner: the stream may have moved since, leaving other copies
+    /// with versions of a history the write is not part of.
+    pub async fn wait_for_token(
+        &self,
+        stream_id: &str,
+        token: &ConsistencyToken,
+        deadline: tokio::time::Instant,
+    ) -> Result<bool, String> {
+        let owner = self.membership.topology().get_owner(stream_id);
+        if token.epoch > owner.epoch {
+            return Err(format!(
+                "StaleEpochError: token for stream {} was issued at Epoch {} but node {} is at Epoch {}",
+                stream_id, token.epoch, self.identity.addr, owner.epoch
+            ));
+        }
+        if token.epoch < owner.epoch && !self.identity.is(&owner.node_addr) {
+            return Ok(false);
+        }
+        loop {
+            if self.stream_head(stream_id).await? >= token.version {
+                return Ok(true);
+            }
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            if remaining.is_zero() {
+                return Ok(false);
+            }
+            tokio::time::sleep(TOKEN_POLL_INTERVAL.min(remaining)).await;
+        }
+    }
+
+    /// Streams this node holds events of.
+    pub async fn list_streams(&self) -> Result<Vec<String>, String> {
+        self.storage.list_streams().await.map_err(|e| e.to_string())
+    }
+
+    /// Streams any member of the cluster holds events of, sorted. Fails if a
+    /// member cannot be asked, rather than leaving its streams out.
+    pub async fn list_cluster_streams(&self) -> Result<Vec<String>, String> {
+        let mut streams: BTreeSet<String> = self.list_streams().await?.into_iter().collect();
+        for peer in self.peers() {
+            streams.extend(self.cluster_client.list_streams(&peer).await?);
+        }
+        Ok(streams.into_iter().collect())
+    }
+
+    /// Digests of this node's copies of streams.
+    pub async fn stream_digests(&self, stream_ids: &[String]) -> Result<Vec<StreamDigest>, String> {
+        let mut digests = Vec::with_capacity(stream_ids.len());
+        for stream_id in stream_ids {
+            digests.push(StreamDigest::of(&self.fetch_stream(stream_id).await?));
+        }
+        Ok(digests)
+    }
+
+    /// Nodes that should hold copies of a stream this node owns, besides
+    /// itself. Empty for streams owned elsewhere, and in raft mode, where
+    /// groups repair their members themselves.
+    pub fn copies_of(&self, stream_id: &str) -> Vec<String> {
+        match &self.replicator {
+            Some(replicator) if self.consensus.is_none() => {
+                let owner = self.topology().get_owner(stream_id);
+                if self.identity.is(&owner.node_addr) {
+                    replicator.copies(stream_id)
+                } else {
+                    Vec::new()
+                }
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    async fn stream_head(&self, stream_id: &str) -> Result<u64, String> {
+        self.storage
+            .stream_head(stream_id)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// Runs the append-time schema steps (type resolution, schema lookup,
+    /// normalization, validation) without persisting anything.
+    ///
+    /// An inline schema replaces the registered one for events of the type it names,
+    /// so a new schema version can be tried before it is upserted.
+    pub async fn validate_events(
+        &self,
+        stream_id: &str,
+        events: Vec<Event>,
+        inline_schema: Option<Schema>,
+    ) -> Result<Vec<(Event, EventCheck)>, String> {
+        if let Some(schema) = &inline_schema {
+            check_schema(schema)?;
+        }
+
+        let mut results = Vec::with_capacity(events.len());
+        for mut event in events {
+            event.stream_id = stream_id.to_string();
+            let check = self.check_event(&mut event, inline_schema.as_ref()).await;
+            results.push((event, check));
+        }
+        Ok(results)
+    }
+
+    async fn check_event(&self, event: &mut Event, inline_schema: Option<&Schema>) -> EventCheck {
+        let event_type = format!("{:?}", event.event_type);
+        let mut check = EventCheck {
+            event_type,
+            schema_found: false,
+            normalized: false,
+            errors: Vec::new(),
+        };
+
+        // Opaque payloads (protobuf, octet-stream, custom) cannot be checked
+        if !event.content_type.is_structured() {
+            return check;
+        }
+
+        let schema = match inline_schema.filter(|s| s.name == check.event_type) {
+            Some(s) => s.clone(),
+            None => match self.storage.get_schema(&check.event_type).await {
+                Ok(Some(s)) => s,
+                _ => return check,
+            },
+        };
+        check.schema_found = true;
+
+        match crate::domain::schema::normalize::normalize_event(event, &schema) {
+            Ok(changed) => check.normalized = changed,
+            Err(e) => {
+                // Undecodable payload: validation would report the same error
+                check.errors.push(e);
+                return check;
+            }
+        }
+
+        if let Err(errs) = crate::domain::schema::validation::validate_event(event, &schema) {
+            check.errors = errs;
+        }
+        check
+    }
+
     pub async fn fetch_stream(&self, stream_id: &str) -> Result<Vec<Event>, String> {
         self.storage
             .fetch_stream(stream_id)
@@ -162,23 +836,675 @@ impl EventPipeline {
             .map_err(|e| e.to_string())
     }
 
-    pub async fn upsert_schema(
+    /// Events of a stream after `from_version`.
+    pub async fn fetch_stream_from(
         &self,
-        schema: crate::domain::schema::model::Schema,
-    ) -> Result<(), String> {
+        stream_id: &str,
+        from_version: u64,
+    ) -> Result<Vec<Event>, String> {
         self.storage
-            .upsert_schema(schema)
+            .fetch_stream_from(stream_id, from_version)
             .await
             .map_err(|e| e.to_string())
     }
 
-    pub async fn get_schema(
+    /// In Raft mode, waits until this node confirmed with a majority that it
+    /// still leads the stream's group and applied every write committed
+    /// before the call, so that the local copy of the stream is up to date.
+    /// Fails with NotLeaderError on the other members.
+    pub async fn confirm_leadership(&self, stream_id: &str) -> Result<(), String> {
+        let Some(consensus) = &self.consensus else {
+            return Ok(());
+        };
+        let group = consensus.group_for(stream_id).ok_or_else(|| {
+            format!(
+                "NotLeaderError: {} is not in the Raft group of stream {}",
+                self.identity.addr, stream_id
+            )
+        })?;
+        group.read_index().await.map(|_| ())
+    }
+
+    /// The node a stream operation should be served by, or None to serve it
+    /// here. Writes to a stream always use `ReadConsistency::Owner`. In Raft
+    /// mode, the leader confirms its leadership before serving a read.
+    pub async fn route(
         &self,
-        name: &str,
-    ) -> Result<Option<crate::domain::schema::model::Schema>, String> {
+        stream_id: &str,
+        consistency: ReadConsistency,
+    ) -> Result<Option<String>, String> {
+        if consistency == ReadConsistency::Local {
+            return Ok(None);
+        }
+
+        let target = match &self.consensus {
+            Some(consensus) => {
+                let leader = match consensus.group_for(stream_id) {
+                    Some(group) => group.status().await.ok().and_then(|s| s.leader),
+                    None => None,
+                };
+                // Without a known leader, any member can pass the request on
+                match leader.or_else(|| consensus.members_of(stream_id).into_iter().next()) {
+                    Some(target) => target,
+                    None => {
+                        return Err(format!(
+                            "NotOwnerError: Raft group of stream {} has no members",
+                            stream_id
+                        ))
+                    }
+                }
+            }
+            None => self.membership.topology().get_owner(stream_id).node_addr,
+        };
+        if !self.identity.is(&target) {
+            return Ok(Some(target));
+        }
+        self.confirm_leadership(stream_id).await?;
+        Ok(None)
+    }
+
+    /// Like `route`, for reads that a read replica serves from its own copy
+    /// unless it lags behind the members by more than `max_staleness`.
+    pub async fn route_read(
+        &self,
+        stream_id: &str,
+        consistency: ReadConsistency,
+        max_staleness: Option<Duration>,
+    ) -> Result<Option<String>, String> {
+        let Some(lag) = self.replica_lag() else {
+            return self.route(stream_id, consistency).await;
+        };
+        let stale = max_staleness.is_some_and(|max| lag > max);
+        if stale && consistency == ReadConsistency::Owner {
+            self.route(stream_id, consistency).await
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// How far behind the members this node is, if it is a read replica.
+    pub fn replica_lag(&self) -> Option<Duration> {
+        let replica = self.replica.as_ref()?;
+        Some(replica.lag(self.topology().get_all_nodes()))
+    }
+
+    /// Records the heartbeat of a member, which holds no writes this read
+    /// replica misses, and follows its topology.
+    pub fn record_heartbeat(
+        &self,
+        member: &str,
+        version: RingVersion,
+        members: Vec<Member>,
+    ) -> Result<(), String> {
+        let replica = self.replica.as_ref().ok_or_else(|| {
+            format!(
+                "NotReplicaError: node {} is not a read replica",
+                self.identity.addr
+            )
+        })?;
+        self.membership.merge(members, Some(version));
+        replica.record(member);
+        Ok(())
+    }
+
+    /// Fails on read replicas, which take writes from the members only.
+    pub fn check_writable(&self) -> Result<(), String> {
+        if self.replica.is_some() {
+            return Err(format!(
+                "ReadOnlyError: node {} is a read replica and does not accept writes",
+                self.identity.addr
+            ));
+        }
+        Ok(())
+    }
+
+    /// Client for forwarding requests to other nodes.
+    pub fn cluster_client(&self) -> &ClusterClient {
+        &self.cluster_client
+    }
+
+    /// Current topology. Changes at runtime when gossip is enabled.
+    pub fn topology(&self) -> Arc<ClusterTopology> {
+        self.membership.topology()
+    }
+
+    pub fn membership(&self) -> &Arc<Membership> {
+        &self.membership
+    }
+
+    pub fn identity(&self) -> &NodeIdentity {
+        &self.identity
+    }
+
+    /// Returns the projected current state of a stream, or its state as of `at_version`.
+    pub async fn get_state(
+        &self,
+        stream_id: &str,
+        at_version: Option<u64>,
+    ) -> Result<Option<StreamState>, String> {
+        self.projector.get_state(stream_id, at_version).await
+    }
+
+    /// Stores a schema locally. Unless `is_forwarded`, it is also sent to
+    /// every other node, so that appends are validated alike wherever they
+    /// land. Returns the nodes that could not be reached.
+    pub async fn upsert_schema(
+        &self,
+        schema: Schema,
+        is_forwarded: bool,
+    ) -> Result<Vec<String>, String> {
+        check_schema(&schema)?;
+        self.check_writable()?;
+
+        self.storage
+            .upsert_schema(schema.clone())
+            .await
+            .map_err(|e| e.to_string())?;
+        crate::domain::schema::rules::register_rules(&schema);
+
+        let mut unreached = Vec::new();
+        if !is_forwarded {
+            for peer in self.peers() {
+                if let Err(e) = self.cluster_client.upsert_schema(&peer, &schema).await {
+                    tracing::warn!(peer = %peer, schema = %schema.name, error = %e, "Failed to propagate schema");
+                    unreached.push(peer);
+                }
+            }
+        }
+        Ok(unreached)
+    }
+
+    pub async fn get_schema(&self, name: &str) -> Result<Option<Schema>, String> {
         self.storage
             .get_schema(name)
             .await
             .map_err(|e| e.to_string())
     }
 }
+
+fn check_schema(schema: &Schema) -> Result<(), String> {
+    crate::domain::schema::rules::check_rules(schema).map_err(|errs| {
+        let details: Vec<String> = errs.iter().map(|e| e.to_string()).collect();
+        format!("InvalidSchema: {}", details.join("; "))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::events::event_kind::{EventKind, EventPayload};
+    use crate::domain::schema::model::{
+        Field, FieldConstraints, FieldType, NormalizationSettings, PrimitiveType,
+    };
+    use crate::storage::memory::InMemoryEventStore;
+    use crate::storage::rocksdb::state_store::RocksStateStore;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn pipeline(storage: Arc<InMemoryEventStore>, dir: &TempDir) -> EventPipeline {
+        let db = Arc::new(rocksdb::DB::open_default(dir.path()).unwrap());
+        EventPipeline::new(
+            storage,
+            Arc::new(RocksStateStore::new(db)),
+            ClusterTopology::new(vec!["127.0.0.1:50051".to_string()], 0),
+            NodeIdentity::new(None, "127.0.0.1:50051"),
+            ClusterClient::default(),
+            None,
+            ReplicationConfig::default(),
+        )
+    }
+
+    fn age_schema(min_age: f64) -> Schema {
+        let mut fields = HashMap::new();
+        fields.insert(
+            "age".to_string(),
+            Field {
+                field_type: FieldType::Primitive(PrimitiveType::Number),
+                nullable: false,
+                overrides_on_null: false,
+                constraints: Some(FieldConstraints {
+                    required: true,
+                    min_value: Some(min_age),
+                    ..Default::default()
+                }),
+                default_value: None,
+            },
+        );
+        Schema {
+            name: "Internal".to_string(),
+            fields,
+            normalization: NormalizationSettings {
+                coerce_numeric_strings: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn event(payload: &str) -> Event {
+        Event::new(
+            "",
+            EventKind::Internal,
+            EventPayload(payload.as_bytes().to_vec()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_validate_events_dry_run() {
+        let dir = TempDir::new().unwrap();
+        let storage = Arc::new(InMemoryEventStore::new());
+        storage.upsert_schema(age_schema(18.0)).await.unwrap();
+        let pipeline = pipeline(storage.clone(), &dir);
+
+        let events = vec![event(r#"{"age":"30"}"#), event(r#"{"age":10}"#)];
+        let results = pipeline
+            .validate_events("user-1", events.clone(), None)
+            .await
+            .unwrap();
+
+        assert!(results[0].1.schema_found);
+        assert!(results[0].1.normalized);
+        assert!(results[0].1.errors.is_empty());
+        assert_eq!(results[0].0.payload.0, br#"{"age":30}"#.to_vec());
+        assert_eq!(results[1].1.errors[0].code(), "MIN_VALUE");
+
+        // Inline schema takes precedence over the registered one
+        let results = pipeline
+            .validate_events("user-1", events, Some(age_schema(5.0)))
+            .await
+            .unwrap();
+        assert!(results.iter().all(|(_, check)| check.errors.is_empty()));
+
+        // Nothing was persisted
+        assert!(storage.fetch_stream("user-1").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_copied_events_are_stored_verbatim() {
+        let dir = TempDir::new().unwrap();
+        let storage = Arc::new(InMemoryEventStore::new());
+        storage.upsert_schema(age_schema(18.0)).await.unwrap();
+        let pipeline = pipeline(storage.clone(), &dir);
+
+        pipeline
+            .append_event("user-1", vec![event(r#"{"age":"30"}"#)], 0)
+            .await
+            .unwrap()
+            .unwrap();
+        let copied = pipeline
+            .copy_events("user-1", vec![event(r#"{"age":"40"}"#)], 1)
+            .await
+            .unwrap();
+        assert_eq!(copied.map(|t| t.version), Some(2));
+        // Expected versions still hold for copies
+        assert_eq!(
+            pipeline
+                .copy_events("user-1", vec![event(r#"{"age":"50"}"#)], 1)
+                .await,
+            Ok(None)
+        );
+
+        let stored = storage.fetch_stream("user-1").await.unwrap();
+        assert_eq!(stored[0].payload.0, br#"{"age":30}"#.to_vec());
+        assert_eq!(stored[1].payload.0, br#"{"age":"40"}"#.to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_writes_are_fenced_by_epoch() {
+        let forward = |epoch| Forward::new("127.0.0.1:50052", epoch);
+        let dir = TempDir::new().unwrap();
+        let pipeline = pipeline(Arc::new(InMemoryEventStore::new()), &dir);
+
+        let ok = pipeline
+            .append_event_as_owner("user-1", vec![event("{}")], -1, Some(forward(0)), false)
+            .await;
+        assert_eq!(
+            ok,
+            Ok(Some(ConsistencyToken {
+                version: 1,
+                epoch: 0
+            }))
+        );
+
+        // Applied locally only, as if propagated by another node
+        let epoch = pipeline.add_node("127.0.0.1:1", 1, true).await;
+        assert_eq!(epoch, 1);
+
+        // Routed at an older epoch, even to the owner at both: the sender is
+        // told the current owner and epoch to route again
+        let stale = pipeline
+            .append_event_as_owner("user-1", vec![event("{}")], -1, Some(forward(0)), false)
+            .await
+            .unwrap_err();
+        assert!(stale.contains("StaleEpochError"), "{}", stale);
+        assert!(
+            stale.contains("owner is 127.0.0.1:50051 (Epoch 1)"),
+            "{}",
+            stale
+        );
+
+        // Routed at the current epoch, once the change to it has been processed
+        let current = loop {
+            let res = pipeline
+                .append_event_as_owner("user-1", vec![event("{}")], -1, Some(forward(1)), false)
+                .await;
+            match res {
+                Err(e) if e.contains("HandoffPendingError") => {
+                    tokio::time::sleep(Duration::from_millis(10)).await
+                }
+                res => break res,
+            }
+        };
+        assert!(matches!(current, Ok(Some(_))), "{:?}", current);
+        // Routed at an epoch this node has not seen yet
+        let ahead = pipeline
+            .append_event_as_owner("user-1", vec![event("{}")], -1, Some(forward(2)), false)
+            .await
+            .unwrap_err();
+        assert!(ahead.contains("HandoffPendingError"), "{}", ahead);
+
+        let moved = (0..)
+            .map(|i| format!("stream-{}", i))
+            .find(|s| pipeline.topology().get_owner(s).node_addr == "127.0.0.1:1")
+            .unwrap();
+        let err = pipeline
+            .append_event_as_owner(&moved, vec![event("{}")], -1, Some(forward(1)), false)
+            .await
+            .unwrap_err();
+        assert!(err.contains("NotOwnerError"));
+
+        // Sent back to the node that forwarded it
+        let looped = Forward::new("127.0.0.1:1", 1);
+        let err = pipeline
+            .append_event_as_owner(&moved, vec![event("{}")], -1, Some(looped), false)
+            .await
+            .unwrap_err();
+        assert!(err.contains("TopologyMismatchError"), "{}", err);
+
+        let mut exhausted = Forward::new("127.0.0.1:9", 1);
+        exhausted.hops = MAX_FORWARD_HOPS + 1;
+        let err = pipeline
+            .append_event_as_owner("user-1", vec![event("{}")], -1, Some(exhausted), false)
+            .await
+            .unwrap_err();
+        assert!(err.contains("TopologyMismatchError"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_reads_are_routed_to_the_owner() {
+        let dir = TempDir::new().unwrap();
+        let pipeline = pipeline(Arc::new(InMemoryEventStore::new()), &dir);
+        assert_eq!(
+            pipeline.route("user-1", ReadConsistency::Owner).await,
+            Ok(None)
+        );
+
+        pipeline.add_node("127.0.0.1:1", 1, true).await;
+        let moved = (0..)
+            .map(|i| format!("stream-{}", i))
+            .find(|s| pipeline.topology().get_owner(s).node_addr == "127.0.0.1:1")
+            .unwrap();
+        assert_eq!(
+            pipeline.route(&moved, ReadConsistency::Owner).await,
+            Ok(Some("127.0.0.1:1".to_string()))
+        );
+        assert_eq!(
+            pipeline.route(&moved, ReadConsistency::Local).await,
+            Ok(None)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_replicas_serve_reads_within_their_lag() {
+        let dir = TempDir::new().unwrap();
+        let db = Arc::new(rocksdb::DB::open_default(dir.path()).unwrap());
+        let member = "127.0.0.1:50051";
+        let replica = EventPipeline::new(
+            Arc::new(InMemoryEventStore::new()),
+            Arc::new(RocksStateStore::new(db)),
+            ClusterTopology::new(vec![member.to_string()], 0),
+            NodeIdentity::new(None, "127.0.0.1:50061").with_role(NodeRole::ReadReplica),
+            ClusterClient::default(),
+            None,
+            ReplicationConfig::default(),
+        );
+        // Not on the ring, and read-only
+        assert_eq!(replica.topology().get_all_nodes(), [member.to_string()]);
+        let err = replica
+            .append_event("user-1", vec![event("{}")], -1)
+            .await
+            .unwrap_err();
+        assert!(err.contains("ReadOnlyError"), "{}", err);
+
+        let mut versioned = event("{}");
+        versioned.sequence_number = 1;
+        assert_eq!(
+            replica
+                .apply_replicated("user-1", member, 0, vec![versioned])
+                .await,
+            Ok(1)
+        );
+
+        let bound = Some(Duration::from_millis(500));
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert_eq!(
+            replica
+                .route_read("user-1", ReadConsistency::Owner, bound)
+                .await,
+            Ok(Some(member.to_string()))
+        );
+        assert_eq!(
+            replica
+                .route_read("user-1", ReadConsistency::Owner, None)
+                .await,
+            Ok(None)
+        );
+
+        let members = replica.membership().members();
+        let version = replica.topology().version();
+        replica.record_heartbeat(member, version, members).unwrap();
+        assert!(replica.replica_lag().unwrap() < Duration::from_millis(500));
+        assert_eq!(
+            replica
+                .route_read("user-1", ReadConsistency::Owner, bound)
+                .await,
+            Ok(None)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_followers_apply_replicated_events_in_order() {
+        let dir = TempDir::new().unwrap();
+        let storage = Arc::new(InMemoryEventStore::new());
+        let pipeline = pipeline(storage.clone(), &dir);
+        let leader = "127.0.0.1:50051";
+        let versioned = |version| {
+            let mut e = event("{}");
+            e.sequence_number = version;
+            e
+        };
+
+        let second = versioned(2);
+        let head = pipeline
+            .apply_replicated("user-1", leader, 0, vec![versioned(1), second.clone()])
+            .await;
+        assert_eq!(head, Ok(2));
+
+        // Redelivered events are skipped; a gap stops the batch
+        let head = pipeline
+            .apply_replicated(
+                "user-1",
+                leader,
+                0,
+                vec![second, versioned(3), versioned(5)],
+            )
+            .await;
+        assert_eq!(head, Ok(3));
+        assert_eq!(storage.fetch_stream("user-1").await.unwrap().len(), 3);
+
+        // Another event at a version already held is a divergence, not a redelivery
+        let err = pipeline
+            .apply_replicated("user-1", leader, 0, vec![versioned(3), versioned(4)])
+            .await
+            .unwrap_err();
+        assert!(err.contains("DivergenceError"), "{}", err);
+        assert_eq!(storage.fetch_stream("user-1").await.unwrap().len(), 3);
+
+        let err = pipeline
+            .apply_replicated("user-1", "127.0.0.1:1", 0, vec![versioned(4)])
+            .await
+            .unwrap_err();
+        assert!(err.contains("NotOwnerError"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_writes_require_the_stream_lease() {
+        use crate::cluster::lease::InMemoryLeaseStore;
+
+        let dir = TempDir::new().unwrap();
+        let leases = Arc::new(InMemoryLeaseStore::new());
+        // Another node still holds a live lease, e.g. a previous owner cut off by a partition
+        leases
+            .acquire("user-1", "10.0.0.9:50051", 0, u64::MAX)
+            .await
+            .unwrap();
+
+        let db = Arc::new(rocksdb::DB::open_default(dir.path()).unwrap());
+        let pipeline = EventPipeline::new(
+            Arc::new(InMemoryEventStore::new()),
+            Arc::new(RocksStateStore::new(db)),
+            ClusterTopology::new(vec!["127.0.0.1:50051".to_string()], 0),
+            NodeIdentity::new(None, "127.0.0.1:50051"),
+            ClusterClient::default(),
+            Some(leases),
+            ReplicationConfig::default(),
+        );
+
+        let err = pipeline
+            .append_event("user-1", vec![event("{}")], -1)
+            .await
+            .unwrap_err();
+        assert!(err.contains("LeaseHeldError"), "{}", err);
+        assert!(matches!(
+            pipeline.append_event("user-2", vec![event("{}")], -1).await,
+            Ok(Some(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_writes_fail_without_the_lease_store() {
+        use crate::cluster::lease::LeaseOutcome;
+
+        struct Unreachable;
+        #[tonic::async_trait]
+        impl LeaseStore for Unreachable {
+            async fn acquire(
+                &self,
+                _: &str,
+                _: &str,
+                _: u64,
+                _: u64,
+            ) -> Result<LeaseOutcome, String> {
+                Err("connection refused".into())
+            }
+            async fn release(&self, _: &str, _: &str) -> Result<(), String> {
+                Ok(())
+            }
+        }
+
+        let dir = TempDir::new().unwrap();
+        let db = Arc::new(rocksdb::DB::open_default(dir.path()).unwrap());
+        let store = Arc::new(InMemoryEventStore::new());
+        let pipeline = EventPipeline::new(
+            store.clone(),
+            Arc::new(RocksStateStore::new(db)),
+            ClusterTopology::new(vec!["127.0.0.1:50051".to_string()], 0),
+            NodeIdentity::new(None, "127.0.0.1:50051"),
+            ClusterClient::default(),
+            Some(Arc::new(Unreachable)),
+            ReplicationConfig::default(),
+        );
+
+        let err = pipeline
+            .append_event("user-1", vec![event("{}")], -1)
+            .await
+            .unwrap_err();
+        assert!(err.contains("LeaseStoreError"), "{}", err);
+        assert!(store.fetch_stream("user-1").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reads_wait_for_their_consistency_token() {
+        use tokio::time::{Duration, Instant};
+
+        let dir = TempDir::new().unwrap();
+        let pipeline = pipeline(Arc::new(InMemoryEventStore::new()), &dir);
+        let token = pipeline
+            .append_event("user-1", vec![event("{}"), event("{}")], 0)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(token.version, 2);
+
+        let soon = Instant::now() + Duration::from_millis(30);
+        assert_eq!(
+            pipeline.wait_for_token("user-1", &token, soon).await,
+            Ok(true)
+        );
+
+        // A write this node has not seen yet
+        let ahead = ConsistencyToken {
+            version: 3,
+            epoch: 0,
+        };
+        assert_eq!(
+            pipeline.wait_for_token("user-1", &ahead, soon).await,
+            Ok(false)
+        );
+
+        // A write made under a ring this node has not seen
+        let newer = ConsistencyToken {
+            version: 1,
+            epoch: 1,
+        };
+        let err = pipeline
+            .wait_for_token("user-1", &newer, soon)
+            .await
+            .unwrap_err();
+        assert!(err.contains("StaleEpochError"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_tokens_from_before_a_move_are_left_to_the_owner() {
+        use tokio::time::{Duration, Instant};
+
+        let dir = TempDir::new().unwrap();
+        let pipeline = pipeline(Arc::new(InMemoryEventStore::new()), &dir);
+        let before = ConsistencyToken {
+            version: 0,
+            epoch: pipeline.membership.epoch(),
+        };
+        pipeline.membership.add_member("127.0.0.1:50052", 1);
+        let topology = pipeline.topology();
+        let owned_by = |addr: &str| {
+            (0..100)
+                .map(|i| format!("user-{}", i))
+                .find(|s| topology.get_owner(s).node_addr == addr)
+                .unwrap()
+        };
+        let soon = Instant::now() + Duration::from_millis(30);
+
+        // Still the owner after the ring changed: its copy is the stream
+        let kept = owned_by("127.0.0.1:50051");
+        assert_eq!(
+            pipeline.wait_for_token(&kept, &before, soon).await,
+            Ok(true)
+        );
+        // Moved away: the local copy may not hold the write, however far along
+        let moved = owned_by("127.0.0.1:50052");
+        assert_eq!(
+            pipeline.wait_for_token(&moved, &before, soon).await,
+            Ok(false)
+        );
+    }
+}
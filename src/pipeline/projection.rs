@@ -0,0 +1,314 @@
+use crate::domain::events::event::Event;
+use crate::domain::events::event_kind::Timestamp;
+use crate::domain::schema::merge::merge_patch;
+use crate::domain::schema::model::Schema;
+use crate::domain::schema::validation::decode_payload;
+use crate::storage::event_store::EventStore;
+use crate::storage::state::{StateStore, StreamState};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Maintains the built-in "current state" projection.
+///
+/// Events whose type has a schema with `project_state` enabled are folded, in
+/// stream order, into a single JSON document per stream using the schema's
+/// merge semantics. Other events only advance the projected version.
+pub struct StateProjector {
+    storage: Arc<dyn EventStore + Send + Sync>,
+    states: Arc<dyn StateStore>,
+}
+
+impl StateProjector {
+    pub fn new(storage: Arc<dyn EventStore + Send + Sync>, states: Arc<dyn StateStore>) -> Self {
+        Self { storage, states }
+    }
+
+    /// Called by the stream's worker after a successful append.
+    /// Skips the catch-up entirely unless one of the appended types is projected.
+    pub async fn on_append(&self, stream_id: &str, event_types: &[String]) -> Result<(), String> {
+        let mut projected = false;
+        for type_str in event_types {
+            if let Some(schema) = self.schema(type_str).await? {
+                projected |= schema.project_state;
+            }
+        }
+
+        if projected {
+            self.refresh(stream_id).await?;
+        }
+        Ok(())
+    }
+
+    /// Folds every event appended since the stored state and persists the result.
+    /// Only the stream's worker calls it, so that the folds of a stream are
+    /// saved in order.
+    pub async fn refresh(&self, stream_id: &str) -> Result<Option<StreamState>, String> {
+        let (state, advanced) = self.catch_up(stream_id).await?;
+        if let Some(state) = state.as_ref().filter(|_| advanced) {
+            self.states
+                .save_state(state.clone())
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(state)
+    }
+
+    /// The stored state with the events appended since folded in, and
+    /// whether there were any. Nothing is saved.
+    async fn catch_up(&self, stream_id: &str) -> Result<(Option<StreamState>, bool), String> {
+        let stored = self
+            .states
+            .get_state(stream_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let from_version = stored.as_ref().map(|s| s.version).unwrap_or(0);
+
+        let pending = self.fetch_stream_from(stream_id, from_version).await?;
+        let head = match pending.last() {
+            Some(e) => e.sequence_number,
+            None => return Ok((stored, false)),
+        };
+
+        let (document, projected) = self.fold(base_document(stored.as_ref())?, &pending).await?;
+        if stored.is_none() && !projected {
+            // Not a schema-governed stream
+            return Ok((None, false));
+        }
+
+        Ok((
+            Some(StreamState {
+                stream_id: stream_id.to_string(),
+                version: head,
+                document: serde_json::to_vec(&document).map_err(|e| e.to_string())?,
+                timestamp: Timestamp::now().0,
+            }),
+            true,
+        ))
+    }
+
+    /// Returns the current state, or the state as of `at_version` by replaying
+    /// the stream up to that version. Only the worker persists states.
+    pub async fn get_state(
+        &self,
+        stream_id: &str,
+        at_version: Option<u64>,
+    ) -> Result<Option<StreamState>, String> {
+        let at_version = match at_version {
+            Some(v) => v,
+            None => return Ok(self.catch_up(stream_id).await?.0),
+        };
+
+        let stored = self
+            .states
+            .get_state(stream_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        // Reuse the stored state when it is not ahead of the requested version
+        let base = stored.filter(|s| s.version <= at_version);
+        if let Some(s) = &base {
+            if s.version == at_version {
+                return Ok(base);
+            }
+        }
+        let from_version = base.as_ref().map(|s| s.version).unwrap_or(0);
+
+        let mut pending = self.fetch_stream_from(stream_id, from_version).await?;
+        pending.retain(|e| e.sequence_number <= at_version);
+
+        let (document, projected) = self.fold(base_document(base.as_ref())?, &pending).await?;
+        if base.is_none() && !projected {
+            return Ok(None);
+        }
+
+        Ok(Some(StreamState {
+            stream_id: stream_id.to_string(),
+            version: pending
+                .last()
+                .map(|e| e.sequence_number)
+                .unwrap_or(from_version),
+            document: serde_json::to_vec(&document).map_err(|e| e.to_string())?,
+            timestamp: Timestamp::now().0,
+        }))
+    }
+
+    /// Folds events into `document`. Returns whether any event was projected.
+    async fn fold(&self, mut document: Value, events: &[Event]) -> Result<(Value, bool), String> {
+        let mut schemas: HashMap<String, Option<Schema>> = HashMap::new();
+        let mut projected = false;
+
+        for event in events {
+            let type_str = format!("{:?}", event.event_type);
+            if !schemas.contains_key(&type_str) {
+                let schema = self.schema(&type_str).await?;
+                schemas.insert(type_str.clone(), schema);
+            }
+            let schema = match &schemas[&type_str] {
+                Some(s) if s.project_state => s,
+                _ => continue,
+            };
+
+            match decode_payload(&event.payload.0, &event.content_type) {
+                Some(Ok(patch)) => {
+                    merge_patch(&mut document, &patch, &schema.fields);
+                    projected = true;
+                }
+                Some(Err(e)) => {
+                    tracing::warn!(stream_id = %event.stream_id, version = event.sequence_number, error = %e, "Skipping undecodable event in state projection");
+                }
+                None => {}
+            }
+        }
+
+        Ok((document, projected))
+    }
+
+    async fn schema(&self, type_str: &str) -> Result<Option<Schema>, String> {
+        self.storage
+            .get_schema(type_str)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn fetch_stream_from(&self, stream_id: &str, version: u64) -> Result<Vec<Event>, String> {
+        self.storage
+            .fetch_stream_from(stream_id, version)
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+fn base_document(state: Option<&StreamState>) -> Result<Value, String> {
+    match state {
+        Some(s) => serde_json::from_slice(&s.document).map_err(|e| e.to_string()),
+        None => Ok(Value::Object(Default::default())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::events::event_kind::{EventKind, EventPayload};
+    use crate::domain::schema::model::{Field, FieldType, PrimitiveType};
+    use crate::storage::memory::InMemoryEventStore;
+    use crate::storage::rocksdb::state_store::RocksStateStore;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    async fn setup(dir: &TempDir) -> (Arc<InMemoryEventStore>, StateProjector) {
+        let storage = Arc::new(InMemoryEventStore::new());
+        let mut fields = HashMap::new();
+        fields.insert(
+            "email".to_string(),
+            Field {
+                field_type: FieldType::Primitive(PrimitiveType::String),
+                nullable: true,
+                overrides_on_null: false,
+                constraints: None,
+                default_value: None,
+            },
+        );
+        storage
+            .upsert_schema(Schema {
+                name: "Internal".to_string(),
+                fields,
+                project_state: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let db = Arc::new(rocksdb::DB::open_default(dir.path()).unwrap());
+        let projector = StateProjector::new(storage.clone(), Arc::new(RocksStateStore::new(db)));
+        (storage, projector)
+    }
+
+    async fn append(storage: &InMemoryEventStore, version: u64, kind: EventKind, doc: Value) {
+        let payload = EventPayload(serde_json::to_vec(&doc).unwrap());
+        storage
+            .append_event("user-1", Event::new("user-1", kind, payload), version)
+            .await
+            .unwrap();
+    }
+
+    fn document(state: &StreamState) -> Value {
+        serde_json::from_slice(&state.document).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fold_current_and_historical_state() {
+        let dir = TempDir::new().unwrap();
+        let (storage, projector) = setup(&dir).await;
+
+        append(
+            &storage,
+            0,
+            EventKind::Internal,
+            json!({ "name": "Alice", "email": "a@x" }),
+        )
+        .await;
+        append(
+            &storage,
+            1,
+            EventKind::Internal,
+            json!({ "name": "Alicia", "email": null }),
+        )
+        .await;
+        // Not projected: advances the version only
+        append(
+            &storage,
+            2,
+            EventKind::Transactional,
+            json!({ "name": "ignored" }),
+        )
+        .await;
+
+        projector
+            .on_append("user-1", &["Internal".to_string()])
+            .await
+            .unwrap();
+
+        let current = projector.get_state("user-1", None).await.unwrap().unwrap();
+        assert_eq!(current.version, 3);
+        assert_eq!(
+            document(&current),
+            json!({ "name": "Alicia", "email": "a@x" })
+        );
+
+        let first = projector
+            .get_state("user-1", Some(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.version, 1);
+        assert_eq!(document(&first), json!({ "name": "Alice", "email": "a@x" }));
+
+        // Reads fold newer events without persisting them
+        append(&storage, 3, EventKind::Internal, json!({ "name": "Al" })).await;
+        let current = projector.get_state("user-1", None).await.unwrap().unwrap();
+        assert_eq!(current.version, 4);
+        let stored = projector.states.get_state("user-1").await.unwrap().unwrap();
+        assert_eq!(stored.version, 3);
+    }
+
+    #[tokio::test]
+    async fn test_ungoverned_stream_has_no_state() {
+        let dir = TempDir::new().unwrap();
+        let (storage, projector) = setup(&dir).await;
+
+        append(
+            &storage,
+            0,
+            EventKind::Transactional,
+            json!({ "amount": 1 }),
+        )
+        .await;
+
+        assert!(projector.get_state("user-1", None).await.unwrap().is_none());
+        assert!(projector
+            .get_state("user-1", Some(1))
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
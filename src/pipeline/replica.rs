@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often members send read replicas their heartbeat.
+pub const REPLICA_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How far a read replica is behind the members.
+///
+/// Each member heartbeats the replica once every write it owns has been
+/// shipped there, so the replica holds every write made before the oldest of
+/// the members' last heartbeats. Members never heard from count from the
+/// replica's start.
+pub struct ReplicaLag {
+    started: Instant,
+    heartbeats: Mutex<HashMap<String, Instant>>,
+}
+
+impl Default for ReplicaLag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplicaLag {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            heartbeats: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, member: &str) {
+        self.heartbeats
+            .lock()
+            .unwrap()
+            .insert(member.to_string(), Instant::now());
+    }
+
+    /// Lag behind `members`, the nodes on the ring.
+    pub fn lag(&self, members: &[String]) -> Duration {
+        let heartbeats = self.heartbeats.lock().unwrap();
+        members
+            .iter()
+            .map(|m| heartbeats.get(m).copied().unwrap_or(self.started))
+            .min()
+            .map(|oldest| oldest.elapsed())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lag_follows_the_oldest_heartbeat() {
+        let lag = ReplicaLag::new();
+        let members = vec!["a:1".to_string(), "b:1".to_string()];
+        std::thread::sleep(Duration::from_millis(20));
+        lag.record("a:1");
+        // b was never heard from
+        assert!(lag.lag(&members) >= Duration::from_millis(20));
+        lag.record("b:1");
+        assert!(lag.lag(&members) < Duration::from_millis(20));
+        assert_eq!(lag.lag(&[]), Duration::ZERO);
+    }
+}
@@ -0,0 +1,377 @@
+use crate::cluster::client::{current_deadline, ClusterClient};
+use crate::cluster::membership::Membership;
+use crate::domain::events::event::Event;
+use crate::pipeline::replica::REPLICA_HEARTBEAT_INTERVAL;
+use crate::storage::event_store::EventStore;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+/// Events sent per request when catching a lagging follower up.
+const CATCH_UP_BATCH: usize = 500;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReplicationMode {
+    /// The stream's owner copies its writes to the followers.
+    #[default]
+    Leader,
+    /// Each replica set is a Raft group, and writes go through its log.
+    Raft,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReplicationConfig {
+    pub mode: ReplicationMode,
+    /// Copies of each stream, the owner's included. 1 disables replication.
+    pub factor: usize,
+    /// Copies, the owner's included, that must hold the events before an
+    /// append succeeds. Raft always waits for a majority.
+    pub write_acks: usize,
+    /// How long the owner waits for followers to acknowledge an append.
+    pub ack_timeout: Duration,
+    /// Read replicas the owner also copies its writes to, without waiting
+    /// for them.
+    pub read_replicas: Vec<String>,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        Self {
+            mode: ReplicationMode::Leader,
+            factor: 1,
+            write_acks: 1,
+            ack_timeout: Duration::from_secs(3),
+            read_replicas: Vec::new(),
+        }
+    }
+}
+
+impl ReplicationConfig {
+    /// Whether the owner replicates writes itself.
+    pub fn enabled(&self) -> bool {
+        self.mode == ReplicationMode::Leader && (self.factor > 1 || !self.read_replicas.is_empty())
+    }
+}
+
+/// Leader side of stream replication.
+///
+/// The owner of a stream is its leader; the next `factor - 1` nodes on the ring
+/// are its followers, so when the leader leaves the ring its first follower
+/// becomes the owner and already holds the stream. Followers only apply events
+/// in version order: one that misses earlier events reports its head and is
+/// sent the rest from the leader's log.
+///
+/// Read replicas get every write as well, but appends do not wait for them.
+/// Before its first heartbeat, and again after it was unreachable, a read
+/// replica is caught up on every stream owned here, so that the heartbeat
+/// also covers the writes made before it started.
+pub struct Replicator {
+    config: ReplicationConfig,
+    membership: Arc<Membership>,
+    storage: Arc<dyn EventStore + Send + Sync>,
+    cluster_client: ClusterClient,
+    feeds: HashMap<String, Arc<ReplicaFeed>>,
+}
+
+/// Writes on their way to a read replica.
+#[derive(Default)]
+struct ReplicaFeed {
+    in_flight: AtomicUsize,
+    /// Streams the replica failed to take, sent again before the next
+    /// heartbeat.
+    failed: Mutex<HashSet<String>>,
+    /// Whether the replica was caught up on the streams owned here since it
+    /// was last unreachable.
+    bootstrapped: AtomicBool,
+}
+
+impl Replicator {
+    pub fn new(
+        config: ReplicationConfig,
+        membership: Arc<Membership>,
+        storage: Arc<dyn EventStore + Send + Sync>,
+        cluster_client: ClusterClient,
+    ) -> Self {
+        let feeds = config
+            .read_replicas
+            .iter()
+            .map(|r| (r.clone(), Arc::default()))
+            .collect();
+        Self {
+            config,
+            membership,
+            storage,
+            cluster_client,
+            feeds,
+        }
+    }
+
+    /// Nodes holding copies of a stream owned here: its followers and the
+    /// read replicas.
+    pub fn copies(&self, stream_id: &str) -> Vec<String> {
+        let mut copies: Vec<String> = self
+            .membership
+            .topology()
+            .replicas_for(stream_id, self.config.factor)
+            .into_iter()
+            .filter(|n| !self.membership.is_self(n))
+            .collect();
+        copies.extend(self.feeds.keys().cloned());
+        copies
+    }
+
+    pub fn has_read_replicas(&self) -> bool {
+        !self.feeds.is_empty()
+    }
+
+    /// Copies events just written locally at `epoch` to the stream's followers.
+    ///
+    /// The copies are sent right away, each from its own task, so copies of
+    /// successive writes can overtake each other. Followers only apply events
+    /// in version order and report their head, from which a follower left
+    /// behind is caught up with the local log.
+    ///
+    /// The returned future waits for the copies: it resolves to their number
+    /// once `write_acks` of them exist. Followers that have not answered
+    /// within `ack_timeout`, or by the deadline of the request it runs under,
+    /// keep being caught up in the background.
+    ///
+    /// The events are already persisted here: an `UnderReplicatedError` means
+    /// the write happened with too few copies, not that it failed.
+    pub fn replicate(
+        &self,
+        stream_id: &str,
+        epoch: u64,
+        events: &[Event],
+    ) -> impl Future<Output = Result<usize, String>> + Send + 'static {
+        self.feed_replicas(stream_id, epoch, events);
+
+        let followers: Vec<String> = self
+            .membership
+            .topology()
+            .replicas_for(stream_id, self.config.factor)
+            .into_iter()
+            .filter(|n| !self.membership.is_self(n))
+            .collect();
+
+        let (tx, mut rx) = mpsc::channel(followers.len().max(1));
+        for follower in followers {
+            let sync = self.sync(&follower, stream_id, epoch);
+            let events = events.to_vec();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let res = sync.run(events).await;
+                if let Err(e) = &res {
+                    tracing::warn!(stream_id = %sync.stream_id, follower = %sync.follower, error = %e, "Replication to follower failed");
+                }
+                let _ = tx.send(res.is_ok()).await;
+            });
+        }
+        drop(tx);
+
+        let write_acks = self.config.write_acks;
+        let mut ack_timeout = self.config.ack_timeout;
+        let stream_id = stream_id.to_string();
+        let version = events.last().map(|e| e.sequence_number).unwrap_or(0);
+        async move {
+            let mut copies = 1;
+            let wait = async {
+                while copies < write_acks {
+                    match rx.recv().await {
+                        Some(true) => copies += 1,
+                        Some(false) => {}
+                        None => break,
+                    }
+                }
+            };
+            if let Some(deadline) = current_deadline() {
+                ack_timeout = ack_timeout.min(deadline.saturating_duration_since(Instant::now()));
+            }
+            let _ = tokio::time::timeout(ack_timeout, wait).await;
+
+            if copies < write_acks {
+                return Err(format!(
+                    "UnderReplicatedError: stream {} was persisted at version {} with {} of {} required copies",
+                    stream_id, version, copies, write_acks
+                ));
+            }
+            Ok(copies)
+        }
+    }
+
+    fn sync(&self, target: &str, stream_id: &str, epoch: u64) -> FollowerSync {
+        FollowerSync {
+            client: self.cluster_client.clone(),
+            storage: self.storage.clone(),
+            leader: self.membership.self_addr().to_string(),
+            follower: target.to_string(),
+            stream_id: stream_id.to_string(),
+            epoch,
+        }
+    }
+
+    fn feed_replicas(&self, stream_id: &str, epoch: u64, events: &[Event]) {
+        for (replica, feed) in &self.feeds {
+            let sync = self.sync(replica, stream_id, epoch);
+            let events = events.to_vec();
+            let feed = feed.clone();
+            feed.in_flight.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                if let Err(e) = sync.run(events).await {
+                    tracing::debug!(stream_id = %sync.stream_id, replica = %sync.follower, error = %e, "Replication to read replica failed");
+                    feed.failed.lock().unwrap().insert(sync.stream_id.clone());
+                }
+                feed.in_flight.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    }
+
+    /// Heartbeats the read replicas, each time one holds every write made
+    /// here so far. Streams a replica failed to take are sent again first.
+    pub async fn run_heartbeats(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(REPLICA_HEARTBEAT_INTERVAL);
+        loop {
+            interval.tick().await;
+            for (replica, feed) in &self.feeds {
+                if let Err(e) = self.heartbeat(replica, feed).await {
+                    tracing::debug!(replica = %replica, error = %e, "Read replica heartbeat skipped");
+                }
+            }
+        }
+    }
+
+    async fn heartbeat(&self, replica: &str, feed: &ReplicaFeed) -> Result<(), String> {
+        let topology = self.membership.topology();
+        if !feed.bootstrapped.load(Ordering::SeqCst) {
+            self.bootstrap(replica).await?;
+            feed.bootstrapped.store(true, Ordering::SeqCst);
+        }
+
+        let failed: Vec<String> = feed.failed.lock().unwrap().drain().collect();
+        for stream_id in failed {
+            let epoch = topology.get_owner(&stream_id).epoch;
+            match self.sync_latest(replica, &stream_id, epoch).await {
+                Ok(()) => {}
+                // The stream moved: its new owner catches the replica up
+                Err(e) if e.contains("NotOwnerError") || e.contains("StaleEpochError") => {}
+                Err(e) => {
+                    feed.failed.lock().unwrap().insert(stream_id);
+                    return Err(e);
+                }
+            }
+        }
+        if feed.in_flight.load(Ordering::SeqCst) > 0 {
+            return Err("writes still in flight".to_string());
+        }
+        let res = self
+            .cluster_client
+            .replica_heartbeat(
+                replica,
+                self.membership.self_addr(),
+                topology.version(),
+                self.membership.members(),
+            )
+            .await;
+        if res.is_err() {
+            // It may have restarted without the writes of its downtime
+            feed.bootstrapped.store(false, Ordering::SeqCst);
+        }
+        res
+    }
+
+    /// Catches a read replica up on every stream owned here.
+    async fn bootstrap(&self, replica: &str) -> Result<(), String> {
+        let topology = self.membership.topology();
+        let streams = self
+            .storage
+            .list_streams()
+            .await
+            .map_err(|e| e.to_string())?;
+        for stream_id in streams {
+            let owner = topology.get_owner(&stream_id);
+            if !self.membership.is_self(&owner.node_addr) {
+                continue;
+            }
+            match self.sync_latest(replica, &stream_id, owner.epoch).await {
+                Ok(()) => {}
+                Err(e) if e.contains("NotOwnerError") || e.contains("StaleEpochError") => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the last event of a stream to `target`, which is then sent
+    /// whatever else it misses.
+    async fn sync_latest(&self, target: &str, stream_id: &str, epoch: u64) -> Result<(), String> {
+        let head = self
+            .storage
+            .stream_head(stream_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let latest = self
+            .storage
+            .fetch_stream_from(stream_id, head.saturating_sub(1))
+            .await
+            .map_err(|e| e.to_string())?;
+        self.sync(target, stream_id, epoch).run(latest).await
+    }
+}
+
+/// Brings one follower up to date with a batch of new events.
+struct FollowerSync {
+    client: ClusterClient,
+    storage: Arc<dyn EventStore + Send + Sync>,
+    leader: String,
+    follower: String,
+    stream_id: String,
+    epoch: u64,
+}
+
+impl FollowerSync {
+    async fn run(&self, events: Vec<Event>) -> Result<(), String> {
+        let last = events.last().map(|e| e.sequence_number).unwrap_or(0);
+        let mut head = self.send(events).await?;
+        if head >= last {
+            return Ok(());
+        }
+
+        // The follower lags: send what it misses from the local log
+        let missing = self
+            .storage
+            .fetch_stream_from(&self.stream_id, head)
+            .await
+            .map_err(|e| e.to_string())?;
+        for batch in missing.chunks(CATCH_UP_BATCH) {
+            let next = self.send(batch.to_vec()).await?;
+            if next <= head {
+                return Err(format!("follower stuck at version {}", head));
+            }
+            head = next;
+        }
+
+        if head < last {
+            return Err(format!(
+                "follower at version {} after catch-up to {}",
+                head, last
+            ));
+        }
+        Ok(())
+    }
+
+    async fn send(&self, events: Vec<Event>) -> Result<u64, String> {
+        self.client
+            .replicate(
+                &self.follower,
+                &self.leader,
+                &self.stream_id,
+                self.epoch,
+                events,
+            )
+            .await
+    }
+}
@@ -1,17 +1,41 @@
+use crate::cluster::client::with_deadline;
+use crate::cluster::lease::LeaseManager;
+use crate::cluster::membership::Membership;
 use crate::domain::events::event::Event;
 use crate::pipeline::command::PipelineCommand;
+use crate::pipeline::projection::StateProjector;
+use crate::pipeline::replication::Replicator;
 use crate::storage::event_store::EventStore;
+use crate::storage::rocksdb::raft::AppendCommand;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 
 pub struct Worker {
     _id: usize,
     store: Arc<dyn EventStore + Send + Sync>,
+    projector: Arc<StateProjector>,
+    membership: Arc<Membership>,
+    leases: Option<Arc<LeaseManager>>,
+    replicator: Option<Arc<Replicator>>,
 }
 
 impl Worker {
-    pub fn new(_id: usize, store: Arc<dyn EventStore + Send + Sync>) -> Self {
-        Self { _id, store }
+    pub fn new(
+        _id: usize,
+        store: Arc<dyn EventStore + Send + Sync>,
+        projector: Arc<StateProjector>,
+        membership: Arc<Membership>,
+        leases: Option<Arc<LeaseManager>>,
+        replicator: Option<Arc<Replicator>>,
+    ) -> Self {
+        Self {
+            _id,
+            store,
+            projector,
+            membership,
+            leases,
+            replicator,
+        }
     }
 
     pub async fn run(self, mut rx: mpsc::Receiver<PipelineCommand>) {
@@ -21,35 +45,149 @@ impl Worker {
                     stream_id,
                     mut events,
                     expected_version,
+                    epoch,
+                    deadline,
                     resp_tx,
                 } => {
+                    if let Err(e) = self.check_fence(&stream_id, epoch) {
+                        let _ = resp_tx.send(Err(e));
+                        continue;
+                    }
+                    // With a shared store, only the lease holder may write. Without
+                    // the lease store, no node can tell who holds the stream, so the
+                    // write is refused rather than risk a second writer.
+                    let term = match &self.leases {
+                        Some(leases) => match leases.ensure(&stream_id).await {
+                            Ok(term) => Some(term),
+                            Err(e) => {
+                                let _ = resp_tx.send(Err(e));
+                                continue;
+                            }
+                        },
+                        None => None,
+                    };
+
+                    let mut event_types: Vec<String> = events
+                        .iter()
+                        .map(|e| format!("{:?}", e.event_type))
+                        .collect();
+                    event_types.dedup();
+
+                    let mut appended = Vec::new();
                     let res = self
-                        .handle_append(&stream_id, &mut events, expected_version)
+                        .handle_append(
+                            &stream_id,
+                            &mut events,
+                            expected_version,
+                            term,
+                            &mut appended,
+                        )
                         .await;
+
+                    // Project while still serialized on this stream's worker
+                    if let Ok(Some(_)) = res {
+                        if let Err(e) = self.projector.on_append(&stream_id, &event_types).await {
+                            tracing::warn!(stream_id = %stream_id, error = %e, "State projection update failed");
+                        }
+                    }
+
+                    // Copies of consecutive writes may reach a follower out of
+                    // order: it stops at the gap, and the copy of the earlier write
+                    // (or a catch-up from the local log) fills it. Only this write
+                    // waits for their acks: a slow follower must not hold up the
+                    // other streams of this worker.
+                    if let (Some(replicator), false) = (&self.replicator, appended.is_empty()) {
+                        let acks = replicator.replicate(&stream_id, epoch, &appended);
+                        tokio::spawn(async move {
+                            let res = match (res, with_deadline(deadline, acks).await) {
+                                (Ok(Some(_)), Err(e)) => Err(e),
+                                (res, _) => res,
+                            };
+                            let _ = resp_tx.send(res);
+                        });
+                        continue;
+                    }
                     let _ = resp_tx.send(res);
                 }
+                PipelineCommand::Propose {
+                    stream_id,
+                    events,
+                    expected_version,
+                    group,
+                    resp_tx,
+                } => {
+                    let mut event_types: Vec<String> = events
+                        .iter()
+                        .map(|e| format!("{:?}", e.event_type))
+                        .collect();
+                    event_types.dedup();
+
+                    let command = AppendCommand {
+                        stream_id: stream_id.clone(),
+                        events,
+                        expected_version,
+                    };
+                    let res = match command.encode() {
+                        Ok(bytes) => group
+                            .propose(bytes)
+                            .await
+                            .and_then(|r| AppendCommand::decode_response(&r)),
+                        Err(e) => Err(e),
+                    };
+
+                    if let Ok(true) = res {
+                        if let Err(e) = self.projector.on_append(&stream_id, &event_types).await {
+                            tracing::warn!(stream_id = %stream_id, error = %e, "State projection update failed");
+                        }
+                    }
+                    let _ = resp_tx.send(res);
+                }
+                PipelineCommand::Replicate {
+                    stream_id,
+                    events,
+                    resp_tx,
+                } => {
+                    let res = self.apply_replicated(&stream_id, events).await;
+                    let _ = resp_tx.send(res);
+                }
+                PipelineCommand::Barrier { resp_tx } => {
+                    let _ = resp_tx.send(());
+                }
             }
         }
     }
 
+    /// Rejects writes queued before a topology change that moved their stream away.
+    fn check_fence(&self, stream_id: &str, epoch: u64) -> Result<(), String> {
+        let topology = self.membership.topology();
+        if topology.epoch() == epoch {
+            return Ok(());
+        }
+        let owner = topology.get_owner(stream_id);
+        if !self.membership.is_self(&owner.node_addr) {
+            return Err(format!(
+                "NotOwnerError: stream {} moved to {} (Epoch {}) while the write was queued",
+                stream_id, owner.node_addr, owner.epoch
+            ));
+        }
+        Ok(())
+    }
+
     async fn handle_append(
         &self,
         stream_id: &str,
         events: &mut Vec<Event>,
         expected_version: i64,
-    ) -> Result<bool, String> {
+        lease_term: Option<u64>,
+        appended: &mut Vec<Event>,
+    ) -> Result<Option<u64>, String> {
         // 1. Resolve expected version
         let mut current_version_u64 = if expected_version == -1 {
-            // "Any" version: Fetch tail to determine next
-            let current_events = self
-                .store
-                .fetch_stream(stream_id)
+            // "Any" version: append after the current head
+            self.store
+                .stream_head(stream_id)
                 .await
-                .map_err(|e| e.to_string())?;
-            current_events
-                .last()
-                .map(|e| e.sequence_number)
-                .unwrap_or(0)
+                .map_err(|e| e.to_string())?
         } else {
             // Specific version expected
             if expected_version < 0 {
@@ -69,6 +207,13 @@ impl Worker {
         // To fix this proper, EventStore needs transaction support.
         // But with OCC, we at least won't corrupt data, just stop.
         for event in events.drain(..) {
+            // The lease may have lapsed since it was checked, behind a slow
+            // store or the previous events
+            if let (Some(leases), Some(term)) = (&self.leases, lease_term) {
+                leases.check(stream_id, term)?;
+            }
+            // Followers need the appended events with their versions
+            let copy = self.replicator.as_ref().map(|_| event.clone());
             match self
                 .store
                 .append_event(stream_id, event, current_version_u64)
@@ -76,15 +221,75 @@ impl Worker {
             {
                 Ok(_) => {
                     current_version_u64 += 1;
+                    if let Some(mut copy) = copy {
+                        copy.sequence_number = current_version_u64;
+                        appended.push(copy);
+                    }
                 }
                 Err(e) => {
                     tracing::error!("Failed to append event to stream {}: {}", stream_id, e);
                     // Abort batch
-                    return Ok(false);
+                    return Ok(None);
                 }
             }
         }
 
-        Ok(true)
+        Ok(Some(current_version_u64))
+    }
+
+    /// Applies events replicated by the leader in version order, skipping
+    /// those already held. Stops at a gap, so the leader learns from the
+    /// returned head what it has to send again.
+    async fn apply_replicated(&self, stream_id: &str, events: Vec<Event>) -> Result<u64, String> {
+        let mut head = self
+            .store
+            .stream_head(stream_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        // Events already held that the batch delivers again
+        let held = match events.first() {
+            Some(first) if first.sequence_number <= head => self
+                .store
+                .fetch_stream_from(stream_id, first.sequence_number.saturating_sub(1))
+                .await
+                .map_err(|e| e.to_string())?,
+            _ => Vec::new(),
+        };
+
+        let mut event_types = Vec::new();
+        for mut event in events {
+            if event.sequence_number <= head {
+                // A redelivered event must be the one already held at its version
+                let local = held
+                    .iter()
+                    .find(|e| e.sequence_number == event.sequence_number);
+                if let Some(local) = local.filter(|local| local.id.0 != event.id.0) {
+                    return Err(format!(
+                        "DivergenceError: stream {} holds event {} at version {}, the leader sent {}",
+                        stream_id, local.id.0, event.sequence_number, event.id.0
+                    ));
+                }
+                continue;
+            }
+            if event.sequence_number != head + 1 {
+                break;
+            }
+            event.stream_id = stream_id.to_string();
+            event_types.push(format!("{:?}", event.event_type));
+            self.store
+                .append_event(stream_id, event, head)
+                .await
+                .map_err(|e| e.to_string())?;
+            head += 1;
+        }
+
+        // Keep the projected state ready in case this follower is promoted
+        if !event_types.is_empty() {
+            event_types.dedup();
+            if let Err(e) = self.projector.on_append(stream_id, &event_types).await {
+                tracing::warn!(stream_id = %stream_id, error = %e, "State projection update failed");
+            }
+        }
+        Ok(head)
     }
 }
@@ -0,0 +1,98 @@
+use crate::domain::events::event::Event;
+use xxhash_rust::xxh64::Xxh64;
+
+/// Summary of a copy of a stream, cheap to exchange and compare.
+///
+/// `hash` chains the hashes of the events in order, so two copies with the
+/// same count and hash hold the same events, and the digest of a prefix of a
+/// stream tells whether another copy is that prefix.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamDigest {
+    pub count: u64,
+    /// Version of the last event, 0 for an empty stream.
+    pub head: u64,
+    pub hash: u64,
+}
+
+impl StreamDigest {
+    pub fn of(events: &[Event]) -> Self {
+        events.iter().fold(Self::default(), |digest, event| Self {
+            count: digest.count + 1,
+            head: event.sequence_number,
+            hash: event_hash(digest.hash, event),
+        })
+    }
+}
+
+impl From<StreamDigest> for crate::api::StreamDigest {
+    fn from(d: StreamDigest) -> Self {
+        crate::api::StreamDigest {
+            count: d.count,
+            head: d.head,
+            hash: d.hash,
+        }
+    }
+}
+
+impl From<crate::api::StreamDigest> for StreamDigest {
+    fn from(d: crate::api::StreamDigest) -> Self {
+        StreamDigest {
+            count: d.count,
+            head: d.head,
+            hash: d.hash,
+        }
+    }
+}
+
+fn event_hash(seed: u64, event: &Event) -> u64 {
+    let mut hasher = Xxh64::new(seed);
+    hasher.update(event.id.0.as_bytes());
+    hasher.update(&event.sequence_number.to_le_bytes());
+    hasher.update(format!("{:?}", event.event_type).as_bytes());
+    hasher.update(event.content_type.to_string().as_bytes());
+    hasher.update(&event.timestamp.0.to_le_bytes());
+    let mut metadata: Vec<_> = event.metadata.iter().collect();
+    metadata.sort();
+    for (key, value) in metadata {
+        hasher.update(key.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(value.as_bytes());
+        hasher.update(&[0]);
+    }
+    hasher.update(&event.payload.0);
+    hasher.digest()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::events::event_kind::{EventKind, EventPayload};
+
+    fn stream(payloads: &[u8]) -> Vec<Event> {
+        payloads
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let mut event = Event::new("s", EventKind::Internal, EventPayload(vec![*p]));
+                event.sequence_number = i as u64 + 1;
+                event
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_digests_tell_prefixes_from_divergence() {
+        let events = stream(&[1, 2, 3]);
+        let digest = StreamDigest::of(&events);
+        assert_eq!((digest.count, digest.head), (3, 3));
+        assert_eq!(StreamDigest::of(&events.clone()), digest);
+        assert_eq!(StreamDigest::of(&[]), StreamDigest::default());
+
+        let prefix = StreamDigest::of(&events[..2]);
+        assert_ne!(prefix, digest);
+
+        let mut changed = events.clone();
+        changed[1].payload = EventPayload(vec![9]);
+        assert_ne!(StreamDigest::of(&changed[..2]), prefix);
+    }
+}
@@ -15,6 +15,14 @@ pub enum EventStoreError {
     Unknown(String),
 }
 
+impl EventStoreError {
+    /// Whether the store failed to serve the call, rather than refused it.
+    /// Only then may another store serve it instead.
+    pub fn is_unavailable(&self) -> bool {
+        matches!(self, EventStoreError::StorageError(_))
+    }
+}
+
 /// Abstract storage interface for persistence.
 ///
 /// Implementations (e.g., RocksDB, ScyllaDB, Memory) must ensure:
@@ -37,6 +45,26 @@ pub trait EventStore: Send + Sync {
     /// Retrieves all events for a given stream, ordered by sequence number.
     async fn fetch_stream(&self, stream: &str) -> Result<Vec<Event>, EventStoreError>;
 
+    /// Retrieves the events of a stream after `version`, ordered by sequence number.
+    async fn fetch_stream_from(
+        &self,
+        stream: &str,
+        version: u64,
+    ) -> Result<Vec<Event>, EventStoreError> {
+        let mut events = self.fetch_stream(stream).await?;
+        events.retain(|e| e.sequence_number > version);
+        Ok(events)
+    }
+
+    /// Version of the last event of a stream, 0 when it has none.
+    async fn stream_head(&self, stream: &str) -> Result<u64, EventStoreError> {
+        let events = self.fetch_stream(stream).await?;
+        Ok(events.last().map(|e| e.sequence_number).unwrap_or(0))
+    }
+
+    /// Lists the streams holding events, in no particular order.
+    async fn list_streams(&self) -> Result<Vec<String>, EventStoreError>;
+
     /// Registers or updates a schema.
     async fn upsert_schema(
         &self,
@@ -1,18 +1,379 @@
+use crate::cluster::breaker::{BreakerState, CircuitBreaker};
 use crate::domain::events::event::Event;
 use crate::domain::schema::model::Schema;
 use crate::storage::event_store::{EventStore, EventStoreError};
-use std::sync::Arc;
+use crate::storage::rocksdb::outbox::RocksOutbox;
+use opentelemetry::metrics::{Counter, Gauge};
+use opentelemetry::KeyValue;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tonic::async_trait;
-use tracing::warn;
+use tracing::{error, info, warn};
 
+/// Outbox entries replayed per read of the outbox.
+const REPLAY_BATCH: usize = 256;
+
+/// Which store a `HybridEventStore` serves from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageMode {
+    /// The primary, with nothing left to replay into it.
+    Primary,
+    /// The fallback: the primary is failing and its circuit is open.
+    Degraded,
+    /// The primary, while the writes the fallback took are replayed into it.
+    Recovering,
+}
+
+impl StorageMode {
+    const ALL: [StorageMode; 3] = [Self::Primary, Self::Degraded, Self::Recovering];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Primary => "primary",
+            Self::Degraded => "degraded",
+            Self::Recovering => "recovering",
+        }
+    }
+}
+
+impl From<StorageMode> for crate::api::StorageMode {
+    fn from(mode: StorageMode) -> Self {
+        match mode {
+            StorageMode::Primary => crate::api::StorageMode::Primary,
+            StorageMode::Degraded => crate::api::StorageMode::Degraded,
+            StorageMode::Recovering => crate::api::StorageMode::Recovering,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StorageStatus {
+    pub mode: StorageMode,
+    /// Fallback writes not yet replayed into the primary.
+    pub pending_writes: u64,
+    /// Streams whose fallback writes conflicted with the primary.
+    pub conflicted_streams: Vec<String>,
+}
+
+/// Outcome of one replay of the outbox into the primary.
+#[derive(Debug, Default, PartialEq)]
+pub struct ReconcileReport {
+    pub replayed: usize,
+    /// Streams flagged as conflicting.
+    pub conflicts: Vec<String>,
+}
+
+/// Serves from a primary store, and from a fallback while the primary is
+/// unavailable.
+///
+/// Only availability errors fail over: a conflict or an invalid request
+/// reported by the primary is returned as is. After repeated failures a
+/// circuit breaker stops calling the primary, then lets a single probe
+/// through once its cooldown is over.
+///
+/// With an outbox, the fallback copy of a stream continues the primary's
+/// from the last head the primary reported for it. A stream whose head was
+/// never seen takes no writes while the primary is unavailable, and only the
+/// events the fallback holds of a stream are read from it.
 pub struct HybridEventStore {
     primary: Arc<dyn EventStore>,
     fallback: Arc<dyn EventStore>,
+    outbox: Option<RocksOutbox>,
+    breaker: CircuitBreaker,
+    /// Last head the primary reported for each stream.
+    primary_heads: Mutex<HashMap<String, u64>>,
+    mode: Mutex<StorageMode>,
+    mode_gauge: Gauge<u64>,
+    replayed: Counter<u64>,
+    conflicts: Counter<u64>,
 }
 
 impl HybridEventStore {
     pub fn new(primary: Arc<dyn EventStore>, fallback: Arc<dyn EventStore>) -> Self {
-        Self { primary, fallback }
+        let meter = opentelemetry::global::meter("graveyar_db");
+        let store = Self {
+            primary,
+            fallback,
+            outbox: None,
+            breaker: CircuitBreaker::new(5, Duration::from_secs(5)),
+            primary_heads: Mutex::new(HashMap::new()),
+            mode: Mutex::new(StorageMode::Primary),
+            mode_gauge: meter
+                .u64_gauge("storage.mode")
+                .with_description("1 for the mode the event store is in, 0 for the others")
+                .build(),
+            replayed: meter
+                .u64_counter("fallback_outbox.replayed")
+                .with_description("Fallback writes replayed into the primary store")
+                .build(),
+            conflicts: meter
+                .u64_counter("fallback_outbox.conflicts")
+                .with_description("Streams whose fallback writes conflicted with the primary store")
+                .build(),
+        };
+        store.record_mode(StorageMode::Primary);
+        store
+    }
+
+    /// Records fallback writes in `outbox`, which must wrap the fallback
+    /// store, so that `reconcile` can replay them into the primary. Until
+    /// they are, writes to the same streams also go to the outbox, to keep
+    /// their order, and reads of the primary include them.
+    pub fn with_outbox(mut self, outbox: RocksOutbox) -> Self {
+        self.outbox = Some(outbox);
+        self
+    }
+
+    /// Stops calling the primary after `threshold` consecutive availability
+    /// errors, for `cooldown` before each probe (default 5 and 5s).
+    pub fn with_breaker(mut self, threshold: u32, cooldown: Duration) -> Self {
+        self.breaker = CircuitBreaker::new(threshold, cooldown);
+        self
+    }
+
+    /// Records the outcome of a call to the primary. Returns whether it was
+    /// unavailable, in which case the caller falls back.
+    fn record<T>(&self, result: &Result<T, EventStoreError>) -> bool {
+        let before = self.breaker.state();
+        let unavailable = matches!(result, Err(e) if e.is_unavailable());
+        if unavailable {
+            self.breaker.record_failure();
+        } else {
+            self.breaker.record_success();
+        }
+        if self.breaker.state() != before {
+            self.refresh_mode();
+        }
+        unavailable
+    }
+
+    /// Remembers that the primary copy of `stream` reached `head`.
+    fn note_primary_head(&self, stream: &str, head: u64) {
+        let mut heads = self.primary_heads.lock().unwrap();
+        let known = heads.entry(stream.to_string()).or_insert(head);
+        *known = (*known).max(head);
+    }
+
+    /// The head the fallback copy of `stream` continues from, or an
+    /// unavailability error when the primary never reported it.
+    fn primary_head(&self, stream: &str) -> Result<u64, EventStoreError> {
+        self.primary_heads
+            .lock()
+            .unwrap()
+            .get(stream)
+            .copied()
+            .ok_or_else(|| {
+                EventStoreError::StorageError(format!(
+                    "Primary Storage is unavailable and the head of stream {} is unknown",
+                    stream
+                ))
+            })
+    }
+
+    /// Events of `stream` after `version` from the fallback, which only
+    /// holds those after the primary head it continues from.
+    async fn fetch_fallback_from(
+        &self,
+        stream: &str,
+        version: u64,
+    ) -> Result<Vec<Event>, EventStoreError> {
+        if self.outbox.is_some() {
+            let head = self.primary_head(stream)?;
+            if version < head {
+                return Err(EventStoreError::StorageError(format!(
+                    "Primary Storage is unavailable and holds stream {} up to version {}",
+                    stream, head
+                )));
+            }
+        }
+        self.fallback.fetch_stream_from(stream, version).await
+    }
+
+    fn record_mode(&self, mode: StorageMode) {
+        for m in StorageMode::ALL {
+            self.mode_gauge
+                .record(u64::from(m == mode), &[KeyValue::new("mode", m.as_str())]);
+        }
+    }
+
+    /// Works out the current mode, and reports it if it changed.
+    fn refresh_mode(&self) -> StorageMode {
+        let pending = self.outbox.as_ref().is_some_and(|outbox| {
+            outbox
+                .pending(1)
+                .map(|entries| !entries.is_empty())
+                .unwrap_or(false)
+        });
+        let mode = if self.breaker.state() != BreakerState::Closed {
+            StorageMode::Degraded
+        } else if pending {
+            StorageMode::Recovering
+        } else {
+            StorageMode::Primary
+        };
+        let previous = std::mem::replace(&mut *self.mode.lock().unwrap(), mode);
+        if previous != mode {
+            info!(
+                from = previous.as_str(),
+                to = mode.as_str(),
+                "Storage mode changed"
+            );
+            self.record_mode(mode);
+        }
+        mode
+    }
+
+    pub fn status(&self) -> Result<StorageStatus, EventStoreError> {
+        let mode = self.refresh_mode();
+        let (pending_writes, conflicted_streams) = match &self.outbox {
+            Some(outbox) => (
+                outbox.pending_count()?,
+                outbox
+                    .conflicts()?
+                    .into_iter()
+                    .map(|c| c.stream_id)
+                    .collect(),
+            ),
+            None => (0, Vec::new()),
+        };
+        Ok(StorageStatus {
+            mode,
+            pending_writes,
+            conflicted_streams,
+        })
+    }
+
+    async fn append_fallback(
+        &self,
+        stream: &str,
+        event: Event,
+        expected_version: u64,
+    ) -> Result<(), EventStoreError> {
+        match &self.outbox {
+            Some(outbox) => {
+                let primary_head = self.primary_heads.lock().unwrap().get(stream).copied();
+                outbox
+                    .append(stream, event, expected_version, primary_head)
+                    .await
+            }
+            None => {
+                self.fallback
+                    .append_event(stream, event, expected_version)
+                    .await
+            }
+        }
+    }
+
+    /// `events` of the primary followed by the outbox entries of the stream,
+    /// if they continue them.
+    fn with_pending(&self, stream: &str, mut events: Vec<Event>) -> Vec<Event> {
+        let Some(outbox) = &self.outbox else {
+            return events;
+        };
+        match outbox.pending_for(stream) {
+            Ok(pending) => {
+                let head = events.len() as u64;
+                if pending.first().is_some_and(|e| e.expected_version == head) {
+                    events.extend(pending.into_iter().map(|e| e.event));
+                } else if !pending.is_empty() {
+                    warn!(stream = %stream, "Fallback writes do not continue the primary stream, awaiting reconciliation");
+                }
+            }
+            Err(e) => warn!(stream = %stream, error = %e, "Failed to read the outbox"),
+        }
+        events
+    }
+
+    /// Replays the outbox into the primary, oldest entry first, and removes
+    /// what was replayed. An entry the primary no longer accepts at its
+    /// version flags its stream as conflicting, with all its later entries.
+    /// Stops at the first other failure of the primary, or while its circuit
+    /// is open, to be retried later.
+    pub async fn reconcile(&self) -> Result<ReconcileReport, EventStoreError> {
+        let mut report = ReconcileReport::default();
+        let Some(outbox) = &self.outbox else {
+            return Ok(report);
+        };
+        loop {
+            let entries = outbox.pending(REPLAY_BATCH)?;
+            if entries.is_empty() {
+                self.refresh_mode();
+                return Ok(report);
+            }
+            let mut flagged = HashSet::new();
+            for entry in entries {
+                if flagged.contains(&entry.stream_id) {
+                    continue;
+                }
+                if !self.breaker.allow() {
+                    return Err(EventStoreError::StorageError(
+                        "Primary Storage circuit is open".to_string(),
+                    ));
+                }
+                let result = self
+                    .primary
+                    .append_event(
+                        &entry.stream_id,
+                        entry.event.clone(),
+                        entry.expected_version,
+                    )
+                    .await;
+                self.record(&result);
+                match result {
+                    Ok(()) => self.note_primary_head(&entry.stream_id, entry.expected_version + 1),
+                    Err(EventStoreError::ConcurrencyError { .. }) => {
+                        // Not taken from the error: the Scylla primary does not report its head
+                        let actual = self.primary.stream_head(&entry.stream_id).await?;
+                        self.note_primary_head(&entry.stream_id, actual);
+                        // Replayed before, by an interrupted run or a repair
+                        let replayed = actual > entry.expected_version
+                            && self
+                                .primary
+                                .fetch_stream_from(&entry.stream_id, entry.expected_version)
+                                .await?
+                                .first()
+                                .is_some_and(|e| e.id.0 == entry.event.id.0);
+                        if !replayed {
+                            let conflict = outbox.flag_conflict(&entry.stream_id, actual).await?;
+                            error!(
+                                stream = %entry.stream_id,
+                                expected_version = conflict.expected_version,
+                                actual_version = actual,
+                                events = conflict.events.len(),
+                                "Fallback writes conflict with the primary store, no longer replayed"
+                            );
+                            self.conflicts.add(1, &[]);
+                            report.conflicts.push(entry.stream_id.clone());
+                            flagged.insert(entry.stream_id);
+                            continue;
+                        }
+                    }
+                    Err(e) => return Err(e),
+                }
+                outbox.remove(&entry)?;
+                self.replayed.add(1, &[]);
+                report.replayed += 1;
+            }
+        }
+    }
+
+    /// Reconciles the outbox every `interval`, once the primary takes writes.
+    pub async fn run_reconciliation(self: Arc<Self>, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            match self.reconcile().await {
+                Ok(report) if report.replayed > 0 || !report.conflicts.is_empty() => info!(
+                    replayed = report.replayed,
+                    conflicts = report.conflicts.len(),
+                    "Reconciled fallback writes"
+                ),
+                Ok(_) => {}
+                Err(e) => {
+                    warn!(error = %e, "Primary Storage still failing, fallback writes not replayed")
+                }
+            }
+        }
     }
 }
 
@@ -24,66 +385,377 @@ impl EventStore for HybridEventStore {
         event: Event,
         expected_version: u64,
     ) -> Result<(), EventStoreError> {
-        // Try Primary
-        match self
-            .primary
-            .append_event(stream, event.clone(), expected_version)
-            .await
-        {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                warn!(
-                    "Primary Storage failed during append: {}. Falling back to Secondary.",
-                    e
-                );
-                // Try Fallback
-                self.fallback
-                    .append_event(stream, event, expected_version)
-                    .await
+        // Streams with writes awaiting replay keep going to the fallback
+        if let Some(outbox) = &self.outbox {
+            if outbox.has_pending(stream)? {
+                return self.append_fallback(stream, event, expected_version).await;
             }
         }
+
+        // Try Primary, unless its circuit is open
+        if self.breaker.allow() {
+            let result = self
+                .primary
+                .append_event(stream, event.clone(), expected_version)
+                .await;
+            if !self.record(&result) {
+                if result.is_ok() {
+                    self.note_primary_head(stream, expected_version + 1);
+                }
+                return result;
+            }
+            warn!(
+                "Primary Storage failed during append: {}. Falling back to Secondary.",
+                result.unwrap_err()
+            );
+        }
+        // Try Fallback
+        self.append_fallback(stream, event, expected_version).await
     }
 
     async fn fetch_stream(&self, stream: &str) -> Result<Vec<Event>, EventStoreError> {
-        // Try Primary
-        match self.primary.fetch_stream(stream).await {
-            Ok(events) => Ok(events),
-            Err(e) => {
-                warn!(
-                    "Primary Storage failed during fetch: {}. Falling back to Secondary.",
-                    e
-                );
-                self.fallback.fetch_stream(stream).await
+        if self.breaker.allow() {
+            let result = self.primary.fetch_stream(stream).await;
+            if !self.record(&result) {
+                return result.map(|events| {
+                    self.note_primary_head(stream, events.last().map_or(0, |e| e.sequence_number));
+                    self.with_pending(stream, events)
+                });
+            }
+            warn!(
+                "Primary Storage failed during fetch: {}. Falling back to Secondary.",
+                result.unwrap_err()
+            );
+        }
+        self.fetch_fallback_from(stream, 0).await
+    }
+
+    async fn fetch_stream_from(
+        &self,
+        stream: &str,
+        version: u64,
+    ) -> Result<Vec<Event>, EventStoreError> {
+        // Pending fallback writes are merged by fetch_stream
+        if let Some(outbox) = &self.outbox {
+            if outbox.has_pending(stream)? {
+                let known = self.primary_heads.lock().unwrap().get(stream).copied();
+                if known.is_some_and(|head| version >= head) {
+                    // The fallback holds every event after the head it continued from
+                    return self.fallback.fetch_stream_from(stream, version).await;
+                }
+                let mut events = self.fetch_stream(stream).await?;
+                events.retain(|e| e.sequence_number > version);
+                return Ok(events);
+            }
+        }
+        if self.breaker.allow() {
+            let result = self.primary.fetch_stream_from(stream, version).await;
+            if !self.record(&result) {
+                if let Ok(Some(last)) = result.as_ref().map(|events| events.last()) {
+                    self.note_primary_head(stream, last.sequence_number);
+                }
+                return result;
+            }
+            warn!(
+                "Primary Storage failed during fetch: {}. Falling back to Secondary.",
+                result.unwrap_err()
+            );
+        }
+        self.fetch_fallback_from(stream, version).await
+    }
+
+    async fn stream_head(&self, stream: &str) -> Result<u64, EventStoreError> {
+        // Pending fallback writes continue the primary stream
+        if let Some(outbox) = &self.outbox {
+            if outbox.has_pending(stream)? {
+                return self.fallback.stream_head(stream).await;
+            }
+        }
+        if self.breaker.allow() {
+            let result = self.primary.stream_head(stream).await;
+            if !self.record(&result) {
+                if let Ok(head) = result {
+                    self.note_primary_head(stream, head);
+                }
+                return result;
+            }
+            warn!(
+                "Primary Storage failed during fetch: {}. Falling back to Secondary.",
+                result.unwrap_err()
+            );
+        }
+        match self.outbox {
+            Some(_) => self.primary_head(stream),
+            None => self.fallback.stream_head(stream).await,
+        }
+    }
+
+    async fn list_streams(&self) -> Result<Vec<String>, EventStoreError> {
+        if self.breaker.allow() {
+            let result = self.primary.list_streams().await;
+            if !self.record(&result) {
+                return result;
             }
+            warn!(
+                "Primary Storage failed during list_streams: {}. Falling back to Secondary.",
+                result.unwrap_err()
+            );
         }
+        self.fallback.list_streams().await
     }
 
     async fn upsert_schema(&self, schema: Schema) -> Result<(), EventStoreError> {
         // Primary first, then failover.
         // TODO: Consider dual-write for stronger consistency.
-
-        match self.primary.upsert_schema(schema.clone()).await {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                warn!(
-                    "Primary Storage failed during upsert_schema: {}. Falling back to Secondary.",
-                    e
-                );
-                self.fallback.upsert_schema(schema).await
+        if self.breaker.allow() {
+            let result = self.primary.upsert_schema(schema.clone()).await;
+            if !self.record(&result) {
+                return result;
             }
+            warn!(
+                "Primary Storage failed during upsert_schema: {}. Falling back to Secondary.",
+                result.unwrap_err()
+            );
         }
+        self.fallback.upsert_schema(schema).await
     }
 
     async fn get_schema(&self, name: &str) -> Result<Option<Schema>, EventStoreError> {
-        match self.primary.get_schema(name).await {
-            Ok(res) => Ok(res),
-            Err(e) => {
-                warn!(
-                    "Primary Storage failed during get_schema: {}. Falling back to Secondary.",
-                    e
-                );
-                self.fallback.get_schema(name).await
+        if self.breaker.allow() {
+            let result = self.primary.get_schema(name).await;
+            if !self.record(&result) {
+                return result;
+            }
+            warn!(
+                "Primary Storage failed during get_schema: {}. Falling back to Secondary.",
+                result.unwrap_err()
+            );
+        }
+        self.fallback.get_schema(name).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::events::event_kind::{EventKind, EventPayload};
+    use crate::storage::memory::InMemoryEventStore;
+    use crate::storage::rocksdb::event_store::RocksEventStore;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use tempfile::TempDir;
+
+    /// A primary that can be taken down.
+    #[derive(Default)]
+    struct FlakyStore {
+        store: InMemoryEventStore,
+        down: AtomicBool,
+        calls: AtomicUsize,
+        /// Reports version 0 on conflicts, like the Scylla store.
+        hides_head: AtomicBool,
+    }
+
+    impl FlakyStore {
+        fn check(&self) -> Result<(), EventStoreError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.down.load(Ordering::SeqCst) {
+                return Err(EventStoreError::StorageError("unavailable".to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl EventStore for FlakyStore {
+        async fn append_event(
+            &self,
+            stream: &str,
+            event: Event,
+            expected_version: u64,
+        ) -> Result<(), EventStoreError> {
+            self.check()?;
+            let res = self
+                .store
+                .append_event(stream, event, expected_version)
+                .await;
+            match res {
+                Err(EventStoreError::ConcurrencyError { expected, .. })
+                    if self.hides_head.load(Ordering::SeqCst) =>
+                {
+                    Err(EventStoreError::ConcurrencyError {
+                        expected,
+                        actual: 0,
+                    })
+                }
+                res => res,
             }
         }
+
+        async fn fetch_stream(&self, stream: &str) -> Result<Vec<Event>, EventStoreError> {
+            self.check()?;
+            self.store.fetch_stream(stream).await
+        }
+
+        async fn list_streams(&self) -> Result<Vec<String>, EventStoreError> {
+            self.check()?;
+            self.store.list_streams().await
+        }
+
+        async fn upsert_schema(&self, schema: Schema) -> Result<(), EventStoreError> {
+            self.check()?;
+            self.store.upsert_schema(schema).await
+        }
+
+        async fn get_schema(&self, name: &str) -> Result<Option<Schema>, EventStoreError> {
+            self.check()?;
+            self.store.get_schema(name).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fallback_writes_are_replayed_in_order_and_forks_flagged() {
+        let temp_dir = TempDir::new().unwrap();
+        let rocks = Arc::new(RocksEventStore::new(temp_dir.path().to_str().unwrap()).unwrap());
+        let primary = Arc::new(FlakyStore::default());
+        // Kept closed, so that the primary is tried again once back up
+        let hybrid = HybridEventStore::new(primary.clone(), rocks.clone())
+            .with_outbox(RocksOutbox::new(rocks.clone()))
+            .with_breaker(10, Duration::from_secs(5));
+        let event = |p| Event::new("s", EventKind::Internal, EventPayload(vec![p]));
+
+        hybrid.append_event("forked", event(1), 0).await.unwrap();
+        hybrid.append_event("forked", event(1), 1).await.unwrap();
+        assert!(hybrid.fetch_stream("new").await.unwrap().is_empty());
+        primary.down.store(true, Ordering::SeqCst);
+
+        // Fallback copies continue from the primary heads last seen
+        hybrid.append_event("new", event(2), 0).await.unwrap();
+        assert!(matches!(
+            hybrid.append_event("forked", event(3), 1).await,
+            Err(EventStoreError::ConcurrencyError { actual: 2, .. })
+        ));
+        hybrid.append_event("forked", event(3), 2).await.unwrap();
+        assert_eq!(hybrid.stream_head("forked").await.unwrap(), 3);
+        assert_eq!(hybrid.fetch_stream("new").await.unwrap().len(), 1);
+        // The earlier events are only in the primary
+        assert!(hybrid.fetch_stream("forked").await.is_err());
+        assert_eq!(
+            hybrid.fetch_stream_from("forked", 2).await.unwrap()[0]
+                .payload
+                .0,
+            vec![3]
+        );
+        // Streams never seen take no writes
+        assert!(matches!(
+            hybrid.append_event("unseen", event(5), 0).await,
+            Err(EventStoreError::StorageError(_))
+        ));
+        assert!(hybrid.stream_head("unseen").await.is_err());
+
+        // Another writer forks "forked" in the primary meanwhile
+        primary
+            .store
+            .append_event("forked", event(6), 2)
+            .await
+            .unwrap();
+
+        // Back up: pending writes are read, and later ones queued behind them
+        primary.down.store(false, Ordering::SeqCst);
+        assert_eq!(hybrid.fetch_stream("new").await.unwrap().len(), 1);
+        hybrid.append_event("new", event(4), 1).await.unwrap();
+        assert!(primary.store.fetch_stream("new").await.unwrap().is_empty());
+
+        let report = hybrid.reconcile().await.unwrap();
+        assert_eq!(report.replayed, 2);
+        assert_eq!(report.conflicts, vec!["forked".to_string()]);
+        let replayed = primary.store.fetch_stream("new").await.unwrap();
+        let payloads: Vec<_> = replayed.iter().map(|e| e.payload.0.clone()).collect();
+        assert_eq!(payloads, vec![vec![2], vec![4]]);
+        assert_eq!(hybrid.fetch_stream("forked").await.unwrap().len(), 3);
+
+        let conflicts = hybrid.outbox.as_ref().unwrap().conflicts().unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].actual_version, 3);
+        assert_eq!(conflicts[0].events[0].payload.0, vec![3]);
+        assert_eq!(
+            hybrid.reconcile().await.unwrap(),
+            ReconcileReport::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_conflicts_record_the_primary_head() {
+        let temp_dir = TempDir::new().unwrap();
+        let rocks = Arc::new(RocksEventStore::new(temp_dir.path().to_str().unwrap()).unwrap());
+        let primary = Arc::new(FlakyStore::default());
+        primary.hides_head.store(true, Ordering::SeqCst);
+        let hybrid = HybridEventStore::new(primary.clone(), rocks.clone())
+            .with_outbox(RocksOutbox::new(rocks.clone()));
+        let event = |p| Event::new("s", EventKind::Internal, EventPayload(vec![p]));
+
+        hybrid.append_event("s", event(1), 0).await.unwrap();
+        primary.down.store(true, Ordering::SeqCst);
+        hybrid.append_event("s", event(3), 1).await.unwrap();
+        primary.down.store(false, Ordering::SeqCst);
+        for version in 1..3 {
+            primary
+                .store
+                .append_event("s", event(2), version)
+                .await
+                .unwrap();
+        }
+
+        let report = hybrid.reconcile().await.unwrap();
+        assert_eq!(report.conflicts, vec!["s".to_string()]);
+        let conflicts = hybrid.outbox.as_ref().unwrap().conflicts().unwrap();
+        assert_eq!(
+            (conflicts[0].expected_version, conflicts[0].actual_version),
+            (1, 3)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_only_unavailable_primaries_fail_over_behind_a_breaker() {
+        let temp_dir = TempDir::new().unwrap();
+        let rocks = Arc::new(RocksEventStore::new(temp_dir.path().to_str().unwrap()).unwrap());
+        let primary = Arc::new(FlakyStore::default());
+        let hybrid = HybridEventStore::new(primary.clone(), rocks.clone())
+            .with_outbox(RocksOutbox::new(rocks.clone()))
+            .with_breaker(2, Duration::from_millis(50));
+        let event = |p| Event::new("s", EventKind::Internal, EventPayload(vec![p]));
+
+        // A conflict in the primary is not retried on the fallback
+        hybrid.append_event("s", event(1), 0).await.unwrap();
+        let res = hybrid.append_event("s", event(2), 5).await;
+        assert!(matches!(res, Err(EventStoreError::ConcurrencyError { .. })));
+        assert!(rocks.fetch_stream("s").await.unwrap().is_empty());
+
+        // Two failures open the circuit, then the primary is skipped
+        assert_eq!(hybrid.stream_head("t").await.unwrap(), 0);
+        primary.down.store(true, Ordering::SeqCst);
+        assert!(hybrid.fetch_stream("s").await.is_err());
+        hybrid.append_event("t", event(3), 0).await.unwrap();
+        assert_eq!(hybrid.status().unwrap().mode, StorageMode::Degraded);
+        let calls = primary.calls.load(Ordering::SeqCst);
+        assert_eq!(hybrid.fetch_stream("t").await.unwrap().len(), 1);
+        assert!(hybrid.reconcile().await.is_err());
+        assert_eq!(primary.calls.load(Ordering::SeqCst), calls);
+
+        // After the cooldown, a successful probe closes it
+        primary.down.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        hybrid.fetch_stream("s").await.unwrap();
+        let status = hybrid.status().unwrap();
+        assert_eq!(status.mode, StorageMode::Recovering);
+        assert_eq!(status.pending_writes, 1);
+
+        hybrid.reconcile().await.unwrap();
+        assert_eq!(
+            hybrid.status().unwrap(),
+            StorageStatus {
+                mode: StorageMode::Primary,
+                pending_writes: 0,
+                conflicted_streams: Vec::new(),
+            }
+        );
     }
 }
@@ -11,12 +11,15 @@ use crate::{
 pub struct InMemoryEventStore {
     // Key: stream_id, Value: List of events
     store: RwLock<HashMap<String, Vec<Event>>>,
+    // Key: schema name
+    schemas: RwLock<HashMap<String, crate::domain::schema::model::Schema>>,
 }
 
 impl InMemoryEventStore {
     pub fn new() -> Self {
         Self {
             store: RwLock::new(HashMap::new()),
+            schemas: RwLock::new(HashMap::new()),
         }
     }
 }
@@ -79,19 +82,72 @@ impl EventStore for InMemoryEventStore {
         }
     }
 
+    async fn fetch_stream_from(
+        &self,
+        stream: &str,
+        version: u64,
+    ) -> Result<Vec<Event>, EventStoreError> {
+        let store = self
+            .store
+            .read()
+            .map_err(|_| EventStoreError::Unknown("Lock poison".to_string()))?;
+
+        match store.get(stream) {
+            Some(events) => Ok(events
+                .iter()
+                .filter(|e| e.sequence_number > version)
+                .cloned()
+                .collect()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn stream_head(&self, stream: &str) -> Result<u64, EventStoreError> {
+        let store = self
+            .store
+            .read()
+            .map_err(|_| EventStoreError::Unknown("Lock poison".to_string()))?;
+
+        Ok(store
+            .get(stream)
+            .and_then(|events| events.last())
+            .map(|e| e.sequence_number)
+            .unwrap_or(0))
+    }
+
+    async fn list_streams(&self) -> Result<Vec<String>, EventStoreError> {
+        let store = self
+            .store
+            .read()
+            .map_err(|_| EventStoreError::Unknown("Lock poison".to_string()))?;
+        Ok(store
+            .iter()
+            .filter(|(_, events)| !events.is_empty())
+            .map(|(stream, _)| stream.clone())
+            .collect())
+    }
+
     async fn upsert_schema(
         &self,
-        _schema: crate::domain::schema::model::Schema,
+        schema: crate::domain::schema::model::Schema,
     ) -> Result<(), EventStoreError> {
-        // No-op for now or simple log
+        let mut schemas = self
+            .schemas
+            .write()
+            .map_err(|_| EventStoreError::Unknown("Lock poison".to_string()))?;
+        schemas.insert(schema.name.clone(), schema);
         Ok(())
     }
 
     async fn get_schema(
         &self,
-        _name: &str,
+        name: &str,
     ) -> Result<Option<crate::domain::schema::model::Schema>, EventStoreError> {
-        Ok(None)
+        let schemas = self
+            .schemas
+            .read()
+            .map_err(|_| EventStoreError::Unknown("Lock poison".to_string()))?;
+        Ok(schemas.get(name).cloned())
     }
 }
 
@@ -1,6 +1,8 @@
+pub mod digest;
 pub mod event_store;
 pub mod hybrid;
 pub mod memory;
 pub mod rocksdb;
 pub mod scylla;
 pub mod snapshot;
+pub mod state;
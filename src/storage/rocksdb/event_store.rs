@@ -27,31 +27,69 @@ impl RocksEventStore {
     }
 }
 
+impl RocksEventStore {
+    pub fn db(&self) -> &DB {
+        &self.db
+    }
+
+    /// Held by writers between reading the head of a stream and writing
+    /// after it, for those writing their own batches.
+    pub async fn write_guard(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.write_lock.lock().await
+    }
+
+    /// Version of the last event of a stream, 0 if it has none.
+    pub fn head(&self, stream: &str) -> Result<u64, EventStoreError> {
+        let meta_key = format!("meta:{}", stream);
+        match self
+            .db
+            .get(&meta_key)
+            .map_err(|e| EventStoreError::StorageError(e.to_string()))?
+        {
+            Some(v_bytes) => {
+                let v_str = String::from_utf8_lossy(&v_bytes);
+                Ok(v_str.parse::<u64>().unwrap_or(0))
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Adds to `batch` the writes that append `events` after version `head`,
+    /// numbering them from `head + 1`. Returns the new head. The caller must
+    /// make sure nothing else writes the stream until the batch is written.
+    pub fn batch_append(
+        batch: &mut rocksdb::WriteBatch,
+        stream: &str,
+        events: Vec<Event>,
+        head: u64,
+    ) -> Result<u64, EventStoreError> {
+        let mut version = head;
+        for mut event in events {
+            version += 1;
+            event.sequence_number = version;
+            // Stream key now includes sequence number for sorting: stream:{stream_id}:{seq_num}
+            // Note: {:020} creates a sortable string representation of u64.
+            let key = format!("stream:{}:{:020}", stream, version);
+            batch.put(key, serde_cbor::to_vec(&event)?);
+        }
+        batch.put(format!("meta:{}", stream), version.to_string());
+        Ok(version)
+    }
+}
+
 #[async_trait]
 impl EventStore for RocksEventStore {
     async fn append_event(
         &self,
         stream: &str,
-        mut event: Event,
+        event: Event,
         expected_version: u64,
     ) -> Result<(), EventStoreError> {
         // Enforce serial access for atomicity check
         let _guard = self.write_lock.lock().await;
 
-        let meta_key = format!("meta:{}", stream);
-
         // 1. Check current version
-        let current_version = match self
-            .db
-            .get(&meta_key)
-            .map_err(|e| EventStoreError::StorageError(e.to_string()))?
-        {
-            Some(v_bytes) => {
-                let v_str = String::from_utf8_lossy(&v_bytes);
-                v_str.parse::<u64>().unwrap_or(0)
-            }
-            None => 0,
-        };
+        let current_version = self.head(stream)?;
 
         if current_version != expected_version {
             return Err(EventStoreError::ConcurrencyError {
@@ -61,21 +99,8 @@ impl EventStore for RocksEventStore {
         }
 
         // 2. Prepare atomic write batch
-        let next_version = current_version + 1;
-        event.sequence_number = next_version;
-
-        // Stream key now includes sequence number for sorting: stream:{stream_id}:{seq_num}
-        // Previously: stream:{stream_id}:{uuid}.
-        // We should use BigEndian bytes for seq_num to sort correctly in RocksDB.
-        let key = format!("stream:{}:{:020}", stream, next_version);
-        // Note: {:020} creates a sortable string representation of u64, but binary key is better?
-        // Let's stick to string keys for debuggability as established, but pad it!
-
-        let value = serde_cbor::to_vec(&event)?;
-
         let mut batch = rocksdb::WriteBatch::default();
-        batch.put(key, value);
-        batch.put(meta_key, next_version.to_string());
+        Self::batch_append(&mut batch, stream, vec![event], current_version)?;
 
         // 3. Commit
         self.db
@@ -109,6 +134,44 @@ impl EventStore for RocksEventStore {
         Ok(events)
     }
 
+    async fn fetch_stream_from(
+        &self,
+        stream: &str,
+        version: u64,
+    ) -> Result<Vec<Event>, EventStoreError> {
+        let prefix = format!("stream:{}:", stream);
+        let start = format!("{}{:020}", prefix, version.saturating_add(1));
+        let mode = IteratorMode::From(start.as_bytes(), rocksdb::Direction::Forward);
+
+        let mut events = Vec::new();
+        for item in self.db.iterator(mode) {
+            let (key, value) = item.map_err(|e| EventStoreError::StorageError(e.to_string()))?;
+            if !key.starts_with(prefix.as_bytes()) {
+                break;
+            }
+            events.push(serde_cbor::from_slice(&value)?);
+        }
+        Ok(events)
+    }
+
+    async fn stream_head(&self, stream: &str) -> Result<u64, EventStoreError> {
+        self.head(stream)
+    }
+
+    async fn list_streams(&self) -> Result<Vec<String>, EventStoreError> {
+        let prefix = b"meta:";
+        let mode = IteratorMode::From(prefix, rocksdb::Direction::Forward);
+        let mut streams = Vec::new();
+        for item in self.db.iterator(mode) {
+            let (key, _) = item.map_err(|e| EventStoreError::StorageError(e.to_string()))?;
+            match key.strip_prefix(prefix) {
+                Some(stream) => streams.push(String::from_utf8_lossy(stream).into_owned()),
+                None => break,
+            }
+        }
+        Ok(streams)
+    }
+
     async fn upsert_schema(&self, schema: Schema) -> Result<(), EventStoreError> {
         // Schema upsert via event stream for consistency
         let key = format!("schema:{}", schema.name);
@@ -128,7 +191,8 @@ impl EventStore for RocksEventStore {
             &stream,
             crate::domain::events::event_kind::EventKind::Schematic,
             crate::domain::events::event_kind::EventPayload(payload_bytes.clone()),
-        );
+        )
+        .with_content_type(crate::domain::events::event_kind::ContentType::Cbor);
 
         self.append_event(&stream, event, ver).await?;
 
@@ -217,6 +281,12 @@ mod tests {
         assert_eq!(loaded.len(), 2);
         assert_eq!(loaded[0].sequence_number, 1);
         assert_eq!(loaded[1].sequence_number, 2);
+        assert_eq!(store.list_streams().await.unwrap(), vec!["stream-o"]);
+        let tail = store.fetch_stream_from("stream-o", 1).await.unwrap();
+        assert_eq!(tail.len(), 1);
+        assert_eq!(tail[0].sequence_number, 2);
+        assert_eq!(store.stream_head("stream-o").await.unwrap(), 2);
+        assert_eq!(store.stream_head("missing").await.unwrap(), 0);
     }
 
     #[tokio::test]
@@ -1,2 +1,5 @@
 pub mod event_store;
+pub mod outbox;
+pub mod raft;
 pub mod snapshot_store;
+pub mod state_store;
@@ -0,0 +1,318 @@
+use crate::domain::events::event::Event;
+use crate::storage::event_store::EventStoreError;
+use crate::storage::rocksdb::event_store::RocksEventStore;
+use rocksdb::{Direction, IteratorMode, WriteBatch};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+const ENTRY_PREFIX: &str = "outbox:entry:";
+const NEXT_KEY: &str = "outbox:next";
+const CONFLICT_PREFIX: &str = "outbox:conflict:";
+
+/// An event appended to the fallback store while the primary was failing,
+/// and not yet replayed into the primary.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    /// Position in the outbox, in the order the events were appended.
+    pub seq: u64,
+    pub stream_id: String,
+    /// Version the writer expected, which the primary must still be at.
+    pub expected_version: u64,
+    pub event: Event,
+}
+
+/// Fallback writes to a stream that the primary no longer accepts at their
+/// version: the stream forked between the two stores while the primary was
+/// unavailable. They are kept for an operator and no longer replayed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OutboxConflict {
+    pub stream_id: String,
+    pub expected_version: u64,
+    /// Version of the stream in the primary when the conflict was detected.
+    pub actual_version: u64,
+    pub events: Vec<Event>,
+}
+
+/// Durable outbox of the writes a `HybridEventStore` made to its RocksDB
+/// fallback, in the event store's database under `outbox:`.
+///
+/// Appends through the outbox write the event and its entry in one batch, so
+/// every event in the fallback that the primary has not seen is recorded.
+pub struct RocksOutbox {
+    store: Arc<RocksEventStore>,
+}
+
+impl RocksOutbox {
+    pub fn new(store: Arc<RocksEventStore>) -> Self {
+        Self { store }
+    }
+
+    /// Prefix of the index keys of a stream's entries. The stream id is
+    /// length-prefixed: stream ids may contain `:`, and the prefix of `a`
+    /// must not match the entries of `a:b`.
+    fn stream_prefix(stream: &str) -> String {
+        format!("outbox:stream:{}:{}:", stream.len(), stream)
+    }
+
+    fn storage_error(e: rocksdb::Error) -> EventStoreError {
+        EventStoreError::StorageError(e.to_string())
+    }
+
+    /// Appends `event` to the fallback store with the usual version check,
+    /// and records it in the outbox.
+    ///
+    /// A stream without pending entries continues from `primary_head`, the
+    /// head of its copy in the primary, rather than from what the fallback
+    /// kept of it: those events have been replayed since, or never existed.
+    /// It is refused when that head is unknown.
+    pub async fn append(
+        &self,
+        stream: &str,
+        event: Event,
+        expected_version: u64,
+        primary_head: Option<u64>,
+    ) -> Result<(), EventStoreError> {
+        let _guard = self.store.write_guard().await;
+
+        let current_version = if self.has_pending(stream)? {
+            self.store.head(stream)?
+        } else {
+            primary_head.ok_or_else(|| {
+                EventStoreError::StorageError(format!(
+                    "Primary Storage is unavailable and the head of stream {} is unknown",
+                    stream
+                ))
+            })?
+        };
+        if current_version != expected_version {
+            return Err(EventStoreError::ConcurrencyError {
+                expected: expected_version,
+                actual: current_version,
+            });
+        }
+
+        let db = self.store.db();
+        let seq = match db.get(NEXT_KEY).map_err(Self::storage_error)? {
+            Some(bytes) => String::from_utf8_lossy(&bytes).parse::<u64>().unwrap_or(0),
+            None => 0,
+        };
+        let mut batch = WriteBatch::default();
+        let mut event = event;
+        event.sequence_number = expected_version + 1;
+        RocksEventStore::batch_append(&mut batch, stream, vec![event.clone()], current_version)?;
+        let entry = OutboxEntry {
+            seq,
+            stream_id: stream.to_string(),
+            expected_version,
+            event,
+        };
+        batch.put(
+            format!("{}{:020}", ENTRY_PREFIX, seq),
+            serde_cbor::to_vec(&entry)?,
+        );
+        batch.put(format!("{}{:020}", Self::stream_prefix(stream), seq), []);
+        batch.put(NEXT_KEY, (seq + 1).to_string());
+        db.write(batch).map_err(Self::storage_error)
+    }
+
+    /// Up to `limit` entries, oldest first.
+    pub fn pending(&self, limit: usize) -> Result<Vec<OutboxEntry>, EventStoreError> {
+        let mut entries = Vec::new();
+        for item in self.store.db().iterator(IteratorMode::From(
+            ENTRY_PREFIX.as_bytes(),
+            Direction::Forward,
+        )) {
+            let (key, value) = item.map_err(Self::storage_error)?;
+            if !key.starts_with(ENTRY_PREFIX.as_bytes()) || entries.len() == limit {
+                break;
+            }
+            entries.push(serde_cbor::from_slice(&value)?);
+        }
+        Ok(entries)
+    }
+
+    /// Number of entries, counted from their keys without decoding them.
+    pub fn pending_count(&self) -> Result<u64, EventStoreError> {
+        let mut count = 0;
+        for item in self.store.db().iterator(IteratorMode::From(
+            ENTRY_PREFIX.as_bytes(),
+            Direction::Forward,
+        )) {
+            let (key, _) = item.map_err(Self::storage_error)?;
+            if !key.starts_with(ENTRY_PREFIX.as_bytes()) {
+                break;
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Entries of one stream, oldest first.
+    pub fn pending_for(&self, stream: &str) -> Result<Vec<OutboxEntry>, EventStoreError> {
+        let prefix = Self::stream_prefix(stream);
+        let db = self.store.db();
+        let mut entries = Vec::new();
+        for item in db.iterator(IteratorMode::From(prefix.as_bytes(), Direction::Forward)) {
+            let (key, _) = item.map_err(Self::storage_error)?;
+            let Some(seq) = key.strip_prefix(prefix.as_bytes()) else {
+                break;
+            };
+            let entry_key = [ENTRY_PREFIX.as_bytes(), seq].concat();
+            if let Some(value) = db.get(entry_key).map_err(Self::storage_error)? {
+                entries.push(serde_cbor::from_slice(&value)?);
+            }
+        }
+        Ok(entries)
+    }
+
+    pub fn has_pending(&self, stream: &str) -> Result<bool, EventStoreError> {
+        let prefix = Self::stream_prefix(stream);
+        match self
+            .store
+            .db()
+            .iterator(IteratorMode::From(prefix.as_bytes(), Direction::Forward))
+            .next()
+        {
+            Some(item) => Ok(item
+                .map_err(Self::storage_error)?
+                .0
+                .starts_with(prefix.as_bytes())),
+            None => Ok(false),
+        }
+    }
+
+    /// Removes an entry replayed into the primary.
+    pub fn remove(&self, entry: &OutboxEntry) -> Result<(), EventStoreError> {
+        let mut batch = WriteBatch::default();
+        Self::batch_remove(&mut batch, entry);
+        self.store.db().write(batch).map_err(Self::storage_error)
+    }
+
+    fn batch_remove(batch: &mut WriteBatch, entry: &OutboxEntry) {
+        batch.delete(format!("{}{:020}", ENTRY_PREFIX, entry.seq));
+        batch.delete(format!(
+            "{}{:020}",
+            Self::stream_prefix(&entry.stream_id),
+            entry.seq
+        ));
+    }
+
+    /// Moves every entry of `stream` to its conflict record, so that none of
+    /// them is replayed. Returns the record.
+    pub async fn flag_conflict(
+        &self,
+        stream: &str,
+        actual_version: u64,
+    ) -> Result<OutboxConflict, EventStoreError> {
+        let _guard = self.store.write_guard().await;
+
+        let entries = self.pending_for(stream)?;
+        let key = format!("{}{}", CONFLICT_PREFIX, stream);
+        let mut conflict = match self.store.db().get(&key).map_err(Self::storage_error)? {
+            Some(bytes) => serde_cbor::from_slice(&bytes)?,
+            None => OutboxConflict {
+                stream_id: stream.to_string(),
+                expected_version: entries.first().map(|e| e.expected_version).unwrap_or(0),
+                actual_version,
+                events: Vec::new(),
+            },
+        };
+        let mut batch = WriteBatch::default();
+        for entry in entries {
+            Self::batch_remove(&mut batch, &entry);
+            conflict.events.push(entry.event);
+        }
+        batch.put(key, serde_cbor::to_vec(&conflict)?);
+        self.store.db().write(batch).map_err(Self::storage_error)?;
+        Ok(conflict)
+    }
+
+    /// Streams whose fallback writes conflicted with the primary.
+    pub fn conflicts(&self) -> Result<Vec<OutboxConflict>, EventStoreError> {
+        let mut conflicts = Vec::new();
+        for item in self.store.db().iterator(IteratorMode::From(
+            CONFLICT_PREFIX.as_bytes(),
+            Direction::Forward,
+        )) {
+            let (key, value) = item.map_err(Self::storage_error)?;
+            if !key.starts_with(CONFLICT_PREFIX.as_bytes()) {
+                break;
+            }
+            conflicts.push(serde_cbor::from_slice(&value)?);
+        }
+        Ok(conflicts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::events::event_kind::{EventKind, EventPayload};
+    use crate::storage::event_store::EventStore;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_outbox_keeps_fallback_writes_in_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = Arc::new(RocksEventStore::new(temp_dir.path().to_str().unwrap()).unwrap());
+        let outbox = RocksOutbox::new(store.clone());
+        let event = |p| Event::new("s", EventKind::Internal, EventPayload(vec![p]));
+
+        outbox.append("a", event(1), 0, Some(0)).await.unwrap();
+        outbox.append("b", event(2), 0, Some(0)).await.unwrap();
+        outbox.append("a", event(3), 1, Some(0)).await.unwrap();
+        assert!(matches!(
+            outbox.append("a", event(4), 1, Some(0)).await,
+            Err(EventStoreError::ConcurrencyError { actual: 2, .. })
+        ));
+        assert_eq!(store.fetch_stream("a").await.unwrap().len(), 2);
+
+        // The first pending write continues the primary's copy
+        assert!(matches!(
+            outbox.append("c", event(5), 0, Some(7)).await,
+            Err(EventStoreError::ConcurrencyError { actual: 7, .. })
+        ));
+        outbox.append("c", event(5), 7, Some(7)).await.unwrap();
+        assert_eq!(store.head("c").unwrap(), 8);
+        assert!(matches!(
+            outbox.append("d", event(6), 0, None).await,
+            Err(EventStoreError::StorageError(_))
+        ));
+        outbox.remove(&outbox.pending_for("c").unwrap()[0]).unwrap();
+
+        let pending = outbox.pending(10).unwrap();
+        let order: Vec<_> = pending
+            .iter()
+            .map(|e| (e.stream_id.as_str(), e.expected_version))
+            .collect();
+        assert_eq!(order, vec![("a", 0), ("b", 0), ("a", 1)]);
+        assert_eq!(outbox.pending(1).unwrap().len(), 1);
+        assert_eq!(outbox.pending_count().unwrap(), 3);
+
+        outbox.remove(&pending[0]).unwrap();
+        assert_eq!(outbox.pending_for("a").unwrap().len(), 1);
+        let conflict = outbox.flag_conflict("a", 5).await.unwrap();
+        assert_eq!((conflict.expected_version, conflict.actual_version), (1, 5));
+        assert!(!outbox.has_pending("a").unwrap());
+        assert!(outbox.has_pending("b").unwrap());
+        assert_eq!(outbox.conflicts().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_outbox_streams_sharing_a_prefix() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = Arc::new(RocksEventStore::new(temp_dir.path().to_str().unwrap()).unwrap());
+        let outbox = RocksOutbox::new(store);
+        let event = |p| Event::new("s", EventKind::Internal, EventPayload(vec![p]));
+
+        outbox.append("a:b", event(1), 0, Some(0)).await.unwrap();
+        assert!(!outbox.has_pending("a").unwrap());
+        assert!(outbox.pending_for("a").unwrap().is_empty());
+
+        outbox.append("a", event(2), 0, Some(0)).await.unwrap();
+        let conflict = outbox.flag_conflict("a", 3).await.unwrap();
+        assert_eq!(conflict.events.len(), 1);
+        assert!(outbox.has_pending("a:b").unwrap());
+        assert_eq!(outbox.pending_for("a:b").unwrap()[0].stream_id, "a:b");
+    }
+}
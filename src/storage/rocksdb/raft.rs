@@ -0,0 +1,447 @@
+use crate::cluster::raft::log::{Entry, HardState, RaftState, RaftStorage, Snapshot};
+use crate::cluster::raft::{SnapshotBuilder, StateMachine};
+use crate::domain::events::event::Event;
+use crate::storage::rocksdb::event_store::RocksEventStore;
+use rocksdb::checkpoint::Checkpoint;
+use rocksdb::{Direction, IteratorMode, WriteBatch, DB};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+type KeyValue = (Box<[u8]>, Box<[u8]>);
+
+/// Appends replicated through a Raft group.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AppendCommand {
+    pub stream_id: String,
+    pub events: Vec<Event>,
+    /// -1 appends at the head, whatever it is when the entry is applied.
+    pub expected_version: i64,
+}
+
+impl AppendCommand {
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        serde_cbor::to_vec(self).map_err(|e| e.to_string())
+    }
+
+    /// Reads the state machine's response: whether the events were appended.
+    pub fn decode_response(response: &[u8]) -> Result<bool, String> {
+        serde_cbor::from_slice(response).map_err(|e| e.to_string())
+    }
+}
+
+/// Term, vote and log of one Raft group, in the event store's database
+/// under `raft:{group}:`.
+pub struct RocksRaftStorage {
+    store: Arc<RocksEventStore>,
+    prefix: String,
+}
+
+impl RocksRaftStorage {
+    pub fn new(store: Arc<RocksEventStore>, group: &str) -> Self {
+        Self {
+            store,
+            prefix: format!("raft:{}:", group),
+        }
+    }
+
+    fn log_key(&self, index: u64) -> String {
+        format!("{}log:{:020}", self.prefix, index)
+    }
+
+    /// Log keys from `from` on, in index order.
+    fn log_keys(&self, from: u64) -> Result<Vec<KeyValue>, String> {
+        let log_prefix = format!("{}log:", self.prefix);
+        let start = self.log_key(from);
+        let mut items = Vec::new();
+        for item in self
+            .store
+            .db()
+            .iterator(IteratorMode::From(start.as_bytes(), Direction::Forward))
+        {
+            let (key, value) = item.map_err(|e| e.to_string())?;
+            if !key.starts_with(log_prefix.as_bytes()) {
+                break;
+            }
+            items.push((key, value));
+        }
+        Ok(items)
+    }
+
+    fn get<T: for<'de> Deserialize<'de>>(&self, name: &str) -> Result<Option<T>, String> {
+        let key = format!("{}{}", self.prefix, name);
+        match self.store.db().get(key).map_err(|e| e.to_string())? {
+            Some(bytes) => Ok(Some(
+                serde_cbor::from_slice(&bytes).map_err(|e| e.to_string())?,
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+impl RaftStorage for RocksRaftStorage {
+    fn load(&self) -> Result<RaftState, String> {
+        let mut entries = Vec::new();
+        for (_, value) in self.log_keys(0)? {
+            entries.push(serde_cbor::from_slice::<Entry>(&value).map_err(|e| e.to_string())?);
+        }
+        Ok(RaftState {
+            hard_state: self.get("hard_state")?.unwrap_or_default(),
+            compacted: self.get("compacted")?.unwrap_or_default(),
+            entries,
+        })
+    }
+
+    fn save_hard_state(&mut self, state: &HardState) -> Result<(), String> {
+        let bytes = serde_cbor::to_vec(state).map_err(|e| e.to_string())?;
+        self.store
+            .db()
+            .put(format!("{}hard_state", self.prefix), bytes)
+            .map_err(|e| e.to_string())
+    }
+
+    fn append(&mut self, entries: &[Entry]) -> Result<(), String> {
+        let mut batch = WriteBatch::default();
+        for entry in entries {
+            let bytes = serde_cbor::to_vec(entry).map_err(|e| e.to_string())?;
+            batch.put(self.log_key(entry.index), bytes);
+        }
+        self.store.db().write(batch).map_err(|e| e.to_string())
+    }
+
+    fn truncate(&mut self, index: u64) -> Result<(), String> {
+        let mut batch = WriteBatch::default();
+        for (key, _) in self.log_keys(index)? {
+            batch.delete(key);
+        }
+        self.store.db().write(batch).map_err(|e| e.to_string())
+    }
+
+    fn compact(&mut self, index: u64, term: u64) -> Result<(), String> {
+        let mut batch = WriteBatch::default();
+        let last = self.log_key(index);
+        for (key, _) in self.log_keys(0)? {
+            if key.as_ref() > last.as_bytes() {
+                break;
+            }
+            batch.delete(key);
+        }
+        let compacted = serde_cbor::to_vec(&(index, term)).map_err(|e| e.to_string())?;
+        batch.put(format!("{}compacted", self.prefix), compacted);
+        self.store.db().write(batch).map_err(|e| e.to_string())
+    }
+}
+
+/// Key-value pairs of the streams in a snapshot.
+type SnapshotData = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// The event store as the state machine of one Raft group.
+///
+/// Each applied entry is written in one batch with the group's applied
+/// index, so a restart resumes exactly after the last applied entry.
+/// Snapshots hold the group's streams, read from a RocksDB checkpoint off
+/// the group's task.
+pub struct RocksStateMachine {
+    store: Arc<RocksEventStore>,
+    group: String,
+    checkpoint_dir: PathBuf,
+    /// Whether a stream belongs to this group. Other groups share the database.
+    owns: Arc<dyn Fn(&str) -> bool + Send + Sync>,
+    applied: u64,
+    last_checkpoint: Option<PathBuf>,
+}
+
+impl RocksStateMachine {
+    pub fn new(
+        store: Arc<RocksEventStore>,
+        group: &str,
+        checkpoint_dir: impl Into<PathBuf>,
+        owns: Arc<dyn Fn(&str) -> bool + Send + Sync>,
+    ) -> Result<Self, String> {
+        let applied = store
+            .db()
+            .get(applied_key(group))
+            .map_err(|e| e.to_string())?
+            .and_then(|v| String::from_utf8_lossy(&v).parse::<u64>().ok())
+            .unwrap_or(0);
+        Ok(Self {
+            store,
+            group: group.to_string(),
+            checkpoint_dir: checkpoint_dir.into(),
+            owns,
+            applied,
+            last_checkpoint: None,
+        })
+    }
+
+    fn apply_append(&self, command: AppendCommand, batch: &mut WriteBatch) -> Result<bool, String> {
+        let head = self
+            .store
+            .head(&command.stream_id)
+            .map_err(|e| e.to_string())?;
+        let expected = match command.expected_version {
+            -1 => head,
+            v if v < 0 => 0,
+            v => v as u64,
+        };
+        if expected != head {
+            return Ok(false);
+        }
+
+        let mut events = command.events;
+        for event in events.iter_mut() {
+            event.stream_id = command.stream_id.clone();
+        }
+        RocksEventStore::batch_append(batch, &command.stream_id, events, head)
+            .map_err(|e| e.to_string())?;
+        Ok(true)
+    }
+}
+
+impl StateMachine for RocksStateMachine {
+    fn applied_index(&self) -> u64 {
+        self.applied
+    }
+
+    fn apply(&mut self, entry: &Entry) -> Result<Vec<u8>, String> {
+        let mut batch = WriteBatch::default();
+        let appended = if entry.command.is_empty() {
+            false
+        } else {
+            let command: AppendCommand =
+                serde_cbor::from_slice(&entry.command).map_err(|e| e.to_string())?;
+            self.apply_append(command, &mut batch)?
+        };
+
+        batch.put(applied_key(&self.group), entry.index.to_string());
+        self.store.db().write(batch).map_err(|e| e.to_string())?;
+        self.applied = entry.index;
+        serde_cbor::to_vec(&appended).map_err(|e| e.to_string())
+    }
+
+    fn snapshot(&mut self) -> Result<SnapshotBuilder, String> {
+        let name: String = self
+            .group
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let path = self
+            .checkpoint_dir
+            .join(format!("{}-{}", name, self.applied));
+        // A checkpoint can only be created in a new directory
+        let _ = std::fs::remove_dir_all(&path);
+        Checkpoint::new(self.store.db())
+            .and_then(|c| c.create_checkpoint(&path))
+            .map_err(|e| e.to_string())?;
+
+        // Only one snapshot is built at a time, so the previous one is done
+        if let Some(previous) = self.last_checkpoint.replace(path.clone()) {
+            if previous != path {
+                let _ = std::fs::remove_dir_all(previous);
+            }
+        }
+        let owns = self.owns.clone();
+        Ok(Box::new(move || {
+            let checkpoint = DB::open_default(&path).map_err(|e| e.to_string())?;
+            let pairs = owned_pairs(&checkpoint, owns.as_ref())?;
+            drop(checkpoint);
+            serde_cbor::to_vec(&pairs).map_err(|e| e.to_string())
+        }))
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) -> Result<(), String> {
+        let pairs: SnapshotData =
+            serde_cbor::from_slice(&snapshot.data).map_err(|e| e.to_string())?;
+
+        let mut batch = WriteBatch::default();
+        for (key, _) in owned_pairs(self.store.db(), self.owns.as_ref())? {
+            batch.delete(key);
+        }
+        for (key, value) in pairs {
+            batch.put(key, value);
+        }
+        batch.put(applied_key(&self.group), snapshot.index.to_string());
+        self.store.db().write(batch).map_err(|e| e.to_string())?;
+        self.applied = snapshot.index;
+        Ok(())
+    }
+}
+
+/// Keys of the streams `owns` accepts in `db`. System streams
+/// (`$schema:...`) are written locally, not through Raft, and are left out.
+fn owned_pairs(db: &DB, owns: &dyn Fn(&str) -> bool) -> Result<SnapshotData, String> {
+    let mut pairs = Vec::new();
+    for prefix in ["meta:", "stream:"] {
+        for item in db.iterator(IteratorMode::From(prefix.as_bytes(), Direction::Forward)) {
+            let (key, value) = item.map_err(|e| e.to_string())?;
+            if !key.starts_with(prefix.as_bytes()) {
+                break;
+            }
+            let stream = stream_of(&key);
+            if !stream.starts_with('$') && owns(&stream) {
+                pairs.push((key.to_vec(), value.to_vec()));
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+fn applied_key(group: &str) -> String {
+    format!("raft:{}:applied", group)
+}
+
+/// Stream id of a `meta:{stream}` or `stream:{stream}:{version}` key.
+fn stream_of(key: &[u8]) -> String {
+    let key = String::from_utf8_lossy(key);
+    if let Some(stream) = key.strip_prefix("meta:") {
+        return stream.to_string();
+    }
+    let rest = key.strip_prefix("stream:").unwrap_or(&key);
+    match rest.rsplit_once(':') {
+        Some((stream, _)) => stream.to_string(),
+        None => rest.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::raft::group::{LocalTransport, RaftGroup};
+    use crate::cluster::raft::groups::{group_of, RaftGroups};
+    use crate::cluster::raft::RaftConfig;
+    use crate::cluster::ClusterTopology;
+    use crate::domain::events::event_kind::{EventKind, EventPayload};
+    use crate::storage::event_store::EventStore;
+    use std::time::{Duration, Instant};
+    use tempfile::TempDir;
+
+    const NODES: [&str; 3] = ["10.0.0.1:50051", "10.0.0.2:50051", "10.0.0.3:50051"];
+
+    struct Node {
+        store: Arc<RocksEventStore>,
+        groups: RaftGroups,
+    }
+
+    fn start(dir: &TempDir, transport: &Arc<LocalTransport>) -> Vec<Node> {
+        let topology = Arc::new(ClusterTopology::new(
+            NODES.iter().map(|n| n.to_string()).collect(),
+            0,
+        ));
+        let config = RaftConfig {
+            tick_interval: Duration::from_millis(10),
+            snapshot_threshold: 5,
+            ..Default::default()
+        };
+
+        NODES
+            .iter()
+            .enumerate()
+            .map(|(i, addr)| {
+                let path = dir.path().join(format!("node-{}", i));
+                let store = Arc::new(RocksEventStore::new(path.to_str().unwrap()).unwrap());
+                let groups = RaftGroups::start(
+                    addr,
+                    topology.clone(),
+                    3,
+                    config.clone(),
+                    transport.clone(),
+                    |group| {
+                        let (topology, group_id) = (topology.clone(), group.to_string());
+                        let owns = Arc::new(move |stream: &str| {
+                            group_of(&topology, stream, 3) == group_id
+                        });
+                        Ok((
+                            Box::new(RocksRaftStorage::new(store.clone(), group)),
+                            Box::new(RocksStateMachine::new(
+                                store.clone(),
+                                group,
+                                path.join("checkpoints"),
+                                owns,
+                            )?),
+                        ))
+                    },
+                )
+                .unwrap();
+                for group in groups.groups() {
+                    transport.register(addr, group.clone());
+                }
+                Node { store, groups }
+            })
+            .collect()
+    }
+
+    fn append(stream_id: &str, expected_version: i64) -> Vec<u8> {
+        AppendCommand {
+            stream_id: stream_id.to_string(),
+            events: vec![Event::new(
+                stream_id,
+                EventKind::Internal,
+                EventPayload(b"{}".to_vec()),
+            )],
+            expected_version,
+        }
+        .encode()
+        .unwrap()
+    }
+
+    /// Proposes on whichever member currently leads, retrying through elections.
+    async fn propose(nodes: &[&Node], stream_id: &str, command: Vec<u8>) -> (usize, bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            for (i, node) in nodes.iter().enumerate() {
+                let group: &RaftGroup = node.groups.group_for(stream_id).unwrap();
+                if let Ok(response) = group.propose(command.clone()).await {
+                    return (i, AppendCommand::decode_response(&response).unwrap());
+                }
+            }
+            assert!(Instant::now() < deadline, "no leader elected");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_appends_through_raft_survive_leader_loss() {
+        let dir = TempDir::new().unwrap();
+        let transport = Arc::new(LocalTransport::new());
+        let nodes = start(&dir, &transport);
+        let all: Vec<&Node> = nodes.iter().collect();
+
+        let (leader, ok) = propose(&all, "user-1", append("user-1", 0)).await;
+        assert!(ok);
+        // A conflicting expected version is rejected on every replica alike
+        let (_, ok) = propose(&all, "user-1", append("user-1", 0)).await;
+        assert!(!ok);
+
+        // The leader is cut off and the remaining majority elects a new one.
+        // Its log is compacted meanwhile, so the old leader catches up from a snapshot.
+        transport.isolate(NODES[leader]);
+        let survivors: Vec<&Node> = all
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != leader)
+            .map(|(_, n)| *n)
+            .collect();
+        for _ in 0..6 {
+            let (_, ok) = propose(&survivors, "user-1", append("user-1", -1)).await;
+            assert!(ok);
+        }
+        transport.heal();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        for node in &nodes {
+            while node.store.fetch_stream("user-1").await.unwrap().len() != 7 {
+                assert!(Instant::now() < deadline, "replicas did not converge");
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        }
+        let versions: Vec<u64> = nodes[leader]
+            .store
+            .fetch_stream("user-1")
+            .await
+            .unwrap()
+            .iter()
+            .map(|e| e.sequence_number)
+            .collect();
+        assert_eq!(versions, (1..=7).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,55 @@
+use crate::storage::state::{StateError, StateStore, StreamState};
+use rocksdb::DB;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Stores projected stream states next to snapshots, under `state:{stream_id}`.
+pub struct RocksStateStore {
+    db: Arc<DB>,
+}
+
+impl RocksStateStore {
+    pub fn new(db: Arc<DB>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl StateStore for RocksStateStore {
+    async fn save_state(&self, state: StreamState) -> Result<(), StateError> {
+        let key = format!("state:{}", state.stream_id);
+
+        // Same layout as snapshots: [version:8][timestamp:8][document...]
+        let mut buf = Vec::with_capacity(16 + state.document.len());
+        buf.extend_from_slice(&state.version.to_be_bytes());
+        buf.extend_from_slice(&state.timestamp.to_be_bytes());
+        buf.extend_from_slice(&state.document);
+
+        self.db
+            .put(key, buf)
+            .map_err(|e| StateError::StorageError(e.to_string()))
+    }
+
+    async fn get_state(&self, stream_id: &str) -> Result<Option<StreamState>, StateError> {
+        let key = format!("state:{}", stream_id);
+
+        match self
+            .db
+            .get(key)
+            .map_err(|e| StateError::StorageError(e.to_string()))?
+        {
+            Some(bytes) if bytes.len() >= 16 => {
+                let (ver_bytes, rest) = bytes.split_at(8);
+                let (ts_bytes, document) = rest.split_at(8);
+
+                Ok(Some(StreamState {
+                    stream_id: stream_id.to_string(),
+                    version: u64::from_be_bytes(ver_bytes.try_into().unwrap()),
+                    document: document.to_vec(),
+                    timestamp: u64::from_be_bytes(ts_bytes.try_into().unwrap()),
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+}
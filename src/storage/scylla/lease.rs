@@ -0,0 +1,127 @@
+use crate::cluster::lease::{LeaseOutcome, LeaseStore};
+use crate::storage::scylla::session::ScyllaStore;
+use scylla::response::query_result::QueryResult;
+use scylla::value::{CqlValue, Row};
+use tonic::async_trait;
+
+/// Compare-and-set attempts before reporting contention as a held lease.
+const MAX_CAS_ATTEMPTS: usize = 3;
+
+/// Stream leases in `{keyspace}.stream_leases`, changed only through
+/// lightweight transactions so that Paxos arbitrates between nodes.
+#[async_trait]
+impl LeaseStore for ScyllaStore {
+    async fn acquire(
+        &self,
+        stream_id: &str,
+        holder: &str,
+        now_ms: u64,
+        expires_at_ms: u64,
+    ) -> Result<LeaseOutcome, String> {
+        let keyspace = self.keyspace();
+        let mut observed = None;
+
+        for _ in 0..MAX_CAS_ATTEMPTS {
+            let select = format!(
+                "SELECT holder, expires_at, term FROM {}.stream_leases WHERE stream_id = ?",
+                keyspace
+            );
+            let rows = self
+                .get_session()
+                .query_unpaged(select, (stream_id,))
+                .await
+                .map_err(|e| e.to_string())?
+                .into_rows_result()
+                .map_err(|e| e.to_string())?;
+            let current = rows
+                .maybe_first_row::<(String, i64, i64)>()
+                .map_err(|e| e.to_string())?;
+
+            let (term, applied) = match &current {
+                None => {
+                    let insert = format!(
+                        "INSERT INTO {}.stream_leases (stream_id, holder, expires_at, term) VALUES (?, ?, ?, 1) IF NOT EXISTS",
+                        keyspace
+                    );
+                    let result = self
+                        .get_session()
+                        .query_unpaged(insert, (stream_id, holder, expires_at_ms as i64))
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    (1, applied(result)?)
+                }
+                Some((current_holder, expires_at, term))
+                    if current_holder == holder || (*expires_at as u64) < now_ms =>
+                {
+                    // Renewal keeps the term; a takeover of an expired lease bumps it
+                    let next_term = if current_holder == holder {
+                        *term
+                    } else {
+                        term + 1
+                    };
+                    let update = format!(
+                        "UPDATE {}.stream_leases SET holder = ?, expires_at = ?, term = ? WHERE stream_id = ? IF holder = ? AND term = ?",
+                        keyspace
+                    );
+                    let result = self
+                        .get_session()
+                        .query_unpaged(
+                            update,
+                            (
+                                holder,
+                                expires_at_ms as i64,
+                                next_term,
+                                stream_id,
+                                current_holder,
+                                *term,
+                            ),
+                        )
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    (next_term as u64, applied(result)?)
+                }
+                Some((current_holder, expires_at, _)) => {
+                    return Ok(LeaseOutcome::Held {
+                        holder: current_holder.clone(),
+                        expires_at_ms: *expires_at as u64,
+                    });
+                }
+            };
+
+            if applied {
+                return Ok(LeaseOutcome::Acquired { term });
+            }
+            observed = current;
+        }
+
+        // Lost every race: report whoever we last saw
+        let (holder, expires_at, _) = observed.unwrap_or_default();
+        Ok(LeaseOutcome::Held {
+            holder,
+            expires_at_ms: expires_at.max(0) as u64,
+        })
+    }
+
+    async fn release(&self, stream_id: &str, holder: &str) -> Result<(), String> {
+        let update = format!(
+            "UPDATE {}.stream_leases SET expires_at = 0 WHERE stream_id = ? IF holder = ?",
+            self.keyspace()
+        );
+        self.get_session()
+            .query_unpaged(update, (stream_id, holder))
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+/// Reads the `[applied]` column of a lightweight transaction. The other
+/// columns vary with the outcome, so the row is read untyped.
+fn applied(result: QueryResult) -> Result<bool, String> {
+    let rows = result.into_rows_result().map_err(|e| e.to_string())?;
+    let row = rows.maybe_first_row::<Row>().map_err(|e| e.to_string())?;
+    match row.and_then(|r| r.columns.into_iter().next().flatten()) {
+        Some(CqlValue::Boolean(applied)) => Ok(applied),
+        other => Err(format!("Unexpected LWT result: {:?}", other)),
+    }
+}
@@ -1 +1,2 @@
+pub mod lease;
 pub mod session;
@@ -57,6 +57,7 @@ impl ScyllaStore {
              payload blob, \
              timestamp bigint, \
              metadata map<text, text>, \
+             content_type text, \
              PRIMARY KEY (stream_id, version))",
             self.keyspace
         );
@@ -85,16 +86,37 @@ impl ScyllaStore {
         );
         let _ = self.session.query_unpaged(alter_table, &[]).await; // Ignore error if exists
 
+        let alter_table = format!("ALTER TABLE {}.events ADD content_type text", self.keyspace);
+        let _ = self.session.query_unpaged(alter_table, &[]).await; // Ignore error if exists
+
+        // Stream ownership leases (see cluster::lease)
+        let create_leases_table = format!(
+            "CREATE TABLE IF NOT EXISTS {}.stream_leases ( \
+             stream_id text PRIMARY KEY, \
+             holder text, \
+             expires_at bigint, \
+             term bigint)",
+            self.keyspace
+        );
+        self.session
+            .query_unpaged(create_leases_table, &[])
+            .await
+            .map_err(|e| ScyllaError::QueryError(e.to_string()))?;
+
         Ok(())
     }
 
     pub fn get_session(&self) -> &Session {
         &self.session
     }
+
+    pub fn keyspace(&self) -> &str {
+        &self.keyspace
+    }
 }
 
 use crate::domain::events::event::Event;
-use crate::domain::events::event_kind::{EventKind, EventPayload};
+use crate::domain::events::event_kind::{ContentType, EventKind, EventPayload};
 use crate::domain::schema::model::Schema;
 use crate::storage::event_store::{EventStore, EventStoreError};
 use tonic::async_trait;
@@ -109,7 +131,7 @@ impl EventStore for ScyllaStore {
     ) -> Result<(), EventStoreError> {
         // Prepare query with LWT
         let query = format!(
-            "INSERT INTO {}.events (stream_id, version, id, event_type, payload, timestamp, metadata) VALUES (?, ?, ?, ?, ?, ?, ?) IF NOT EXISTS",
+            "INSERT INTO {}.events (stream_id, version, id, event_type, payload, timestamp, metadata, content_type) VALUES (?, ?, ?, ?, ?, ?, ?, ?) IF NOT EXISTS",
             self.keyspace
         );
 
@@ -122,6 +144,7 @@ impl EventStore for ScyllaStore {
         let timestamp = event.timestamp.0 as i64;
         let version = next_version as i64;
         let metadata = event.metadata;
+        let content_type = event.content_type.to_string();
 
         let result = self
             .session
@@ -135,6 +158,7 @@ impl EventStore for ScyllaStore {
                     payload,
                     timestamp,
                     metadata,
+                    content_type,
                 ),
             )
             .await
@@ -162,14 +186,22 @@ impl EventStore for ScyllaStore {
     }
 
     async fn fetch_stream(&self, stream: &str) -> Result<Vec<Event>, EventStoreError> {
+        self.fetch_stream_from(stream, 0).await
+    }
+
+    async fn fetch_stream_from(
+        &self,
+        stream: &str,
+        version: u64,
+    ) -> Result<Vec<Event>, EventStoreError> {
         let query = format!(
-            "SELECT stream_id, version, id, event_type, payload, timestamp, metadata FROM {}.events WHERE stream_id = ? ORDER BY version ASC",
+            "SELECT stream_id, version, id, event_type, payload, timestamp, metadata, content_type FROM {}.events WHERE stream_id = ? AND version > ? ORDER BY version ASC",
             self.keyspace
         );
 
         let query_result = self
             .session
-            .query_unpaged(query, (stream,))
+            .query_unpaged(query, (stream, version as i64))
             .await
             .map_err(|e| EventStoreError::StorageError(e.to_string()))?;
 
@@ -186,14 +218,23 @@ impl EventStore for ScyllaStore {
                 Vec<u8>,
                 i64,
                 Option<std::collections::HashMap<String, String>>,
+                Option<String>,
             )>()
             .map_err(|e| EventStoreError::StorageError(e.to_string()))?;
 
         let mut events = Vec::new();
 
         for row in rows {
-            let (_stream_id, version, id, event_type_str, payload, timestamp, metadata) =
-                row.map_err(|e| EventStoreError::StorageError(e.to_string()))?;
+            let (
+                _stream_id,
+                version,
+                id,
+                event_type_str,
+                payload,
+                timestamp,
+                metadata,
+                content_type,
+            ) = row.map_err(|e| EventStoreError::StorageError(e.to_string()))?;
 
             // Reconstruct Event
             let event_type = match event_type_str.as_str() {
@@ -209,6 +250,9 @@ impl EventStore for ScyllaStore {
                 sequence_number: version as u64,
                 event_type,
                 payload: crate::domain::events::event_kind::EventPayload(payload),
+                content_type: crate::domain::events::event_kind::ContentType::parse(
+                    content_type.as_deref().unwrap_or_default(),
+                ),
                 timestamp: crate::domain::events::event_kind::Timestamp(timestamp as u64),
                 metadata: metadata.unwrap_or_default(),
             });
@@ -216,6 +260,48 @@ impl EventStore for ScyllaStore {
         Ok(events)
     }
 
+    async fn stream_head(&self, stream: &str) -> Result<u64, EventStoreError> {
+        let query = format!(
+            "SELECT version FROM {}.events WHERE stream_id = ? ORDER BY version DESC LIMIT 1",
+            self.keyspace
+        );
+
+        let query_result = self
+            .session
+            .query_unpaged(query, (stream,))
+            .await
+            .map_err(|e| EventStoreError::StorageError(e.to_string()))?;
+
+        let version = query_result
+            .into_rows_result()
+            .map_err(|e| EventStoreError::StorageError(e.to_string()))?
+            .maybe_first_row::<(i64,)>()
+            .map_err(|e| EventStoreError::StorageError(e.to_string()))?
+            .map(|(version,)| version as u64)
+            .unwrap_or(0);
+        Ok(version)
+    }
+
+    async fn list_streams(&self) -> Result<Vec<String>, EventStoreError> {
+        use tokio_stream::StreamExt;
+
+        let query = format!("SELECT DISTINCT stream_id FROM {}.events", self.keyspace);
+        let mut rows = self
+            .session
+            .query_iter(query, &[])
+            .await
+            .map_err(|e| EventStoreError::StorageError(e.to_string()))?
+            .rows_stream::<(String,)>()
+            .map_err(|e| EventStoreError::StorageError(e.to_string()))?;
+
+        let mut streams = Vec::new();
+        while let Some(row) = rows.next().await {
+            let (stream,) = row.map_err(|e| EventStoreError::StorageError(e.to_string()))?;
+            streams.push(stream);
+        }
+        Ok(streams)
+    }
+
     async fn upsert_schema(&self, schema: Schema) -> Result<(), EventStoreError> {
         // 1. Append to Migration Log (Event Stream)
         let stream_id = format!("$schema:{}", schema.name);
@@ -227,7 +313,8 @@ impl EventStore for ScyllaStore {
             &stream_id,
             EventKind::Schematic,
             EventPayload(payload_bytes.clone()),
-        );
+        )
+        .with_content_type(ContentType::Cbor);
 
         // For schema stream, we might need relaxed concurrency or strictly ordered.
         // For now, let's assume schemas are rare and we can just try to append with version 0 if new, OR better,
@@ -0,0 +1,28 @@
+use tonic::async_trait;
+
+/// The folded current state of a stream, as maintained by the state projection.
+#[derive(Debug, Clone)]
+pub struct StreamState {
+    pub stream_id: String,
+    /// Sequence number of the last event folded into `document`.
+    pub version: u64,
+    /// JSON document.
+    pub document: Vec<u8>,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StateError {
+    #[error("Storage error: {0}")]
+    StorageError(String),
+    #[error("Serialization error: {0}")]
+    SerializationError(#[from] serde_json::Error),
+    #[error("Unknown error: {0}")]
+    Unknown(String),
+}
+
+#[async_trait]
+pub trait StateStore: Send + Sync {
+    async fn save_state(&self, state: StreamState) -> Result<(), StateError>;
+    async fn get_state(&self, stream_id: &str) -> Result<Option<StreamState>, StateError>;
+}
@@ -0,0 +1,151 @@
+//! Geo-replication between two single-node clusters, each a separate
+//! `graveyar_db` process on RocksDB.
+
+use graveyar_db::api::event_store_client::EventStoreClient;
+use graveyar_db::api::{AppendEventRequest, Event as ProtoEvent, GetEventsRequest};
+use graveyar_db::geo::checkpoint::CheckpointFile;
+use graveyar_db::geo::{GeoConfig, GeoReplicator};
+use std::net::TcpListener;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tonic::transport::Channel;
+
+/// A node process, killed when dropped.
+struct Node {
+    child: Child,
+    addr: String,
+}
+
+impl Node {
+    fn start(dir: &TempDir, name: &str) -> Self {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let child = Command::new(env!("CARGO_BIN_EXE_graveyar_db"))
+            .env("SCYLLA_KEYSPACE", "graveyar")
+            .env_remove("SCYLLA_URI")
+            .env_remove("CLUSTER_SEEDS")
+            .env("PORT", port.to_string())
+            .env("CLUSTER_NODES", format!("127.0.0.1:{}", port))
+            .env("DB_PATH", dir.path().join(name))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        Self {
+            child,
+            addr: format!("http://127.0.0.1:{}", port),
+        }
+    }
+
+    async fn client(&self) -> EventStoreClient<Channel> {
+        let started = Instant::now();
+        loop {
+            match EventStoreClient::connect(self.addr.clone()).await {
+                Ok(client) => return client,
+                Err(e) if started.elapsed() > Duration::from_secs(30) => {
+                    panic!("{} did not start: {}", self.addr, e)
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+            }
+        }
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn event() -> ProtoEvent {
+    ProtoEvent {
+        id: uuid::Uuid::new_v4().to_string(),
+        event_type: "Internal".to_string(),
+        payload: b"{}".to_vec(),
+        ..Default::default()
+    }
+}
+
+async fn append(
+    client: &mut EventStoreClient<Channel>,
+    stream_id: &str,
+    expected_version: u64,
+) -> ProtoEvent {
+    let event = event();
+    let resp = client
+        .append_event(AppendEventRequest {
+            stream_id: stream_id.to_string(),
+            events: vec![event.clone()],
+            expected_version,
+            redirect: false,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(resp.success);
+    event
+}
+
+async fn ids(client: &mut EventStoreClient<Channel>, stream_id: &str) -> Vec<String> {
+    let mut stream = client
+        .get_events(GetEventsRequest {
+            stream_id: stream_id.to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let mut ids = Vec::new();
+    while let Some(event) = stream.message().await.unwrap() {
+        ids.push(event.id);
+    }
+    ids
+}
+
+#[tokio::test]
+async fn test_streams_are_discovered_and_copied_from_the_checkpoint() {
+    let dir = TempDir::new().unwrap();
+    let (source_node, target_node) = (Node::start(&dir, "source"), Node::start(&dir, "target"));
+    let (mut source, mut target) = (source_node.client().await, target_node.client().await);
+
+    let a = vec![
+        append(&mut source, "a", 0).await.id,
+        append(&mut source, "a", 1).await.id,
+    ];
+    let b = vec![append(&mut source, "b", 0).await.id];
+
+    let checkpoint_path = dir.path().join("checkpoint.json");
+    let config = GeoConfig {
+        source_addr: source_node.addr.clone(),
+        target_addr: target_node.addr.clone(),
+        source_token: None,
+        target_token: None,
+        streams: Vec::new(),
+        checkpoint_path: checkpoint_path.to_str().unwrap().to_string(),
+        poll_interval: Duration::from_millis(100),
+        batch_size: 1,
+    };
+    let mut replicator = GeoReplicator::new(config).unwrap();
+    assert_eq!(replicator.poll().await, 3);
+    assert_eq!(ids(&mut target, "a").await, a);
+    assert_eq!(ids(&mut target, "b").await, b);
+    assert_eq!(replicator.poll().await, 0);
+
+    // Only the new event is copied
+    let c = append(&mut source, "b", 1).await.id;
+    assert_eq!(replicator.poll().await, 1);
+    assert_eq!(ids(&mut target, "b").await, vec![b[0].clone(), c]);
+
+    // Written on the target: the stream forks and is no longer copied
+    append(&mut target, "a", 2).await;
+    append(&mut source, "a", 2).await;
+    assert_eq!(replicator.poll().await, 0);
+    let checkpoint = CheckpointFile::load(&checkpoint_path).unwrap().checkpoint;
+    assert!(checkpoint.conflicts["a"].contains("GeoConflictError"));
+    assert_eq!(checkpoint.streams["b"], 2);
+}